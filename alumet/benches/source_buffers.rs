@@ -0,0 +1,98 @@
+//! Measures the allocations of a source whose buffers are preallocated according to `points_per_poll_hint`.
+//!
+//! Run with `cargo bench -p alumet --features testing --bench source_buffers`.
+//! The number of allocations per run is printed before the timings.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use alumet::measurement::{MeasurementAccumulator, MeasurementPoint, Timestamp, WrappedMeasurementValue};
+use alumet::metrics::RawMetricId;
+use alumet::pipeline::{testing, trigger, PollError, Source};
+use alumet::resources::{Resource, ResourceConsumer};
+use criterion::{criterion_group, criterion_main, Criterion};
+
+/// Counts the allocations (including the reallocations) of the whole process.
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+const POINTS_PER_POLL: usize = 64;
+const FLUSH_ROUNDS: usize = 10;
+const POLLS: usize = 1000;
+
+/// Pushes `POINTS_PER_POLL` points at each poll, like a source that reads many counters.
+struct BatchSource {
+    hinted: bool,
+}
+
+impl Source for BatchSource {
+    fn poll(&mut self, measurements: &mut MeasurementAccumulator, timestamp: Timestamp) -> Result<(), PollError> {
+        for i in 0..POINTS_PER_POLL {
+            measurements.push(MeasurementPoint::new_untyped(
+                timestamp,
+                RawMetricId(0),
+                Resource::LocalMachine,
+                ResourceConsumer::LocalMachine,
+                WrappedMeasurementValue::U64(i as u64),
+            ));
+        }
+        Ok(())
+    }
+
+    fn points_per_poll_hint(&self) -> Option<usize> {
+        self.hinted.then_some(POINTS_PER_POLL)
+    }
+}
+
+fn run(hinted: bool) {
+    let trigger = trigger::builder::time_interval(Duration::from_secs(3600))
+        .flush_rounds(FLUSH_ROUNDS)
+        .build()
+        .unwrap();
+    let buffers = testing::drive_source(Box::new(BatchSource { hinted }), trigger, Vec::new(), POLLS).unwrap();
+    assert_eq!(buffers.len(), POLLS / FLUSH_ROUNDS);
+}
+
+fn allocations(hinted: bool) -> usize {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    run(hinted);
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+fn source_buffers(c: &mut Criterion) {
+    // The runtime and the channels allocate the same in both cases: the difference comes from the buffers.
+    println!(
+        "allocations for {POLLS} polls of {POINTS_PER_POLL} points: {} without hint, {} with hint",
+        allocations(false),
+        allocations(true)
+    );
+
+    let mut group = c.benchmark_group("source_buffers");
+    group.bench_function("without_hint", |b| b.iter(|| run(false)));
+    group.bench_function("with_hint", |b| b.iter(|| run(true)));
+    group.finish();
+}
+
+criterion_group!(benches, source_buffers);
+criterion_main!(benches);
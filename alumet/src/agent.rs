@@ -9,7 +9,7 @@ use crate::{
     pipeline::{
         self,
         builder::PipelineBuilder,
-        runtime::{IdlePipeline, RunningPipeline},
+        runtime::{IdlePipeline, RunningPipeline, SourceOverflowPolicy},
         trigger::TriggerConstraints,
     },
     plugin::{AlumetStart, ConfigTable, Plugin, PluginMetadata},
@@ -78,6 +78,10 @@ pub struct AgentBuilder {
     f_after_operation_begin: fn(&mut RunningPipeline),
     allow_no_metrics: bool,
     source_constraints: TriggerConstraints,
+    source_overflow_policy: SourceOverflowPolicy,
+    source_channel_capacity: Option<usize>,
+    output_channel_capacity: Option<usize>,
+    instrumentation: bool,
 }
 
 enum AgentConfigSource {
@@ -204,6 +208,16 @@ impl Agent {
         let mut pipeline_builder = pipeline::builder::PipelineBuilder::new();
         pipeline_builder.source_constraints = self.settings.source_constraints;
         pipeline_builder.allow_no_metrics = self.settings.allow_no_metrics;
+        pipeline_builder.source_overflow_policy(self.settings.source_overflow_policy);
+        if let Some(n) = self.settings.source_channel_capacity {
+            pipeline_builder.source_channel_capacity(n);
+        }
+        if let Some(n) = self.settings.output_channel_capacity {
+            pipeline_builder.output_channel_capacity(n);
+        }
+        if self.settings.instrumentation {
+            pipeline_builder.with_instrumentation();
+        }
 
         for plugin in initialized_plugins.iter_mut() {
             log::debug!("Starting plugin {} v{}", plugin.name(), plugin.version());
@@ -295,40 +309,58 @@ impl Agent {
 impl RunningAgent {
     /// Waits until the measurement pipeline stops, then stops the plugins.
     ///
-    /// If an element of the pipeline returns an error or panicks, the other elements are aborted and an error is returned.
+    /// If some elements of the pipeline return an error or panic, the other elements are still awaited,
+    /// and an error is returned after the plugins are stopped.
     pub fn wait_for_shutdown(self) -> anyhow::Result<()> {
-        let mut n_errors = 0;
-
         // Wait for the pipeline to be stopped, by Ctrl+C or a command.
         // Also, **drop** the pipeline before stopping the plugin, because Plugin::stop expects
         // the sources, transforms and outputs to be stopped and dropped before it is called.
         // All tokio tasks that have not finished yet will abort.
-        if let Err(err) = self.pipeline.wait_for_shutdown() {
-            log::error!("Error in the measurement pipeline: {err}");
-            n_errors += 1;
-        }
+        let pipeline_res = self.pipeline.wait_for_shutdown();
+        stop_plugins(pipeline_res, self.initialized_plugins)
+    }
+
+    /// Shuts the measurement pipeline down, then stops the plugins.
+    ///
+    /// The elements of the pipeline that do not stop before the `timeout` are aborted,
+    /// see [`RunningPipeline::shutdown`].
+    pub fn shutdown(self, timeout: Duration) -> anyhow::Result<()> {
+        let pipeline_res = self.pipeline.shutdown(timeout);
+        stop_plugins(pipeline_res, self.initialized_plugins)
+    }
+}
 
-        // Stop all the plugins, even if some of them fail to stop properly.
-        log::info!("Stopping the plugins...");
-        for mut plugin in self.initialized_plugins {
-            let name = plugin.name().to_owned();
-            let version = plugin.version().to_owned();
-            log::info!("Stopping plugin {name} v{version}");
+/// Stops all the plugins, after the shutdown of the pipeline.
+fn stop_plugins(
+    pipeline_res: Result<(), pipeline::runtime::ShutdownError>,
+    initialized_plugins: Vec<Box<dyn Plugin>>,
+) -> anyhow::Result<()> {
+    let mut n_errors = 0;
+    if let Err(err) = pipeline_res {
+        log::error!("Error in the measurement pipeline: {err}");
+        n_errors += err.errors.len();
+    }
 
-            if let Err(error) = plugin.stop() {
-                log::error!("Error while stopping plugin {name} v{version} - {error:#}");
-                n_errors += 1;
-            }
-        }
-        log::info!("All plugins have stopped.");
+    // Stop all the plugins, even if some of them fail to stop properly.
+    log::info!("Stopping the plugins...");
+    for mut plugin in initialized_plugins {
+        let name = plugin.name().to_owned();
+        let version = plugin.version().to_owned();
+        log::info!("Stopping plugin {name} v{version}");
 
-        if n_errors == 0 {
-            Ok(())
-        } else {
-            let error_str = if n_errors == 1 { "error" } else { "errors" };
-            Err(anyhow!("{n_errors} {error_str} occured during the shutdown phase"))
+        if let Err(error) = plugin.stop() {
+            log::error!("Error while stopping plugin {name} v{version} - {error:#}");
+            n_errors += 1;
         }
     }
+    log::info!("All plugins have stopped.");
+
+    if n_errors == 0 {
+        Ok(())
+    } else {
+        let error_str = if n_errors == 1 { "error" } else { "errors" };
+        Err(anyhow!("{n_errors} {error_str} occured during the shutdown phase"))
+    }
 }
 
 fn load_config_from_file(
@@ -450,6 +482,10 @@ impl AgentBuilder {
             f_after_operation_begin: |_| (),
             allow_no_metrics: false,
             source_constraints: TriggerConstraints::default(),
+            source_overflow_policy: SourceOverflowPolicy::default(),
+            source_channel_capacity: None,
+            output_channel_capacity: None,
+            instrumentation: false,
         }
     }
 
@@ -521,6 +557,38 @@ impl AgentBuilder {
         self
     }
 
+    /// Sets what the sources should do when the channel that connects them to the transforms is full.
+    ///
+    /// See [`SourceOverflowPolicy`].
+    pub fn source_overflow_policy(mut self, policy: SourceOverflowPolicy) -> Self {
+        self.source_overflow_policy = policy;
+        self
+    }
+
+    /// Sets the capacity of the channel that connects the sources to the transforms.
+    ///
+    /// See [`PipelineBuilder::source_channel_capacity`].
+    pub fn source_channel_capacity(mut self, n: usize) -> Self {
+        self.source_channel_capacity = Some(n);
+        self
+    }
+
+    /// Sets the capacity of the broadcast queue that connects the transforms to the outputs.
+    ///
+    /// See [`PipelineBuilder::output_channel_capacity`].
+    pub fn output_channel_capacity(mut self, n: usize) -> Self {
+        self.output_channel_capacity = Some(n);
+        self
+    }
+
+    /// Enables the instrumentation of the measurement pipeline.
+    ///
+    /// See [`PipelineBuilder::with_instrumentation`].
+    pub fn with_instrumentation(mut self) -> Self {
+        self.instrumentation = true;
+        self
+    }
+
     /// Disables the "no metrics registered" warning.
     ///
     /// Use this if you only expect late metrics to be registered.
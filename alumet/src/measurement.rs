@@ -29,7 +29,11 @@ use core::fmt;
 use std::borrow::Cow;
 use fxhash::FxBuildHasher;
 use smallvec::SmallVec;
-use std::{collections::HashMap, fmt::Display, time::SystemTime};
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    time::{Instant, SystemTime},
+};
 
 use crate::resources::ResourceConsumer;
 
@@ -133,6 +137,14 @@ impl MeasurementPoint {
         self.attributes.push((key, value));
     }
 
+    /// Replaces the value of the attribute `key`, or adds the attribute if the point does not have it.
+    pub(crate) fn set_attr(&mut self, key: &Cow<'static, str>, value: AttributeValue) {
+        match self.attributes.iter_mut().find(|(k, _)| k == key) {
+            Some((_, v)) => *v = value,
+            None => self.attributes.push((key.clone(), value)),
+        }
+    }
+
     /// Sets an attribute on this measurement point.
     /// If an attribute with the same key already exists, its value is replaced.
     pub fn with_attr<K: Into<Cow<'static, str>>, V: Into<AttributeValue>>(mut self, key: K, value: V) -> Self {
@@ -305,18 +317,31 @@ impl From<&'static str> for AttributeValue {
 #[derive(Clone)]
 pub struct MeasurementBuffer {
     points: Vec<MeasurementPoint>,
+    /// When the first point of the buffer has been polled, only set if the instrumentation is enabled.
+    ///
+    /// This is a monotonic instant, separate from the timestamps of the points, which are provided by the
+    /// sources and can come from another clock (or be adjusted by the system).
+    ingested_at: Option<Instant>,
+    /// If `true`, the buffer bypasses the transforms, see [`MeasurementBuffer::mark_processed`].
+    processed: bool,
 }
 
 impl MeasurementBuffer {
     /// Constructs a new buffer.
     pub fn new() -> MeasurementBuffer {
-        MeasurementBuffer { points: Vec::new() }
+        MeasurementBuffer {
+            points: Vec::new(),
+            ingested_at: None,
+            processed: false,
+        }
     }
 
     /// Constructs a new buffer with at least the specified capacity (allocated on construction).
     pub fn with_capacity(capacity: usize) -> MeasurementBuffer {
         MeasurementBuffer {
             points: Vec::with_capacity(capacity),
+            ingested_at: None,
+            processed: false,
         }
     }
     
@@ -330,6 +355,11 @@ impl MeasurementBuffer {
         self.points.len()
     }
 
+    /// Returns the number of measurement points that the buffer can hold without reallocating.
+    pub fn capacity(&self) -> usize {
+        self.points.capacity()
+    }
+
     /// Reserves capacity for at least `additional` more elements.
     /// See [`Vec::reserve`].
     pub fn reserve(&mut self, additional: usize) {
@@ -347,6 +377,45 @@ impl MeasurementBuffer {
         self.points.clear();
     }
 
+    /// Keeps only the measurements for which `f` returns `true`, in their original order.
+    /// See [`Vec::retain`].
+    pub fn retain(&mut self, f: impl FnMut(&MeasurementPoint) -> bool) {
+        self.points.retain(f);
+    }
+
+    /// Keeps only the measurements for which `f` returns `true`, in their original order,
+    /// and allows `f` to modify them. See [`Vec::retain_mut`].
+    pub fn retain_mut(&mut self, f: impl FnMut(&mut MeasurementPoint) -> bool) {
+        self.points.retain_mut(f);
+    }
+
+    /// Keeps the first `len` measurements and drops the others.
+    /// See [`Vec::truncate`].
+    pub fn truncate(&mut self, len: usize) {
+        self.points.truncate(len);
+    }
+
+    /// Splits the buffer into buffers of at most `max_len` measurements each, in their original order.
+    ///
+    /// Panics if `max_len` is zero.
+    pub fn split_into_chunks(self, max_len: usize) -> Vec<MeasurementBuffer> {
+        assert!(max_len > 0, "the chunks must not be empty");
+        let mut chunks = Vec::with_capacity(self.points.len().div_ceil(max_len));
+        let mut points = self.points.into_iter();
+        loop {
+            let chunk: Vec<MeasurementPoint> = points.by_ref().take(max_len).collect();
+            if chunk.is_empty() {
+                break;
+            }
+            chunks.push(MeasurementBuffer {
+                points: chunk,
+                ingested_at: self.ingested_at,
+                processed: self.processed,
+            });
+        }
+        chunks
+    }
+
     /// Creates an iterator on the buffer's content.
     pub fn iter(&self) -> impl Iterator<Item = &MeasurementPoint> {
         self.points.iter()
@@ -357,6 +426,31 @@ impl MeasurementBuffer {
         self.points.iter_mut()
     }
 
+    /// Marks the buffer as "already processed": the pipeline sends it directly to the outputs,
+    /// without applying the transforms.
+    ///
+    /// This avoids processing the measurements twice when several Alumet instances are chained:
+    /// the source that receives the measurements of another instance, whose transforms have already run,
+    /// can mark its buffers as processed. The flag is kept by the copies of the buffer.
+    pub fn mark_processed(&mut self) {
+        self.processed = true;
+    }
+
+    /// Returns `true` if the buffer has been marked with [`mark_processed`](Self::mark_processed).
+    pub fn is_processed(&self) -> bool {
+        self.processed
+    }
+
+    /// Returns the time at which the first point of the buffer has been polled, if it has been recorded.
+    pub(crate) fn ingested_at(&self) -> Option<Instant> {
+        self.ingested_at
+    }
+
+    /// Records the time at which the first point of the buffer has been polled.
+    pub(crate) fn set_ingested_at(&mut self, t: Option<Instant>) {
+        self.ingested_at = t;
+    }
+
     /// Returns a `MeasurementAccumulator` that will push all measurements to this buffer.
     pub fn as_accumulator(&mut self) -> MeasurementAccumulator {
         MeasurementAccumulator(self)
@@ -382,7 +476,11 @@ impl std::fmt::Debug for MeasurementBuffer {
 
 impl From<Vec<MeasurementPoint>> for MeasurementBuffer {
     fn from(value: Vec<MeasurementPoint>) -> Self {
-        MeasurementBuffer { points: value }
+        MeasurementBuffer {
+            points: value,
+            ingested_at: None,
+            processed: false,
+        }
     }
 }
 
@@ -396,4 +494,12 @@ impl<'a> MeasurementAccumulator<'a> {
     pub fn push(&mut self, point: MeasurementPoint) {
         self.0.push(point)
     }
+
+    /// Marks the underlying buffer as "already processed", see [`MeasurementBuffer::mark_processed`].
+    ///
+    /// The buffer accumulates the measurements of several polls, until it is flushed (see the `flush_rounds`
+    /// of the trigger): all these measurements bypass the transforms.
+    pub fn mark_processed(&mut self) {
+        self.0.mark_processed()
+    }
 }
@@ -0,0 +1,107 @@
+//! A transform that attaches constant attributes to every measurement point, like the identifier of the host.
+
+use std::borrow::Cow;
+
+use crate::measurement::{AttributeValue, MeasurementBuffer};
+
+use super::{Transform, TransformError};
+
+/// What to do when a point already has an attribute with the same key as a constant attribute.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AttributeConflictPolicy {
+    /// Keep the attribute of the point, and ignore the constant one.
+    #[default]
+    KeepExisting,
+    /// Replace the value of the attribute of the point by the constant one.
+    Override,
+}
+
+/// A transform that attaches the same attributes to every measurement point.
+///
+/// It is usually added at the front of the transforms of every route,
+/// with [`PipelineBuilder::add_constant_attributes`](super::builder::PipelineBuilder::add_constant_attributes).
+///
+/// ## Example
+/// ```
+/// use alumet::measurement::AttributeValue;
+/// use alumet::pipeline::attributes::{AttributeConflictPolicy, ConstantAttributesTransform};
+///
+/// let transform = ConstantAttributesTransform::new(
+///     vec![
+///         ("host_id", AttributeValue::Str("node-42")),
+///         ("environment", AttributeValue::Str("production")),
+///     ],
+///     AttributeConflictPolicy::KeepExisting,
+/// );
+/// ```
+#[derive(Debug, Clone)]
+pub struct ConstantAttributesTransform {
+    attributes: Vec<(Cow<'static, str>, AttributeValue)>,
+    policy: AttributeConflictPolicy,
+}
+
+impl ConstantAttributesTransform {
+    pub fn new<K: Into<Cow<'static, str>>>(
+        attributes: Vec<(K, AttributeValue)>,
+        policy: AttributeConflictPolicy,
+    ) -> Self {
+        Self {
+            attributes: attributes.into_iter().map(|(k, v)| (k.into(), v)).collect(),
+            policy,
+        }
+    }
+}
+
+impl Transform for ConstantAttributesTransform {
+    fn apply(&mut self, measurements: &mut MeasurementBuffer) -> Result<(), TransformError> {
+        for point in measurements.iter_mut() {
+            for (key, value) in &self.attributes {
+                let exists = point.attributes_keys().any(|k| k == key);
+                match (exists, self.policy) {
+                    (false, _) => point.add_attr(key.clone(), value.clone()),
+                    (true, AttributeConflictPolicy::Override) => point.set_attr(key, value.clone()),
+                    (true, AttributeConflictPolicy::KeepExisting) => (),
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::measurement::{AttributeValue, MeasurementBuffer};
+    use crate::pipeline::testing;
+    use crate::pipeline::Transform;
+
+    use super::{AttributeConflictPolicy, ConstantAttributesTransform};
+
+    fn attributes(policy: AttributeConflictPolicy) -> Vec<Vec<(String, String)>> {
+        let point = testing::point().build();
+        let mut buf = MeasurementBuffer::from(vec![point.clone(), point.with_attr("env", "test")]);
+        let constants = vec![("host", AttributeValue::Str("node")), ("env", AttributeValue::Str("prod"))];
+        ConstantAttributesTransform::new(constants, policy).apply(&mut buf).unwrap();
+        buf.iter()
+            .map(|p| p.attributes().map(|(k, v)| (k.to_owned(), v.to_string())).collect())
+            .collect()
+    }
+
+    #[test]
+    fn merge_attributes() {
+        let pair = |k: &str, v: &str| (k.to_owned(), v.to_owned());
+        assert_eq!(
+            attributes(AttributeConflictPolicy::KeepExisting),
+            vec![
+                vec![pair("host", "node"), pair("env", "prod")],
+                vec![pair("env", "test"), pair("host", "node")],
+            ]
+        );
+        assert_eq!(
+            attributes(AttributeConflictPolicy::Override),
+            vec![
+                vec![pair("host", "node"), pair("env", "prod")],
+                vec![pair("env", "prod"), pair("host", "node")],
+            ]
+        );
+    }
+}
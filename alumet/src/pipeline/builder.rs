@@ -1,11 +1,13 @@
 use core::fmt;
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::future::Future;
 use std::io::{self, ErrorKind};
 use std::path::PathBuf;
 use std::pin::Pin;
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use anyhow::Context;
 
@@ -15,12 +17,22 @@ use tokio_util::sync::CancellationToken;
 
 use crate::metrics::{Metric, MetricRegistry, RawMetricId};
 use crate::{
-    measurement::MeasurementBuffer,
-    pipeline::{Output, Source, Transform},
+    measurement::{AttributeValue, MeasurementBuffer, MeasurementPoint},
+    pipeline::{AsyncOutput, AsyncSource, Output, Source, Transform},
 };
 
-use super::runtime::{self, IdlePipeline, OutputMsg};
-use super::trigger::{TriggerConstraints, TriggerSpec};
+use super::attributes::{AttributeConflictPolicy, ConstantAttributesTransform};
+use super::runtime::{
+    self, BufferSizeLimit, CircuitBreakerPolicy, IdlePipeline, OutputMsg, OversizedBufferPolicy, PollErrorPolicy,
+    RetryPolicy, SlowOutputPolicy, SourceOverflowPolicy, TransformErrorPolicy,
+};
+use super::trigger::{self, TriggerConstraints, TriggerSpec};
+
+/// Default capacity of the channels of the pipeline.
+const DEFAULT_CHANNEL_CAPACITY: usize = 256;
+
+/// Default poll interval of the sources added with [`PipelineBuilder::add_source`].
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(1);
 
 /// A builder of measurement pipeline.
 pub struct PipelineBuilder {
@@ -31,21 +43,43 @@ pub struct PipelineBuilder {
     pub(crate) autonomous_sources: Vec<AutonomousSourceBuilder>,
 
     pub(crate) source_constraints: TriggerConstraints,
+    pub(crate) source_overflow_policy: SourceOverflowPolicy,
+    pub(crate) source_channel_capacity: usize,
+    pub(crate) output_channel_capacity: usize,
+    pub(crate) constant_attributes: Vec<ConstantAttributesTransform>,
+    pub(crate) buffer_size_limit: Option<BufferSizeLimit>,
 
     pub(crate) metrics: MetricRegistry,
     pub(crate) allow_no_metrics: bool,
+    pub(crate) instrumentation: bool,
 
     pub(crate) normal_worker_threads: Option<usize>,
     pub(crate) priority_worker_threads: Option<usize>,
+    pub(crate) blocking_worker_threads: Option<usize>,
+    /// Prefix of the names of the worker threads, to distinguish the threads of several pipelines.
+    pub(crate) thread_name_prefix: Option<String>,
+    /// If `true`, the pipeline fails to build when the priority of its threads cannot be increased.
+    pub(crate) require_realtime_priority: bool,
+
+    /// Sources with an invalid configuration, by name, with the reason.
+    pub(crate) invalid_sources: HashMap<String, String>,
 }
 
-pub type SourceBuildFn = dyn FnOnce(&PendingPipelineContext) -> Box<dyn Source>;
+pub type SourceBuildFn = dyn FnOnce(&PendingPipelineContext) -> SourceKind;
 pub type AutonomousSourceBuildFn = dyn FnOnce(
     &PendingPipelineContext,
     CancellationToken,
     mpsc::Sender<MeasurementBuffer>,
 ) -> Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send>>;
 
+/// A managed source, which can be sync or async.
+pub enum SourceKind {
+    /// A source whose poll returns when the measurements are ready.
+    Sync(Box<dyn Source>),
+    /// An async source, whose poll is awaited.
+    Async(Box<dyn AsyncSource>),
+}
+
 pub struct ManagedSourceBuilder {
     pub name: String,
     pub plugin: String,
@@ -59,16 +93,79 @@ pub struct AutonomousSourceBuilder {
     pub build: Box<AutonomousSourceBuildFn>,
 }
 
+/// Name of the route of the elements that are not explicitly assigned to a route.
+pub const DEFAULT_ROUTE: &str = "default";
+
 pub struct TransformBuilder {
     pub name: String,
     pub plugin: String,
     pub build: Box<dyn FnOnce(&PendingPipelineContext) -> Box<dyn Transform>>,
+    /// The route that the transform belongs to (see [`DEFAULT_ROUTE`]).
+    pub route: String,
+    /// What to do when the transform fails with a fatal error.
+    pub error_policy: TransformErrorPolicy,
+}
+
+/// A predicate that decides which measurement points are given to an output.
+pub type OutputFilter = dyn Fn(&MeasurementPoint) -> bool + Send + Sync;
+
+/// An output, which can be blocking or async.
+pub enum OutputKind {
+    /// A blocking output, which is run on a dedicated thread.
+    Blocking(Box<dyn Output>),
+    /// An async output, which is awaited directly.
+    Async(Box<dyn AsyncOutput>),
 }
 
 pub struct OutputBuilder {
     pub name: String,
     pub plugin: String,
-    pub build: Box<dyn FnOnce(&PendingPipelineContext) -> anyhow::Result<Box<dyn Output>>>,
+    pub build: Box<dyn FnOnce(&PendingPipelineContext) -> anyhow::Result<OutputKind>>,
+    /// If set, the output only receives the points that match this filter.
+    pub filter: Option<Box<OutputFilter>>,
+    /// If set, the writes that fail with a non-fatal error are retried according to this policy.
+    pub retry: Option<RetryPolicy>,
+    /// If set, [`Output::flush`](super::Output::flush) is called periodically, with this interval.
+    pub flush_interval: Option<Duration>,
+    /// What happens when the output is too slow to write the measurements that it receives.
+    pub slow_policy: SlowOutputPolicy,
+    /// If set, the writes are skipped for a while after repeated failures.
+    pub circuit_breaker: Option<CircuitBreakerPolicy>,
+    /// If set, [`Output::health`](super::Output::health) is checked periodically, with this interval.
+    pub health_check_interval: Option<Duration>,
+    /// If set to `k`, the output only writes one of every `k` buffers that it receives.
+    ///
+    /// This downsamples the measurements over time, for this output only. See [`OutputRegistration::sampling`].
+    pub sampling: Option<u32>,
+    /// The route that the output belongs to (see [`DEFAULT_ROUTE`]).
+    ///
+    /// The output receives the measurements produced by the transforms of the same route.
+    pub route: String,
+}
+
+impl OutputBuilder {
+    /// Creates the builder of an output with the default options: no filter, no retry, no periodic flush,
+    /// no circuit breaker, no health check and no sampling, in the [`DEFAULT_ROUTE`], and which loses
+    /// the oldest measurements when it is too slow ([`SlowOutputPolicy::DropOldest`]).
+    pub fn new(
+        name: String,
+        plugin: String,
+        build: Box<dyn FnOnce(&PendingPipelineContext) -> anyhow::Result<OutputKind>>,
+    ) -> Self {
+        Self {
+            name,
+            plugin,
+            build,
+            filter: None,
+            retry: None,
+            flush_interval: None,
+            slow_policy: SlowOutputPolicy::DropOldest,
+            circuit_breaker: None,
+            health_check_interval: None,
+            sampling: None,
+            route: String::from(DEFAULT_ROUTE),
+        }
+    }
 }
 
 /// Information about a pipeline that is being built.
@@ -78,6 +175,13 @@ pub struct PendingPipelineContext<'a> {
 }
 
 impl<'a> PendingPipelineContext<'a> {
+    pub(super) fn new(
+        to_output: &'a broadcast::Sender<runtime::OutputMsg>,
+        rt_handle: &'a tokio::runtime::Handle,
+    ) -> Self {
+        Self { to_output, rt_handle }
+    }
+
     pub fn late_registration_handle(&self) -> LateRegistrationHandle {
         let (reply_tx, reply_rx) = mpsc::channel::<Vec<RawMetricId>>(256);
         LateRegistrationHandle {
@@ -123,7 +227,7 @@ impl LateRegistrationHandle {
 /// A source that is ready to run.
 pub(super) struct ConfiguredSource {
     /// The source.
-    pub source: Box<dyn Source>,
+    pub source: SourceKind,
     /// Name of the source.
     pub name: String,
     /// Name of the plugin that registered the source.
@@ -137,6 +241,8 @@ pub(super) struct ConfiguredAutonomousSource {
     pub source: Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send>>,
     /// Name of the source.
     pub name: String,
+    /// Name of the plugin that has registered the source.
+    pub plugin: String,
 }
 /// A transform that is ready to run.
 pub(super) struct ConfiguredTransform {
@@ -146,15 +252,35 @@ pub(super) struct ConfiguredTransform {
     pub name: String,
     /// Name of the plugin that registered the source.
     pub plugin_name: String,
+    /// The route that the transform belongs to.
+    pub route: String,
+    /// What to do when the transform fails with a fatal error.
+    pub error_policy: TransformErrorPolicy,
 }
 /// An output that is ready to run.
 pub(super) struct ConfiguredOutput {
     /// The output.
-    pub output: Box<dyn Output>,
+    pub output: OutputKind,
     /// Name of the output.
     pub name: String,
     /// Name of the plugin that registered the source.
     pub plugin_name: String,
+    /// Optional filter, applied to the measurements before they are written.
+    pub filter: Option<Box<OutputFilter>>,
+    /// Optional retry policy, applied to the writes that fail with a non-fatal error.
+    pub retry: Option<RetryPolicy>,
+    /// Optional interval between two flushes of the output.
+    pub flush_interval: Option<Duration>,
+    /// What happens when the output is too slow.
+    pub slow_policy: SlowOutputPolicy,
+    /// If set, the writes are skipped for a while after repeated failures.
+    pub circuit_breaker: Option<CircuitBreakerPolicy>,
+    /// If set, [`Output::health`](super::Output::health) is checked periodically, with this interval.
+    pub health_check_interval: Option<Duration>,
+    /// If set to `k`, only one of every `k` received buffers is written.
+    pub sampling: Option<u32>,
+    /// The route that the output belongs to.
+    pub route: String,
 }
 
 #[derive(Debug)]
@@ -167,7 +293,7 @@ pub enum PipelineBuildError {
     ElementBuild(anyhow::Error, ElementType, String),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ElementType {
     Source,
     Transform,
@@ -178,6 +304,18 @@ pub enum ElementType {
 pub enum InvalidReason {
     NoSource,
     NoOutput,
+    /// The capacity of a channel of the pipeline is zero.
+    ZeroChannelCapacity,
+    /// The limit of the size of the buffers is zero.
+    ZeroBufferSizeLimit,
+    /// Some transforms belong to a route that has no output.
+    RouteWithoutOutput(String),
+    /// The configuration of a source, given by its name, is invalid.
+    InvalidSource(String, String),
+    /// The number of worker threads of a runtime, given by its name, is zero.
+    ZeroWorkerThreads(&'static str),
+    /// Several elements of the same type have the same name.
+    DuplicateName(ElementType, String),
 }
 
 impl fmt::Display for InvalidReason {
@@ -185,6 +323,14 @@ impl fmt::Display for InvalidReason {
         match self {
             InvalidReason::NoSource => write!(f, "no Source"),
             InvalidReason::NoOutput => write!(f, "no Output"),
+            InvalidReason::ZeroChannelCapacity => write!(f, "the capacity of the channels must be non-zero"),
+            InvalidReason::ZeroBufferSizeLimit => write!(f, "the maximum size of the buffers must be non-zero"),
+            InvalidReason::RouteWithoutOutput(route) => write!(f, "no Output in route {route}"),
+            InvalidReason::InvalidSource(name, reason) => write!(f, "invalid source {name}: {reason}"),
+            InvalidReason::ZeroWorkerThreads(runtime) => {
+                write!(f, "the number of worker threads of the {runtime} runtime must be non-zero")
+            }
+            InvalidReason::DuplicateName(typ, name) => write!(f, "duplicate {typ:?} name {name}"),
         }
     }
 }
@@ -210,6 +356,186 @@ impl From<io::Error> for PipelineBuildError {
     }
 }
 
+/// Configures the trigger of a source added with [`PipelineBuilder::add_source`].
+///
+/// An invalid configuration, like a flush interval that is smaller than the poll interval,
+/// makes [`PipelineBuilder::build`] fail.
+pub struct SourceRegistration<'a> {
+    builder: &'a mut PipelineBuilder,
+    index: usize,
+    poll_interval: Duration,
+    flush_interval: Option<Duration>,
+    realtime_priority: bool,
+    poll_error_policy: PollErrorPolicy,
+}
+
+impl SourceRegistration<'_> {
+    /// Polls the source every `poll_interval`.
+    pub fn every(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self.update_trigger();
+        self
+    }
+
+    /// Flushes the measurements of the source every `flush_interval`.
+    ///
+    /// The flush interval cannot be smaller than the poll interval.
+    pub fn flush_every(mut self, flush_interval: Duration) -> Self {
+        self.flush_interval = Some(flush_interval);
+        self.update_trigger();
+        self
+    }
+
+    /// Runs the source on the "realtime priority" runtime.
+    pub fn priority(mut self) -> Self {
+        self.realtime_priority = true;
+        self.update_trigger();
+        self
+    }
+
+    /// Chooses what the source does when its poll fails with a non-fatal error, see [`PollErrorPolicy`].
+    pub fn on_poll_error(mut self, policy: PollErrorPolicy) -> Self {
+        self.poll_error_policy = policy;
+        self.update_trigger();
+        self
+    }
+
+    fn update_trigger(&mut self) {
+        let source = &mut self.builder.sources[self.index];
+        let flush_interval = self.flush_interval.unwrap_or(self.poll_interval);
+        let res = if flush_interval < self.poll_interval {
+            Err(format!(
+                "the flush interval ({flush_interval:?}) is smaller than the poll interval ({:?})",
+                self.poll_interval
+            ))
+        } else {
+            let mut spec_builder = trigger::builder::time_interval(self.poll_interval).flush_interval(flush_interval);
+            if self.realtime_priority {
+                spec_builder = spec_builder.realtime_priority();
+            }
+            spec_builder
+                .build()
+                .map(|spec| spec.with_poll_error_policy(self.poll_error_policy.clone()))
+                .map_err(|e| e.to_string())
+        };
+        match res {
+            Ok(trigger) => {
+                source.trigger = trigger;
+                self.builder.invalid_sources.remove(&source.name);
+            }
+            Err(reason) => {
+                self.builder.invalid_sources.insert(source.name.clone(), reason);
+            }
+        }
+    }
+}
+
+/// Configures an output added with [`PipelineBuilder::add_output`] or [`AlumetStart::add_output`].
+///
+/// The options can be combined, for instance to retry the writes of an output whose health is checked:
+/// ```no_run
+/// # use std::time::Duration;
+/// # use alumet::pipeline::{builder::PipelineBuilder, runtime::RetryPolicy, Output};
+/// # fn f(output: Box<dyn Output>, policy: RetryPolicy) {
+/// let mut builder = PipelineBuilder::new();
+/// builder
+///     .add_output("my-plugin", output)
+///     .retry(policy)
+///     .health_check(Duration::from_secs(10));
+/// # }
+/// ```
+///
+/// [`AlumetStart::add_output`]: crate::plugin::AlumetStart::add_output
+pub struct OutputRegistration<'a> {
+    output: &'a mut OutputBuilder,
+}
+
+impl OutputRegistration<'_> {
+    /// Only gives the output the measurement points that match `filter`.
+    ///
+    /// The filter is applied before each call to [`Output::write`](super::Output::write). If no point matches,
+    /// the output is not called at all.
+    pub fn filter<F: Fn(&MeasurementPoint) -> bool + Send + Sync + 'static>(self, filter: F) -> Self {
+        self.output.filter = Some(Box::new(filter));
+        self
+    }
+
+    /// Retries the writes that fail with [`WriteError::CanRetry`](super::WriteError::CanRetry)
+    /// according to `policy`.
+    ///
+    /// By default, such failures are logged and the measurements are dropped.
+    pub fn retry(self, policy: RetryPolicy) -> Self {
+        self.output.retry = Some(policy);
+        self
+    }
+
+    /// Flushes the output every `flush_interval`.
+    ///
+    /// [`Output::flush`](super::Output::flush) is called periodically, even if no new measurements have arrived.
+    /// This is useful for outputs that batch their writes.
+    pub fn flush_every(self, flush_interval: Duration) -> Self {
+        self.output.flush_interval = Some(flush_interval);
+        self
+    }
+
+    /// Chooses what the output does when it is too slow, see [`SlowOutputPolicy`].
+    ///
+    /// By default, a slow output loses the oldest measurements ([`SlowOutputPolicy::DropOldest`]).
+    pub fn on_slow(self, policy: SlowOutputPolicy) -> Self {
+        self.output.slow_policy = policy;
+        self
+    }
+
+    /// Stops calling the output for a while after it has failed several times in a row,
+    /// according to `policy` (see [`CircuitBreakerPolicy`]).
+    pub fn circuit_breaker(self, policy: CircuitBreakerPolicy) -> Self {
+        self.output.circuit_breaker = Some(policy);
+        self
+    }
+
+    /// Only writes one of every `k` measurement buffers that the output receives.
+    ///
+    /// This is useful for an expensive output, like a remote database, that does not need all the measurements,
+    /// while the other outputs receive them all. The first buffer is written, then the next `k - 1` buffers are
+    /// dropped, and so on. The dropped buffers and points are counted, see
+    /// [`ControlHandle::sampled_out_buffers`](super::runtime::ControlHandle::sampled_out_buffers).
+    /// If `k` is 0 or 1, every buffer is written.
+    ///
+    /// The buffers are counted when the output receives them: if the output lags behind, the messages that it loses
+    /// (see [`SlowOutputPolicy`]) are not part of the count, therefore the output still writes one of every `k`
+    /// buffers that reach it, but less than one of every `k` buffers produced by the transforms.
+    pub fn sampling(self, k: u32) -> Self {
+        self.output.sampling = Some(k);
+        self
+    }
+
+    /// Checks the health of the output every `interval` (see [`Output::health`](super::Output::health)).
+    ///
+    /// While the output is unhealthy, for instance because its database is down, it is paused: it stops receiving
+    /// the measurements, instead of failing to write them. The measurements that it misses are counted as lost
+    /// messages, like those of a slow output, but they do not count toward its detachment (see [`SlowOutputPolicy`]).
+    /// The other outputs are not affected. In a reduced pipeline, where the output is the only one, the sources are
+    /// slowed down instead, according to their overflow policy.
+    /// The state of the output is available through
+    /// [`ControlHandle::output_health`](super::runtime::ControlHandle::output_health).
+    ///
+    /// Unlike a [circuit breaker](Self::circuit_breaker), which reacts to the failures of the writes,
+    /// this does not try to write the measurements to a dependency that is known to be unavailable.
+    pub fn health_check(self, interval: Duration) -> Self {
+        self.output.health_check_interval = Some(interval);
+        self
+    }
+
+    /// Adds the output to `route` instead of the [`DEFAULT_ROUTE`].
+    ///
+    /// The output receives the measurements produced by the transforms of the route,
+    /// see [`AlumetStart::add_transform_to_route`](crate::plugin::AlumetStart::add_transform_to_route).
+    pub fn route(self, route: &str) -> Self {
+        self.output.route = route.to_owned();
+        self
+    }
+}
+
 impl PipelineBuilder {
     pub fn new() -> Self {
         Self {
@@ -220,9 +546,19 @@ impl PipelineBuilder {
             autonomous_sources: Vec::new(),
             metrics: MetricRegistry::new(),
             allow_no_metrics: false,
+            instrumentation: false,
             normal_worker_threads: None,
             priority_worker_threads: None,
+            blocking_worker_threads: None,
+            thread_name_prefix: None,
+            require_realtime_priority: false,
+            invalid_sources: HashMap::new(),
             source_constraints: TriggerConstraints::default(),
+            source_overflow_policy: SourceOverflowPolicy::default(),
+            source_channel_capacity: DEFAULT_CHANNEL_CAPACITY,
+            output_channel_capacity: DEFAULT_CHANNEL_CAPACITY,
+            constant_attributes: Vec::new(),
+            buffer_size_limit: None,
         }
     }
 
@@ -246,30 +582,302 @@ impl PipelineBuilder {
         self.metrics.iter()
     }
 
-    pub fn build(self) -> Result<IdlePipeline, PipelineBuildError> {
-        // Check some conditions.
-        if self.metrics.is_empty() && !self.allow_no_metrics {
-            log::warn!("No metrics have been registered, have you loaded the right plugins?")
+    /// Sets what the sources should do when the channel that connects them to the transforms is full.
+    ///
+    /// The default policy is [`SourceOverflowPolicy::DropNewest`].
+    pub fn source_overflow_policy(&mut self, policy: SourceOverflowPolicy) {
+        self.source_overflow_policy = policy;
+    }
+
+    /// Sets the capacity of the channel that connects the sources to the transforms,
+    /// in number of [`MeasurementBuffer`]s.
+    ///
+    /// The default capacity is 256. When many fast sources are running, a bigger capacity
+    /// prevents the [overflow policy](Self::source_overflow_policy) from being triggered too often.
+    ///
+    /// The capacity must be non-zero, otherwise [`build`](Self::build) fails.
+    pub fn source_channel_capacity(&mut self, n: usize) {
+        self.source_channel_capacity = n;
+    }
+
+    /// Sets the capacity of the broadcast queue that connects the transforms to the outputs,
+    /// in number of messages.
+    ///
+    /// The default capacity is 256. Every output keeps its own copy of the buffers that it has not
+    /// read yet, therefore the memory used by this queue can grow up to
+    /// `n * (size of a MeasurementBuffer) * (number of outputs)`. On a memory-constrained machine,
+    /// consider lowering it. If an output is too slow, it loses the oldest messages.
+    ///
+    /// The capacity must be non-zero, otherwise [`build`](Self::build) fails.
+    pub fn output_channel_capacity(&mut self, n: usize) {
+        self.output_channel_capacity = n;
+    }
+
+    /// Limits the number of measurement points of the buffers that are sent to the outputs.
+    ///
+    /// This is a safety valve against the sources that produce enormous buffers, which would be copied
+    /// for every output. The limit is enforced after the transforms, with the given `policy`,
+    /// and a warning is logged for every buffer that exceeds it. By default, there is no limit.
+    ///
+    /// The limit must be non-zero, otherwise [`build`](Self::build) fails.
+    pub fn max_buffer_size(&mut self, max_points: usize, policy: OversizedBufferPolicy) {
+        self.buffer_size_limit = Some(BufferSizeLimit { max_points, policy });
+    }
+
+    /// Sets the number of worker threads of the normal runtime, which runs most of the pipeline.
+    ///
+    /// By default, tokio uses one thread per CPU core.
+    ///
+    /// Tokio runtimes cannot be resized: to change the number of threads after the start of the pipeline,
+    /// use [`RunningPipeline::rebuild_runtime`](runtime::RunningPipeline::rebuild_runtime), which moves
+    /// the elements to a new runtime (this is not possible if the pipeline has autonomous sources).
+    pub fn normal_worker_threads(&mut self, n: usize) {
+        self.normal_worker_threads = Some(n);
+    }
+
+    /// Sets the number of worker threads of the "realtime priority" runtime.
+    ///
+    /// By default, there is one thread per source that requires a realtime priority.
+    /// Like [`normal_worker_threads`](Self::normal_worker_threads), this can be changed after the start of the pipeline
+    /// with [`RunningPipeline::rebuild_runtime`](runtime::RunningPipeline::rebuild_runtime).
+    pub fn priority_worker_threads(&mut self, n: usize) {
+        self.priority_worker_threads = Some(n);
+    }
+
+    /// Fails to build the pipeline if a source requires a "realtime priority", and the scheduling priority
+    /// of the threads cannot be increased (for instance because the agent is missing the `SYS_NICE` capability).
+    ///
+    /// By default, the sources run at the normal priority in this case: the measurements are still taken, but
+    /// the time between two measurements may differ from the configuration. Use this method if you require
+    /// the realtime guarantees.
+    ///
+    /// See also [`RunningPipeline::realtime_priority`](runtime::RunningPipeline::realtime_priority).
+    pub fn require_realtime_priority(&mut self) {
+        self.require_realtime_priority = true;
+    }
+
+    /// Sets the number of threads of the runtime that runs the blocking sources
+    /// (see [`TimeTriggerBuilder::blocking`](super::trigger::builder::TimeTriggerBuilder::blocking)).
+    ///
+    /// By default, there is one thread per blocking source. The runtime is only created
+    /// if at least one source is blocking.
+    pub fn blocking_worker_threads(&mut self, n: usize) {
+        self.blocking_worker_threads = Some(n);
+    }
+
+    /// Sets a prefix for the names of the worker threads of the pipeline.
+    ///
+    /// By default, the threads are named `normal-worker-{id}`, `priority-worker-{id}` and `blocking-worker-{id}`.
+    /// With the prefix `vm42`, they are named `vm42-normal-worker-{id}`, and so on, which allows to tell apart
+    /// the threads of the pipelines that run in the same process (e.g. in `top` or in a profiler).
+    ///
+    /// Note that the names of the threads are truncated to 15 bytes on Linux.
+    pub fn thread_name_prefix(&mut self, prefix: impl Into<String>) {
+        self.thread_name_prefix = Some(prefix.into());
+    }
+
+    /// Enables the instrumentation of the pipeline, which counts the measurements that go through it.
+    ///
+    /// The statistics are available with [`ControlHandle::stats`](super::runtime::ControlHandle::stats)
+    /// and [`ControlHandle::latency_stats`](super::runtime::ControlHandle::latency_stats).
+    /// When the instrumentation is disabled (the default), the pipeline does not count anything.
+    pub fn with_instrumentation(&mut self) {
+        self.instrumentation = true;
+    }
+
+    /// Adds a source, registered by the given plugin, to the pipeline.
+    ///
+    /// By default, the source is polled every second, and its measurements are flushed after each poll.
+    /// Use the returned [`SourceRegistration`] to change that:
+    /// ```no_run
+    /// # use std::time::Duration;
+    /// # use alumet::pipeline::{builder::PipelineBuilder, Source};
+    /// # fn f(source: Box<dyn Source>) {
+    /// let mut builder = PipelineBuilder::new();
+    /// builder
+    ///     .add_source("my-plugin", source)
+    ///     .every(Duration::from_millis(100))
+    ///     .flush_every(Duration::from_secs(1))
+    ///     .priority();
+    /// # }
+    /// ```
+    ///
+    /// For other kinds of triggers, use [`AlumetStart::add_source`](crate::plugin::AlumetStart::add_source).
+    pub fn add_source(&mut self, plugin: &str, source: Box<dyn Source>) -> SourceRegistration<'_> {
+        let name = self.namegen.deduplicate(format!("{plugin}/source"), true);
+        let trigger = trigger::builder::time_interval(DEFAULT_POLL_INTERVAL)
+            .build()
+            .expect("the default trigger should be valid");
+        self.sources.push(ManagedSourceBuilder {
+            name,
+            plugin: plugin.to_owned(),
+            trigger,
+            build: Box::new(|_| SourceKind::Sync(source)),
+        });
+        SourceRegistration {
+            index: self.sources.len() - 1,
+            builder: self,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            flush_interval: None,
+            realtime_priority: false,
+            poll_error_policy: PollErrorPolicy::default(),
+        }
+    }
+
+    /// Adds a transform, registered by the given plugin, to the default route of the pipeline.
+    pub fn add_transform(&mut self, plugin: &str, transform: Box<dyn Transform>) {
+        let name = self.namegen.deduplicate(format!("{plugin}/transform"), true);
+        self.transforms.push(TransformBuilder {
+            name,
+            plugin: plugin.to_owned(),
+            build: Box::new(|_| transform),
+            route: String::from(DEFAULT_ROUTE),
+            error_policy: TransformErrorPolicy::default(),
+        });
+    }
+
+    /// Attaches constant attributes, like the identifier of the host, to every measurement of the pipeline.
+    ///
+    /// The attributes are added by a transform that runs before the other transforms of every route, and
+    /// `policy` tells what to do with the points that already have an attribute with the same key.
+    /// The attributes are still added after a restart of the transforms and outputs,
+    /// see [`RunningPipeline::restart_processing`](super::runtime::RunningPipeline::restart_processing).
+    ///
+    /// The transforms that add the attributes belong to the plugin `alumet`, which means that
+    /// they are disabled, like the others, when all the transforms are disabled.
+    pub fn add_constant_attributes<K: Into<Cow<'static, str>>>(
+        &mut self,
+        attributes: Vec<(K, AttributeValue)>,
+        policy: AttributeConflictPolicy,
+    ) {
+        self.constant_attributes.push(ConstantAttributesTransform::new(attributes, policy));
+    }
+
+    /// Adds an output, registered by the given plugin, to the default route of the pipeline.
+    ///
+    /// Use the returned [`OutputRegistration`] to configure the output.
+    pub fn add_output(&mut self, plugin: &str, output: Box<dyn Output>) -> OutputRegistration<'_> {
+        self.push_output(plugin, Box::new(|_| Ok(OutputKind::Blocking(output))))
+    }
+
+    /// Adds an output that is built by `build`, with the default options.
+    pub(crate) fn push_output(
+        &mut self,
+        plugin: &str,
+        build: Box<dyn FnOnce(&PendingPipelineContext) -> anyhow::Result<OutputKind>>,
+    ) -> OutputRegistration<'_> {
+        let name = self.namegen.deduplicate(format!("{plugin}/output"), true);
+        self.outputs.push(OutputBuilder::new(name, plugin.to_owned(), build));
+        OutputRegistration {
+            output: self.outputs.last_mut().unwrap(),
         }
+    }
+
+    /// Checks the configuration of the pipeline, without building it.
+    ///
+    /// Unlike [`build`](Self::build), which stops at the first problem, this returns all the problems that
+    /// have been found, for instance to report them at once in a "dry run".
+    /// No runtime is created and no element is built: the errors that depend on the environment,
+    /// such as a source that fails to start or a scheduling priority that cannot be increased,
+    /// are only detected when the pipeline is built and started.
+    pub fn validate(&self) -> Result<(), Vec<InvalidReason>> {
+        let mut errors = Vec::new();
+
         // The pipeline requires at least 1 source and 1 output, otherwise the channels close (and it would be useless anyway).
         if self.sources.is_empty() && self.autonomous_sources.is_empty() {
-            return Err(PipelineBuildError::Invalid(InvalidReason::NoSource));
+            errors.push(InvalidReason::NoSource);
         }
         if self.outputs.is_empty() {
-            return Err(PipelineBuildError::Invalid(InvalidReason::NoSource));
+            errors.push(InvalidReason::NoOutput);
+        }
+        if self.source_channel_capacity == 0 || self.output_channel_capacity == 0 {
+            errors.push(InvalidReason::ZeroChannelCapacity);
+        }
+        if matches!(self.buffer_size_limit, Some(BufferSizeLimit { max_points: 0, .. })) {
+            errors.push(InvalidReason::ZeroBufferSizeLimit);
+        }
+        // Tokio panics when a runtime has no worker thread.
+        let worker_threads = [
+            ("normal", self.normal_worker_threads),
+            ("realtime priority", self.priority_worker_threads),
+            ("blocking", self.blocking_worker_threads),
+        ];
+        for (runtime, n) in worker_threads {
+            if n == Some(0) {
+                errors.push(InvalidReason::ZeroWorkerThreads(runtime));
+            }
+        }
+
+        let mut invalid_sources: Vec<_> = self.invalid_sources.iter().collect();
+        invalid_sources.sort();
+        for (name, reason) in invalid_sources {
+            errors.push(InvalidReason::InvalidSource(name.clone(), reason.clone()));
+        }
+        for source in &self.sources {
+            if self.invalid_sources.contains_key(&source.name) {
+                continue;
+            }
+            if let Err(reason) = source.trigger.check() {
+                errors.push(InvalidReason::InvalidSource(source.name.clone(), reason));
+            }
+        }
+
+        // The transforms of a route that has no output would be useless.
+        let mut routes_without_output: Vec<&String> = Vec::new();
+        for t in &self.transforms {
+            if !self.outputs.iter().any(|o| o.route == t.route) && !routes_without_output.contains(&&t.route) {
+                routes_without_output.push(&t.route);
+            }
+        }
+        for route in routes_without_output {
+            errors.push(InvalidReason::RouteWithoutOutput(route.clone()));
+        }
+
+        // The names are used to control the elements, they must be unique.
+        let source_names = self.sources.iter().map(|s| &s.name).chain(self.autonomous_sources.iter().map(|s| &s.name));
+        let names = [
+            (ElementType::Source, source_names.collect::<Vec<_>>()),
+            (ElementType::Transform, self.transforms.iter().map(|t| &t.name).collect()),
+            (ElementType::Output, self.outputs.iter().map(|o| &o.name).collect()),
+        ];
+        for (typ, mut names) in names {
+            names.sort();
+            let mut duplicates: Vec<&String> = names.windows(2).filter(|w| w[0] == w[1]).map(|w| w[0]).collect();
+            duplicates.dedup();
+            for name in duplicates {
+                errors.push(InvalidReason::DuplicateName(typ, name.clone()));
+            }
         }
 
-        // Create the normal runtime, the priority one is initialized on demand.
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    pub fn build(self) -> Result<IdlePipeline, PipelineBuildError> {
+        // Check some conditions.
+        if self.metrics.is_empty() && !self.allow_no_metrics {
+            log::warn!("No metrics have been registered, have you loaded the right plugins?")
+        }
+        if let Some(reason) = self.validate().err().and_then(|errors| errors.into_iter().next()) {
+            return Err(PipelineBuildError::Invalid(reason));
+        }
+
+        // Create the normal runtime, the priority and blocking ones are initialized on demand.
         let rt_normal: Runtime = self.build_normal_runtime()?;
-        let rt_priority: Option<Runtime> = self.build_priority_runtime()?;
+        let priority_threads = Arc::new(AtomicUsize::new(0));
+        let rt_priority: Option<Runtime> = self.build_priority_runtime(&priority_threads)?;
+        let rt_blocking: Option<Runtime> = self.build_blocking_runtime()?;
 
         // Channel: source -> transforms.
-        let (in_tx, in_rx) = mpsc::channel::<MeasurementBuffer>(256);
+        let (in_tx, in_rx) = mpsc::channel::<MeasurementBuffer>(self.source_channel_capacity);
 
         // Broadcast queue, used for two things:
         // - transforms -> outputs
         // - late metric registration -> outputs
-        let out_tx = broadcast::Sender::<OutputMsg>::new(256);
+        let out_tx = broadcast::Sender::<OutputMsg>::new(self.output_channel_capacity);
 
         // Create the pipeline elements.
         let sources: Vec<ConfiguredSource> = self
@@ -280,7 +888,10 @@ impl PipelineBuilder {
                 let mut trigger = builder.trigger;
                 let pending = PendingPipelineContext {
                     to_output: &out_tx,
-                    rt_handle: if trigger.realtime_priority {
+                    rt_handle: if trigger.blocking {
+                        // always created when a source is blocking
+                        rt_blocking.as_ref().unwrap_or(&rt_normal).handle()
+                    } else if trigger.realtime_priority {
                         rt_priority
                             .as_ref()
                             .unwrap_or_else(|| {
@@ -310,33 +921,8 @@ impl PipelineBuilder {
             to_output: &out_tx,
             rt_handle: rt_normal.handle(),
         };
-        let transforms: Vec<ConfiguredTransform> = self
-            .transforms
-            .into_iter()
-            .map(|builder| {
-                let transform = (builder.build)(&pending);
-                ConfiguredTransform {
-                    transform,
-                    name: builder.name,
-                    plugin_name: builder.plugin,
-                }
-            })
-            .collect();
-        let outputs: Result<Vec<ConfiguredOutput>, PipelineBuildError> = self
-            .outputs
-            .into_iter()
-            .map(|builder| {
-                let output = (builder.build)(&pending).map_err(|err| {
-                    PipelineBuildError::ElementBuild(err, ElementType::Output, builder.plugin.clone())
-                })?;
-                Ok(ConfiguredOutput {
-                    output,
-                    name: builder.name,
-                    plugin_name: builder.plugin,
-                })
-            })
-            .collect();
-        let outputs = outputs?;
+        let transforms = build_transforms(self.transforms, &pending);
+        let outputs = build_outputs(self.outputs, &pending)?;
 
         // Create the autonomous sources
         let autonomous_shutdown_token = CancellationToken::new();
@@ -350,7 +936,11 @@ impl PipelineBuilder {
                 // It can also be cancelled on its own, in which case only this source will be stopped.
                 let cancel_token = autonomous_shutdown_token.child_token();
                 let source = (builder.build)(&pending, cancel_token, data_tx);
-                ConfiguredAutonomousSource { source, name }
+                ConfiguredAutonomousSource {
+                    source,
+                    name,
+                    plugin: builder.plugin,
+                }
             })
             .collect();
 
@@ -362,111 +952,235 @@ impl PipelineBuilder {
             autonomous_shutdown_token,
             metrics: self.metrics,
             from_sources: (in_tx, in_rx),
+            source_overflow_policy: self.source_overflow_policy,
             to_outputs: out_tx,
             rt_normal,
             rt_priority,
+            rt_blocking,
+            priority_threads,
+            thread_name_prefix: self.thread_name_prefix,
+            instrumentation: self.instrumentation,
+            source_channel_capacity: self.source_channel_capacity,
+            output_channel_capacity: self.output_channel_capacity,
+            constant_attributes: self.constant_attributes,
+            buffer_size_limit: self.buffer_size_limit,
         })
     }
 
     fn build_normal_runtime(&self) -> io::Result<Runtime> {
-        let mut builder = tokio::runtime::Builder::new_multi_thread();
-        builder.enable_all().thread_name_fn(|| {
-            static ATOMIC_ID: AtomicUsize = AtomicUsize::new(0);
-            let id = ATOMIC_ID.fetch_add(1, Ordering::SeqCst);
-            format!("normal-worker-{id}")
-        });
-        if let Some(n) = self.normal_worker_threads {
-            builder.worker_threads(n);
-        }
-        builder.build()
+        new_normal_runtime(self.normal_worker_threads, self.thread_name_prefix.as_deref())
     }
 
-    fn build_priority_runtime(&self) -> io::Result<Option<Runtime>> {
-        fn resolve_application_path() -> io::Result<PathBuf> {
-            std::env::current_exe()?.canonicalize()
-        }
-
-        // Count how many sources require a "realtime priority" runtime
+    fn build_priority_runtime(&self, priority_threads: &Arc<AtomicUsize>) -> io::Result<Option<Runtime>> {
+        // Count how many sources require a "realtime priority" runtime (the blocking sources run elsewhere)
         let n_rt_sources = self
             .sources
             .iter()
-            .filter(|builder| builder.trigger.realtime_priority)
+            .filter(|builder| builder.trigger.realtime_priority && !builder.trigger.blocking)
             .count();
 
         if n_rt_sources > 0 {
-            // If `on_thread_start` fails, `builder.build()` will still return a runtime,
-            // but it will be unusable. To avoid that, we store the error here and don't return Some(runtime).
-            static THREAD_START_FAILURE: Mutex<Option<io::Error>> = Mutex::new(None);
-
-            let mut builder = tokio::runtime::Builder::new_multi_thread();
-            builder
-                .enable_all()
-                .worker_threads(n_rt_sources)
-                .on_thread_start(|| {
-                    if let Err(e) = super::threading::increase_thread_priority() {
-                        let mut failure = THREAD_START_FAILURE.lock().unwrap();
-                        if failure.is_none() {
-                            let hint =
-                                if e.kind() == ErrorKind::PermissionDenied {
-                                    let app_path = resolve_application_path()
-                                        .ok()
-                                        .and_then(|p| p.to_str().map(|s| s.to_owned()))
-                                        .unwrap_or(String::from("path/to/agent"));
-
-                                    indoc::formatdoc! {"
-                                        This is probably caused by insufficient privileges.
-                                        
-                                        To fix this, you have two possibilities:
-                                        1. Grant the SYS_NICE capability to the agent binary.
-                                             sudo setcap cap_sys_nice+ep \"{app_path}\"
-                                        
-                                           Note: to grant multiple capabilities to the binary, you must put all the capabilities in the same command.
-                                             sudo setcap \"cap_sys_nice+ep cap_perfmon=ep\" \"{app_path}\"
-                                        
-                                        2. Run the agent as root (not recommended).
-                                    "}
-                                } else {
-                                    String::from("This does not seem to be caused by insufficient privileges. Please report an issue on the GitHub repository.")
-                                };
-                            log::error!("I tried to increase the scheduling priority of the thread in order to improve the accuracy of the measurement timing, but I failed: {e}\n{hint}");
-                            log::warn!("Alumet will still work, but the time between two measurements may differ from the configuration.");
-                            *failure = Some(e);
-                        }
-                        let current_thread = std::thread::current();
-                        let thread_name = current_thread.name().unwrap_or("<unnamed>");
-                        log::warn!("Unable to increase the scheduling priority of thread {thread_name}.");
-                    };
-                })
-                .thread_name_fn(|| {
-                    static ATOMIC_ID: AtomicUsize = AtomicUsize::new(0);
-                    let id = ATOMIC_ID.fetch_add(1, Ordering::SeqCst);
-                    format!("priority-worker-{id}")
-                });
             let n_threads = self.priority_worker_threads.unwrap_or(n_rt_sources);
-            builder.worker_threads(n_threads);
-
-            // Build the runtime.
-            let runtime = builder.build()?;
-
-            // Try to spawn a task to ensure that the worker threads have started properly.
-            // Otherwise, builder.build() may return and the threads may fail after the failure check.
-            runtime.block_on(async {
-                let _ = runtime
-                    .spawn(tokio::time::sleep(tokio::time::Duration::from_millis(1)))
-                    .await;
-            });
-
-            // If the worker threads failed to start, don't use this runtime.
-            if THREAD_START_FAILURE.lock().unwrap().take().is_some() {
-                return Ok(None);
+            let rt = new_priority_runtime(n_threads, priority_threads.clone(), self.thread_name_prefix.as_deref())?;
+            if rt.is_none() && self.require_realtime_priority {
+                return Err(io::Error::other(
+                    "the scheduling priority of the threads cannot be increased (see the previous errors), \
+                     but the realtime priority is required",
+                ));
             }
-            Ok(Some(runtime))
+            Ok(rt)
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn build_blocking_runtime(&self) -> io::Result<Option<Runtime>> {
+        // Count how many sources block their thread when polled
+        let n_blocking_sources = self.sources.iter().filter(|builder| builder.trigger.blocking).count();
+
+        if n_blocking_sources > 0 {
+            // Each blocking source can occupy a worker thread during its entire poll, hence one thread per source.
+            let n_threads = self.blocking_worker_threads.unwrap_or(n_blocking_sources);
+            new_blocking_runtime(n_threads, self.thread_name_prefix.as_deref()).map(Some)
         } else {
             Ok(None)
         }
     }
 }
 
+/// Builds the transforms in the context of a pipeline.
+pub(super) fn build_transforms(
+    builders: Vec<TransformBuilder>,
+    pending: &PendingPipelineContext,
+) -> Vec<ConfiguredTransform> {
+    builders
+        .into_iter()
+        .map(|builder| {
+            let transform = (builder.build)(pending);
+            ConfiguredTransform {
+                transform,
+                name: builder.name,
+                plugin_name: builder.plugin,
+                route: builder.route,
+                error_policy: builder.error_policy,
+            }
+        })
+        .collect()
+}
+
+/// Builds the outputs in the context of a pipeline.
+pub(super) fn build_outputs(
+    builders: Vec<OutputBuilder>,
+    pending: &PendingPipelineContext,
+) -> Result<Vec<ConfiguredOutput>, PipelineBuildError> {
+    builders
+        .into_iter()
+        .map(|builder| {
+            let output = (builder.build)(pending)
+                .map_err(|err| PipelineBuildError::ElementBuild(err, ElementType::Output, builder.plugin.clone()))?;
+            Ok(ConfiguredOutput {
+                output,
+                name: builder.name,
+                plugin_name: builder.plugin,
+                filter: builder.filter,
+                retry: builder.retry,
+                flush_interval: builder.flush_interval,
+                slow_policy: builder.slow_policy,
+                circuit_breaker: builder.circuit_breaker,
+                health_check_interval: builder.health_check_interval,
+                sampling: builder.sampling,
+                route: builder.route,
+            })
+        })
+        .collect()
+}
+
+/// Returns a function that generates the names of the worker threads of a runtime:
+/// `{prefix}-{kind}-worker-{id}`, or `{kind}-worker-{id}` without prefix.
+fn thread_name_fn(prefix: Option<&str>, kind: &'static str) -> impl Fn() -> String + Send + Sync + 'static {
+    let prefix = prefix.map(|p| format!("{p}-")).unwrap_or_default();
+    let next_id = AtomicUsize::new(0);
+    move || {
+        let id = next_id.fetch_add(1, Ordering::SeqCst);
+        format!("{prefix}{kind}-worker-{id}")
+    }
+}
+
+/// Creates the runtime that runs most of the pipeline, with one worker thread per CPU core if `n_threads` is `None`.
+pub(super) fn new_normal_runtime(n_threads: Option<usize>, thread_name_prefix: Option<&str>) -> io::Result<Runtime> {
+    let mut builder = tokio::runtime::Builder::new_multi_thread();
+    builder
+        .enable_all()
+        .thread_name_fn(thread_name_fn(thread_name_prefix, "normal"));
+    if let Some(n) = n_threads {
+        builder.worker_threads(n);
+    }
+    builder.build()
+}
+
+/// Creates a runtime whose worker threads have a high scheduling priority.
+///
+/// Returns `None` if the priority of the threads cannot be increased (the reason is logged).
+/// This function blocks until the threads have started, it must not be called from an async context.
+///
+/// `priority_threads` counts the worker threads that are running with the increased priority.
+pub(super) fn new_priority_runtime(
+    n_threads: usize,
+    priority_threads: Arc<AtomicUsize>,
+    thread_name_prefix: Option<&str>,
+) -> io::Result<Option<Runtime>> {
+    fn resolve_application_path() -> io::Result<PathBuf> {
+        std::env::current_exe()?.canonicalize()
+    }
+
+    // If `on_thread_start` fails, `builder.build()` will still return a runtime,
+    // but it will be unusable. To avoid that, we store the error here and don't return Some(runtime).
+    static THREAD_START_FAILURE: Mutex<Option<io::Error>> = Mutex::new(None);
+
+    thread_local! {
+        /// `true` if the priority of the current thread has been increased.
+        static PRIORITY_INCREASED: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+    }
+    let started = priority_threads.clone();
+    let stopped = priority_threads;
+
+    let mut builder = tokio::runtime::Builder::new_multi_thread();
+    builder
+        .enable_all()
+        .worker_threads(n_threads)
+        .on_thread_start(move || {
+            if let Err(e) = super::threading::increase_thread_priority() {
+                let mut failure = THREAD_START_FAILURE.lock().unwrap();
+                if failure.is_none() {
+                    let hint =
+                        if e.kind() == ErrorKind::PermissionDenied {
+                            let app_path = resolve_application_path()
+                                .ok()
+                                .and_then(|p| p.to_str().map(|s| s.to_owned()))
+                                .unwrap_or(String::from("path/to/agent"));
+
+                            indoc::formatdoc! {"
+                                This is probably caused by insufficient privileges.
+                                
+                                To fix this, you have two possibilities:
+                                1. Grant the SYS_NICE capability to the agent binary.
+                                     sudo setcap cap_sys_nice+ep \"{app_path}\"
+                                
+                                   Note: to grant multiple capabilities to the binary, you must put all the capabilities in the same command.
+                                     sudo setcap \"cap_sys_nice+ep cap_perfmon=ep\" \"{app_path}\"
+                                
+                                2. Run the agent as root (not recommended).
+                            "}
+                        } else {
+                            String::from("This does not seem to be caused by insufficient privileges. Please report an issue on the GitHub repository.")
+                        };
+                    log::error!("I tried to increase the scheduling priority of the thread in order to improve the accuracy of the measurement timing, but I failed: {e}\n{hint}");
+                    log::warn!("Alumet will still work, but the time between two measurements may differ from the configuration.");
+                    *failure = Some(e);
+                }
+                let current_thread = std::thread::current();
+                let thread_name = current_thread.name().unwrap_or("<unnamed>");
+                log::warn!("Unable to increase the scheduling priority of thread {thread_name}.");
+            } else {
+                PRIORITY_INCREASED.set(true);
+                started.fetch_add(1, Ordering::SeqCst);
+            }
+        })
+        .on_thread_stop(move || {
+            if PRIORITY_INCREASED.replace(false) {
+                stopped.fetch_sub(1, Ordering::SeqCst);
+            }
+        })
+        .thread_name_fn(thread_name_fn(thread_name_prefix, "priority"));
+
+    // Build the runtime.
+    let runtime = builder.build()?;
+
+    // Try to spawn a task to ensure that the worker threads have started properly.
+    // Otherwise, builder.build() may return and the threads may fail after the failure check.
+    runtime.block_on(async {
+        let _ = runtime
+            .spawn(tokio::time::sleep(tokio::time::Duration::from_millis(1)))
+            .await;
+    });
+
+    // If the worker threads failed to start, don't use this runtime.
+    if THREAD_START_FAILURE.lock().unwrap().take().is_some() {
+        return Ok(None);
+    }
+    Ok(Some(runtime))
+}
+
+/// Creates a runtime for the sources that block their thread when polled.
+pub(super) fn new_blocking_runtime(n_threads: usize, thread_name_prefix: Option<&str>) -> io::Result<Runtime> {
+    let mut builder = tokio::runtime::Builder::new_multi_thread();
+    builder
+        .enable_all()
+        .worker_threads(n_threads)
+        .thread_name_fn(thread_name_fn(thread_name_prefix, "blocking"));
+    builder.build()
+}
+
 /// Generates names for the pipeline elements.
 pub(crate) struct ElementNameGenerator {
     existing_names: HashMap<String, usize>,
@@ -0,0 +1,168 @@
+//! A transform that only keeps the measurements whose value has changed, to reduce the volume of stable metrics.
+
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+use crate::measurement::{MeasurementBuffer, MeasurementPoint, WrappedMeasurementValue};
+
+use super::window::{series_key, SeriesKey};
+use super::{Transform, TransformError};
+
+/// A transform that removes the points whose value is the same as the last value kept for their time series.
+///
+/// A time series is identified by the metric, the resource, the consumer and the attributes of the points.
+/// The first point of a series is always kept.
+///
+/// So that the outputs know that an unchanged metric is still measured, a point is also kept if the last point
+/// of its series has been kept for more than the `heartbeat` interval, even if its value is the same.
+/// Like the windows of [`WindowTransform`](super::window::WindowTransform), the heartbeat is based on the timestamps
+/// of the points, not on the time at which they are transformed.
+///
+/// ## Example
+/// ```
+/// use std::time::Duration;
+/// use alumet::pipeline::change_only::ChangeOnlyTransform;
+///
+/// // keep at least one point per minute, and ignore the float variations smaller than 0.01
+/// let transform = ChangeOnlyTransform::new(Duration::from_secs(60)).with_epsilon(0.01);
+/// ```
+pub struct ChangeOnlyTransform {
+    heartbeat: Duration,
+    epsilon: f64,
+    /// The last point kept for each time series.
+    last: HashMap<SeriesKey, LastKept>,
+    /// The time after which the series that have not been kept for a while are forgotten.
+    next_cleanup: Option<SystemTime>,
+}
+
+struct LastKept {
+    value: WrappedMeasurementValue,
+    timestamp: SystemTime,
+}
+
+impl ChangeOnlyTransform {
+    /// Creates a transform that keeps a point of each time series at least every `heartbeat`.
+    pub fn new(heartbeat: Duration) -> Self {
+        Self {
+            heartbeat,
+            epsilon: 0.0,
+            last: HashMap::new(),
+            next_cleanup: None,
+        }
+    }
+
+    /// Considers that two float values are the same if they differ by `epsilon` or less.
+    ///
+    /// By default, the float values must be exactly equal. The integer values are always compared exactly.
+    pub fn with_epsilon(mut self, epsilon: f64) -> Self {
+        self.epsilon = epsilon;
+        self
+    }
+
+    fn is_same(&self, a: &WrappedMeasurementValue, b: &WrappedMeasurementValue) -> bool {
+        match (a, b) {
+            (WrappedMeasurementValue::F64(a), WrappedMeasurementValue::F64(b)) => (a - b).abs() <= self.epsilon,
+            (WrappedMeasurementValue::U64(a), WrappedMeasurementValue::U64(b)) => a == b,
+            _ => false,
+        }
+    }
+
+    /// Returns `true` if the point must be kept, and remembers it in that case.
+    fn keep(&mut self, point: &MeasurementPoint) -> bool {
+        let t = SystemTime::from(point.timestamp);
+        let key = series_key(point);
+        if let Some(last) = self.last.get(&key) {
+            // A point that is older than the last kept one does not trigger the heartbeat.
+            let since_last = t.duration_since(last.timestamp).unwrap_or_default();
+            if self.is_same(&last.value, &point.value) && since_last < self.heartbeat {
+                return false;
+            }
+        }
+        self.last.insert(
+            key,
+            LastKept {
+                value: point.value.clone(),
+                timestamp: t,
+            },
+        );
+        true
+    }
+
+    /// Forgets the series that have not been kept for more than one heartbeat (e.g. the processes that have ended).
+    ///
+    /// This does not change the result: their next point would be kept anyway, because of the heartbeat.
+    fn cleanup(&mut self, now: SystemTime) {
+        match self.next_cleanup {
+            Some(next) if now < next => (),
+            _ => {
+                let heartbeat = self.heartbeat;
+                self.last
+                    .retain(|_, last| now.duration_since(last.timestamp).unwrap_or_default() < heartbeat);
+                self.next_cleanup = Some(now + heartbeat);
+            }
+        }
+    }
+}
+
+impl Transform for ChangeOnlyTransform {
+    fn apply(&mut self, measurements: &mut MeasurementBuffer) -> Result<(), TransformError> {
+        let latest = measurements.iter().map(|p| SystemTime::from(p.timestamp)).max();
+        measurements.retain(|p| self.keep(p));
+        if let Some(latest) = latest {
+            self.cleanup(latest);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use crate::measurement::{MeasurementBuffer, MeasurementPoint};
+    use crate::pipeline::{testing, Transform};
+    use crate::resources::Resource;
+
+    use super::ChangeOnlyTransform;
+
+    fn point(secs: u64, pkg: u32, value: f64) -> MeasurementPoint {
+        testing::point().secs(secs).package(pkg).f64(value).build()
+    }
+
+    fn kept(transform: &mut ChangeOnlyTransform, points: Vec<MeasurementPoint>) -> Vec<(u64, u32)> {
+        let mut buf = MeasurementBuffer::from(points);
+        transform.apply(&mut buf).unwrap();
+        buf.iter()
+            .map(|p| {
+                let secs = std::time::SystemTime::from(p.timestamp).duration_since(UNIX_EPOCH).unwrap();
+                let Resource::CpuPackage { id } = p.resource else { unreachable!() };
+                (secs.as_secs(), id)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn only_changes() {
+        let mut transform = ChangeOnlyTransform::new(Duration::from_secs(60)).with_epsilon(0.1);
+        assert_eq!(
+            kept(&mut transform, vec![point(0, 0, 1.0), point(0, 1, 5.0)]),
+            vec![(0, 0), (0, 1)]
+        );
+        // the series are independent, and the small variations are ignored
+        assert_eq!(
+            kept(&mut transform, vec![point(1, 0, 1.05), point(1, 1, 6.0)]),
+            vec![(1, 1)]
+        );
+        assert_eq!(kept(&mut transform, vec![point(2, 0, 2.0), point(2, 1, 6.0)]), vec![(2, 0)]);
+    }
+
+    #[test]
+    fn heartbeat() {
+        let mut transform = ChangeOnlyTransform::new(Duration::from_secs(10));
+        assert_eq!(kept(&mut transform, vec![point(0, 0, 1.0)]), vec![(0, 0)]);
+        assert_eq!(kept(&mut transform, vec![point(9, 0, 1.0)]), vec![]);
+        // the same value is kept again after the heartbeat interval
+        assert_eq!(kept(&mut transform, vec![point(10, 0, 1.0)]), vec![(10, 0)]);
+        assert_eq!(kept(&mut transform, vec![point(15, 0, 1.0)]), vec![]);
+    }
+}
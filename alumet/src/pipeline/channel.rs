@@ -0,0 +1,97 @@
+//! A source that receives its measurements from a channel, to integrate producers that live outside of the pipeline.
+
+use tokio::sync::mpsc::{self, error::TryRecvError, UnboundedReceiver, UnboundedSender};
+
+use crate::measurement::{MeasurementAccumulator, MeasurementPoint, Timestamp};
+
+use super::{PollError, Source};
+
+/// A source that pushes the points that it receives from a channel.
+///
+/// The points are built by the code that owns the [`UnboundedSender`], which can be cloned and used
+/// from any thread, with or without an async runtime. At each poll, the source drains all the points
+/// that are currently in the channel, without waiting for new ones. The points keep their timestamp,
+/// the timestamp of the poll is not used.
+///
+/// When all the senders have been dropped, the source pushes the remaining points, then its next poll
+/// fails with [`PollError::Fatal`]: no new point can arrive, hence the source is stopped by the pipeline.
+///
+/// ## Example
+/// ```
+/// use alumet::pipeline::channel::ChannelSource;
+///
+/// let (source, tx) = ChannelSource::new();
+/// // give `tx` to the external producer, and add `source` to the pipeline with `AlumetStart::add_source`
+/// # drop((source, tx));
+/// ```
+pub struct ChannelSource {
+    rx: UnboundedReceiver<MeasurementPoint>,
+}
+
+impl ChannelSource {
+    /// Creates a new source and the sender that feeds it.
+    pub fn new() -> (ChannelSource, UnboundedSender<MeasurementPoint>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        (ChannelSource { rx }, tx)
+    }
+}
+
+impl Source for ChannelSource {
+    fn poll(&mut self, measurements: &mut MeasurementAccumulator, _timestamp: Timestamp) -> Result<(), PollError> {
+        let mut received = false;
+        loop {
+            match self.rx.try_recv() {
+                Ok(point) => {
+                    measurements.push(point);
+                    received = true;
+                }
+                Err(TryRecvError::Empty) => return Ok(()),
+                Err(TryRecvError::Disconnected) if received => return Ok(()),
+                Err(TryRecvError::Disconnected) => {
+                    return Err(PollError::Fatal(anyhow::anyhow!("all the senders of the channel have been dropped")))
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::measurement::{MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementValue};
+    use crate::metrics::RawMetricId;
+    use crate::pipeline::{PollError, Source};
+    use crate::resources::{Resource, ResourceConsumer};
+
+    use super::ChannelSource;
+
+    fn point(value: u64) -> MeasurementPoint {
+        MeasurementPoint::new_untyped(
+            Timestamp::now(),
+            RawMetricId(0),
+            Resource::LocalMachine,
+            ResourceConsumer::LocalMachine,
+            WrappedMeasurementValue::U64(value),
+        )
+    }
+
+    #[test]
+    fn drain_channel() {
+        let (mut source, tx) = ChannelSource::new();
+        let mut buf = MeasurementBuffer::new();
+        source.poll(&mut buf.as_accumulator(), Timestamp::now()).unwrap();
+        assert!(buf.is_empty());
+
+        tx.send(point(1)).unwrap();
+        tx.send(point(2)).unwrap();
+        source.poll(&mut buf.as_accumulator(), Timestamp::now()).unwrap();
+        assert_eq!(buf.len(), 2);
+
+        // the points sent before the sender is dropped are not lost
+        tx.send(point(3)).unwrap();
+        drop(tx);
+        source.poll(&mut buf.as_accumulator(), Timestamp::now()).unwrap();
+        assert_eq!(buf.len(), 3);
+        let res = source.poll(&mut buf.as_accumulator(), Timestamp::now());
+        assert!(matches!(res, Err(PollError::Fatal(_))));
+    }
+}
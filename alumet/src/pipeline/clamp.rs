@@ -0,0 +1,287 @@
+//! A transform that handles the absurd values, like the spikes caused by the wraparound of an energy counter.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use crate::measurement::{MeasurementBuffer, MeasurementPoint, WrappedMeasurementValue};
+use crate::metrics::RawMetricId;
+
+use super::window::{series_key, SeriesKey};
+use super::{Transform, TransformError};
+
+/// What to do with a value that is outside of the valid range of its metric.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutOfRange {
+    /// Replaces the value by the nearest bound of the range.
+    Clamp,
+    /// Drops the point.
+    Drop,
+}
+
+/// A transform that checks the values of some metrics against their valid range.
+///
+/// Two kinds of metrics are supported:
+/// - the gauges (e.g. a power or a temperature), whose values must be between a minimum and a maximum,
+///   see [`with_range`](Self::with_range). The values that are out of the range are clamped or dropped.
+/// - the monotonic counters (e.g. an energy), which must not decrease nor increase faster than a maximum rate,
+///   see [`with_counter`](Self::with_counter). A discontinuity, like the wraparound of a RAPL counter, is detected
+///   for each time series and the value is replaced by an estimate, extrapolated from the previous rate of the series.
+///   The next values of the series are shifted accordingly, so that the counter stays continuous.
+///
+/// The points of the other metrics are not modified. The number of modified and dropped points is counted,
+/// see [`counters`](Self::counters).
+///
+/// ## Interaction with [`RateTransform`](super::rate::RateTransform)
+/// To compute the rate of a counter, add this transform _before_ the rate transform: the rate transform then
+/// receives a continuous counter, and the rate of the interval that contains a discontinuity is the estimated one.
+/// Without it, the rate transform skips the interval of a wraparound (the counter decreases), but a spike gives
+/// an absurd rate. Alternatively, to bound the rates themselves, add this transform _after_ the rate transform,
+/// with a range on the metric of the rates. Both transforms keep one state per time series of the counters.
+///
+/// ## Example
+/// ```
+/// use alumet::metrics::{MetricId, TypedMetricId};
+/// use alumet::pipeline::clamp::{ClampTransform, OutOfRange};
+///
+/// # fn example(energy: TypedMetricId<u64>, temperature: TypedMetricId<f64>) {
+/// // the metrics are created by the plugin, for instance with `AlumetStart::create_metric`
+/// let transform = ClampTransform::new()
+///     // at most 1000 W, the energy is in µJ
+///     .with_counter(energy.untyped_id(), 1e9)
+///     .with_range(temperature.untyped_id(), -40.0, 150.0, OutOfRange::Drop);
+/// # }
+/// ```
+#[derive(Default)]
+pub struct ClampTransform {
+    /// The rule of each checked metric.
+    rules: HashMap<RawMetricId, Rule>,
+    /// The state of each series of the counters.
+    series: HashMap<SeriesKey, CounterSeries>,
+    counters: Arc<ClampCounters>,
+}
+
+enum Rule {
+    Range { min: f64, max: f64, policy: OutOfRange },
+    Counter { max_rate: f64 },
+}
+
+struct CounterSeries {
+    /// The last value of the series, as produced by the source.
+    raw: f64,
+    /// The last value of the series, after the correction.
+    corrected: f64,
+    timestamp: SystemTime,
+    /// The last valid increase of the series, per second.
+    rate: f64,
+    /// The difference between the corrected values and the raw values, since the last discontinuity.
+    offset: f64,
+}
+
+/// Counts the points that have been modified or dropped by a [`ClampTransform`].
+#[derive(Debug, Default)]
+pub struct ClampCounters {
+    clamped: AtomicU64,
+    dropped: AtomicU64,
+    interpolated: AtomicU64,
+}
+
+impl ClampCounters {
+    /// Number of values that have been replaced by a bound of their range.
+    pub fn clamped(&self) -> u64 {
+        self.clamped.load(Ordering::Relaxed)
+    }
+
+    /// Number of points that have been dropped because their value was out of their range.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Number of counter values that have been replaced by an estimate, because of a discontinuity.
+    pub fn interpolated(&self) -> u64 {
+        self.interpolated.load(Ordering::Relaxed)
+    }
+}
+
+impl ClampTransform {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Checks that the values of `metric` are between `min` and `max` (inclusive).
+    ///
+    /// The values that are out of the range are handled according to `policy`. When an integer value is clamped,
+    /// the bound is converted to an integer, rounded toward zero.
+    pub fn with_range(mut self, metric: RawMetricId, min: f64, max: f64, policy: OutOfRange) -> Self {
+        self.rules.insert(metric, Rule::Range { min, max, policy });
+        self
+    }
+
+    /// Checks that the values of the monotonic counter `metric` never decrease, nor increase by more than
+    /// `max_rate` per second.
+    ///
+    /// A value that fails the check is replaced by the last value of its series plus the last valid rate of the series
+    /// multiplied by the elapsed time, and the next values are shifted by the same difference. The first value
+    /// of each series is kept as is, and the estimated increase is 0 if the series had no valid increase yet.
+    /// The computations are done on `f64`: the integer values above 2^53 lose some precision.
+    pub fn with_counter(mut self, metric: RawMetricId, max_rate: f64) -> Self {
+        self.rules.insert(metric, Rule::Counter { max_rate });
+        self
+    }
+
+    /// Returns the counters of the modified and dropped points, which can be read from another thread.
+    pub fn counters(&self) -> Arc<ClampCounters> {
+        self.counters.clone()
+    }
+
+    /// Checks the point against the rule of its metric. Returns `false` if the point must be dropped.
+    fn check(&mut self, point: &mut MeasurementPoint) -> bool {
+        let Some(rule) = self.rules.get(&point.metric) else {
+            return true;
+        };
+        let value = match point.value {
+            WrappedMeasurementValue::F64(x) => x,
+            WrappedMeasurementValue::U64(n) => n as f64,
+        };
+        match *rule {
+            Rule::Range { min, max, policy } => {
+                if (min..=max).contains(&value) {
+                    return true;
+                }
+                match policy {
+                    OutOfRange::Drop => {
+                        self.counters.dropped.fetch_add(1, Ordering::Relaxed);
+                        false
+                    }
+                    OutOfRange::Clamp => {
+                        set_value(point, value.clamp(min, max));
+                        self.counters.clamped.fetch_add(1, Ordering::Relaxed);
+                        true
+                    }
+                }
+            }
+            Rule::Counter { max_rate } => {
+                let timestamp = SystemTime::from(point.timestamp);
+                let Some(series) = self.series.get_mut(&series_key(point)) else {
+                    let series = CounterSeries {
+                        raw: value,
+                        corrected: value,
+                        timestamp,
+                        rate: 0.0,
+                        offset: 0.0,
+                    };
+                    self.series.insert(series_key(point), series);
+                    return true;
+                };
+                let Ok(elapsed) = timestamp.duration_since(series.timestamp) else {
+                    // the point is older than the previous one: only shift it
+                    set_value(point, value + series.offset);
+                    return true;
+                };
+                let elapsed = elapsed.as_secs_f64();
+                let increase = value - series.raw;
+                if (0.0..=max_rate * elapsed).contains(&increase) {
+                    if elapsed > 0.0 {
+                        series.rate = increase / elapsed;
+                    }
+                } else {
+                    // discontinuity: continue the series at its last rate
+                    let estimate = series.corrected + series.rate * elapsed;
+                    series.offset = estimate - value;
+                    self.counters.interpolated.fetch_add(1, Ordering::Relaxed);
+                }
+                series.raw = value;
+                series.corrected = value + series.offset;
+                series.timestamp = timestamp;
+                if series.offset != 0.0 {
+                    set_value(point, series.corrected);
+                }
+                true
+            }
+        }
+    }
+}
+
+/// Replaces the value of the point, keeping its type.
+fn set_value(point: &mut MeasurementPoint, value: f64) {
+    point.value = match point.value {
+        WrappedMeasurementValue::F64(_) => WrappedMeasurementValue::F64(value),
+        WrappedMeasurementValue::U64(_) => WrappedMeasurementValue::U64(value as u64),
+    };
+}
+
+impl Transform for ClampTransform {
+    fn apply(&mut self, measurements: &mut MeasurementBuffer) -> Result<(), TransformError> {
+        measurements.retain_mut(|p| self.check(p));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::measurement::{MeasurementBuffer, MeasurementPoint, WrappedMeasurementValue};
+    use crate::metrics::RawMetricId;
+    use crate::pipeline::testing;
+    use crate::pipeline::Transform;
+
+    use super::{ClampTransform, OutOfRange};
+
+    fn point(secs: u64, metric: usize, value: WrappedMeasurementValue) -> MeasurementPoint {
+        testing::point().secs(secs).metric(RawMetricId(metric)).value(value).build()
+    }
+
+    fn values(transform: &mut ClampTransform, points: Vec<MeasurementPoint>) -> Vec<(usize, f64)> {
+        let mut buf = MeasurementBuffer::from(points);
+        transform.apply(&mut buf).unwrap();
+        buf.iter()
+            .map(|p| match p.value {
+                WrappedMeasurementValue::F64(x) => (p.metric.0, x),
+                WrappedMeasurementValue::U64(n) => (p.metric.0, n as f64),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn clamp_ranges() {
+        let mut transform = ClampTransform::new()
+            .with_range(RawMetricId(0), 0.0, 100.0, OutOfRange::Clamp)
+            .with_range(RawMetricId(1), 0.0, 100.0, OutOfRange::Drop);
+        let counters = transform.counters();
+        let points = vec![
+            point(0, 0, WrappedMeasurementValue::F64(-3.0)),
+            point(0, 0, WrappedMeasurementValue::U64(250)),
+            point(0, 0, WrappedMeasurementValue::F64(42.0)),
+            point(0, 1, WrappedMeasurementValue::F64(101.0)),
+            point(0, 1, WrappedMeasurementValue::F64(100.0)),
+            point(0, 2, WrappedMeasurementValue::F64(1e12)),
+        ];
+        assert_eq!(
+            values(&mut transform, points),
+            vec![(0, 0.0), (0, 100.0), (0, 42.0), (1, 100.0), (2, 1e12)]
+        );
+        assert_eq!((counters.clamped(), counters.dropped(), counters.interpolated()), (2, 1, 0));
+    }
+
+    #[test]
+    fn counter_wraparound() {
+        let energy = |secs, n| point(secs, 0, WrappedMeasurementValue::U64(n));
+        let mut transform = ClampTransform::new().with_counter(RawMetricId(0), 50.0);
+        let counters = transform.counters();
+        assert_eq!(
+            values(&mut transform, vec![energy(0, 900), energy(1, 930)]),
+            vec![(0, 900.0), (0, 930.0)]
+        );
+        // the counter wraps around: the value is estimated from the previous rate, the next ones are shifted
+        assert_eq!(
+            values(&mut transform, vec![energy(2, 5), energy(3, 35)]),
+            vec![(0, 960.0), (0, 990.0)]
+        );
+        // a spike is replaced, then the series continues from the estimate
+        assert_eq!(
+            values(&mut transform, vec![energy(4, 1_000_000), energy(5, 95)]),
+            vec![(0, 1020.0), (0, 1050.0)]
+        );
+        assert_eq!(counters.interpolated(), 3);
+    }
+}
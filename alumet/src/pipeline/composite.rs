@@ -0,0 +1,158 @@
+//! A source that groups several sources, to poll them with a single task and trigger.
+
+use anyhow::anyhow;
+
+use crate::measurement::{MeasurementAccumulator, Timestamp};
+
+use super::{PollError, Source};
+
+/// A source that polls several sources, one after another, with the same accumulator and timestamp.
+///
+/// This is useful for small sources that belong together: they share one task and one trigger,
+/// instead of having one each.
+///
+/// ## Errors
+/// A failing source does not prevent the others from being polled. The errors of a poll are aggregated
+/// into one [`PollError::CanRetry`], so that the composite keeps running, with these differences:
+/// - a source that fails with a [`PollError::CanRetry`] is polled again the next time;
+/// - a source that fails with a [`PollError::Fatal`] is removed from the composite.
+///
+/// The composite only fails with a [`PollError::Fatal`] when all its sources have been removed.
+///
+/// ## Example
+/// ```
+/// use alumet::pipeline::{composite::CompositeSource, Source};
+///
+/// let sources: Vec<Box<dyn Source>> = Vec::new(); // add the sources of the group here
+/// let group = CompositeSource::new(sources);
+/// ```
+pub struct CompositeSource {
+    /// The sources that have not failed fatally, with their index in the original list (for the error messages).
+    sources: Vec<(usize, Box<dyn Source>)>,
+}
+
+impl CompositeSource {
+    pub fn new(sources: Vec<Box<dyn Source>>) -> Self {
+        Self {
+            sources: sources.into_iter().enumerate().collect(),
+        }
+    }
+
+    /// Returns the number of sources that are still polled.
+    pub fn len(&self) -> usize {
+        self.sources.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sources.is_empty()
+    }
+}
+
+impl Source for CompositeSource {
+    fn poll(&mut self, measurements: &mut MeasurementAccumulator, timestamp: Timestamp) -> Result<(), PollError> {
+        let mut errors = Vec::new();
+        let mut fatal = false;
+        self.sources.retain_mut(|(i, source)| match source.poll(measurements, timestamp) {
+            Ok(()) => true,
+            Err(PollError::CanRetry(e)) => {
+                errors.push(format!("source #{i}: {e:#}"));
+                true
+            }
+            Err(PollError::Fatal(e)) => {
+                errors.push(format!("source #{i} (removed from the composite): {e:#}"));
+                fatal = true;
+                false
+            }
+        });
+        if errors.is_empty() {
+            return Ok(());
+        }
+        let error = anyhow!("{} sources of the composite have failed: {}", errors.len(), errors.join("; "));
+        if fatal && self.sources.is_empty() {
+            Err(PollError::Fatal(error.context("all the sources of the composite have failed")))
+        } else {
+            Err(PollError::CanRetry(error))
+        }
+    }
+
+    fn points_per_poll_hint(&self) -> Option<usize> {
+        self.sources.iter().filter_map(|(_, s)| s.points_per_poll_hint()).reduce(usize::saturating_add)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+
+    use crate::measurement::{
+        MeasurementAccumulator, MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementValue,
+    };
+    use crate::metrics::RawMetricId;
+    use crate::pipeline::{PollError, Source};
+    use crate::resources::{Resource, ResourceConsumer};
+
+    use super::CompositeSource;
+
+    /// Pushes one point, then fails after `n_ok` polls (fatally or not).
+    struct TestSource {
+        n_ok: usize,
+        fatal: bool,
+    }
+
+    impl Source for TestSource {
+        fn poll(&mut self, measurements: &mut MeasurementAccumulator, timestamp: Timestamp) -> Result<(), PollError> {
+            if self.n_ok == 0 {
+                let e = anyhow!("test error");
+                return Err(if self.fatal { PollError::Fatal(e) } else { PollError::CanRetry(e) });
+            }
+            self.n_ok -= 1;
+            measurements.push(MeasurementPoint::new_untyped(
+                timestamp,
+                RawMetricId(0),
+                Resource::LocalMachine,
+                ResourceConsumer::LocalMachine,
+                WrappedMeasurementValue::U64(1),
+            ));
+            Ok(())
+        }
+    }
+
+    fn poll(composite: &mut CompositeSource, buf: &mut MeasurementBuffer) -> Result<(), PollError> {
+        composite.poll(&mut buf.as_accumulator(), Timestamp::now())
+    }
+
+    #[test]
+    fn errors_do_not_stop_the_others() {
+        let sources: Vec<Box<dyn Source>> = vec![
+            Box::new(TestSource { n_ok: usize::MAX, fatal: false }),
+            Box::new(TestSource { n_ok: 0, fatal: false }),
+            Box::new(TestSource { n_ok: 1, fatal: true }),
+        ];
+        let mut composite = CompositeSource::new(sources);
+        let mut buf = MeasurementBuffer::new();
+
+        // the second source fails, but can retry
+        assert!(matches!(poll(&mut composite, &mut buf), Err(PollError::CanRetry(_))));
+        assert_eq!(buf.len(), 2);
+        assert_eq!(composite.len(), 3);
+
+        // the third source fails fatally: it is removed, the composite keeps running
+        assert!(matches!(poll(&mut composite, &mut buf), Err(PollError::CanRetry(_))));
+        assert_eq!(buf.len(), 3);
+        assert_eq!(composite.len(), 2);
+    }
+
+    #[test]
+    fn fatal_when_all_removed() {
+        let sources: Vec<Box<dyn Source>> = vec![
+            Box::new(TestSource { n_ok: 0, fatal: true }),
+            Box::new(TestSource { n_ok: 1, fatal: true }),
+        ];
+        let mut composite = CompositeSource::new(sources);
+        let mut buf = MeasurementBuffer::new();
+        assert!(matches!(poll(&mut composite, &mut buf), Err(PollError::CanRetry(_))));
+        assert!(matches!(poll(&mut composite, &mut buf), Err(PollError::Fatal(_))));
+        assert!(composite.is_empty());
+        assert_eq!(buf.len(), 1);
+    }
+}
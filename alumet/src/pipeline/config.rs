@@ -0,0 +1,213 @@
+//! A declarative description of a pipeline, separated from the code that builds its elements.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context};
+
+use crate::plugin::AlumetStart;
+
+use super::builder::PipelineBuilder;
+use super::{Output, Source, Transform};
+
+/// Describes a pipeline as plain data: its elements, their triggers and the threads that run them.
+///
+/// The elements are built by the constructors of their plugin, see [`instantiate`](Self::instantiate).
+/// This separates the configuration of the pipeline, which could be loaded from a file, from its wiring.
+///
+/// ## Example
+/// ```no_run
+/// use std::time::Duration;
+/// use alumet::pipeline::config::{Constructors, ElementConfig, PipelineConfig, SourceConfig};
+/// use alumet::pipeline::{Output, Source};
+///
+/// # fn example(make_source: fn() -> Box<dyn Source>, make_output: fn() -> Box<dyn Output>) -> anyhow::Result<()> {
+/// let constructors = Constructors::new()
+///     .source("rapl", move |_alumet, _params| Ok(make_source()))
+///     .output("csv", move |_alumet, _params| Ok(make_output()));
+///
+/// let mut config = PipelineConfig::default();
+/// config.sources.push(SourceConfig::new("rapl", Duration::from_millis(100)));
+/// config.outputs.push(ElementConfig::new("csv"));
+/// let pipeline = config.instantiate(&constructors)?.build()?.start();
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PipelineConfig {
+    pub sources: Vec<SourceConfig>,
+    /// The transforms, in the order in which they are applied.
+    pub transforms: Vec<ElementConfig>,
+    pub outputs: Vec<ElementConfig>,
+    pub threads: ThreadsConfig,
+}
+
+/// Describes a source of a [`PipelineConfig`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceConfig {
+    /// The plugin whose constructor builds the source.
+    pub plugin: String,
+    /// The parameters given to the constructor.
+    pub params: toml::Table,
+    /// How often the source is polled.
+    pub poll_interval: Duration,
+    /// How often the measurements of the source are flushed. By default, they are flushed after each poll.
+    pub flush_interval: Option<Duration>,
+    /// Whether the source runs on the "realtime priority" runtime.
+    pub realtime_priority: bool,
+}
+
+/// Describes a transform or an output of a [`PipelineConfig`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ElementConfig {
+    /// The plugin whose constructor builds the element.
+    pub plugin: String,
+    /// The parameters given to the constructor.
+    pub params: toml::Table,
+}
+
+/// The threads of the pipeline, see [`PipelineBuilder::normal_worker_threads`] and the related methods.
+///
+/// The settings that are `None` keep their default value.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ThreadsConfig {
+    pub normal_worker_threads: Option<usize>,
+    pub priority_worker_threads: Option<usize>,
+    pub blocking_worker_threads: Option<usize>,
+    pub thread_name_prefix: Option<String>,
+}
+
+impl SourceConfig {
+    /// Describes a source of `plugin`, without parameters, that is polled every `poll_interval`.
+    pub fn new(plugin: impl Into<String>, poll_interval: Duration) -> Self {
+        Self {
+            plugin: plugin.into(),
+            params: toml::Table::new(),
+            poll_interval,
+            flush_interval: None,
+            realtime_priority: false,
+        }
+    }
+}
+
+impl ElementConfig {
+    /// Describes an element of `plugin`, without parameters.
+    pub fn new(plugin: impl Into<String>) -> Self {
+        Self {
+            plugin: plugin.into(),
+            params: toml::Table::new(),
+        }
+    }
+}
+
+/// Builds a source from its parameters. The [`AlumetStart`] allows to create the metrics of the source.
+pub type SourceConstructor = Box<dyn Fn(&mut AlumetStart, &toml::Table) -> anyhow::Result<Box<dyn Source>>>;
+/// Builds a transform from its parameters.
+pub type TransformConstructor = Box<dyn Fn(&mut AlumetStart, &toml::Table) -> anyhow::Result<Box<dyn Transform>>>;
+/// Builds an output from its parameters.
+pub type OutputConstructor = Box<dyn Fn(&mut AlumetStart, &toml::Table) -> anyhow::Result<Box<dyn Output>>>;
+
+/// The constructors of the elements, by plugin name, used by [`PipelineConfig::instantiate`].
+///
+/// A plugin has at most one constructor of each kind: registering another one replaces it.
+#[derive(Default)]
+pub struct Constructors {
+    sources: HashMap<String, SourceConstructor>,
+    transforms: HashMap<String, TransformConstructor>,
+    outputs: HashMap<String, OutputConstructor>,
+}
+
+impl Constructors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers the constructor of the sources of `plugin`.
+    pub fn source<F>(mut self, plugin: &str, f: F) -> Self
+    where
+        F: Fn(&mut AlumetStart, &toml::Table) -> anyhow::Result<Box<dyn Source>> + 'static,
+    {
+        self.sources.insert(plugin.to_owned(), Box::new(f));
+        self
+    }
+
+    /// Registers the constructor of the transforms of `plugin`.
+    pub fn transform<F>(mut self, plugin: &str, f: F) -> Self
+    where
+        F: Fn(&mut AlumetStart, &toml::Table) -> anyhow::Result<Box<dyn Transform>> + 'static,
+    {
+        self.transforms.insert(plugin.to_owned(), Box::new(f));
+        self
+    }
+
+    /// Registers the constructor of the outputs of `plugin`.
+    pub fn output<F>(mut self, plugin: &str, f: F) -> Self
+    where
+        F: Fn(&mut AlumetStart, &toml::Table) -> anyhow::Result<Box<dyn Output>> + 'static,
+    {
+        self.outputs.insert(plugin.to_owned(), Box::new(f));
+        self
+    }
+}
+
+impl PipelineConfig {
+    /// Builds the elements of the pipeline with the `constructors` of their plugin, and adds them to a new builder.
+    ///
+    /// The elements are built in order: the sources, then the transforms, then the outputs.
+    /// Fails if an element has no constructor, or if a constructor fails. The other problems, like an invalid
+    /// poll interval, are detected by [`PipelineBuilder::build`] (or [`PipelineBuilder::validate`]).
+    pub fn instantiate(&self, constructors: &Constructors) -> anyhow::Result<PipelineBuilder> {
+        let mut builder = PipelineBuilder::new();
+        let threads = &self.threads;
+        if let Some(n) = threads.normal_worker_threads {
+            builder.normal_worker_threads(n);
+        }
+        if let Some(n) = threads.priority_worker_threads {
+            builder.priority_worker_threads(n);
+        }
+        if let Some(n) = threads.blocking_worker_threads {
+            builder.blocking_worker_threads(n);
+        }
+        if let Some(prefix) = &threads.thread_name_prefix {
+            builder.thread_name_prefix(prefix);
+        }
+
+        for (i, config) in self.sources.iter().enumerate() {
+            let plugin = &config.plugin;
+            let constructor = constructors
+                .sources
+                .get(plugin)
+                .ok_or_else(|| anyhow!("source {i}: plugin '{plugin}' has no source constructor"))?;
+            let source = constructor(&mut AlumetStart::new(&mut builder, plugin.clone()), &config.params)
+                .with_context(|| format!("source {i}: the constructor of plugin '{plugin}' has failed"))?;
+            let mut registration = builder.add_source(plugin, source).every(config.poll_interval);
+            if let Some(flush_interval) = config.flush_interval {
+                registration = registration.flush_every(flush_interval);
+            }
+            if config.realtime_priority {
+                registration.priority();
+            }
+        }
+        for (i, config) in self.transforms.iter().enumerate() {
+            let plugin = &config.plugin;
+            let constructor = constructors
+                .transforms
+                .get(plugin)
+                .ok_or_else(|| anyhow!("transform {i}: plugin '{plugin}' has no transform constructor"))?;
+            let transform = constructor(&mut AlumetStart::new(&mut builder, plugin.clone()), &config.params)
+                .with_context(|| format!("transform {i}: the constructor of plugin '{plugin}' has failed"))?;
+            builder.add_transform(plugin, transform);
+        }
+        for (i, config) in self.outputs.iter().enumerate() {
+            let plugin = &config.plugin;
+            let constructor = constructors
+                .outputs
+                .get(plugin)
+                .ok_or_else(|| anyhow!("output {i}: plugin '{plugin}' has no output constructor"))?;
+            let output = constructor(&mut AlumetStart::new(&mut builder, plugin.clone()), &config.params)
+                .with_context(|| format!("output {i}: the constructor of plugin '{plugin}' has failed"))?;
+            builder.add_output(plugin, output);
+        }
+        Ok(builder)
+    }
+}
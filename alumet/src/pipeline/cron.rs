@@ -0,0 +1,261 @@
+//! Cron expressions, used by the cron trigger.
+
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const MINUTES_PER_DAY: u64 = 24 * 60;
+
+/// Maximum number of minutes to look ahead when searching for the next matching time.
+///
+/// Some valid expressions match very rarely: `0 0 29 2 *` matches once every 4 years (sometimes 8).
+const MAX_LOOKAHEAD: u64 = 10 * 366 * MINUTES_PER_DAY;
+
+/// A parsed cron expression.
+///
+/// The expression has five fields, separated by whitespace:
+/// minute (0-59), hour (0-23), day of month (1-31), month (1-12) and day of week (0-7, 0 and 7 are sunday).
+/// Each field is a comma-separated list of `*`, `n` or `a-b`, optionally followed by a step `/s`.
+///
+/// Like in the standard cron, if both the day of month and the day of week are restricted (they don't
+/// start with `*`), the expression matches when _either_ of them matches.
+///
+/// The times are evaluated in UTC.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct CronSchedule {
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+    dom_restricted: bool,
+    dow_restricted: bool,
+}
+
+/// Error returned when a cron expression is malformed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ParseError(String);
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl CronSchedule {
+    /// Parses a cron expression.
+    pub fn parse(expression: &str) -> Result<Self, ParseError> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let &[minute, hour, dom, month, dow] = fields.as_slice() else {
+            return Err(ParseError(format!("expected 5 fields, found {}", fields.len())));
+        };
+        let mut days_of_week = parse_field(dow, 0, 7, "day of week")?;
+        if days_of_week & (1 << 7) != 0 {
+            // 7 is another name for sunday
+            days_of_week = (days_of_week & !(1 << 7)) | 1;
+        }
+        Ok(Self {
+            minutes: parse_field(minute, 0, 59, "minute")?,
+            hours: parse_field(hour, 0, 23, "hour")?,
+            days_of_month: parse_field(dom, 1, 31, "day of month")?,
+            months: parse_field(month, 1, 12, "month")?,
+            days_of_week,
+            dom_restricted: !dom.starts_with('*'),
+            dow_restricted: !dow.starts_with('*'),
+        })
+    }
+
+    /// Returns the first time that matches the schedule, strictly after the minute of `time`.
+    ///
+    /// Returns `None` if the schedule never matches (for instance `0 0 31 2 *`).
+    pub fn next_after(&self, time: SystemTime) -> Option<SystemTime> {
+        let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or(Duration::ZERO);
+        // number of minutes since the epoch
+        let mut t = since_epoch.as_secs() / 60 + 1;
+        let end = t + MAX_LOOKAHEAD;
+        while t < end {
+            let days = t / MINUTES_PER_DAY;
+            let (year, month, day) = civil_from_days(days);
+            if !has(self.months, month) {
+                // go to the first day of the next month
+                let (year, month) = if month == 12 { (year + 1, 1) } else { (year, month + 1) };
+                t = days_from_civil(year, month, 1) * MINUTES_PER_DAY;
+                continue;
+            }
+            if !self.matches_day(day, weekday(days)) {
+                t = (days + 1) * MINUTES_PER_DAY;
+                continue;
+            }
+            let minute_of_day = t % MINUTES_PER_DAY;
+            let hour = minute_of_day / 60;
+            if !has(self.hours, hour) {
+                t = days * MINUTES_PER_DAY + (hour + 1) * 60;
+                continue;
+            }
+            if !has(self.minutes, minute_of_day % 60) {
+                t += 1;
+                continue;
+            }
+            return Some(UNIX_EPOCH + Duration::from_secs(t * 60));
+        }
+        None
+    }
+
+    fn matches_day(&self, day_of_month: u64, day_of_week: u64) -> bool {
+        let dom = has(self.days_of_month, day_of_month);
+        let dow = has(self.days_of_week, day_of_week);
+        if self.dom_restricted && self.dow_restricted {
+            dom || dow
+        } else {
+            dom && dow
+        }
+    }
+}
+
+fn has(set: u64, value: u64) -> bool {
+    set & (1 << value) != 0
+}
+
+/// Parses a field of a cron expression into a set of values, represented as a bitset.
+fn parse_field(field: &str, min: u64, max: u64, name: &str) -> Result<u64, ParseError> {
+    let invalid = |reason: &str| ParseError(format!("invalid {name} field {field:?}: {reason}"));
+    let parse_value = |s: &str| -> Result<u64, ParseError> {
+        let value: u64 = s.parse().map_err(|_| invalid(&format!("{s:?} is not a number")))?;
+        if !(min..=max).contains(&value) {
+            return Err(invalid(&format!("{value} is not in the range {min}-{max}")));
+        }
+        Ok(value)
+    };
+
+    let mut set = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step: u64 = step.parse().map_err(|_| invalid(&format!("invalid step {step:?}")))?;
+                if step == 0 {
+                    return Err(invalid("the step must be non-zero"));
+                }
+                (range, Some(step))
+            }
+            None => (part, None),
+        };
+        let (first, last) = if range == "*" {
+            (min, max)
+        } else if let Some((a, b)) = range.split_once('-') {
+            (parse_value(a)?, parse_value(b)?)
+        } else {
+            let value = parse_value(range)?;
+            // `n/s` means "from n to the max, every s"
+            (value, if step.is_some() { max } else { value })
+        };
+        if first > last {
+            return Err(invalid(&format!("the range {first}-{last} is empty")));
+        }
+        for value in (first..=last).step_by(step.unwrap_or(1) as usize) {
+            set |= 1 << value;
+        }
+    }
+    Ok(set)
+}
+
+/// Returns the day of the week (0 is sunday) of the given day since the UNIX epoch.
+fn weekday(days_since_epoch: u64) -> u64 {
+    // 1970-01-01 was a thursday
+    (days_since_epoch + 4) % 7
+}
+
+/// Converts a number of days since the UNIX epoch to a date `(year, month, day)`.
+///
+/// See <http://howardhinnant.github.io/date_algorithms.html#civil_from_days>.
+fn civil_from_days(days_since_epoch: u64) -> (u64, u64, u64) {
+    let z = days_since_epoch + 719468;
+    let era = z / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Converts a date to a number of days since the UNIX epoch. The date must not be before the epoch.
+///
+/// See <http://howardhinnant.github.io/date_algorithms.html#days_from_civil>.
+fn days_from_civil(year: u64, month: u64, day: u64) -> u64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let yoe = year - era * 400;
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use super::{civil_from_days, days_from_civil, CronSchedule};
+
+    fn time(year: u64, month: u64, day: u64, hour: u64, minute: u64) -> SystemTime {
+        let days = days_from_civil(year, month, day);
+        UNIX_EPOCH + Duration::from_secs(days * 86400 + hour * 3600 + minute * 60)
+    }
+
+    fn next(expression: &str, after: SystemTime) -> Option<SystemTime> {
+        CronSchedule::parse(expression).unwrap().next_after(after)
+    }
+
+    #[test]
+    fn civil_dates() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(days_from_civil(2000, 3, 1), 11017);
+        for days in [0, 59, 365, 11016, 11017, 19782, 50000] {
+            let (y, m, d) = civil_from_days(days);
+            assert_eq!(days_from_civil(y, m, d), days);
+        }
+    }
+
+    #[test]
+    fn parse_errors() {
+        for expr in [
+            "",
+            "* * * *",
+            "* * * * * *",
+            "60 * * * *",
+            "* 24 * * *",
+            "* * 0 * *",
+            "* * * 13 *",
+            "* * * * 8",
+            "*/0 * * * *",
+            "5-1 * * * *",
+            "a * * * *",
+            "1,,2 * * * *",
+        ] {
+            assert!(CronSchedule::parse(expr).is_err(), "{expr:?} should be invalid");
+        }
+    }
+
+    #[test]
+    fn next_times() {
+        let now = time(2024, 3, 9, 15, 30); // a saturday
+        assert_eq!(next("0 0 * * *", now), Some(time(2024, 3, 10, 0, 0)));
+        assert_eq!(next("*/15 * * * *", now), Some(time(2024, 3, 9, 15, 45)));
+        assert_eq!(next("* * * * *", now), Some(time(2024, 3, 9, 15, 31)));
+        // weekdays only: next monday
+        assert_eq!(next("30 8 * * 1-5", now), Some(time(2024, 3, 11, 8, 30)));
+        // 7 is sunday
+        assert_eq!(next("0 12 * * 7", now), Some(time(2024, 3, 10, 12, 0)));
+        // both days restricted: the 13th or a friday, whichever comes first
+        assert_eq!(next("0 0 13 * 5", now), Some(time(2024, 3, 13, 0, 0)));
+        assert_eq!(next("0 0 13 * 5", time(2024, 3, 13, 0, 0)), Some(time(2024, 3, 15, 0, 0)));
+        // leap day, year change
+        assert_eq!(next("0 12 29 2 *", now), Some(time(2028, 2, 29, 12, 0)));
+        assert_eq!(next("0 0 1 1 *", now), Some(time(2025, 1, 1, 0, 0)));
+        // never matches
+        assert_eq!(next("0 0 31 2 *", now), None);
+    }
+}
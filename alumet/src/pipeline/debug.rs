@@ -0,0 +1,255 @@
+//! An output that prints the measurements in a human-readable form, useful to develop and debug a plugin.
+
+use std::fmt::{self, Write as _};
+use std::io::{self, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::measurement::{MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementValue};
+use crate::metrics::MetricRegistry;
+use crate::resources::{Resource, ResourceConsumer};
+
+use super::{Output, OutputContext, WriteError};
+
+/// How a [`DebugOutput`] formats the measurements.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DebugFormat {
+    /// One line per buffer, with the metric, resource, value and timestamp of each point.
+    #[default]
+    Compact,
+    /// One line per point, which also contains its consumer and attributes, after a header line for each buffer.
+    Verbose,
+}
+
+/// An output that prints each point with the name and unit of its metric, its resource, its value and its timestamp.
+///
+/// The names and units are resolved from the [`MetricRegistry`] of the pipeline. The timestamps are printed as
+/// seconds since the Unix epoch. The writer is flushed after each buffer.
+///
+/// ## Example
+/// ```
+/// use alumet::pipeline::debug::{DebugFormat, DebugOutput};
+///
+/// // print to stdout, one line per point
+/// let output = DebugOutput::stdout().with_format(DebugFormat::Verbose);
+/// // or to any writer, for instance a file
+/// let output = DebugOutput::new(std::io::sink());
+/// ```
+pub struct DebugOutput {
+    writer: Box<dyn Write + Send>,
+    format: DebugFormat,
+}
+
+impl DebugOutput {
+    /// Creates an output that prints the measurements to `writer`, in the [compact](DebugFormat::Compact) format.
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        Self {
+            writer: Box::new(writer),
+            format: DebugFormat::default(),
+        }
+    }
+
+    /// Creates an output that prints the measurements to the standard output.
+    pub fn stdout() -> Self {
+        Self::new(io::stdout())
+    }
+
+    /// Chooses how the measurements are formatted.
+    pub fn with_format(mut self, format: DebugFormat) -> Self {
+        self.format = format;
+        self
+    }
+}
+
+impl Output for DebugOutput {
+    fn write(&mut self, measurements: &MeasurementBuffer, ctx: &OutputContext) -> Result<(), WriteError> {
+        let text = format_buffer(measurements, &ctx.metrics, self.format);
+        self.writer.write_all(text.as_bytes())?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), WriteError> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Formats the buffer, including the final newline.
+fn format_buffer(measurements: &MeasurementBuffer, metrics: &MetricRegistry, format: DebugFormat) -> String {
+    // writing to a String never fails
+    let mut res = String::new();
+    let n = measurements.len();
+    let plural = if n == 1 { "" } else { "s" };
+    match format {
+        DebugFormat::Compact => {
+            write!(res, "[{n} point{plural}]").unwrap();
+            for (i, point) in measurements.iter().enumerate() {
+                res.push_str(if i == 0 { " " } else { "; " });
+                write_point(&mut res, point, metrics).unwrap();
+                write!(res, " @{}", DisplayTimestamp(point.timestamp)).unwrap();
+            }
+            res.push('\n');
+        }
+        DebugFormat::Verbose => {
+            writeln!(res, "buffer of {n} point{plural}:").unwrap();
+            for point in measurements.iter() {
+                write!(res, "  {} ", DisplayTimestamp(point.timestamp)).unwrap();
+                write_point(&mut res, point, metrics).unwrap();
+                write!(res, " by {}", DisplayConsumer(&point.consumer)).unwrap();
+                let mut attributes = point.attributes().peekable();
+                if attributes.peek().is_some() {
+                    res.push_str(" {");
+                    for (i, (key, value)) in attributes.enumerate() {
+                        let sep = if i == 0 { "" } else { ", " };
+                        write!(res, "{sep}{key}={value}").unwrap();
+                    }
+                    res.push('}');
+                }
+                res.push('\n');
+            }
+        }
+    }
+    res
+}
+
+/// Writes `metric{resource} = value unit`.
+fn write_point(f: &mut String, point: &MeasurementPoint, metrics: &MetricRegistry) -> fmt::Result {
+    let metric = metrics.with_id(&point.metric);
+    match metric {
+        Some(m) => f.write_str(&m.name)?,
+        None => write!(f, "<unknown metric {}>", point.metric.as_u64())?,
+    }
+    write!(f, "{{{}}} = ", DisplayResource(&point.resource))?;
+    match point.value {
+        WrappedMeasurementValue::F64(x) => write!(f, "{x}")?,
+        WrappedMeasurementValue::U64(n) => write!(f, "{n}")?,
+    }
+    if let Some(m) = metric {
+        let unit = m.unit.to_string();
+        if !unit.is_empty() {
+            write!(f, " {unit}")?;
+        }
+    }
+    Ok(())
+}
+
+/// Displays the kind and id of a resource, e.g. `cpu_package 0`.
+struct DisplayResource<'a>(&'a Resource);
+
+impl fmt::Display for DisplayResource<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0.id_string() {
+            Some(id) => write!(f, "{} {id}", self.0.kind()),
+            None => f.write_str(self.0.kind()),
+        }
+    }
+}
+
+/// Displays the kind and id of a consumer, e.g. `process 1234`.
+struct DisplayConsumer<'a>(&'a ResourceConsumer);
+
+impl fmt::Display for DisplayConsumer<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0.id_string() {
+            Some(id) => write!(f, "{} {id}", self.0.kind()),
+            None => f.write_str(self.0.kind()),
+        }
+    }
+}
+
+/// Displays a timestamp as seconds since the Unix epoch, with a nanosecond precision.
+struct DisplayTimestamp(Timestamp);
+
+impl fmt::Display for DisplayTimestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match SystemTime::from(self.0).duration_since(UNIX_EPOCH) {
+            Ok(t) => write!(f, "{}.{:09}", t.as_secs(), t.subsec_nanos()),
+            Err(e) => write!(f, "-{:.9}", e.duration().as_secs_f64()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Write};
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use crate::measurement::{
+        AttributeValue, MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementType, WrappedMeasurementValue,
+    };
+    use crate::metrics::{Metric, MetricRegistry, RawMetricId};
+    use crate::pipeline::{Output, OutputContext};
+    use crate::resources::{Resource, ResourceConsumer};
+    use crate::units::{PrefixedUnit, Unit};
+
+    use super::{DebugFormat, DebugOutput};
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn printed(format: DebugFormat) -> String {
+        let mut metrics = MetricRegistry::new();
+        let energy = metrics
+            .register(Metric {
+                name: String::from("rapl_energy"),
+                description: String::new(),
+                value_type: WrappedMeasurementType::F64,
+                unit: PrefixedUnit::micro(Unit::Joule),
+            })
+            .unwrap();
+        let t = Timestamp::from(UNIX_EPOCH + Duration::from_millis(1500));
+        let buf = MeasurementBuffer::from(vec![
+            MeasurementPoint::new_untyped(
+                t,
+                energy,
+                Resource::CpuPackage { id: 0 },
+                ResourceConsumer::LocalMachine,
+                WrappedMeasurementValue::F64(12.5),
+            )
+            .with_attr("domain", AttributeValue::Str("pkg")),
+            MeasurementPoint::new_untyped(
+                t,
+                RawMetricId(7),
+                Resource::LocalMachine,
+                ResourceConsumer::LocalMachine,
+                WrappedMeasurementValue::U64(3),
+            ),
+        ]);
+
+        let out = SharedBuffer::default();
+        let mut output = DebugOutput::new(out.clone()).with_format(format);
+        output.write(&buf, &OutputContext { metrics }).unwrap();
+        let bytes = out.0.lock().unwrap().clone();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn compact_format() {
+        assert_eq!(
+            printed(DebugFormat::Compact),
+            "[2 points] rapl_energy{cpu_package 0} = 12.5 μJ @1.500000000; \
+             <unknown metric 7>{local_machine} = 3 @1.500000000\n"
+        );
+    }
+
+    #[test]
+    fn verbose_format() {
+        assert_eq!(
+            printed(DebugFormat::Verbose),
+            "buffer of 2 points:\n  \
+             1.500000000 rapl_energy{cpu_package 0} = 12.5 μJ by local_machine {domain=pkg}\n  \
+             1.500000000 <unknown metric 7>{local_machine} = 3 by local_machine\n"
+        );
+    }
+}
@@ -0,0 +1,203 @@
+//! A transform that attaches external metadata to the measurements, like the tenant that owns a virtual machine.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock, Weak};
+use std::time::Duration;
+
+use crate::measurement::{AttributeValue, MeasurementBuffer};
+use crate::resources::Resource;
+
+use super::attributes::AttributeConflictPolicy;
+use super::{Transform, TransformError};
+
+/// The attributes to attach to the points of each resource.
+pub type Metadata = HashMap<Resource, Vec<(Cow<'static, str>, AttributeValue)>>;
+
+/// A transform that attaches attributes to the points, according to their resource.
+///
+/// The metadata is shared: it can be updated while the pipeline is running, either through the handle returned by
+/// [`metadata`](Self::metadata), or periodically by a background task (see [`spawn_refresh`](Self::spawn_refresh)).
+/// The points whose resource has no metadata are not modified.
+///
+/// ## Example
+/// ```no_run
+/// use alumet::measurement::AttributeValue;
+/// use alumet::pipeline::enrich::{EnrichTransform, Metadata};
+/// use alumet::resources::Resource;
+/// use std::time::Duration;
+///
+/// # fn example(alumet: &mut alumet::plugin::AlumetStart) {
+/// // the refresh task needs the runtime of the pipeline, hence the transform is created by a builder
+/// alumet.add_transform_builder(|ctx| {
+///     let transform = EnrichTransform::new();
+///     transform.spawn_refresh(ctx.async_runtime_handle(), Duration::from_secs(60), || {
+///         let mut metadata = Metadata::new();
+///         // e.g. query the hypervisor for the owner of each virtual machine
+///         metadata.insert(
+///             Resource::custom("vm", "vm42"),
+///             vec![("tenant".into(), AttributeValue::String(String::from("acme")))],
+///         );
+///         Ok(metadata)
+///     });
+///     Box::new(transform)
+/// });
+/// # }
+/// ```
+pub struct EnrichTransform {
+    metadata: Arc<RwLock<Metadata>>,
+    policy: AttributeConflictPolicy,
+}
+
+impl Default for EnrichTransform {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EnrichTransform {
+    /// Creates a transform without any metadata.
+    pub fn new() -> Self {
+        Self {
+            metadata: Arc::new(RwLock::new(Metadata::new())),
+            policy: AttributeConflictPolicy::default(),
+        }
+    }
+
+    /// Chooses what to do when a point already has an attribute with the same key as the metadata.
+    ///
+    /// By default, the attribute of the point is kept.
+    pub fn with_conflict_policy(mut self, policy: AttributeConflictPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Returns a handle to the metadata, which can be used to update it from another thread.
+    ///
+    /// The lock is also taken by the transform for each buffer: keep it for a short time only.
+    pub fn metadata(&self) -> Arc<RwLock<Metadata>> {
+        self.metadata.clone()
+    }
+
+    /// Spawns a task on `rt` that replaces the metadata by the result of `refresh`, now and then every `interval`.
+    ///
+    /// `refresh` is called on a thread that is allowed to block, for instance to query an external service,
+    /// and the lock is only taken to replace the metadata by the new one: a slow refresh does not delay the transform.
+    /// If `refresh` fails, the error is logged and the previous metadata is kept until the next refresh.
+    /// The task stops when the transform is dropped.
+    pub fn spawn_refresh<F>(&self, rt: &tokio::runtime::Handle, interval: Duration, refresh: F)
+    where
+        F: FnMut() -> anyhow::Result<Metadata> + Send + 'static,
+    {
+        rt.spawn(refresh_metadata(Arc::downgrade(&self.metadata), interval, refresh));
+    }
+}
+
+async fn refresh_metadata<F>(metadata: Weak<RwLock<Metadata>>, interval: Duration, mut refresh: F)
+where
+    F: FnMut() -> anyhow::Result<Metadata> + Send + 'static,
+{
+    let mut ticks = tokio::time::interval(interval);
+    ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        ticks.tick().await;
+        if metadata.strong_count() == 0 {
+            break;
+        }
+        let (f, result) = match tokio::task::spawn_blocking(move || {
+            let result = refresh();
+            (refresh, result)
+        })
+        .await
+        {
+            Ok(res) => res,
+            Err(e) => {
+                log::error!("The refresh of the metadata has panicked, the metadata will not be refreshed anymore: {e}");
+                break;
+            }
+        };
+        refresh = f;
+        let Some(metadata) = metadata.upgrade() else {
+            break;
+        };
+        match result {
+            Ok(new) => *metadata.write().unwrap() = new,
+            Err(e) => log::warn!("Could not refresh the metadata, the previous metadata is kept: {e:#}"),
+        }
+    }
+}
+
+impl Transform for EnrichTransform {
+    fn apply(&mut self, measurements: &mut MeasurementBuffer) -> Result<(), TransformError> {
+        let metadata = self.metadata.read().unwrap();
+        if metadata.is_empty() {
+            return Ok(());
+        }
+        for point in measurements.iter_mut() {
+            let Some(attributes) = metadata.get(&point.resource) else {
+                continue;
+            };
+            for (key, value) in attributes {
+                let exists = point.attributes_keys().any(|k| k == key);
+                match (exists, self.policy) {
+                    (false, _) => point.add_attr(key.clone(), value.clone()),
+                    (true, AttributeConflictPolicy::Override) => point.set_attr(key, value.clone()),
+                    (true, AttributeConflictPolicy::KeepExisting) => (),
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use crate::measurement::{AttributeValue, MeasurementBuffer, MeasurementPoint};
+    use crate::pipeline::testing;
+    use crate::pipeline::Transform;
+    use crate::resources::Resource;
+
+    use super::{EnrichTransform, Metadata};
+
+    fn point(resource: Resource) -> MeasurementPoint {
+        testing::point().resource(resource).build()
+    }
+
+    fn tenants(transform: &mut EnrichTransform) -> Vec<Option<String>> {
+        let mut buf = MeasurementBuffer::from(vec![point(Resource::custom("vm", "a")), point(Resource::LocalMachine)]);
+        transform.apply(&mut buf).unwrap();
+        buf.iter()
+            .map(|p| p.attributes().find(|(k, _)| *k == "tenant").map(|(_, v)| v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn refresh_metadata() {
+        let rt = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        let mut transform = EnrichTransform::new();
+        assert_eq!(tenants(&mut transform), vec![None, None]);
+
+        // the first refresh succeeds, the next ones fail and keep the previous metadata
+        let refreshes = Arc::new(AtomicU64::new(0));
+        let counter = refreshes.clone();
+        transform.spawn_refresh(rt.handle(), Duration::from_millis(5), move || {
+            if counter.fetch_add(1, Ordering::Relaxed) > 0 {
+                anyhow::bail!("the external service is down");
+            }
+            let mut metadata = Metadata::new();
+            metadata.insert(Resource::custom("vm", "a"), vec![("tenant".into(), AttributeValue::Str("acme"))]);
+            Ok(metadata)
+        });
+        rt.block_on(tokio::time::sleep(Duration::from_millis(30)));
+        assert!(refreshes.load(Ordering::Relaxed) > 1);
+        assert_eq!(tenants(&mut transform), vec![Some(String::from("acme")), None]);
+
+        // the metadata can also be updated directly
+        transform.metadata().write().unwrap().clear();
+        assert_eq!(tenants(&mut transform), vec![None, None]);
+    }
+}
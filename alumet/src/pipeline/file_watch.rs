@@ -0,0 +1,188 @@
+//! Watches a path with inotify, used by the file-watch trigger.
+
+use std::ffi::{CString, OsString};
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use tokio::io::unix::AsyncFd;
+
+/// The events that are considered as a change of the watched path.
+///
+/// The events about the children (creation, deletion, renaming) only occur when the path is a directory.
+const WATCH_MASK: u32 = libc::IN_MODIFY
+    | libc::IN_ATTRIB
+    | libc::IN_CLOSE_WRITE
+    | libc::IN_MOVE_SELF
+    | libc::IN_DELETE_SELF
+    | libc::IN_CREATE
+    | libc::IN_DELETE
+    | libc::IN_MOVED_FROM
+    | libc::IN_MOVED_TO;
+
+/// The events of the parent directory that can replace the watched path, e.g. by renaming a new file over it.
+const PARENT_MASK: u32 = libc::IN_CREATE | libc::IN_DELETE | libc::IN_MOVED_FROM | libc::IN_MOVED_TO;
+
+/// The events after which the watch does not follow the watched path anymore: the inode has been deleted,
+/// for instance because another file has been renamed over it, or the inode has been moved elsewhere.
+const LOST_MASK: u32 = libc::IN_IGNORED | libc::IN_MOVE_SELF;
+
+/// Size of the header of an `inotify_event`, which is followed by `len` bytes of name.
+const EVENT_HEADER_SIZE: usize = 16;
+
+/// An inotify instance that watches one path.
+///
+/// The path is watched in addition to its parent directory, filtered by the name of the path.
+/// When the path is replaced, for instance by writing to a temporary file and renaming it over
+/// the path (which is how most editors and configuration tools update a file), the new file is watched.
+pub(crate) struct FileWatch {
+    fd: AsyncFd<OwnedFd>,
+    path: PathBuf,
+    c_path: CString,
+    /// The watch descriptor of the path, which changes when the path is replaced.
+    wd: i32,
+    /// The watch descriptor of the parent directory and the name of the path in it,
+    /// `None` if the path has no parent (e.g. `/`).
+    parent: Option<(i32, OsString)>,
+}
+
+impl FileWatch {
+    /// Starts watching `path`. Fails if the path does not exist.
+    pub fn new(path: &Path) -> io::Result<Self> {
+        let c_path = CString::new(path.as_os_str().as_bytes())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "the path contains a null byte"))?;
+
+        // SAFETY: inotify_init1 has no precondition, and the fd is owned by us if it's valid.
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        let wd = add_watch(&fd, &c_path, path, WATCH_MASK)?;
+        let parent = match (path.parent(), path.file_name()) {
+            (Some(dir), Some(name)) => {
+                // the parent of a relative path with only one component is empty
+                let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+                let c_dir = CString::new(dir.as_os_str().as_bytes()).expect("the path contains no null byte");
+                let parent_wd = add_watch(&fd, &c_dir, dir, PARENT_MASK | libc::IN_ONLYDIR)?;
+                Some((parent_wd, name.to_owned()))
+            }
+            _ => None,
+        };
+        Ok(Self {
+            fd: AsyncFd::new(fd)?,
+            path: path.to_owned(),
+            c_path,
+            wd,
+            parent,
+        })
+    }
+
+    /// Waits for the watched path to change.
+    ///
+    /// All the events that are pending when the inotify instance becomes readable are consumed at once,
+    /// so that a burst of events only results in one change. If the path has been replaced, the new file
+    /// is watched from now on, and the replacement counts as a change.
+    /// Returns an error of kind [`io::ErrorKind::NotFound`] if the path has been deleted or unmounted:
+    /// it cannot be watched anymore.
+    pub async fn changed(&mut self) -> io::Result<()> {
+        let mut buf = [0u8; 4096];
+        loop {
+            let mut guard = self.fd.readable().await?;
+            let mut n_events = 0;
+            let mut lost = false;
+            loop {
+                // SAFETY: the buffer is valid for `buf.len()` bytes.
+                let n = unsafe { libc::read(self.fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len()) };
+                if n < 0 {
+                    let err = io::Error::last_os_error();
+                    match err.kind() {
+                        io::ErrorKind::WouldBlock => {
+                            guard.clear_ready();
+                            break;
+                        }
+                        io::ErrorKind::Interrupted => continue,
+                        _ => return Err(err),
+                    }
+                }
+                for event in events(&buf[..n as usize]) {
+                    if event.wd == self.wd {
+                        n_events += 1;
+                        lost |= event.mask & LOST_MASK != 0;
+                    } else if let Some((parent_wd, name)) = &self.parent {
+                        // The other children of the parent are not watched.
+                        if event.wd == *parent_wd && event.name == name.as_bytes() {
+                            n_events += 1;
+                            lost = true;
+                        }
+                    }
+                    // The events of the previous watches of the path are ignored.
+                }
+            }
+            if lost {
+                self.rewatch()?;
+            }
+            if n_events > 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Watches the file that is now at the watched path, and stops watching the previous one.
+    fn rewatch(&mut self) -> io::Result<()> {
+        let fd = self.fd.get_ref();
+        // The previous watch may have already been removed by the kernel (IN_IGNORED), in which case this fails.
+        // SAFETY: inotify_rm_watch has no precondition.
+        unsafe { libc::inotify_rm_watch(fd.as_raw_fd(), self.wd) };
+        match add_watch(fd, &self.c_path, &self.path, WATCH_MASK) {
+            Ok(wd) => {
+                self.wd = wd;
+                Ok(())
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let msg = format!("{} has been removed or unmounted", self.path.display());
+                Err(io::Error::new(io::ErrorKind::NotFound, msg))
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Adds a watch on `c_path`, which is `path` as a C string, and returns its watch descriptor.
+fn add_watch(fd: &OwnedFd, c_path: &CString, path: &Path, mask: u32) -> io::Result<i32> {
+    // SAFETY: c_path is a valid nul-terminated string.
+    let wd = unsafe { libc::inotify_add_watch(fd.as_raw_fd(), c_path.as_ptr(), mask) };
+    if wd < 0 {
+        let err = io::Error::last_os_error();
+        return Err(io::Error::new(err.kind(), format!("cannot watch {}: {err}", path.display())));
+    }
+    Ok(wd)
+}
+
+/// An inotify event, read from the inotify instance.
+struct Event<'a> {
+    wd: i32,
+    mask: u32,
+    /// The name of the child that the event is about, empty if the event is about the watched path itself.
+    name: &'a [u8],
+}
+
+/// Returns the inotify events contained in `buf`.
+fn events(buf: &[u8]) -> impl Iterator<Item = Event<'_>> + '_ {
+    let mut offset = 0;
+    std::iter::from_fn(move || {
+        let header = buf.get(offset..offset + EVENT_HEADER_SIZE)?;
+        // struct inotify_event { int wd; uint32_t mask; uint32_t cookie; uint32_t len; char name[]; }
+        let wd = i32::from_ne_bytes(header[0..4].try_into().unwrap());
+        let mask = u32::from_ne_bytes(header[4..8].try_into().unwrap());
+        let len = u32::from_ne_bytes(header[12..16].try_into().unwrap()) as usize;
+        let start = offset + EVENT_HEADER_SIZE;
+        // the name is padded with null bytes
+        let name = buf.get(start..start + len).unwrap_or_default();
+        let name = name.split(|&b| b == 0).next().unwrap_or_default();
+        offset = start + len;
+        Some(Event { wd, mask, name })
+    })
+}
@@ -0,0 +1,138 @@
+//! An output that keeps the measurements in memory, useful to test a pipeline from end to end.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use crate::measurement::MeasurementBuffer;
+
+use super::{Output, OutputContext, WriteError};
+
+/// An output that stores the last measurement buffers that it receives, in memory.
+///
+/// When the output is full, the oldest buffer is discarded to make room for the new one.
+/// Use [`MemoryOutput::handle`] to read the buffers after (or while) running the pipeline.
+///
+/// ## Example
+/// ```
+/// use alumet::pipeline::memory::MemoryOutput;
+///
+/// let output = MemoryOutput::with_capacity(16);
+/// let handle = output.handle();
+/// // add the output to the pipeline, run it, then:
+/// assert!(handle.buffers().is_empty());
+/// ```
+pub struct MemoryOutput {
+    buffers: Arc<Mutex<VecDeque<MeasurementBuffer>>>,
+    capacity: usize,
+}
+
+/// Gives access to the buffers collected by a [`MemoryOutput`].
+#[derive(Clone)]
+pub struct MemoryOutputHandle {
+    buffers: Arc<Mutex<VecDeque<MeasurementBuffer>>>,
+}
+
+impl MemoryOutput {
+    /// Creates an output that stores, at most, the last `capacity` buffers.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            buffers: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            capacity,
+        }
+    }
+
+    /// Returns a handle to read the collected buffers.
+    pub fn handle(&self) -> MemoryOutputHandle {
+        MemoryOutputHandle {
+            buffers: self.buffers.clone(),
+        }
+    }
+}
+
+impl Output for MemoryOutput {
+    fn write(&mut self, measurements: &MeasurementBuffer, _ctx: &OutputContext) -> Result<(), WriteError> {
+        if self.capacity == 0 {
+            return Ok(());
+        }
+        let mut buffers = self.buffers.lock().unwrap();
+        if buffers.len() == self.capacity {
+            buffers.pop_front();
+        }
+        buffers.push_back(measurements.clone());
+        Ok(())
+    }
+}
+
+impl MemoryOutputHandle {
+    /// Returns a copy of the collected buffers, from the oldest to the newest.
+    pub fn buffers(&self) -> Vec<MeasurementBuffer> {
+        self.buffers.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Returns a copy of the collected measurements, from the oldest to the newest, in a single buffer.
+    pub fn measurements(&self) -> MeasurementBuffer {
+        let buffers = self.buffers.lock().unwrap();
+        let mut res = MeasurementBuffer::with_capacity(buffers.iter().map(|b| b.len()).sum());
+        for point in buffers.iter().flatten() {
+            res.push(point.clone());
+        }
+        res
+    }
+
+    /// Discards the collected buffers.
+    pub fn clear(&self) {
+        self.buffers.lock().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::measurement::{MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementValue};
+    use crate::metrics::{MetricRegistry, RawMetricId};
+    use crate::pipeline::{Output, OutputContext};
+    use crate::resources::{Resource, ResourceConsumer};
+
+    use super::MemoryOutput;
+
+    fn buffer(value: u64) -> MeasurementBuffer {
+        MeasurementBuffer::from(vec![MeasurementPoint::new_untyped(
+            Timestamp::now(),
+            RawMetricId(0),
+            Resource::LocalMachine,
+            ResourceConsumer::LocalMachine,
+            WrappedMeasurementValue::U64(value),
+        )])
+    }
+
+    fn values(buf: &MeasurementBuffer) -> Vec<u64> {
+        buf.iter()
+            .map(|p| match p.value {
+                WrappedMeasurementValue::U64(n) => n,
+                _ => unreachable!(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn ring_buffer() {
+        let ctx = OutputContext {
+            metrics: MetricRegistry::new(),
+        };
+        let mut output = MemoryOutput::with_capacity(2);
+        let handle = output.handle();
+        for n in 1..=3 {
+            output.write(&buffer(n), &ctx).unwrap();
+        }
+        // the oldest buffer has been discarded
+        let buffers = handle.buffers();
+        assert_eq!(buffers.len(), 2);
+        assert_eq!(values(&handle.measurements()), vec![2, 3]);
+
+        handle.clear();
+        assert!(handle.buffers().is_empty());
+
+        let mut output = MemoryOutput::with_capacity(0);
+        output.write(&buffer(1), &ctx).unwrap();
+        assert!(output.handle().buffers().is_empty());
+    }
+}
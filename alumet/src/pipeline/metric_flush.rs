@@ -0,0 +1,198 @@
+//! A transform that delays the measurements of some metrics, to flush each metric at its own rate.
+
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+use crate::measurement::{MeasurementBuffer, MeasurementPoint};
+use crate::metrics::RawMetricId;
+
+use super::{Transform, TransformError};
+
+/// The default maximum number of points that a group keeps, see [`MetricFlushTransform::with_max_points`].
+pub const DEFAULT_MAX_POINTS: usize = 10_000;
+
+/// A transform that keeps the points of some metrics, and emits them by group, one group per metric,
+/// with a flush interval per metric.
+///
+/// A source has a single flush interval, which suits the metrics that it reports the most often. This transform
+/// allows the other metrics to reach the outputs less often, in larger groups, while the points of the metrics
+/// that have no interval pass through immediately: the slow metrics do not delay the fast ones.
+///
+/// Like [`WindowTransform`](super::window::WindowTransform), the transform is based on the timestamps of the points,
+/// not on the time at which they are transformed. A group starts with its first point, and is complete when the
+/// transform receives a point (of any metric) whose timestamp is at least one interval later. The points of
+/// the complete groups are then sent, grouped by metric, after the points that pass through.
+///
+/// ## Coordination with the flush of the sources
+/// The transform only runs when it receives a buffer, which happens when a source flushes its measurements.
+/// Therefore, a group is emitted with the first buffer that arrives after the end of its interval: the interval
+/// of a metric is rounded up to the flush interval of the sources, and an interval that is shorter than the flush
+/// interval has no effect. [`SourceCmd::Flush`](super::runtime::SourceCmd::Flush) forces a source to send its buffer,
+/// but the points of the delayed metrics are still kept until the end of their interval.
+///
+/// ## Memory
+/// Each group keeps the points that its metric produces during one interval, which depends on the number of time
+/// series of the metric and on their polling rate. To bound the memory, a group is emitted early when it reaches
+/// a maximum number of points, [`DEFAULT_MAX_POINTS`] by default (see [`with_max_points`](Self::with_max_points)).
+/// The points that are kept when the pipeline stops are lost.
+///
+/// ## Example
+/// ```
+/// use std::time::Duration;
+/// use alumet::metrics::{MetricId, TypedMetricId};
+/// use alumet::pipeline::metric_flush::MetricFlushTransform;
+///
+/// # fn example(temperature: TypedMetricId<f64>) {
+/// // the sources flush every second, but the temperature is sent every minute
+/// let transform = MetricFlushTransform::new().with_interval(temperature.untyped_id(), Duration::from_secs(60));
+/// # }
+/// ```
+pub struct MetricFlushTransform {
+    /// The groups, in the order in which their metric has been added.
+    groups: Vec<Group>,
+    index_by_metric: HashMap<RawMetricId, usize>,
+    max_points: usize,
+    /// The latest timestamp that the transform has received.
+    clock: Option<SystemTime>,
+}
+
+struct Group {
+    interval: Duration,
+    /// The timestamp of the first point of the group, `None` if the group is empty.
+    start: Option<SystemTime>,
+    points: Vec<MeasurementPoint>,
+}
+
+impl Default for MetricFlushTransform {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MetricFlushTransform {
+    /// Creates a transform that lets every point pass through, until intervals are set.
+    pub fn new() -> Self {
+        Self {
+            groups: Vec::new(),
+            index_by_metric: HashMap::new(),
+            max_points: DEFAULT_MAX_POINTS,
+            clock: None,
+        }
+    }
+
+    /// Emits the points of `metric` every `interval`.
+    ///
+    /// If the metric already has an interval, it is replaced. An interval of zero lets the points pass through.
+    pub fn with_interval(mut self, metric: RawMetricId, interval: Duration) -> Self {
+        match self.index_by_metric.get(&metric) {
+            Some(&i) => self.groups[i].interval = interval,
+            None => {
+                self.index_by_metric.insert(metric, self.groups.len());
+                self.groups.push(Group {
+                    interval,
+                    start: None,
+                    points: Vec::new(),
+                });
+            }
+        }
+        self
+    }
+
+    /// Emits a group before the end of its interval when it reaches `max_points` points.
+    ///
+    /// Panics if `max_points` is zero.
+    pub fn with_max_points(mut self, max_points: usize) -> Self {
+        assert!(max_points > 0, "max_points must be non-zero");
+        self.max_points = max_points;
+        self
+    }
+}
+
+impl Transform for MetricFlushTransform {
+    fn apply(&mut self, measurements: &mut MeasurementBuffer) -> Result<(), TransformError> {
+        if self.groups.is_empty() {
+            return Ok(());
+        }
+        measurements.retain(|point| {
+            let t = SystemTime::from(point.timestamp);
+            self.clock = Some(self.clock.map_or(t, |c| c.max(t)));
+            let Some(&i) = self.index_by_metric.get(&point.metric) else {
+                return true;
+            };
+            let group = &mut self.groups[i];
+            if group.interval.is_zero() {
+                return true;
+            }
+            group.start.get_or_insert(t);
+            group.points.push(point.clone());
+            false
+        });
+
+        let clock = self.clock;
+        for group in &mut self.groups {
+            let Some(start) = group.start else {
+                continue;
+            };
+            let complete = clock.is_some_and(|c| c >= start + group.interval);
+            if complete || group.points.len() >= self.max_points {
+                for point in group.points.drain(..) {
+                    measurements.push(point);
+                }
+                group.start = None;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::measurement::{MeasurementBuffer, MeasurementPoint, WrappedMeasurementValue};
+    use crate::metrics::RawMetricId;
+    use crate::pipeline::testing;
+    use crate::pipeline::Transform;
+
+    use super::MetricFlushTransform;
+
+    fn point(secs: u64, metric: usize) -> MeasurementPoint {
+        testing::point().secs(secs).metric(RawMetricId(metric)).u64(secs).build()
+    }
+
+    /// Applies the transform to one buffer with a point of each metric, returns the (metric, secs) of the output.
+    fn flush(transform: &mut MetricFlushTransform, secs: u64) -> Vec<(usize, u64)> {
+        let mut buf = MeasurementBuffer::from(vec![point(secs, 0), point(secs, 1), point(secs, 2)]);
+        transform.apply(&mut buf).unwrap();
+        buf.iter()
+            .map(|p| match p.value {
+                WrappedMeasurementValue::U64(n) => (p.metric.0, n),
+                WrappedMeasurementValue::F64(x) => (p.metric.0, x as u64),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn per_metric_intervals() {
+        let mut transform = MetricFlushTransform::new()
+            .with_interval(RawMetricId(1), Duration::from_secs(2))
+            .with_interval(RawMetricId(2), Duration::from_secs(3));
+        // metric 0 passes through, the others are emitted at the end of their interval, grouped by metric
+        assert_eq!(flush(&mut transform, 0), vec![(0, 0)]);
+        assert_eq!(flush(&mut transform, 1), vec![(0, 1)]);
+        assert_eq!(flush(&mut transform, 2), vec![(0, 2), (1, 0), (1, 1), (1, 2)]);
+        assert_eq!(flush(&mut transform, 3), vec![(0, 3), (2, 0), (2, 1), (2, 2), (2, 3)]);
+        assert_eq!(flush(&mut transform, 4), vec![(0, 4)]);
+        assert_eq!(flush(&mut transform, 5), vec![(0, 5), (1, 3), (1, 4), (1, 5)]);
+    }
+
+    #[test]
+    fn max_points() {
+        let mut transform = MetricFlushTransform::new()
+            .with_interval(RawMetricId(1), Duration::from_secs(60))
+            .with_max_points(2);
+        assert_eq!(flush(&mut transform, 0), vec![(0, 0), (2, 0)]);
+        assert_eq!(flush(&mut transform, 1), vec![(0, 1), (2, 1), (1, 0), (1, 1)]);
+        assert_eq!(flush(&mut transform, 2), vec![(0, 2), (2, 2)]);
+    }
+}
@@ -9,23 +9,204 @@ pub mod builder;
 mod threading;
 mod scoped;
 pub mod trigger;
+mod cron;
+#[cfg(target_os = "linux")]
+mod file_watch;
+pub mod memory;
+pub mod snapshot;
+pub mod window;
+pub mod change_only;
+pub mod rate_limit;
+pub mod rate;
+pub mod composite;
+pub mod attributes;
+pub mod channel;
+pub mod unit_convert;
+pub mod enrich;
+pub mod remap;
+pub mod partition;
+pub mod clamp;
+pub mod debug;
+pub mod metric_flush;
+pub mod config;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 
 /// Produces measurements related to some metrics.
 pub trait Source: Send {
     /// Polls the source for new measurements.
+    ///
+    /// `timestamp` is the time at which the trigger of the source has fired, taken once per poll.
+    /// Use it for sources that measure everything at (about) the same time, for instance by reading a few
+    /// hardware counters: all their points then share the same timestamp, which is easier to correlate.
+    ///
+    /// Sources that take a significant time between their measurements, for instance by iterating over
+    /// many processes or virtual machines, should instead timestamp each point with [`Timestamp::now`],
+    /// right after measuring it. Otherwise, the last measurement appears to have been taken at the same
+    /// instant as the first one.
+    ///
+    /// ## Concurrency
+    /// Each source is run by a single task, which polls it sequentially: `poll` is never called before the previous
+    /// call has returned, and a slow source cannot accumulate a backlog of pending polls. Instead, it falls behind its
+    /// trigger, which is counted in [`PipelineStats::poll_overruns`](runtime::PipelineStats::poll_overruns).
+    /// A source that blocks its thread should use a [`blocking`](trigger::builder::TimeTriggerBuilder::blocking)
+    /// trigger, to run on a dedicated runtime instead of delaying the other sources of the normal runtime.
     fn poll(&mut self, measurements: &mut MeasurementAccumulator, timestamp: Timestamp) -> Result<(), PollError>;
+
+    /// Prepares the source to be polled, for instance by opening the files that it reads.
+    ///
+    /// This is called once, by the task of the source, just before the first call to [`poll`](Self::poll).
+    /// If it fails with [`PollError::CanRetry`], it is called again at the next tick of the trigger, and the source
+    /// is not polled until it succeeds. If it fails with [`PollError::Fatal`], the source stops.
+    /// The source is not initialized again when it moves to another runtime
+    /// (see [`SourceCmd::SetPriority`](runtime::SourceCmd::SetPriority)).
+    ///
+    /// The default implementation does nothing.
+    fn init(&mut self) -> Result<(), PollError> {
+        Ok(())
+    }
+
+    /// Releases the resources that the source has acquired in [`init`](Self::init).
+    ///
+    /// This is called once, when the source stops (because of [`SourceCmd::Stop`](runtime::SourceCmd::Stop),
+    /// an error or the shutdown of the pipeline), only if `init` has succeeded.
+    ///
+    /// The default implementation does nothing.
+    fn shutdown(&mut self) {}
+
+    /// Returns the number of measurement points that the source usually produces at each poll, if it is known.
+    ///
+    /// The pipeline uses it to allocate the buffers of the source with the right capacity from the start,
+    /// instead of growing them during the first polls. The hint does not limit the number of points.
+    ///
+    /// The capacity that is allocated in advance is bounded, to protect the pipeline from an oversized hint.
+    ///
+    /// The default implementation returns `None`: the capacity is estimated from the previous flushes.
+    fn points_per_poll_hint(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// Produces measurements related to some metrics, asynchronously.
+///
+/// Unlike [`Source::poll`], which cannot wait without blocking its thread, `poll_async` is awaited directly by
+/// the task of the source. This is more efficient for sources that wait for an external entity with an async
+/// library, for instance for the response to a request on a socket.
+/// An async source is added with [`AlumetStart::add_async_source`](crate::plugin::AlumetStart::add_async_source).
+/// It is polled like a [`Source`], according to its trigger.
+pub trait AsyncSource: Send {
+    /// Polls the source for new measurements, see [`Source::poll`].
+    ///
+    /// The returned future must not block the thread.
+    fn poll_async<'a>(
+        &'a mut self,
+        measurements: &'a mut MeasurementAccumulator,
+        timestamp: Timestamp,
+    ) -> trigger::BoxFuture<'a, Result<(), PollError>>;
 }
 
 /// Transforms measurements.
 pub trait Transform: Send {
     /// Applies the transform on the measurements.
+    ///
+    /// To discard the whole buffer (e.g. because its measurements are not worth storing), clear it.
+    /// An empty buffer is not passed to the next transforms, and is not sent to the outputs.
+    /// This is not an error.
     fn apply(&mut self, measurements: &mut MeasurementBuffer) -> Result<(), TransformError>;
+
+    /// Returns `true` if the transform is independent from the other ones, which allows it to run in parallel.
+    ///
+    /// An independent transform only adds new points to the buffer: it does not modify or remove the existing points,
+    /// and the points that it adds only depend on the measurements that come from the sources.
+    /// Consecutive independent transforms are applied concurrently, each on its own copy of the buffer,
+    /// and the points that they add are then merged in the order of the transforms.
+    ///
+    /// The default implementation returns `false`: the transform is applied after the previous one.
+    fn parallelizable(&self) -> bool {
+        false
+    }
 }
 
 /// Exports measurements to an external entity, like a file or a database.
 pub trait Output: Send {
     /// Writes the measurements to the output.
     fn write(&mut self, measurements: &MeasurementBuffer, ctx: &OutputContext) -> Result<(), WriteError>;
+
+    /// Prepares the output to write the measurements of the given metrics.
+    ///
+    /// This is called once, when the output task starts, before the first call to [`write`](Self::write).
+    /// Outputs can use it to resolve the metrics that they need (e.g. names and units) without depending on
+    /// a global registry, which also allows to test them in isolation.
+    /// The metrics that are registered later (while the pipeline is running) are only available in the
+    /// [`OutputContext`] given to `write`.
+    ///
+    /// The default implementation does nothing.
+    fn register(&mut self, metrics: &MetricRegistry) -> Result<(), WriteError> {
+        let _ = metrics;
+        Ok(())
+    }
+
+    /// Writes the measurements that the output has buffered, if any.
+    ///
+    /// This is called periodically if the output has a flush interval, even if no new measurements
+    /// have arrived, and once when the output stops. Outputs that batch their writes can use it
+    /// to avoid delaying the data for too long.
+    ///
+    /// The default implementation does nothing.
+    fn flush(&mut self) -> Result<(), WriteError> {
+        Ok(())
+    }
+
+    /// Reconnects the output to the external entity that it writes to.
+    ///
+    /// This is called when [`write`](Self::write) fails with [`WriteError::Disconnected`].
+    /// If the reconnection succeeds, the measurements are written again, once. If it fails with
+    /// [`WriteError::CanRetry`] (or if writing again fails), the retry policy of the output applies,
+    /// and the output is reconnected again at the next attempt if needed.
+    ///
+    /// The default implementation does nothing: the measurements are simply written again, once.
+    fn reconnect(&mut self) -> Result<(), WriteError> {
+        Ok(())
+    }
+
+    /// Checks whether the external entity that the output writes to is available.
+    ///
+    /// This is called periodically if the output has a health check interval, see
+    /// [`OutputRegistration::health_check`](builder::OutputRegistration::health_check).
+    /// While the output is unhealthy, it is not called to write the measurements: it stops receiving them,
+    /// as if it was too slow, until a check reports that it is healthy again. It can block, like `write`.
+    ///
+    /// The default implementation always returns [`Health::Healthy`].
+    fn health(&self) -> Health {
+        Health::Healthy
+    }
+}
+
+/// The result of a health check, see [`Output::health`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Health {
+    /// The output can write the measurements.
+    #[default]
+    Healthy,
+    /// The output cannot write the measurements for now, for the given reason (e.g. "the database is down").
+    Unhealthy(String),
+}
+
+/// Exports measurements to an external entity, without blocking.
+///
+/// Unlike [`Output::write`], which is called on a dedicated thread because it can block,
+/// `write_async` is awaited directly by the pipeline.
+/// This is more efficient for outputs that use an async library, for instance to push the measurements
+/// to an HTTP endpoint.
+pub trait AsyncOutput: Send {
+    /// Writes the measurements to the output.
+    ///
+    /// The returned future must not block the thread.
+    fn write_async<'a>(
+        &'a mut self,
+        measurements: &'a MeasurementBuffer,
+        ctx: &'a OutputContext,
+    ) -> trigger::BoxFuture<'a, Result<(), WriteError>>;
 }
 
 pub struct OutputContext {
@@ -69,6 +250,11 @@ pub enum WriteError {
     /// - The output communicates with an external entity that you know can fail from time to time.
     /// - And the output's `write` method can be called again and work. Pay attention to the internal state of the output.
     CanRetry(anyhow::Error),
+    /// The output has lost its connection to the external entity, it must reconnect before writing again.
+    ///
+    /// The pipeline calls [`Output::reconnect`], then writes the measurements again.
+    /// An [`AsyncOutput`] has no reconnection hook: for them, this error is the same as [`WriteError::CanRetry`].
+    Disconnected(anyhow::Error),
 }
 
 impl fmt::Display for PollError {
@@ -95,6 +281,7 @@ impl fmt::Display for WriteError {
         match self {
             WriteError::Fatal(e) => write!(f, "fatal error in Output::write: {e}"),
             WriteError::CanRetry(e) => write!(f, "writing failed (but could work later): {e}"),
+            WriteError::Disconnected(e) => write!(f, "writing failed because the output is disconnected: {e}"),
         }
     }
 }
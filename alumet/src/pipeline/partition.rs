@@ -0,0 +1,135 @@
+//! A transform that assigns the measurements to shards, for instance to store each virtual machine in its own database.
+
+use std::borrow::Cow;
+use std::hash::{Hash, Hasher};
+
+use fxhash::FxHasher;
+
+use crate::measurement::{AttributeValue, MeasurementBuffer, MeasurementPoint};
+
+use super::{Transform, TransformError};
+
+/// The default key of the attribute that contains the shard of the points.
+pub const DEFAULT_SHARD_ATTRIBUTE: &str = "shard";
+
+/// A transform that attaches the number of its shard to every measurement point, as a `U64` attribute.
+///
+/// The shard of a point is computed from a partition key, by default its resource: all the points of
+/// a resource (e.g. a virtual machine) belong to the same shard. The shard is the hash of the key, modulo
+/// the number of shards. The hash does not depend on the run, hence a resource stays in the same shard
+/// when Alumet restarts (as long as the number of shards and the version of Alumet do not change).
+///
+/// Each shard is then written by its own output, which only receives the points of the shard,
+/// thanks to the filter returned by [`shard_filter`](Self::shard_filter).
+///
+/// ## Example
+/// ```no_run
+/// use alumet::pipeline::partition::PartitionTransform;
+///
+/// # fn example(alumet: &mut alumet::plugin::AlumetStart, outputs: Vec<Box<dyn alumet::pipeline::Output>>) {
+/// let transform = PartitionTransform::by_resource(outputs.len() as u64);
+/// for (shard, output) in outputs.into_iter().enumerate() {
+///     alumet.add_filtered_output(output, transform.shard_filter(shard as u64));
+/// }
+/// alumet.add_transform(Box::new(transform));
+/// # }
+/// ```
+pub struct PartitionTransform {
+    shards: u64,
+    attribute: Cow<'static, str>,
+    partition_key: Box<dyn Fn(&MeasurementPoint, &mut FxHasher) + Send>,
+}
+
+impl PartitionTransform {
+    /// Assigns the points to `shards` shards, according to their resource.
+    ///
+    /// ## Panics
+    /// Panics if `shards` is zero.
+    pub fn by_resource(shards: u64) -> Self {
+        Self::by_key(shards, |p| p.resource.clone())
+    }
+
+    /// Assigns the points to `shards` shards, according to the key returned by `partition_key`.
+    ///
+    /// ## Panics
+    /// Panics if `shards` is zero.
+    pub fn by_key<K: Hash>(shards: u64, partition_key: impl Fn(&MeasurementPoint) -> K + Send + 'static) -> Self {
+        assert!(shards > 0, "the number of shards must be non-zero");
+        Self {
+            shards,
+            attribute: Cow::Borrowed(DEFAULT_SHARD_ATTRIBUTE),
+            partition_key: Box::new(move |p, hasher| partition_key(p).hash(hasher)),
+        }
+    }
+
+    /// Changes the key of the attribute that contains the shard (by default, [`DEFAULT_SHARD_ATTRIBUTE`]).
+    pub fn with_attribute(mut self, key: impl Into<Cow<'static, str>>) -> Self {
+        self.attribute = key.into();
+        self
+    }
+
+    /// Returns the shard of the point, between 0 and the number of shards (excluded).
+    pub fn shard_of(&self, point: &MeasurementPoint) -> u64 {
+        let mut hasher = FxHasher::default();
+        (self.partition_key)(point, &mut hasher);
+        hasher.finish() % self.shards
+    }
+
+    /// Returns a filter that only accepts the points of the given shard, for
+    /// [`AlumetStart::add_filtered_output`](crate::plugin::AlumetStart::add_filtered_output).
+    ///
+    /// The filter reads the attribute set by the transform: the output must belong to the same route.
+    pub fn shard_filter(&self, shard: u64) -> impl Fn(&MeasurementPoint) -> bool + Send + Sync + 'static {
+        let attribute = self.attribute.clone();
+        move |p| {
+            p.attributes()
+                .any(|(k, v)| k == attribute && matches!(v, AttributeValue::U64(s) if *s == shard))
+        }
+    }
+}
+
+impl Transform for PartitionTransform {
+    fn apply(&mut self, measurements: &mut MeasurementBuffer) -> Result<(), TransformError> {
+        for point in measurements.iter_mut() {
+            let shard = self.shard_of(point);
+            point.set_attr(&self.attribute, AttributeValue::U64(shard));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::measurement::{MeasurementBuffer, MeasurementPoint};
+    use crate::pipeline::testing;
+    use crate::pipeline::Transform;
+    use crate::resources::Resource;
+
+    use super::PartitionTransform;
+
+    fn point(vm: &str) -> MeasurementPoint {
+        testing::point().resource(Resource::custom("vm", vm.to_owned())).build()
+    }
+
+    #[test]
+    fn partition_by_resource() {
+        let mut transform = PartitionTransform::by_resource(4);
+        let vms: Vec<String> = (0..32).map(|i| format!("vm{i}")).collect();
+        let mut buf = MeasurementBuffer::from(vms.iter().chain(&vms).map(|vm| point(vm.as_str())).collect::<Vec<_>>());
+        transform.apply(&mut buf).unwrap();
+
+        // each point matches the filter of its shard only, and the points of a resource are in the same shard
+        let filters: Vec<_> = (0..4).map(|shard| transform.shard_filter(shard)).collect();
+        let shards: Vec<usize> = buf
+            .iter()
+            .map(|p| {
+                let matching: Vec<usize> = (0..4).filter(|s| filters[*s](p)).collect();
+                assert_eq!(matching.len(), 1);
+                matching[0]
+            })
+            .collect();
+        assert_eq!(shards[..32], shards[32..]);
+        // the resources are spread over the shards
+        assert!((0..4).all(|s| shards.contains(&s)), "{shards:?}");
+    }
+}
@@ -0,0 +1,150 @@
+//! A transform that converts cumulative counters into rates, for instance an energy in joules into a power in watts.
+
+use std::collections::HashMap;
+use std::time::SystemTime;
+
+use crate::measurement::{MeasurementBuffer, MeasurementPoint, WrappedMeasurementValue};
+use crate::metrics::RawMetricId;
+
+use super::window::{series_key, SeriesKey};
+use super::{Transform, TransformError};
+
+/// A transform that replaces the points of monotonic counters by their rate of change per second.
+///
+/// For each counter, given with [`with_counter`](Self::with_counter), the transform remembers the last value
+/// and timestamp of each time series (the metric, the resource, the consumer and the attributes of the points).
+/// A new point of the series is replaced by a point of the rate metric, with the same timestamp, resource,
+/// consumer and attributes, whose value is the difference with the previous value divided by the time elapsed
+/// between them, in seconds, as a `f64`. The points of the other metrics are not modified.
+///
+/// Some points are dropped:
+/// - the first point of each series, since it has no previous value;
+/// - the point that follows a reset of the counter (its value has decreased), the next rate is computed from it;
+/// - the points whose timestamp is not after the previous point of their series.
+///
+/// The transform keeps one value per time series, including the series that disappear
+/// (e.g. the processes that have ended).
+///
+/// ## Example
+/// ```
+/// use alumet::metrics::{MetricId, TypedMetricId};
+/// use alumet::pipeline::rate::RateTransform;
+///
+/// # fn example(energy: TypedMetricId<u64>, power: TypedMetricId<f64>) {
+/// // the metrics are created by the plugin, for instance with `AlumetStart::create_metric`
+/// let transform = RateTransform::new().with_counter(energy.untyped_id(), power.untyped_id());
+/// # }
+/// ```
+#[derive(Default)]
+pub struct RateTransform {
+    /// The metric of the rates, by counter metric.
+    rate_metrics: HashMap<RawMetricId, RawMetricId>,
+    /// The last point of each series of the counters.
+    last: HashMap<SeriesKey, LastValue>,
+}
+
+struct LastValue {
+    value: WrappedMeasurementValue,
+    timestamp: SystemTime,
+}
+
+impl RateTransform {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Converts the points of the metric `counter` into points of the metric `rate`.
+    ///
+    /// The values of `rate` are always `f64`, whatever the type of the counter.
+    pub fn with_counter(mut self, counter: RawMetricId, rate: RawMetricId) -> Self {
+        self.rate_metrics.insert(counter, rate);
+        self
+    }
+
+    /// Replaces the point by its rate, if the previous point of its series allows it.
+    /// Returns `false` if the point must be dropped.
+    fn convert(&mut self, point: &mut MeasurementPoint) -> bool {
+        let Some(rate_metric) = self.rate_metrics.get(&point.metric).copied() else {
+            return true;
+        };
+        let timestamp = SystemTime::from(point.timestamp);
+        let current = LastValue {
+            value: point.value.clone(),
+            timestamp,
+        };
+        let previous = match self.last.get_mut(&series_key(point)) {
+            Some(last) if last.timestamp >= timestamp => return false,
+            Some(last) => std::mem::replace(last, current),
+            None => {
+                self.last.insert(series_key(point), current);
+                return false;
+            }
+        };
+        let delta = match (&previous.value, &point.value) {
+            (WrappedMeasurementValue::U64(prev), WrappedMeasurementValue::U64(v)) if v >= prev => (v - prev) as f64,
+            (WrappedMeasurementValue::F64(prev), WrappedMeasurementValue::F64(v)) if v >= prev => v - prev,
+            // the counter has been reset (or its type has changed): skip this interval
+            _ => return false,
+        };
+        let elapsed = timestamp.duration_since(previous.timestamp).unwrap_or_default().as_secs_f64();
+        point.metric = rate_metric;
+        point.value = WrappedMeasurementValue::F64(delta / elapsed);
+        true
+    }
+}
+
+impl Transform for RateTransform {
+    fn apply(&mut self, measurements: &mut MeasurementBuffer) -> Result<(), TransformError> {
+        measurements.retain_mut(|p| self.convert(p));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::measurement::{MeasurementBuffer, MeasurementPoint, WrappedMeasurementValue};
+    use crate::metrics::RawMetricId;
+    use crate::pipeline::{testing, Transform};
+    use crate::resources::Resource;
+
+    use super::RateTransform;
+
+    fn point(millis: u64, metric: usize, pkg: u32, value: u64) -> MeasurementPoint {
+        testing::point().millis(millis).metric(RawMetricId(metric)).package(pkg).u64(value).build()
+    }
+
+    fn rates(transform: &mut RateTransform, points: Vec<MeasurementPoint>) -> Vec<(usize, u32, f64)> {
+        let mut buf = MeasurementBuffer::from(points);
+        transform.apply(&mut buf).unwrap();
+        buf.iter()
+            .map(|p| {
+                let Resource::CpuPackage { id } = p.resource else { unreachable!() };
+                let value = match p.value {
+                    WrappedMeasurementValue::F64(x) => x,
+                    WrappedMeasurementValue::U64(n) => n as f64,
+                };
+                (p.metric.0, id, value)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn counters_to_rates() {
+        let mut transform = RateTransform::new().with_counter(RawMetricId(0), RawMetricId(1));
+        // the first observation of each series is dropped, the other metrics are kept
+        assert_eq!(
+            rates(&mut transform, vec![point(0, 0, 0, 100), point(0, 0, 1, 10), point(0, 5, 0, 7)]),
+            vec![(5, 0, 7.0)]
+        );
+        assert_eq!(
+            rates(&mut transform, vec![point(500, 0, 0, 150), point(2000, 0, 1, 20)]),
+            vec![(1, 0, 100.0), (1, 1, 5.0)]
+        );
+        // a reset is skipped, the next rate is computed from the value after the reset
+        assert_eq!(rates(&mut transform, vec![point(1000, 0, 0, 30)]), vec![]);
+        assert_eq!(rates(&mut transform, vec![point(2000, 0, 0, 60)]), vec![(1, 0, 30.0)]);
+        // a point that is not after the previous one is dropped
+        assert_eq!(rates(&mut transform, vec![point(2000, 0, 0, 90)]), vec![]);
+        assert_eq!(rates(&mut transform, vec![point(3000, 0, 0, 70)]), vec![(1, 0, 10.0)]);
+    }
+}
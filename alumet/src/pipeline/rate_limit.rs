@@ -0,0 +1,162 @@
+//! A transform that limits the number of points that reach the outputs, to protect a fragile downstream store.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::measurement::MeasurementBuffer;
+
+use super::{Transform, TransformError};
+
+/// A transform that forwards at most a given number of points per second, and drops the excess.
+///
+/// The limit is enforced with a token bucket, maintained across the buffers: each point that is forwarded
+/// consumes one token, and the tokens are refilled continuously at the maximum rate, based on the time at which
+/// the buffers are transformed. The bucket starts full.
+///
+/// ## Bursts
+/// By default, the bucket holds one second of budget: a burst of up to `max_points_per_sec` points goes through
+/// at once, but a source that flushes its measurements less often than once per second cannot send more than one
+/// second of budget per flush, even if it stays under the limit on average. Use [`with_burst`](Self::with_burst)
+/// to allow larger bursts.
+///
+/// ## Trimming
+/// When a buffer does not fit in the budget, its tail is dropped: the first points of the buffer are forwarded,
+/// in their original order. The dropped points are counted, see [`RateLimitTransform::handle`].
+///
+/// ## Example
+/// ```
+/// use alumet::pipeline::rate_limit::RateLimitTransform;
+///
+/// let transform = RateLimitTransform::new(1000.0).with_burst(5000.0);
+/// let handle = transform.handle();
+/// // add the transform to the pipeline, run it, then:
+/// assert_eq!(handle.dropped_points(), 0);
+/// ```
+pub struct RateLimitTransform {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    last_refill: Option<Instant>,
+    dropped: Arc<AtomicU64>,
+}
+
+/// Gives access to the number of points dropped by a [`RateLimitTransform`].
+#[derive(Clone)]
+pub struct RateLimitHandle {
+    dropped: Arc<AtomicU64>,
+}
+
+impl RateLimitTransform {
+    /// Creates a transform that forwards at most `max_points_per_sec` points per second.
+    ///
+    /// Panics if `max_points_per_sec` is not strictly positive.
+    pub fn new(max_points_per_sec: f64) -> Self {
+        assert!(max_points_per_sec > 0.0, "the maximum rate must be positive");
+        Self {
+            rate: max_points_per_sec,
+            burst: max_points_per_sec,
+            tokens: max_points_per_sec,
+            last_refill: None,
+            dropped: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Sets the capacity of the bucket, that is, the number of points that can be forwarded at once.
+    pub fn with_burst(mut self, max_points: f64) -> Self {
+        self.burst = max_points;
+        self.tokens = max_points;
+        self
+    }
+
+    /// Returns a handle to read the number of dropped points.
+    pub fn handle(&self) -> RateLimitHandle {
+        RateLimitHandle {
+            dropped: self.dropped.clone(),
+        }
+    }
+
+    fn apply_at(&mut self, measurements: &mut MeasurementBuffer, now: Instant) {
+        if let Some(last) = self.last_refill {
+            let elapsed = now.saturating_duration_since(last).as_secs_f64();
+            self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+        }
+        self.last_refill = Some(now);
+
+        let allowed = self.tokens.floor() as usize;
+        let len = measurements.len();
+        if len > allowed {
+            let mut n = 0;
+            measurements.retain(|_| {
+                n += 1;
+                n <= allowed
+            });
+            let n_dropped = len - allowed;
+            let total = self.dropped.fetch_add(n_dropped as u64, Ordering::Relaxed) + n_dropped as u64;
+            log::debug!("The rate limit has been reached, {n_dropped} points have been dropped ({total} so far).");
+        }
+        self.tokens -= measurements.len() as f64;
+    }
+}
+
+impl RateLimitHandle {
+    /// Returns the number of points that have been dropped because of the rate limit.
+    pub fn dropped_points(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl Transform for RateLimitTransform {
+    fn apply(&mut self, measurements: &mut MeasurementBuffer) -> Result<(), TransformError> {
+        self.apply_at(measurements, Instant::now());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::measurement::{MeasurementBuffer, WrappedMeasurementValue};
+    use crate::pipeline::testing;
+
+    use super::RateLimitTransform;
+
+    fn buffer(values: std::ops::Range<u64>) -> MeasurementBuffer {
+        MeasurementBuffer::from(values.map(|n| testing::point().u64(n).build()).collect::<Vec<_>>())
+    }
+
+    fn values(buf: &MeasurementBuffer) -> Vec<u64> {
+        buf.iter()
+            .map(|p| match p.value {
+                WrappedMeasurementValue::U64(n) => n,
+                _ => unreachable!(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn token_bucket() {
+        let mut transform = RateLimitTransform::new(10.0);
+        let handle = transform.handle();
+        let start = Instant::now();
+
+        // the bucket starts full, the tail of the buffer is dropped
+        let mut buf = buffer(0..15);
+        transform.apply_at(&mut buf, start);
+        assert_eq!(values(&buf), (0..10).collect::<Vec<_>>());
+        assert_eq!(handle.dropped_points(), 5);
+
+        // half a second later, 5 tokens have been refilled
+        let mut buf = buffer(0..8);
+        transform.apply_at(&mut buf, start + Duration::from_millis(500));
+        assert_eq!(values(&buf), (0..5).collect::<Vec<_>>());
+        assert_eq!(handle.dropped_points(), 8);
+
+        // the bucket never holds more than the burst
+        let mut buf = buffer(0..30);
+        transform.apply_at(&mut buf, start + Duration::from_secs(10));
+        assert_eq!(buf.len(), 10);
+        assert_eq!(handle.dropped_points(), 28);
+    }
+}
@@ -0,0 +1,124 @@
+//! A transform that moves the points of some metrics to other metrics, for instance to a canonical namespace.
+
+use std::collections::HashMap;
+
+use crate::measurement::MeasurementBuffer;
+use crate::metrics::{MetricRegistry, RawMetricId};
+
+use super::{Transform, TransformError};
+
+/// What to do with the points whose metric has no mapping.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnmappedMetrics {
+    /// Keep the points unchanged.
+    #[default]
+    PassThrough,
+    /// Drop the points.
+    Drop,
+}
+
+/// A transform that replaces the metric of the points, according to a mapping between metric names.
+///
+/// This is useful to merge the measurements of several instances of Alumet whose metrics have different names.
+/// Only the metric of the points is replaced: their value, timestamp, resource, consumer and attributes are kept.
+/// The names are resolved once, when the mapping is added, with the [`MetricRegistry`]
+/// (see [`AlumetStart::metrics`](crate::plugin::AlumetStart::metrics)).
+///
+/// ## Example
+/// ```
+/// use alumet::metrics::MetricRegistry;
+/// use alumet::pipeline::remap::{RemapTransform, UnmappedMetrics};
+///
+/// # fn example(registry: &MetricRegistry) {
+/// let transform = RemapTransform::new()
+///     .with_mapping(registry, "rapl_consumed_energy", "energy")
+///     .with_unmapped(UnmappedMetrics::Drop);
+/// # }
+/// ```
+#[derive(Default)]
+pub struct RemapTransform {
+    /// The target metric, by source metric.
+    mapping: HashMap<RawMetricId, RawMetricId>,
+    unmapped: UnmappedMetrics,
+}
+
+impl RemapTransform {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Moves the points of the metric named `from` to the metric named `to`.
+    ///
+    /// If one of the metrics is not in the registry, a warning is logged and the mapping is ignored.
+    /// The metrics should have the same type of values, since the values are not converted.
+    pub fn with_mapping(mut self, registry: &MetricRegistry, from: &str, to: &str) -> Self {
+        let (Some(from_id), Some(to_id)) = (registry.metrics_by_name.get(from), registry.metrics_by_name.get(to)) else {
+            log::warn!("Cannot remap metric {from} to {to}: unknown metric, the mapping is ignored.");
+            return self;
+        };
+        self.mapping.insert(*from_id, *to_id);
+        self
+    }
+
+    /// Chooses what to do with the points whose metric has no mapping.
+    ///
+    /// By default, they pass through the transform unchanged.
+    pub fn with_unmapped(mut self, unmapped: UnmappedMetrics) -> Self {
+        self.unmapped = unmapped;
+        self
+    }
+}
+
+impl Transform for RemapTransform {
+    fn apply(&mut self, measurements: &mut MeasurementBuffer) -> Result<(), TransformError> {
+        measurements.retain_mut(|point| match self.mapping.get(&point.metric) {
+            Some(target) => {
+                point.metric = *target;
+                true
+            }
+            None => self.unmapped == UnmappedMetrics::PassThrough,
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::measurement::{MeasurementBuffer, WrappedMeasurementType};
+    use crate::metrics::{MetricRegistry, RawMetricId};
+    use crate::pipeline::testing;
+    use crate::pipeline::Transform;
+    use crate::units::Unit;
+
+    use super::{RemapTransform, UnmappedMetrics};
+
+    fn register(registry: &mut MetricRegistry, name: &str) -> RawMetricId {
+        testing::register_metric(registry, name, WrappedMeasurementType::U64, Unit::Joule)
+    }
+
+    fn remap(transform: &mut RemapTransform, metrics: &[RawMetricId]) -> Vec<RawMetricId> {
+        let points = metrics.iter().map(|m| testing::point().metric(*m).build());
+        let mut buf = MeasurementBuffer::from(points.collect::<Vec<_>>());
+        transform.apply(&mut buf).unwrap();
+        buf.iter().map(|p| p.metric).collect()
+    }
+
+    #[test]
+    fn remap_metrics() {
+        let mut registry = MetricRegistry::new();
+        let a = register(&mut registry, "a_energy");
+        let b = register(&mut registry, "b_energy");
+        let energy = register(&mut registry, "energy");
+        let other = register(&mut registry, "other");
+
+        let mut transform = RemapTransform::new()
+            .with_mapping(&registry, "a_energy", "energy")
+            .with_mapping(&registry, "b_energy", "energy")
+            // unknown target: ignored
+            .with_mapping(&registry, "other", "unknown");
+        assert_eq!(remap(&mut transform, &[a, b, other]), vec![energy, energy, other]);
+
+        let mut transform = transform.with_unmapped(UnmappedMetrics::Drop);
+        assert_eq!(remap(&mut transform, &[a, other, b]), vec![energy, energy]);
+    }
+}
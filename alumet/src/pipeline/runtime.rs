@@ -1,35 +1,40 @@
 //! Implementation of the measurement pipeline.
 
 use std::collections::HashMap;
-use std::ops::BitOrAssign;
-use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::Arc;
-use std::time::Duration;
+use std::fmt;
+use std::future::Future;
+use std::ops::{BitOrAssign, Deref, DerefMut};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
 
 use anyhow::{anyhow, Context};
 
 use tokio::sync::mpsc::error::TrySendError;
 use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
-use tokio::sync::{broadcast, mpsc};
-use tokio::task::{JoinError, JoinHandle, JoinSet};
+use tokio::sync::{broadcast, mpsc, oneshot, Notify};
+use tokio::task::{AbortHandle, JoinError, JoinHandle, JoinSet};
 use tokio::time::timeout;
 use tokio::{runtime::Runtime, sync::watch};
 use tokio_util::sync::CancellationToken;
 
-use crate::measurement::Timestamp;
 use crate::metrics::{Metric, RawMetricId};
 use crate::pipeline::scoped;
-use crate::pipeline::trigger::TriggerReason;
+use crate::pipeline::trigger::{is_transient_init_error, TriggerReason};
 use crate::{
-    measurement::MeasurementBuffer,
+    measurement::{MeasurementBuffer, MeasurementPoint},
     metrics::MetricRegistry,
-    pipeline::{Output, Source},
+    pipeline::Source,
 };
 
+use super::attributes::ConstantAttributesTransform;
 use super::builder;
-use super::builder::{ConfiguredTransform, ElementType};
+use super::builder::{
+    ConfiguredTransform, ElementType, InvalidReason, OutputBuilder, OutputKind, PendingPipelineContext,
+    PipelineBuildError, SourceKind, TransformBuilder,
+};
 use super::trigger::{Trigger, TriggerSpec};
-use super::{OutputContext, PollError, TransformError, WriteError};
+use super::{Health, OutputContext, PollError, Transform, TransformError, WriteError};
 
 /// A measurement pipeline that has not been started yet.
 pub struct IdlePipeline {
@@ -45,6 +50,18 @@ pub struct IdlePipeline {
     // tokio Runtimes that execute the tasks
     pub(super) rt_normal: Runtime,
     pub(super) rt_priority: Option<Runtime>,
+    pub(super) rt_blocking: Option<Runtime>,
+    /// Number of worker threads that run with an increased scheduling priority.
+    pub(super) priority_threads: Arc<AtomicUsize>,
+    /// Prefix of the names of the worker threads, also used by the runtimes created after the start.
+    pub(super) thread_name_prefix: Option<String>,
+
+    // Enables the instrumentation of the pipeline (see `ControlHandle::stats`).
+    pub(super) instrumentation: bool,
+
+    // Capacities of the channels, used to create one channel per route.
+    pub(super) source_channel_capacity: usize,
+    pub(super) output_channel_capacity: usize,
 
     // registries
     pub(super) metrics: MetricRegistry,
@@ -52,13 +69,23 @@ pub struct IdlePipeline {
     /// Channel: source -> transforms
     pub(super) from_sources: (mpsc::Sender<MeasurementBuffer>, mpsc::Receiver<MeasurementBuffer>),
 
+    /// What to do when the channel `from_sources` is full.
+    pub(super) source_overflow_policy: SourceOverflowPolicy,
+
     /// Broadcast queue to outputs
     pub(super) to_outputs: broadcast::Sender<OutputMsg>,
+
+    /// Transforms that add the constant attributes to every measurement, before the other transforms.
+    pub(super) constant_attributes: Vec<ConstantAttributesTransform>,
+
+    /// Limit of the buffers that are sent to the outputs.
+    pub(super) buffer_size_limit: Option<BufferSizeLimit>,
 }
 
 /// A message to control the pipeline.
 enum ControlMessage {
-    Shutdown,
+    /// Shuts the pipeline down. If a timeout is given, the tasks that are still running after it are aborted.
+    Shutdown(Option<Duration>),
     AddSource {
         requested_name: String,
         plugin_name: String,
@@ -66,14 +93,67 @@ enum ControlMessage {
         trigger: TriggerSpec,
     },
     ModifySource(ElementCommand<SourceCmd>),
+    /// Polls the sources now, regardless of their trigger (only useful for manual triggers).
+    PollSourcesNow(ElementCommand<()>),
+    /// Stops the sources. The reply is sent once all the stopped sources have exited.
+    StopSources(ElementCommand<()>),
+    /// Pauses the sources, then the outputs.
+    /// The command is the optional timeout of the drain, see [`ScopedControlHandle::pause`].
+    Pause(ElementCommand<Option<Duration>>),
+    /// Resumes the outputs, then the sources.
+    Resume(ElementCommand<()>),
+    /// Stops the sources and removes them from the pipeline.
+    /// The reply is sent once all the removed sources have exited.
+    RemoveSources(ElementCommand<()>),
     ModifyTransform(ElementCommand<TransformCmd>),
+    /// Replaces a transform, see [`ControlHandle::replace_transform`].
+    ReplaceTransform {
+        selector: TransformSelector,
+        transform: Box<dyn Transform>,
+        reply: oneshot::Sender<anyhow::Result<()>>,
+    },
     ModifyOutput(ElementCommand<OutputCmd>),
+    QuerySourceStates(StateQuery),
+    QueryOutputStates(StateQuery),
+    /// Checks whether the processing stage is idle, see [`ControlHandle::wait_idle`].
+    QueryIdle(oneshot::Sender<bool>),
+    /// Measures the occupancy of the internal queues, see [`ControlHandle::channel_stats`].
+    QueryQueues(oneshot::Sender<Vec<QueueStats>>),
+    /// Aborts the tasks of the outputs, see [`ScopedControlHandle::abort_outputs`].
+    AbortOutputs(ElementCommand<()>),
+    /// Replaces the transforms and the outputs, see [`RunningPipeline::restart_processing`].
+    /// The reply is sent once the new elements have been started.
+    RestartProcessing {
+        transforms: Vec<ConfiguredTransform>,
+        outputs: Vec<builder::ConfiguredOutput>,
+        timeout: Duration,
+        reply: oneshot::Sender<()>,
+    },
+    /// Moves the elements to new runtimes, see [`RunningPipeline::rebuild_runtime`].
+    /// The reply is sent once the elements have been restarted.
+    RebuildRuntime {
+        /// The new normal runtime, if it is rebuilt.
+        rt_normal: Option<tokio::runtime::Handle>,
+        /// The new "realtime priority" runtime, if it is rebuilt.
+        rt_priority: Option<tokio::runtime::Handle>,
+        timeout: Duration,
+        reply: oneshot::Sender<()>,
+    },
+}
+
+/// A request for the state of one or multiple elements of the pipeline.
+struct StateQuery {
+    destination: MessageDestination,
+    /// Receives the name and state of each element that matches the destination.
+    reply: oneshot::Sender<Vec<(String, ElementState)>>,
 }
 
 /// A command sent to one or multiple elements of the pipeline.
 struct ElementCommand<T> {
     destination: MessageDestination,
     command: T,
+    /// Receives the number of elements that have been addressed by the command.
+    reply: oneshot::Sender<usize>,
 }
 
 /// Specifies the destination of the [`ElementCommand`].
@@ -86,28 +166,140 @@ enum MessageDestination {
     Plugin(String),
 }
 
+/// Returns an error if no source has been removed by [`ControlMessage::RemoveSources`].
+fn check_removed_sources(n: usize, destination: &MessageDestination) -> anyhow::Result<usize> {
+    match (n, destination) {
+        (0, MessageDestination::All) => Err(anyhow!("there is no source to remove")),
+        (0, MessageDestination::Plugin(plugin)) => Err(anyhow!("there is no source registered by plugin '{plugin}'")),
+        (n, _) => Ok(n),
+    }
+}
+
 /// A measurement pipeline that is currently running.
 pub struct RunningPipeline {
-    // Keep the tokio runtimes alive
-    _rt_normal: Runtime,
-    _rt_priority: Option<Runtime>,
+    // Keep the tokio runtimes alive.
+    // They are only taken by `shutdown`, in order to drop them without waiting for the blocking threads.
+    rt_normal: Option<Runtime>,
+    rt_priority: Option<Runtime>,
+    rt_blocking: Option<Runtime>,
+    /// The runtimes that have been replaced by `rebuild_runtime`, which still run the control task
+    /// and the tasks spawned by the plugins.
+    retired_runtimes: Vec<Runtime>,
 
     /// Handle to the task that handles the shutdown of the pipeline.
     ///
     /// When this task finishes, the pipeline has shut down.
-    shutdown_task_handle: Option<JoinHandle<()>>,
+    shutdown_task_handle: Option<JoinHandle<Vec<PipelineError>>>,
 
     /// Controls the pipeline.
     control_handle: ControlHandle,
+
+    /// Broadcast queue to the outputs, given to the new elements built by `restart_processing`.
+    ///
+    /// Taken before waiting for the shutdown, because the task that forwards the registrations of metrics
+    /// to the routes stops when the queue is closed.
+    to_outputs: Option<broadcast::Sender<OutputMsg>>,
+
+    /// Whether the threads of the sources have a realtime priority, at the start of the pipeline.
+    realtime_priority: RealtimePriority,
+
+    /// Number of worker threads that run with an increased scheduling priority.
+    priority_threads: Arc<AtomicUsize>,
+
+    /// Prefix of the names of the worker threads, also used by the runtimes created by `rebuild_runtime`.
+    thread_name_prefix: Option<String>,
+
+    /// Number of autonomous sources, which prevent the runtimes from being rebuilt.
+    autonomous_sources: usize,
+}
+
+/// The number of worker threads of the runtimes that are rebuilt by [`RunningPipeline::rebuild_runtime`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RuntimeParameters {
+    /// Number of worker threads of the new normal runtime, or `None` to keep the current runtime.
+    pub normal_worker_threads: Option<usize>,
+    /// Number of worker threads of the new "realtime priority" runtime, or `None` to keep the current runtime.
+    pub priority_worker_threads: Option<usize>,
+}
+
+/// A signal that shuts the pipeline down, see [`RunningPipeline::run_until_signal`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShutdownSignal {
+    /// `SIGINT`, sent by Ctrl+C. This is the only signal that is supported on every platform.
+    Interrupt,
+    /// `SIGTERM`, sent by service managers like systemd (Unix only).
+    Terminate,
+    /// `SIGHUP`, sent when the terminal is closed (Unix only).
+    Hangup,
+    /// `SIGQUIT` (Unix only).
+    Quit,
+}
+
+impl ShutdownSignal {
+    /// The signals that usually stop a daemon: `SIGINT` and `SIGTERM`.
+    pub const DEFAULT: [ShutdownSignal; 2] = [ShutdownSignal::Interrupt, ShutdownSignal::Terminate];
+}
+
+/// Waits for one of the `signals`, and returns it. Never returns if no signal can be listened to.
+async fn wait_for_signal(signals: &[ShutdownSignal]) -> ShutdownSignal {
+    let mut listeners = JoinSet::new();
+    for &signal in signals {
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{self, SignalKind};
+            let kind = match signal {
+                ShutdownSignal::Interrupt => SignalKind::interrupt(),
+                ShutdownSignal::Terminate => SignalKind::terminate(),
+                ShutdownSignal::Hangup => SignalKind::hangup(),
+                ShutdownSignal::Quit => SignalKind::quit(),
+            };
+            match unix::signal(kind) {
+                Ok(mut stream) => {
+                    listeners.spawn(async move {
+                        stream.recv().await;
+                        signal
+                    });
+                }
+                Err(e) => log::error!("Cannot listen for {signal:?}, it will not shut the pipeline down: {e}"),
+            }
+        }
+        #[cfg(not(unix))]
+        match signal {
+            ShutdownSignal::Interrupt => {
+                listeners.spawn(async move {
+                    let _ = tokio::signal::ctrl_c().await;
+                    signal
+                });
+            }
+            _ => log::warn!("{signal:?} is not supported on this platform, it will not shut the pipeline down."),
+        }
+    }
+    match listeners.join_next().await {
+        Some(Ok(signal)) => signal,
+        // No signal to wait for (the listeners do not panic).
+        _ => std::future::pending().await,
+    }
+}
+
+/// Whether the sources that require a "realtime priority" run on threads with an increased scheduling priority.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RealtimePriority {
+    /// No source requires a realtime priority.
+    NotRequired,
+    /// The priority of the threads has been increased.
+    Enabled,
+    /// The priority of the threads could not be increased (the reason is logged):
+    /// the sources that require it run at the normal priority.
+    Unavailable,
 }
 
 struct PipelineControllerState {
     /// Send a message to this channel in order to shutdown the entire pipeline.
-    global_shutdown_send: UnboundedSender<()>,
+    global_shutdown_send: UnboundedSender<Option<Duration>>,
 
     // Senders to keep the receivers alive and to send commands.
-    source_command_senders_by_plugin: HashMap<String, Vec<watch::Sender<SourceCmd>>>,
-    output_command_senders_by_plugin: HashMap<String, Vec<watch::Sender<OutputCmd>>>,
+    sources_by_plugin: HashMap<String, Vec<SourceController>>,
+    outputs_by_plugin: HashMap<String, Vec<OutputController>>,
 
     /// Currently active transforms.
     /// Note: it could be generalized to support more than 64 values,
@@ -115,6 +307,17 @@ struct PipelineControllerState {
     active_transforms: Arc<AtomicU64>,
     transforms_mask_by_plugin: HashMap<String, u64>,
 
+    /// Names of the transforms, in the order of their flags in `active_transforms`.
+    transform_names: Vec<String>,
+    /// The transforms that wait to replace the running ones.
+    transform_replacements: Arc<TransformReplacements>,
+
+    /// The internal queues of the processing stage.
+    queues: ProcessingQueues,
+
+    /// Allows to move the transforms and outputs to other runtimes.
+    processing_handover: ProcessingHandover,
+
     // Allows to shut the autonomous sources down.
     autonomous_shutdown_token: CancellationToken,
 
@@ -122,6 +325,87 @@ struct PipelineControllerState {
     modifier: PipelineModifierState,
 }
 
+impl PipelineControllerState {
+    /// Replaces the controllers of the transforms and outputs, after they have been restarted.
+    fn set_processing(&mut self, controllers: ProcessingControllers) {
+        self.outputs_by_plugin = controllers.outputs_by_plugin;
+        self.active_transforms = controllers.active_transforms;
+        self.transforms_mask_by_plugin = controllers.transforms_mask_by_plugin;
+        self.transform_names = controllers.transform_names;
+        self.transform_replacements = controllers.transform_replacements;
+        self.queues = controllers.queues;
+        self.processing_handover = controllers.handover;
+        *self.modifier.output_counters_by_plugin.lock().unwrap() = controllers.output_counters_by_plugin;
+    }
+}
+
+/// Allows the [`PipelineControllerState`] to interact with a managed source.
+struct SourceController {
+    /// Name of the source.
+    name: String,
+    /// State requested by the last command (the trigger updates are not taken into account).
+    state: ElementState,
+    /// Sends commands to the source.
+    command: watch::Sender<SourceCmd>,
+    /// Wakes the source up when its trigger is [manual](super::trigger::builder::manual).
+    poll_now: Arc<Notify>,
+    /// Name of the plugin that registered the source.
+    plugin_name: String,
+    /// The last trigger given to the source, to restart it on another runtime (see [`SourceCmd::SetPriority`]).
+    trigger: TriggerSpec,
+    /// Receives the source when its task exits to be moved to another runtime.
+    handover: oneshot::Receiver<ManagedSource>,
+    /// The source that has been moved while paused, it is restarted when it resumes.
+    parked: Option<ManagedSource>,
+}
+
+/// A source, with the state of its lifecycle, which is kept when it moves to another runtime.
+///
+/// When it is dropped, for instance because the source has stopped, [`Source::shutdown`] is called
+/// if [`Source::init`] has succeeded.
+pub(super) struct ManagedSource {
+    source: SourceKind,
+    /// `true` if [`Source::init`] has succeeded (async sources have no initialization).
+    initialized: bool,
+}
+
+impl From<SourceKind> for ManagedSource {
+    fn from(source: SourceKind) -> Self {
+        ManagedSource {
+            source,
+            initialized: false,
+        }
+    }
+}
+
+impl From<Box<dyn Source>> for ManagedSource {
+    fn from(source: Box<dyn Source>) -> Self {
+        ManagedSource::from(SourceKind::Sync(source))
+    }
+}
+
+impl Drop for ManagedSource {
+    fn drop(&mut self) {
+        if let (true, SourceKind::Sync(source)) = (self.initialized, &mut self.source) {
+            source.shutdown();
+        }
+    }
+}
+
+/// Allows the [`PipelineControllerState`] to interact with an output.
+struct OutputController {
+    /// Name of the output.
+    name: String,
+    /// Sends commands to the output.
+    command: watch::Sender<OutputCmd>,
+    /// Aborts the task of the output, see [`ScopedControlHandle::abort_outputs`].
+    abort: AbortHandle,
+    /// The counters of the output, which tell whether it has been detached.
+    counters: Arc<OutputCounters>,
+    /// Receives the output when its task exits to be moved to another runtime, see [`ProcessingHandover`].
+    handover: oneshot::Receiver<MovedOutput>,
+}
+
 /// Things necessary for modifying the pipeline at runtime,
 /// that is, adding or removing pipeline elements.
 struct PipelineModifierState {
@@ -134,8 +418,88 @@ struct PipelineModifierState {
     /// Sends measurements from Sources.
     in_tx: mpsc::Sender<MeasurementBuffer>,
 
+    /// What to do when `in_tx` is full.
+    source_overflow_policy: SourceOverflowPolicy,
+
+    /// Counts the buffers that have been dropped because `in_tx` was full.
+    dropped_source_buffers: Arc<AtomicU64>,
+
     /// Handle to the tokio runtime with "normal" threads.
     rt_normal: tokio::runtime::Handle,
+
+    /// Handle to the tokio runtime with "realtime priority" threads, if it exists.
+    rt_priority: Option<tokio::runtime::Handle>,
+
+    /// Number of worker threads that run with an increased scheduling priority.
+    priority_threads: Arc<AtomicUsize>,
+
+    /// Handle to the tokio runtime of the blocking sources, if it exists.
+    rt_blocking: Option<tokio::runtime::Handle>,
+    /// Prefix of the names of the worker threads, see [`PipelineBuilder::thread_name_prefix`](builder::PipelineBuilder::thread_name_prefix).
+    thread_name_prefix: Option<String>,
+
+    /// The runtimes that have been created after the start of the pipeline, for the new sources.
+    late_runtimes: Vec<LateRuntime>,
+
+    /// Counters of the measurements that enter the pipeline, if the instrumentation is enabled.
+    input_counters: Option<Arc<InputCounters>>,
+
+    /// Allows to restart the transforms and outputs.
+    processing: ProcessingConfig,
+
+    /// Counters of each output, shared with the [`ControlHandle`], which must see the new outputs after a restart.
+    output_counters_by_plugin: Arc<Mutex<OutputCountersByPlugin>>,
+}
+
+impl PipelineModifierState {
+    /// Returns the runtime that must run a new source with the given trigger.
+    ///
+    /// The priority and blocking runtimes are created on demand, if no source has required them before.
+    /// Unlike the runtimes created at the start of the pipeline, they have only one worker thread.
+    fn source_runtime(&mut self, trigger: &TriggerSpec) -> tokio::runtime::Handle {
+        if trigger.blocking {
+            if self.rt_blocking.is_none() {
+                match builder::new_blocking_runtime(1, self.thread_name_prefix.as_deref()) {
+                    Ok(rt) => {
+                        self.rt_blocking = Some(rt.handle().clone());
+                        self.late_runtimes.push(LateRuntime(Some(rt)));
+                    }
+                    Err(e) => log::error!("Could not create a runtime for the blocking sources, using the normal runtime: {e}"),
+                }
+            }
+            self.rt_blocking.clone().unwrap_or_else(|| self.rt_normal.clone())
+        } else if trigger.realtime_priority {
+            if self.rt_priority.is_none() {
+                // new_priority_runtime blocks the thread until the workers have started
+                let (priority_threads, prefix) = (self.priority_threads.clone(), self.thread_name_prefix.as_deref());
+                match tokio::task::block_in_place(|| builder::new_priority_runtime(1, priority_threads, prefix)) {
+                    Ok(Some(rt)) => {
+                        self.rt_priority = Some(rt.handle().clone());
+                        self.late_runtimes.push(LateRuntime(Some(rt)));
+                    }
+                    Ok(None) => log::warn!("Could not provide a \"realtime priority\" runtime for the new source, using the normal runtime (see previous warnings)."),
+                    Err(e) => log::error!("Could not create a \"realtime priority\" runtime, using the normal runtime: {e}"),
+                }
+            }
+            self.rt_priority.clone().unwrap_or_else(|| self.rt_normal.clone())
+        } else {
+            self.rt_normal.clone()
+        }
+    }
+}
+
+/// A tokio runtime that has been created after the start of the pipeline.
+///
+/// It is owned by the control task. Since a runtime cannot be dropped in an async context,
+/// it is shut down in the background when dropped.
+struct LateRuntime(Option<Runtime>);
+
+impl Drop for LateRuntime {
+    fn drop(&mut self) {
+        if let Some(rt) = self.0.take() {
+            rt.shutdown_background();
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -144,6 +508,22 @@ pub struct ControlHandle {
     ///
     /// Closed when the pipeline shuts down.
     tx: mpsc::Sender<ControlMessage>,
+
+    /// Number of measurement buffers that have been dropped by the sources.
+    dropped_source_buffers: Arc<AtomicU64>,
+
+    /// Counters of each output (lost messages, failed writes), with the name of the output.
+    /// The map is replaced when the outputs are restarted, see [`RunningPipeline::restart_processing`].
+    output_counters_by_plugin: Arc<Mutex<OutputCountersByPlugin>>,
+
+    /// Counters of the measurements that enter the pipeline, if the instrumentation is enabled.
+    input_counters: Option<Arc<InputCounters>>,
+
+    /// The last error of the tasks of each element, written by the tasks themselves.
+    last_errors: Arc<LastErrors>,
+
+    /// The metrics registered before the start of the pipeline.
+    metrics: Arc<MetricRegistry>,
 }
 
 impl IdlePipeline {
@@ -157,111 +537,181 @@ impl IdlePipeline {
 
     /// Starts the measurement pipeline.
     pub fn start(self) -> RunningPipeline {
-        // Use a JoinSet to keep track of the spawned tasks.
-        let mut source_set = JoinSet::new();
-        let mut transform_set = JoinSet::new();
-        let mut output_set = JoinSet::new();
-
         // Store the command senders in order to keep the receivers alive,
         // and to be able to send commands after the launch.
-        let mut source_command_senders_by_plugin: HashMap<_, Vec<_>> = HashMap::new();
-        let mut output_command_senders_by_plugin: HashMap<_, Vec<_>> = HashMap::new();
-        let mut transforms_mask_by_plugin: HashMap<_, u64> = HashMap::new();
+        let mut sources_by_plugin: HashMap<_, Vec<_>> = HashMap::new();
 
         // Start the tasks, starting at the end of the pipeline (to avoid filling the buffers too quickly).
         let (in_tx, in_rx) = self.from_sources;
 
-        // 1. Outputs
-        for out in self.outputs {
-            let msg_rx = self.to_outputs.subscribe();
-            let (command_tx, command_rx) = watch::channel(OutputCmd::Run);
-            let ctx = OutputContext {
-                // Each output task owns its OutputContext, which contains a copy of the MetricRegistry.
-                // This allows fast, uncontended access to the registry, and avoids a global state (no Arc<Mutex<...>>).
-                // The cost is a duplication of the registry (increased memory use) in the case where multiple outputs exist.
-                metrics: self.metrics.clone(),
-            };
-
-            // Store command_tx so that we can accept commands later (commands can target the outputs of a specific plugin).
-            output_command_senders_by_plugin
-                .entry(out.plugin_name)
-                .or_default()
-                .push(command_tx);
-
-            // Spawn the task in the JoinSet.
-            let task = run_output_from_broadcast(out.name, out.output, msg_rx, command_rx, ctx);
-            output_set.spawn_on(task, self.rt_normal.handle());
-        }
-
-        // 2. Transforms (all in the same task because they are applied one after another)
-        let active_transforms = Arc::new(AtomicU64::new(u64::MAX)); // all active by default
-        for (i, t) in self.transforms.iter().enumerate() {
-            let mask: u64 = 1 << i;
-            transforms_mask_by_plugin
-                .entry(t.plugin_name.clone())
-                .or_default()
-                .bitor_assign(mask);
-        }
-        let transforms_task = run_transforms(self.transforms, in_rx, self.to_outputs, active_transforms.clone());
-        transform_set.spawn_on(transforms_task, self.rt_normal.handle());
-
-        // 3. Managed sources
+        // Count the measurements only if the instrumentation is enabled, to avoid any overhead otherwise.
+        let input_counters = self.instrumentation.then(|| Arc::new(InputCounters::default()));
+
+        // 1 and 2. Outputs and transforms
+        let last_errors = Arc::new(LastErrors::default());
+        let processing = ProcessingConfig {
+            input: Arc::new(tokio::sync::Mutex::new(in_rx)),
+            to_outputs: self.to_outputs,
+            source_channel_capacity: self.source_channel_capacity,
+            output_channel_capacity: self.output_channel_capacity,
+            metrics: self.metrics.clone(),
+            instrumentation: self.instrumentation,
+            input_counters: input_counters.clone(),
+            constant_attributes: self.constant_attributes,
+            buffer_size_limit: self.buffer_size_limit,
+            in_flight: Arc::default(),
+            last_errors: last_errors.clone(),
+        };
+        let input = processing
+            .input
+            .clone()
+            .try_lock_owned()
+            .expect("the input of the transforms should not be used before the start of the pipeline");
+        let input = BufferReceiver::Shared(input);
+        // Store the JoinSets to be able to wait for the tasks in a specific order (see pipeline_control_task).
+        let mut join_sets = ElementJoinSets {
+            source_set: ElementSet::new(ElementType::Source, last_errors.clone()),
+            transform_set: ElementSet::new(ElementType::Transform, last_errors.clone()),
+            output_set: ElementSet::new(ElementType::Output, last_errors.clone()),
+        };
+        let ProcessingControllers {
+            outputs_by_plugin,
+            output_counters_by_plugin,
+            active_transforms,
+            transforms_mask_by_plugin,
+            transform_names,
+            transform_replacements,
+            outputs_ready,
+            queues,
+            handover,
+        } = spawn_processing(
+            self.transforms,
+            self.outputs.into_iter().map(ProcessingOutput::New).collect(),
+            input,
+            &processing,
+            &mut join_sets,
+            self.rt_normal.handle(),
+        );
+        let output_counters_by_plugin = Arc::new(Mutex::new(output_counters_by_plugin));
+        let to_outputs = processing.to_outputs.clone();
+
+        // 3. Managed sources, which do not poll before the outputs have started (see OutputsReady).
+        let realtime_priority = if !self
+            .sources
+            .iter()
+            .any(|src| src.trigger_provider.realtime_priority && !src.trigger_provider.blocking)
+        {
+            RealtimePriority::NotRequired
+        } else if self.rt_priority.is_some() {
+            RealtimePriority::Enabled
+        } else {
+            RealtimePriority::Unavailable
+        };
+        let dropped_source_buffers = Arc::new(AtomicU64::new(0));
         for src in self.sources {
-            let data_tx = in_tx.clone();
-            let runtime = match src.trigger_provider.realtime_priority {
-                true => self.rt_priority.as_ref().unwrap_or(&self.rt_normal),
-                false => &self.rt_normal,
+            let data_tx = SourceChannel::new(
+                in_tx.clone(),
+                self.source_overflow_policy,
+                dropped_source_buffers.clone(),
+                last_errors.clone(),
+            );
+            let runtime = if src.trigger_provider.blocking {
+                self.rt_blocking.as_ref().unwrap_or(&self.rt_normal)
+            } else if src.trigger_provider.realtime_priority {
+                self.rt_priority.as_ref().unwrap_or(&self.rt_normal)
+            } else {
+                &self.rt_normal
             };
+            let trigger = src.trigger_provider.clone();
             let (command_tx, command_rx) = watch::channel(SourceCmd::SetTrigger(Some(src.trigger_provider)));
-            source_command_senders_by_plugin
-                .entry(src.plugin_name)
-                .or_default()
-                .push(command_tx);
-
-            let task = run_source(src.name, src.source, data_tx, command_rx);
-            source_set.spawn_on(task, runtime.handle());
+            let poll_now = Arc::new(Notify::new());
+            let (handover_tx, handover_rx) = oneshot::channel();
+            sources_by_plugin.entry(src.plugin_name.clone()).or_default().push(SourceController {
+                name: src.name.clone(),
+                state: ElementState::Running,
+                command: command_tx,
+                poll_now: poll_now.clone(),
+                plugin_name: src.plugin_name.clone(),
+                trigger,
+                handover: handover_rx,
+                parked: None,
+            });
+
+            let task = run_source(
+                src.name.clone(),
+                src.plugin_name.clone(),
+                src.source,
+                data_tx,
+                command_rx,
+                poll_now,
+                input_counters.clone(),
+            );
+            let ready = outputs_ready.clone();
+            let task = async move {
+                ready.wait().await;
+                handover_task(task, handover_tx).await
+            };
+            join_sets.source_set.spawn_on(src.name, src.plugin_name, task, runtime.handle());
         }
 
         // 4. Autonomous sources
+        let n_autonomous_sources = self.autonomous_sources.len();
         for src in self.autonomous_sources {
+            let (name, plugin) = (src.name.clone(), src.plugin.clone());
+            let ready = outputs_ready.clone();
             let task = async move {
+                ready.wait().await;
                 src.source
                     .await
                     .map_err(|e| e.context(format!("error in autonomous source {}", src.name)))
             };
-            source_set.spawn_on(task, self.rt_normal.handle());
+            join_sets.source_set.spawn_on(name, plugin, task, self.rt_normal.handle());
         }
 
         // 5. Graceful shutdown and pipeline control.
 
         // mpsc channel for global shutdown order.
-        let (global_shutdown_send, global_shutdown_recv) = mpsc::unbounded_channel::<()>();
-
-        // Store the JoinSets to be able to wait for the tasks in a specific order (see pipeline_control_task).
-        let join_sets = ElementJoinSets {
-            source_set,
-            transform_set,
-            output_set,
-        };
+        let (global_shutdown_send, global_shutdown_recv) = mpsc::unbounded_channel::<Option<Duration>>();
 
         // Spawn a task to control the pipeline and orchestrate its shutdown.
         // Most of the state (command senders, mask of the active transforms, etc.) is moved to this task.
         let (control_tx, control_rx) = mpsc::channel::<ControlMessage>(256);
         let controller_state = PipelineControllerState {
             global_shutdown_send,
-            source_command_senders_by_plugin,
-            output_command_senders_by_plugin,
+            sources_by_plugin,
+            outputs_by_plugin,
             active_transforms,
             transforms_mask_by_plugin,
+            transform_names,
+            transform_replacements,
+            queues,
+            processing_handover: handover,
             autonomous_shutdown_token: self.autonomous_shutdown_token,
             modifier: PipelineModifierState {
                 namegen: builder::ElementNameGenerator::new(),
                 join_sets,
                 in_tx,
+                source_overflow_policy: self.source_overflow_policy,
+                dropped_source_buffers: dropped_source_buffers.clone(),
                 rt_normal: self.rt_normal.handle().clone(),
+                rt_priority: self.rt_priority.as_ref().map(|rt| rt.handle().clone()),
+                priority_threads: self.priority_threads.clone(),
+                rt_blocking: self.rt_blocking.as_ref().map(|rt| rt.handle().clone()),
+                thread_name_prefix: self.thread_name_prefix.clone(),
+                late_runtimes: Vec::new(),
+                input_counters: input_counters.clone(),
+                processing,
+                output_counters_by_plugin: output_counters_by_plugin.clone(),
             },
         };
-        let control_handle = ControlHandle { tx: control_tx };
+        let control_handle = ControlHandle {
+            tx: control_tx,
+            dropped_source_buffers,
+            output_counters_by_plugin,
+            input_counters,
+            last_errors,
+            metrics: Arc::new(self.metrics),
+        };
         let control_task_handle = self.rt_normal.spawn(pipeline_control_task(
             global_shutdown_recv,
             control_rx,
@@ -269,10 +719,17 @@ impl IdlePipeline {
         ));
 
         RunningPipeline {
-            _rt_normal: self.rt_normal,
-            _rt_priority: self.rt_priority,
+            rt_normal: Some(self.rt_normal),
+            rt_priority: self.rt_priority,
+            rt_blocking: self.rt_blocking,
+            retired_runtimes: Vec::new(),
             shutdown_task_handle: Some(control_task_handle),
             control_handle,
+            to_outputs: Some(to_outputs),
+            realtime_priority,
+            priority_threads: self.priority_threads,
+            thread_name_prefix: self.thread_name_prefix,
+            autonomous_sources: n_autonomous_sources,
         }
     }
 }
@@ -280,121 +737,984 @@ impl IdlePipeline {
 /// Stores [`JoinSet`]s for all the tasks of the pipeline
 /// that correspond to an element (source, transform, output).
 struct ElementJoinSets {
-    source_set: JoinSet<anyhow::Result<()>>,
-    transform_set: JoinSet<anyhow::Result<()>>,
-    output_set: JoinSet<anyhow::Result<()>>,
+    source_set: ElementSet,
+    transform_set: ElementSet,
+    output_set: ElementSet,
+}
+
+/// Capacity of the channel returned by [`ControlHandle::error_receiver`].
+const ERROR_EVENTS_CAPACITY: usize = 256;
+
+/// An error that has occurred in the pipeline, see [`ControlHandle::error_receiver`].
+#[derive(Debug, Clone)]
+pub struct PipelineEvent {
+    /// When the error has occurred.
+    pub timestamp: SystemTime,
+    /// The stage of the pipeline that has failed.
+    pub stage: ElementType,
+    /// The plugin of the element that has failed.
+    ///
+    /// It is empty for the transforms, since their tasks are shared by all the plugins.
+    pub plugin: String,
+    pub kind: PipelineErrorKind,
+    /// The error, formatted with its context.
+    pub error: String,
+}
+
+/// The consequence of an error, see [`PipelineEvent`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PipelineErrorKind {
+    /// The element keeps running, but some measurements may have been lost (e.g. a poll that has failed
+    /// with [`PollError::CanRetry`], or a write that has failed after all its retries).
+    Recoverable,
+    /// The element has stopped.
+    Fatal,
+}
+
+/// The last error returned by the tasks of the pipeline, see [`ControlHandle::source_last_error`],
+/// and the channel that publishes the errors, see [`ControlHandle::error_receiver`].
+///
+/// The errors are formatted when they occur, because they are also returned by the tasks.
+#[derive(Default)]
+pub(crate) struct LastErrors {
+    /// By plugin.
+    sources: Mutex<HashMap<String, String>>,
+    transforms: Mutex<Option<String>>,
+    /// By plugin.
+    outputs: Mutex<HashMap<String, String>>,
+    /// The sender of the error events, if a consumer has asked for them.
+    events: Mutex<Option<mpsc::Sender<PipelineEvent>>>,
+    /// Whether `events` is set, checked without locking: the errors are not formatted again when there is no consumer.
+    events_enabled: AtomicBool,
+}
+
+impl LastErrors {
+    /// Returns a new receiver of the error events, which replaces the previous one (if any).
+    fn subscribe(&self) -> mpsc::Receiver<PipelineEvent> {
+        let (tx, rx) = mpsc::channel(ERROR_EVENTS_CAPACITY);
+        *self.events.lock().unwrap() = Some(tx);
+        self.events_enabled.store(true, Ordering::Relaxed);
+        rx
+    }
+
+    /// Sends an error event to the consumer, if any, without waiting: the event is dropped if the channel is full.
+    fn publish(&self, stage: ElementType, plugin: &str, kind: PipelineErrorKind, error: &anyhow::Error) {
+        if !self.events_enabled.load(Ordering::Relaxed) {
+            return;
+        }
+        let mut events = self.events.lock().unwrap();
+        let Some(tx) = events.as_ref() else {
+            return;
+        };
+        let event = PipelineEvent {
+            timestamp: SystemTime::now(),
+            stage,
+            plugin: plugin.to_owned(),
+            kind,
+            error: format!("{error:#}"),
+        };
+        match tx.try_send(event) {
+            Ok(()) => (),
+            Err(TrySendError::Full(event)) => {
+                log::debug!("The consumer of the error events is too slow, an event has been dropped: {event:?}");
+            }
+            Err(TrySendError::Closed(_)) => {
+                // the receiver has been dropped, stop formatting the events
+                *events = None;
+                self.events_enabled.store(false, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn record(&self, kind: ElementType, plugin: String, error: &anyhow::Error) {
+        self.publish(kind, &plugin, PipelineErrorKind::Fatal, error);
+        let error = format!("{error:#}");
+        match kind {
+            ElementType::Source => {
+                self.sources.lock().unwrap().insert(plugin, error);
+            }
+            ElementType::Transform => *self.transforms.lock().unwrap() = Some(error),
+            ElementType::Output => {
+                self.outputs.lock().unwrap().insert(plugin, error);
+            }
+        }
+    }
+}
+
+/// A [`JoinSet`] that remembers the name of the tasks that are running,
+/// in order to report the elements that did not stop on time.
+struct ElementSet {
+    set: JoinSet<anyhow::Result<()>>,
+    running: Arc<Mutex<HashMap<u64, String>>>,
+    next_id: u64,
+    /// The kind of the elements, to record their errors in `last_errors`.
+    kind: ElementType,
+    last_errors: Arc<LastErrors>,
+}
+
+impl ElementSet {
+    fn new(kind: ElementType, last_errors: Arc<LastErrors>) -> Self {
+        Self {
+            set: JoinSet::new(),
+            running: Arc::new(Mutex::new(HashMap::new())),
+            next_id: 0,
+            kind,
+            last_errors,
+        }
+    }
+
+    /// Spawns the task of the element `name`, registered by the plugin `plugin`, on the given runtime.
+    ///
+    /// If the task fails, its error is recorded in the [`LastErrors`] before it is returned.
+    /// The transform tasks are shared by all the plugins, their `plugin` is ignored.
+    ///
+    /// Returns a handle that allows to abort this task only.
+    fn spawn_on<F>(&mut self, name: String, plugin: String, task: F, rt: &tokio::runtime::Handle) -> AbortHandle
+    where
+        F: Future<Output = anyhow::Result<()>> + Send + 'static,
+    {
+        /// Removes the task from `running` when it finishes or is aborted.
+        struct RunningGuard(u64, Arc<Mutex<HashMap<u64, String>>>);
+        impl Drop for RunningGuard {
+            fn drop(&mut self) {
+                self.1.lock().unwrap().remove(&self.0);
+            }
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.running.lock().unwrap().insert(id, name);
+        let guard = RunningGuard(id, self.running.clone());
+        let (kind, last_errors) = (self.kind, self.last_errors.clone());
+        self.set.spawn_on(
+            async move {
+                let _guard = guard;
+                let res = task.await;
+                if let Err(e) = &res {
+                    last_errors.record(kind, plugin, e);
+                }
+                res
+            },
+            rt,
+        )
+    }
+
+    async fn join_next(&mut self) -> Option<Result<anyhow::Result<()>, JoinError>> {
+        self.set.join_next().await
+    }
+
+    fn is_empty(&self) -> bool {
+        self.set.is_empty()
+    }
+
+    /// Aborts all the tasks, without waiting for them, and returns the name of the tasks that were running.
+    fn abort_all(&mut self) -> Vec<String> {
+        let mut names: Vec<String> = self.running.lock().unwrap().values().cloned().collect();
+        names.sort();
+        self.set.abort_all();
+        // Don't wait for the tasks: an Output may be stuck in a blocking call, which cannot be cancelled.
+        self.set.detach_all();
+        names
+    }
 }
 
 #[derive(Clone, Debug)]
 pub enum SourceCmd {
     Run,
     Pause,
+    /// Stops the source.
+    ///
+    /// The measurements that the source has collected since its last flush are sent downstream before it exits,
+    /// according to the [`SourceOverflowPolicy`] of the pipeline. Nothing is sent if there is none.
     Stop,
     SetTrigger(Option<TriggerSpec>),
+    /// Sends the measurements of the source downstream now, even if the source is paused.
+    Flush,
+    /// Changes the intervals of the current trigger, without replacing it.
+    ///
+    /// Only applies to time-based triggers, other triggers ignore this command.
+    SetInterval {
+        poll_interval: Duration,
+        flush_interval: Duration,
+    },
+    /// Moves the source to the runtime that matches the given type.
+    ///
+    /// A tokio task cannot be moved between runtimes: the task of the source sends its measurements downstream,
+    /// like [`Stop`](Self::Stop), then exits and gives the source back to the pipeline, which restarts it on the
+    /// other runtime with the same trigger. The changes made by [`SetInterval`](Self::SetInterval) are lost.
+    /// A paused source stays paused: it is only restarted when it resumes.
+    ///
+    /// If the "realtime priority" runtime, or the runtime of the blocking sources, does not exist yet,
+    /// it is created with one worker thread, like for [`ControlHandle::add_source`].
+    SetPriority(SourceType),
 }
 
-async fn run_source(
-    source_name: String,
-    mut source: Box<dyn Source>,
-    tx: mpsc::Sender<MeasurementBuffer>,
-    mut commands: watch::Receiver<SourceCmd>,
-) -> anyhow::Result<()> {
-    /// Takes the [`Trigger`] from the option and initializes it.
-    fn init_trigger(
-        trigger_spec: &mut Option<TriggerSpec>,
-        interrupt_signal: watch::Receiver<SourceCmd>,
-    ) -> Result<Trigger, std::io::Error> {
-        let spec = trigger_spec
-            .take()
-            .expect("invalid empty trigger in message Init(trigger)");
-        Trigger::new(spec, interrupt_signal)
+/// The kind of runtime that runs a managed source, see [`SourceCmd::SetPriority`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SourceType {
+    /// The "normal" runtime, shared with the transforms and outputs.
+    Normal,
+    /// The runtime whose threads have an increased scheduling priority,
+    /// see [`TimeTriggerBuilder::realtime_priority`](super::trigger::builder::TimeTriggerBuilder::realtime_priority).
+    RealtimePriority,
+    /// The runtime of the sources that block their thread,
+    /// see [`TimeTriggerBuilder::blocking`](super::trigger::builder::TimeTriggerBuilder::blocking).
+    Blocking,
+}
+
+impl SourceType {
+    /// Returns the kind of runtime that runs a source with this trigger.
+    fn of(trigger: &TriggerSpec) -> Self {
+        if trigger.blocking {
+            SourceType::Blocking
+        } else if trigger.realtime_priority {
+            SourceType::RealtimePriority
+        } else {
+            SourceType::Normal
+        }
     }
 
-    // the first command must be "init"
-    let mut trigger: Trigger = {
-        let signal = commands.clone();
-        let init_cmd = commands
-            .wait_for(|c| matches!(c, SourceCmd::SetTrigger(_)))
-            .await
-            .expect("watch channel must stay open during run_source");
+    /// Modifies the trigger so that the source runs on this kind of runtime.
+    fn apply(self, trigger: &mut TriggerSpec) {
+        trigger.realtime_priority = self == SourceType::RealtimePriority;
+        trigger.blocking = self == SourceType::Blocking;
+    }
+}
 
-        // cloning required to borrow opt as mut below
-        match init_cmd.clone() {
-            SourceCmd::SetTrigger(mut opt) => {
-                init_trigger(&mut opt, signal).with_context(|| format!("init_trigger failed for {source_name}"))?
-            }
-            _ => unreachable!(),
-        }
-    };
+/// How an output retries the writes that fail with a non-fatal error ([`WriteError::CanRetry`]).
+/// Also used to retry the initialization of a source trigger, see [`TriggerSpec::with_init_retry`].
+///
+/// The delay between two attempts starts at `initial_backoff` and doubles after each attempt,
+/// up to `max_backoff`. When the last attempt fails, the measurements are dropped.
+///
+/// While it waits before a retry, the output does not process its commands nor the new measurements.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Maximum number of calls to `write` for the same measurements, including the first one.
+    pub max_attempts: u32,
+    /// Delay before the first retry.
+    pub initial_backoff: Duration,
+    /// Maximum delay between two attempts.
+    pub max_backoff: Duration,
+}
 
-    // Store measurements in this buffer, and replace it every `flush_rounds` rounds.
-    // For now, we don't know how many measurements the source will produce, so we allocate 1 per round.
-    let mut buffer = MeasurementBuffer::with_capacity(trigger.config.flush_rounds);
+impl RetryPolicy {
+    /// Returns the delay to wait after the failure of the `attempt`-th attempt (starting at 1).
+    fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.initial_backoff.saturating_mul(factor).min(self.max_backoff)
+    }
+}
 
-    // main loop
-    let mut i = 1usize;
-    'run: loop {
-        // Wait for the trigger. It can return for two reasons:
-        // - "normal case": the underlying mechanism (e.g. timer) triggers <- this is the most likely case
-        // - "interrupt case": the underlying mechanism was idle (e.g. sleeping) but a new command arrived
-        let reason = trigger.next().await.with_context(|| source_name.clone())?;
+/// Stops calling an output that keeps failing, for a while.
+///
+/// When the output has dropped `failure_threshold` buffers in a row, because their writes have failed with
+/// a non-fatal error (after the retries of its [`RetryPolicy`], if any), the breaker opens: the new buffers
+/// are dropped without calling the output, and counted in [`ControlHandle::skipped_output_writes`].
+/// After `cooldown`, the next buffer is written once, without retry: if it succeeds, the breaker closes,
+/// otherwise it opens again for another `cooldown`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CircuitBreakerPolicy {
+    /// Number of consecutive failures that open the breaker. Must be at least 1.
+    pub failure_threshold: u32,
+    /// How long the writes are skipped when the breaker is open.
+    pub cooldown: Duration,
+}
 
-        let update = match reason {
-            TriggerReason::Triggered => {
-                // poll the source
-                let timestamp = Timestamp::now();
-                match source.poll(&mut buffer.as_accumulator(), timestamp) {
-                    Ok(()) => (),
-                    Err(PollError::CanRetry(e)) => {
-                        log::error!("Non-fatal error when polling {source_name} (will retry): {e:#}");
-                    }
-                    Err(PollError::Fatal(e)) => {
-                        log::error!("Fatal error when polling {source_name} (will stop running): {e:?}");
-                        return Err(e.context(format!("fatal error when polling {source_name}")));
-                    }
-                };
+/// The state of the circuit breaker of an output, see [`CircuitBreakerPolicy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitBreakerState {
+    /// The output is called normally. This is also the state of the outputs without a circuit breaker.
+    Closed,
+    /// The writes are skipped until the end of the cooldown.
+    Open,
+    /// The cooldown has ended, the next write will decide whether the breaker closes or opens again.
+    HalfOpen,
+}
 
-                // Flush the measurements, not on every round for performance reasons.
-                // This is done _after_ polling, to ensure that we poll at least once before flushing, even if flush_rounds is 1.
-                if i % trigger.config.flush_rounds == 0 {
-                    // flush and create a new buffer
+/// The state of the circuit breaker of an output, shared with the [`ControlHandle`].
+#[derive(Debug, Default)]
+struct CircuitBreaker {
+    consecutive_failures: u32,
+    /// When the breaker is open, the end of the cooldown.
+    open_until: Option<Instant>,
+}
 
-                    // Hint for the new buffer capacity, great if the number of measurements per flush doesn't change much,
-                    // which is often the case.
-                    let prev_length = buffer.len();
+impl CircuitBreaker {
+    fn state(&self) -> CircuitBreakerState {
+        match self.open_until {
+            None => CircuitBreakerState::Closed,
+            Some(t) if Instant::now() < t => CircuitBreakerState::Open,
+            Some(_) => CircuitBreakerState::HalfOpen,
+        }
+    }
+}
 
-                    buffer = match tx.try_send(buffer) {
-                        Ok(()) => {
-                            // buffer has been sent, create a new one
-                            log::debug!("{source_name} flushed {prev_length} measurements");
-                            MeasurementBuffer::with_capacity(prev_length)
-                        }
-                        Err(TrySendError::Closed(_buf)) => {
-                            // the channel Receiver has been closed
-                            panic!("source channel should stay open");
-                        }
-                        Err(TrySendError::Full(_buf)) => {
-                            // the channel's buffer is full! reduce the measurement frequency
-                            // TODO it would be better to choose which source to slow down based
-                            // on its frequency and number of measurements per poll.
-                            // buf
-                            todo!("buffer is full")
-                        }
-                    };
-                }
+/// What a source does when its poll fails with a non-fatal error ([`PollError::CanRetry`]).
+///
+/// A [`PollError::Fatal`] always stops the source. The policy is set on the trigger of the source,
+/// see [`TriggerSpec::with_poll_error_policy`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum PollErrorPolicy {
+    /// Log the error and poll the source again at the next tick of its trigger.
+    ///
+    /// The points that the failed poll has pushed before returning the error are kept.
+    #[default]
+    Retry,
+    /// Log the error, discard the points that the failed poll has pushed, and poll the source again at the next tick.
+    SkipRound,
+    /// Log the error and skip the ticks of the trigger for a while, to let the polled device recover.
+    ///
+    /// The delay starts at `initial` and doubles after each consecutive failure, up to `max`.
+    /// The commands are still applied while the source waits, and a successful poll resets the delay.
+    Backoff { initial: Duration, max: Duration },
+    /// Stop the source, as if the error was fatal.
+    Abort,
+}
 
-                // only update on some rounds, for performance reasons.
-                let update = (i % trigger.config.update_rounds) == 0;
+/// What to do when a transform fails with a fatal error ([`TransformError::Fatal`]).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TransformErrorPolicy {
+    /// Stop the transform task: the outputs of its route no longer receive measurements.
+    #[default]
+    Abort,
+    /// Log the error and apply the next transforms, to the measurements in their current state.
+    ///
+    /// This is suitable for non-critical transforms, like the ones that enrich the measurements.
+    SkipAndContinue,
+}
 
-                // increase i
-                i = i.wrapping_add(1);
+/// The counters of each output, with the name of the output, by plugin.
+type OutputCountersByPlugin = HashMap<String, Vec<(String, Arc<OutputCounters>)>>;
+
+/// Counters updated by an output task, and read through the [`ControlHandle`].
+#[derive(Debug, Default)]
+struct OutputCounters {
+    /// Number of messages lost because the output was lagging behind.
+    lost_messages: AtomicU64,
+    /// Number of buffers dropped because the output failed to write them.
+    failed_writes: AtomicU64,
+    /// Number of buffers written by the output, only counted if the instrumentation is enabled.
+    written_buffers: Option<AtomicU64>,
+    /// Where the latency of the writes is recorded, only if the instrumentation is enabled.
+    latency: Option<Arc<LatencyHistogram>>,
+    /// Number of messages that the processing stage is handling, shared by all its tasks.
+    in_flight: Arc<InFlight>,
+    /// Whether the output has been detached because it was too slow, see [`SlowOutputPolicy::Detach`].
+    detached: AtomicBool,
+    /// Number of buffers dropped without calling the output, because its circuit breaker was open.
+    skipped_writes: AtomicU64,
+    breaker: Mutex<CircuitBreaker>,
+    /// The result of the last health check of the output, see [`Output::health`](super::Output::health).
+    health: Mutex<Health>,
+    /// Number of buffers received by the output, to write one of every `k` buffers (see `ConfiguredOutput::sampling`).
+    received_buffers: AtomicU64,
+    /// Number of buffers, and of points, that the sampling has dropped.
+    sampled_out_buffers: AtomicU64,
+    sampled_out_points: AtomicU64,
+    /// Where the output publishes the errors that it recovers from, see [`ControlHandle::error_receiver`].
+    errors: Arc<LastErrors>,
+}
 
-                update
-            }
+/// Counters of the measurements that enter the pipeline, only used if the instrumentation is enabled.
+#[derive(Debug, Default)]
+struct InputCounters {
+    /// Number of buffers received from the sources.
+    buffers: AtomicU64,
+    /// Number of points in these buffers.
+    points: AtomicU64,
+    /// Number of times that a source has overrun its poll interval, see [`OVERRUN_ROUNDS`].
+    poll_overruns: AtomicU64,
+    /// Latency between the poll of the measurements and their write, shared by all the outputs.
+    latency: Arc<LatencyHistogram>,
+}
+
+impl InputCounters {
+    fn count(&self, measurements: &MeasurementBuffer) {
+        self.buffers.fetch_add(1, Ordering::Relaxed);
+        self.points.fetch_add(measurements.len() as u64, Ordering::Relaxed);
+    }
+}
+
+/// Statistics about the measurements that have gone through the pipeline since its start.
+///
+/// See [`ControlHandle::stats`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PipelineStats {
+    /// Number of measurement buffers that the pipeline received from the sources.
+    pub buffers_in: u64,
+    /// Number of measurement points in these buffers.
+    pub points_in: u64,
+    /// Number of measurement buffers written by each output, by output name.
+    pub buffers_written: HashMap<String, u64>,
+    /// Number of times that a source has been slower than its poll interval for several consecutive polls.
+    ///
+    /// A source that overruns its interval falls behind: its trigger fires back-to-back.
+    /// This is often caused by a source that blocks, for instance on a slow IO operation.
+    pub poll_overruns: u64,
+}
+
+/// The occupancy of the internal queues of the pipeline, and the messages that they have lost.
+///
+/// See [`ControlHandle::channel_stats`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChannelStats {
+    /// The queues, in the order of the pipeline: from the sources to the outputs.
+    pub queues: Vec<QueueStats>,
+    /// Number of measurement buffers that have been dropped because the queue of the sources was full,
+    /// see [`SourceOverflowPolicy`].
+    pub dropped_source_buffers: u64,
+    /// Number of messages that each output has lost because it lagged behind its queue, by output name.
+    pub lost_output_messages: HashMap<String, u64>,
+}
+
+/// The occupancy of an internal queue of the pipeline, see [`ChannelStats`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueueStats {
+    /// Name of the queue.
+    ///
+    /// - `sources`: from the sources to the transforms;
+    /// - `transforms (<route>)`: from the sources to the transforms of a route, if there are multiple routes;
+    /// - `outputs`: from the transforms to the outputs;
+    /// - `outputs (<route>)`: from the transforms of a route to its outputs, if there are multiple routes.
+    pub name: String,
+    /// Number of messages in the queue.
+    ///
+    /// For the queues of the outputs, which are broadcast to every output, this is the number of messages
+    /// that the slowest output has not received yet.
+    pub len: usize,
+    /// Maximum number of messages in the queue. When the queue of the outputs is full, the slowest output
+    /// loses its oldest message (see [`ChannelStats::lost_output_messages`]) or the transforms wait,
+    /// according to the [`SlowOutputPolicy`] of the outputs.
+    pub capacity: usize,
+}
+
+/// Number of buckets of a [`LatencyHistogram`].
+const LATENCY_BUCKETS: usize = 32;
+
+/// A histogram of the latencies between the poll of the measurements and their write by an output.
+///
+/// The bucket `i` counts the latencies in `[2^(i-1), 2^i)` microseconds (the first one counts the latencies below
+/// one microsecond), and the last bucket also counts all the larger latencies.
+#[derive(Debug, Default)]
+struct LatencyHistogram {
+    buckets: [AtomicU64; LATENCY_BUCKETS],
+    total_nanos: AtomicU64,
+    max_nanos: AtomicU64,
+}
+
+impl LatencyHistogram {
+    fn record(&self, latency: Duration) {
+        let micros = latency.as_micros();
+        let bucket = ((u128::BITS - micros.leading_zeros()) as usize).min(LATENCY_BUCKETS - 1);
+        let nanos = u64::try_from(latency.as_nanos()).unwrap_or(u64::MAX);
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.total_nanos.fetch_add(nanos, Ordering::Relaxed);
+        self.max_nanos.fetch_max(nanos, Ordering::Relaxed);
+    }
+
+    fn stats(&self) -> LatencyStats {
+        let buckets: Vec<(Duration, u64)> = self
+            .buckets
+            .iter()
+            .enumerate()
+            .map(|(i, n)| {
+                let upper_bound = if i == LATENCY_BUCKETS - 1 {
+                    Duration::MAX
+                } else {
+                    Duration::from_micros(1 << i)
+                };
+                (upper_bound, n.load(Ordering::Relaxed))
+            })
+            .collect();
+        let count = buckets.iter().map(|(_, n)| n).sum();
+        let mean = match count {
+            0 => Duration::ZERO,
+            n => Duration::from_nanos(self.total_nanos.load(Ordering::Relaxed) / n),
+        };
+        LatencyStats {
+            count,
+            mean,
+            max: Duration::from_nanos(self.max_nanos.load(Ordering::Relaxed)),
+            buckets,
+        }
+    }
+}
+
+/// Statistics about the latency between the poll of the measurements by the sources and their write by the outputs.
+///
+/// The latency of a buffer is measured from the first poll of the source that contributes to it, with a monotonic
+/// clock: it does not depend on the timestamps of the points. It includes the time spent in the transforms, in the
+/// queues and in the output (including the retries), and is recorded once per successful write.
+/// Since a buffer is flushed every `flush_rounds` polls, its latency includes the time spent waiting for the flush.
+///
+/// Only the measurements of the managed sources are taken into account, autonomous sources are not.
+///
+/// See [`ControlHandle::latency_stats`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LatencyStats {
+    /// Number of writes whose latency has been recorded.
+    pub count: u64,
+    /// Average latency of the writes.
+    pub mean: Duration,
+    /// Maximum latency of the writes.
+    pub max: Duration,
+    /// Number of writes in each bucket of the histogram, with the (exclusive) upper bound of the bucket.
+    ///
+    /// The upper bounds are powers of two of microseconds, except for the last bucket, which has no upper bound.
+    pub buckets: Vec<(Duration, u64)>,
+}
+
+impl LatencyStats {
+    /// Returns an upper bound of the `q`-quantile of the latency (e.g. `0.99` for the 99th percentile),
+    /// or `None` if no latency has been recorded.
+    ///
+    /// The result is the upper bound of the bucket that contains the quantile, hence it is only a rough estimate.
+    pub fn quantile(&self, q: f64) -> Option<Duration> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = ((q.clamp(0.0, 1.0) * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        self.buckets.iter().find_map(|(upper_bound, n)| {
+            seen += n;
+            (seen >= rank).then_some(*upper_bound)
+        })
+    }
+}
+
+/// A limit on the number of measurement points per buffer, enforced before the buffers are sent to the outputs.
+///
+/// Every output gets its own copy of each buffer: an enormous buffer, produced by a misbehaving source,
+/// can exhaust the memory of a small machine.
+/// See [`PipelineBuilder::max_buffer_size`](super::builder::PipelineBuilder::max_buffer_size).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BufferSizeLimit {
+    /// The maximum number of points of a buffer.
+    pub max_points: usize,
+    /// What to do with the buffers that contain more points.
+    pub policy: OversizedBufferPolicy,
+}
+
+/// What to do with a buffer that exceeds the [`BufferSizeLimit`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OversizedBufferPolicy {
+    /// Split the buffer into multiple buffers, which are sent one after another. No measurement is lost.
+    #[default]
+    Split,
+    /// Keep the first points of the buffer, and drop the others.
+    Truncate,
+}
+
+impl BufferSizeLimit {
+    /// Applies the limit to a buffer that exceeds it, and returns the buffers to send.
+    fn apply(&self, mut measurements: MeasurementBuffer) -> Vec<MeasurementBuffer> {
+        let len = measurements.len();
+        let max = self.max_points;
+        match self.policy {
+            OversizedBufferPolicy::Split => {
+                let chunks = measurements.split_into_chunks(max);
+                log::warn!(
+                    "A buffer of {len} points exceeds the limit of {max} points, it has been split into {} buffers.",
+                    chunks.len()
+                );
+                chunks
+            }
+            OversizedBufferPolicy::Truncate => {
+                measurements.truncate(max);
+                log::warn!(
+                    "A buffer of {len} points exceeds the limit of {max} points, {} points have been dropped.",
+                    len - max
+                );
+                vec![measurements]
+            }
+        }
+    }
+}
+
+/// What a source should do when the channel that connects it to the transforms is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SourceOverflowPolicy {
+    /// Wait for the channel to have some free space.
+    ///
+    /// The source is not polled while waiting, which can delay its next measurements.
+    Block,
+    /// Keep the new buffer and drop the oldest buffer that has not been sent yet.
+    ///
+    /// The source keeps at most one buffer on the side: if it is still impossible
+    /// to send it at the next flush, it is dropped and replaced by the new buffer.
+    DropOldest,
+    /// Drop the new buffer.
+    #[default]
+    DropNewest,
+}
+
+/// Sending half of the channel that connects a source to the transforms.
+pub(crate) struct SourceChannel {
+    tx: mpsc::Sender<MeasurementBuffer>,
+    policy: SourceOverflowPolicy,
+    /// Buffer that could not be sent because the channel was full (only used with [`SourceOverflowPolicy::DropOldest`]).
+    pending: Option<MeasurementBuffer>,
+    /// Counts the buffers that have been dropped, shared with the [`ControlHandle`].
+    dropped_buffers: Arc<AtomicU64>,
+    /// Where the source publishes the errors that it recovers from, see [`ControlHandle::error_receiver`].
+    errors: Arc<LastErrors>,
+}
+
+impl SourceChannel {
+    pub fn new(
+        tx: mpsc::Sender<MeasurementBuffer>,
+        policy: SourceOverflowPolicy,
+        dropped_buffers: Arc<AtomicU64>,
+        errors: Arc<LastErrors>,
+    ) -> Self {
+        Self {
+            tx,
+            policy,
+            pending: None,
+            dropped_buffers,
+            errors,
+        }
+    }
+
+    /// Sends the measurements to the transforms, applying the overflow policy if the channel is full.
+    ///
+    /// Only fails if the channel has been closed.
+    pub async fn send(&mut self, buffer: MeasurementBuffer, source_name: &str) -> anyhow::Result<()> {
+        match self.policy {
+            SourceOverflowPolicy::Block => self.tx.send(buffer).await.map_err(|_| Self::closed_error()),
+            SourceOverflowPolicy::DropNewest => match self.tx.try_send(buffer) {
+                Ok(()) => Ok(()),
+                Err(TrySendError::Full(_buf)) => {
+                    self.count_dropped(source_name);
+                    Ok(())
+                }
+                Err(TrySendError::Closed(_buf)) => Err(Self::closed_error()),
+            },
+            SourceOverflowPolicy::DropOldest => {
+                // Send the oldest buffer first, to preserve the order of the measurements.
+                if let Some(pending) = self.pending.take() {
+                    match self.tx.try_send(pending) {
+                        Ok(()) => (),
+                        Err(TrySendError::Full(_oldest)) => {
+                            // still full: drop the oldest buffer and keep the newest one for later
+                            self.count_dropped(source_name);
+                            self.pending = Some(buffer);
+                            return Ok(());
+                        }
+                        Err(TrySendError::Closed(_buf)) => return Err(Self::closed_error()),
+                    }
+                }
+                match self.tx.try_send(buffer) {
+                    Ok(()) => Ok(()),
+                    Err(TrySendError::Full(buf)) => {
+                        self.pending = Some(buf);
+                        Ok(())
+                    }
+                    Err(TrySendError::Closed(_buf)) => Err(Self::closed_error()),
+                }
+            }
+        }
+    }
+
+    /// Tries to send the buffer that is waiting for some free space in the channel, if any.
+    ///
+    /// If the channel is still full, the buffer is dropped.
+    /// This should be called before stopping the source.
+    pub fn flush_pending(&mut self, source_name: &str) {
+        if let Some(pending) = self.pending.take() {
+            if self.tx.try_send(pending).is_err() {
+                self.count_dropped(source_name);
+            }
+        }
+    }
+
+    fn count_dropped(&self, source_name: &str) {
+        let n = self.dropped_buffers.fetch_add(1, Ordering::Relaxed) + 1;
+        log::warn!("The channel connected to the transforms is full, {source_name} dropped a measurement buffer ({n} buffers dropped by the sources so far).");
+    }
+
+    fn closed_error() -> anyhow::Error {
+        anyhow!("the channel connected to the transforms has been closed")
+    }
+}
+
+/// Number of consecutive polls that must overrun the poll interval before the source is reported as too slow.
+const OVERRUN_ROUNDS: u32 = 3;
+
+/// Number of consecutive temporary failures of a trigger after which the source stops.
+const MAX_TRIGGER_ERRORS: u32 = 3;
+
+/// Maximum number of points that are allocated in advance in the buffer of a source.
+///
+/// The capacity comes from [`Source::points_per_poll_hint`], which must not be able to exhaust the memory
+/// (or overflow) with an oversized hint. A source that produces more points still works: its buffer grows.
+pub(super) const MAX_PREALLOCATED_POINTS: usize = 65_536;
+
+/// Returns the capacity to allocate for `rounds` polls of `points_per_poll` points.
+fn preallocated_capacity(rounds: usize, points_per_poll: usize) -> usize {
+    rounds.saturating_mul(points_per_poll).min(MAX_PREALLOCATED_POINTS)
+}
+
+/// Runs a managed source until it stops.
+///
+/// Returns the source if it must be moved to another runtime, see [`SourceCmd::SetPriority`].
+pub(super) async fn run_source(
+    source_name: String,
+    plugin_name: String,
+    source: impl Into<ManagedSource>,
+    mut tx: SourceChannel,
+    mut commands: watch::Receiver<SourceCmd>,
+    poll_now: Arc<Notify>,
+    input_counters: Option<Arc<InputCounters>>,
+) -> anyhow::Result<Option<ManagedSource>> {
+    /// Takes the [`Trigger`] from the option and initializes it.
+    ///
+    /// If the spec has an `init_retry` policy, the transient failures are retried with a backoff.
+    async fn init_trigger(
+        trigger_spec: &mut Option<TriggerSpec>,
+        interrupt_signal: watch::Receiver<SourceCmd>,
+        poll_now: &Arc<Notify>,
+        source_name: &str,
+    ) -> Result<Trigger, std::io::Error> {
+        let spec = trigger_spec
+            .take()
+            .expect("invalid empty trigger in message Init(trigger)");
+        let mut attempt = 1;
+        loop {
+            match Trigger::new(spec.clone(), interrupt_signal.clone(), poll_now.clone()) {
+                Ok(trigger) => return Ok(trigger),
+                Err(e) => match &spec.init_retry {
+                    Some(policy) if attempt < policy.max_attempts && is_transient_init_error(&e) => {
+                        let backoff = policy.backoff(attempt);
+                        log::warn!("Failed to initialize the trigger of {source_name} (attempt {attempt}/{}, retrying in {backoff:?}): {e}", policy.max_attempts);
+                        tokio::time::sleep(backoff).await;
+                        attempt += 1;
+                    }
+                    _ => return Err(e),
+                },
+            }
+        }
+    }
+
+    let mut source: ManagedSource = source.into();
+
+    // the first command must be "init"
+    let mut trigger: Trigger = {
+        let signal = commands.clone();
+        // cloning required to borrow opt as mut below, and to release the lock of the channel before awaiting
+        let init_cmd = commands
+            .wait_for(|c| matches!(c, SourceCmd::SetTrigger(_)))
+            .await
+            .expect("watch channel must stay open during run_source")
+            .clone();
+
+        match init_cmd {
+            SourceCmd::SetTrigger(mut opt) => init_trigger(&mut opt, signal, &poll_now, &source_name)
+                .await
+                .with_context(|| format!("init_trigger failed for {source_name} (plugin {plugin_name})"))?,
+            _ => unreachable!(),
+        }
+    };
+
+    // Store measurements in this buffer, and replace it every `flush_rounds` rounds.
+    // The buffer is moved to the transforms at each flush, its allocation cannot be reused. Instead, each new buffer
+    // is allocated with the capacity that the source needs, according to the length of the previous buffer and
+    // to the hint of the source (or 1 point per round, if it has none).
+    let points_per_poll = match &source.source {
+        SourceKind::Sync(source) => source.points_per_poll_hint(),
+        SourceKind::Async(_) => None,
+    }
+    .unwrap_or(1);
+    let capacity = preallocated_capacity(trigger.config.flush_rounds, points_per_poll);
+    let mut buffer = MeasurementBuffer::with_capacity(capacity);
+
+    // Number of consecutive polls that took longer than the poll interval.
+    let mut overrun_rounds = 0u32;
+    // Number of consecutive temporary failures of the trigger.
+    let mut trigger_errors = 0u32;
+    // Number of consecutive non-fatal failures of the poll, and when to poll again (see PollErrorPolicy::Backoff).
+    let mut poll_errors = 0u32;
+    let mut backoff_until: Option<Instant> = None;
+    // Number of polls since the trigger has been set, to stop after `max_polls` (if any).
+    let mut polls = 0usize;
+    // When the source has been polled for the last time, to coalesce the ticks (see TriggerConfig::min_interval).
+    let mut last_poll: Option<Instant> = None;
+
+    // main loop
+    let mut i = 1usize;
+    'run: loop {
+        // Wait for the trigger. It can return for two reasons:
+        // - "normal case": the underlying mechanism (e.g. timer) triggers <- this is the most likely case
+        // - "interrupt case": the underlying mechanism was idle (e.g. sleeping) but a new command arrived,
+        //   which is applied right away, without waiting for the end of the flush or update rounds
+        // If the trigger has a minimum interval, the ticks that occur too soon after the last poll are coalesced.
+        let not_before = trigger.config.min_interval.zip(last_poll).map(|(min, last)| last + min);
+        let reason = match trigger.next_not_before(not_before).await {
+            Ok(reason) => {
+                trigger_errors = 0;
+                reason
+            }
+            Err(PollError::CanRetry(e)) if trigger_errors < MAX_TRIGGER_ERRORS => {
+                // e.g. the timer has been disturbed by the suspension of the VM, wait for its next tick
+                trigger_errors += 1;
+                log::warn!("The trigger of {source_name} (plugin {plugin_name}) has failed, waiting for its next tick: {e:#}");
+                continue 'run;
+            }
+            Err(PollError::CanRetry(e) | PollError::Fatal(e)) => {
+                return Err(e.context(format!("the trigger of {source_name} (plugin {plugin_name}) has failed")));
+            }
+        };
+
+        let update = match reason {
+            TriggerReason::Triggered if backoff_until.is_some_and(|t| Instant::now() < t) => {
+                // the source is backing off after a failed poll: skip this tick, but keep applying the commands
+                true
+            }
+            TriggerReason::Triggered => {
+                // initialize the source before its first poll (it is kept initialized when it moves to another runtime)
+                if !source.initialized {
+                    let init_res = match &mut source.source {
+                        SourceKind::Sync(source) => source.init(),
+                        SourceKind::Async(_) => Ok(()),
+                    };
+                    match init_res {
+                        Ok(()) => source.initialized = true,
+                        Err(PollError::CanRetry(e)) => {
+                            log::error!("Non-fatal error when initializing {source_name} (plugin {plugin_name}, will retry at the next poll): {e:#}");
+                            tx.errors.publish(ElementType::Source, &plugin_name, PipelineErrorKind::Recoverable, &e);
+                            continue 'run;
+                        }
+                        Err(PollError::Fatal(e)) => {
+                            log::error!("Fatal error when initializing {source_name} (plugin {plugin_name}, will stop running): {e:?}");
+                            let context = format!("fatal error when initializing {source_name} (plugin {plugin_name})");
+                            return Err(e.context(context));
+                        }
+                    }
+                }
+
+                // poll the source, with the time at which the trigger has fired
+                let timestamp = trigger.fired_at();
+                // measure the duration of the poll only if the instrumentation is enabled
+                let poll_start = input_counters.as_ref().map(|_| Instant::now());
+                // the latency of the buffer is measured from its first poll
+                if buffer.ingested_at().is_none() {
+                    buffer.set_ingested_at(poll_start);
+                }
+                let len_before_poll = buffer.len();
+                last_poll = Some(Instant::now());
+                let poll_res = match &mut source.source {
+                    SourceKind::Sync(source) => source.poll(&mut buffer.as_accumulator(), timestamp),
+                    // the future is awaited directly, it must not block the thread
+                    SourceKind::Async(source) => source.poll_async(&mut buffer.as_accumulator(), timestamp).await,
+                };
+                match poll_res {
+                    Ok(()) => {
+                        poll_errors = 0;
+                        backoff_until = None;
+                    }
+                    Err(PollError::CanRetry(e)) => {
+                        poll_errors = poll_errors.saturating_add(1);
+                        if trigger.config.poll_error_policy != PollErrorPolicy::Abort {
+                            tx.errors.publish(ElementType::Source, &plugin_name, PipelineErrorKind::Recoverable, &e);
+                        }
+                        match &trigger.config.poll_error_policy {
+                            PollErrorPolicy::Retry => {
+                                log::error!("Non-fatal error when polling {source_name} (plugin {plugin_name}, will retry): {e:#}");
+                            }
+                            PollErrorPolicy::SkipRound => {
+                                buffer.truncate(len_before_poll);
+                                log::error!("Non-fatal error when polling {source_name} (plugin {plugin_name}, its measurements are discarded, will retry): {e:#}");
+                            }
+                            PollErrorPolicy::Backoff { initial, max } => {
+                                let factor = 2u32.saturating_pow(poll_errors - 1);
+                                let delay = initial.saturating_mul(factor).min(*max);
+                                backoff_until = Some(Instant::now() + delay);
+                                log::error!("Non-fatal error when polling {source_name} (plugin {plugin_name}, will retry in {delay:?}): {e:#}");
+                            }
+                            PollErrorPolicy::Abort => {
+                                log::error!("Error when polling {source_name} (plugin {plugin_name}, will stop running): {e:?}");
+                                let context = format!("error when polling {source_name} (plugin {plugin_name})");
+                                return Err(e.context(context));
+                            }
+                        }
+                    }
+                    Err(PollError::Fatal(e)) => {
+                        log::error!("Fatal error when polling {source_name} (plugin {plugin_name}, will stop running): {e:?}");
+                        return Err(e.context(format!("fatal error when polling {source_name} (plugin {plugin_name})")));
+                    }
+                };
+                if let (Some(start), Some(counters)) = (poll_start, &input_counters) {
+                    if let Some(poll_interval) = trigger.poll_interval() {
+                        let poll_duration = start.elapsed();
+                        if poll_duration > poll_interval {
+                            overrun_rounds += 1;
+                            if overrun_rounds == OVERRUN_ROUNDS {
+                                log::warn!("{source_name} is too slow: its last {OVERRUN_ROUNDS} polls took longer than its poll interval ({poll_interval:?}), the last one took {poll_duration:?}. Does it block?");
+                                counters.poll_overruns.fetch_add(1, Ordering::Relaxed);
+                            }
+                        } else {
+                            overrun_rounds = 0;
+                        }
+                    }
+                }
+
+                // Flush the measurements, not on every round for performance reasons.
+                // This is done _after_ polling, to ensure that we poll at least once before flushing, even if flush_rounds is 1.
+                if i % trigger.config.flush_rounds == 0 {
+                    // flush and create a new buffer
+
+                    // Hint for the new buffer capacity, great if the number of measurements per flush doesn't change much,
+                    // which is often the case.
+                    let prev_length = buffer.len();
+                    let capacity = prev_length.max(preallocated_capacity(trigger.config.flush_rounds, points_per_poll));
+
+                    // If the channel is full, the overflow policy decides what happens to the buffer.
+                    // TODO it would be better to choose which source to slow down based
+                    // on its frequency and number of measurements per poll.
+                    tx.send(buffer, &source_name)
+                        .await
+                        .with_context(|| format!("{source_name} could not flush its measurements"))?;
+                    log::debug!("{source_name} flushed {prev_length} measurements");
+                    buffer = MeasurementBuffer::with_capacity(capacity);
+                }
+
+                // stop by itself after the last poll, if the trigger has a limit
+                polls += 1;
+                if trigger.config.max_polls.is_some_and(|max| polls >= max) {
+                    if !buffer.is_empty() {
+                        tx.send(buffer, &source_name).await.with_context(|| {
+                            format!("{source_name} failed to flush its measurements after its last poll")
+                        })?;
+                    }
+                    tx.flush_pending(&source_name);
+                    log::info!("{source_name} (plugin {plugin_name}) has been polled {polls} times, it stops.");
+                    break 'run;
+                }
+
+                // only update on some rounds, for performance reasons.
+                let update = (i % trigger.config.update_rounds) == 0;
+
+                // increase i
+                i = i.wrapping_add(1);
+
+                update
+            }
             TriggerReason::Interrupted => {
                 // interrupted because of a new command, forcibly update the command (see below)
                 true
             }
+            TriggerReason::Finished => {
+                // the trigger has no more events (see TriggerAction::Stop): flush now, then stop
+                if !buffer.is_empty() {
+                    tx.send(buffer, &source_name).await.with_context(|| {
+                        format!("{source_name} failed to flush its measurements after the end of its trigger")
+                    })?;
+                }
+                tx.flush_pending(&source_name);
+                log::info!("The trigger of {source_name} (plugin {plugin_name}) has finished, the source stops.");
+                break 'run;
+            }
         };
 
         if update {
@@ -413,39 +1733,92 @@ async fn run_source(
                 let mut cmd = cmd;
                 let mut paused = false;
                 'pause: loop {
-                    log::trace!("{source_name} received {cmd:?}");
+                    // the plugin is a structured field, for the loggers that filter or index the records by plugin
+                    log::debug!(
+                        source = source_name.as_str(), plugin = plugin_name.as_str();
+                        "{source_name} received {cmd:?}"
+                    );
                     match cmd {
                         SourceCmd::Run => break 'pause,
                         SourceCmd::Pause => paused = true,
                         SourceCmd::Stop => {
                             // flush now, then stop
                             if !buffer.is_empty() {
-                                tx.try_send(buffer)
-                                    .expect("failed to flush measurements after receiving SourceCmd::Stop");
+                                tx.send(buffer, &source_name).await.with_context(|| {
+                                    format!("{source_name} failed to flush its measurements after receiving SourceCmd::Stop")
+                                })?;
                             }
+                            tx.flush_pending(&source_name);
+                            // the source is shut down when it is dropped, at the end of the task
                             break 'run;
                         }
+                        SourceCmd::SetPriority(_) => {
+                            // flush now, then give the source back to the pipeline, which restarts it elsewhere
+                            if !buffer.is_empty() {
+                                tx.send(buffer, &source_name).await.with_context(|| {
+                                    format!("{source_name} failed to flush its measurements before moving to another runtime")
+                                })?;
+                            }
+                            tx.flush_pending(&source_name);
+                            return Ok(Some(source));
+                        }
                         SourceCmd::SetTrigger(mut opt) => {
                             let prev_flush_rounds = trigger.config.flush_rounds;
 
                             // update the trigger
                             let signal = commands.clone();
-                            trigger = init_trigger(&mut opt, signal).unwrap();
+                            trigger = init_trigger(&mut opt, signal, &poll_now, &source_name)
+                                .await
+                                .with_context(|| {
+                                    format!("init_trigger failed for {source_name} (plugin {plugin_name})")
+                                })?;
 
-                            // don't reset the round count
-                            // i = 1;
+                            // Restart the round count, so that the next flush occurs exactly `flush_rounds` polls later.
+                            // The measurements that are already in the buffer are kept.
+                            i = 1;
+                            polls = 0;
 
                             // estimate the required buffer capacity and allocate it
                             let prev_length = buffer.len();
                             let remaining_rounds = trigger.config.flush_rounds;
-                            let hint_additional_elems = remaining_rounds * prev_length / prev_flush_rounds;
-                            buffer.reserve(hint_additional_elems);
+                            let per_round = (prev_length / prev_flush_rounds).max(points_per_poll);
+                            buffer.reserve(preallocated_capacity(remaining_rounds, per_round));
 
                             // don't be stuck here
                             if !paused {
                                 break 'pause;
                             }
                         }
+                        SourceCmd::Flush => {
+                            // flush now, even if the buffer is not full (but don't send empty buffers)
+                            if !buffer.is_empty() {
+                                let prev_length = buffer.len();
+                                let flushed =
+                                    std::mem::replace(&mut buffer, MeasurementBuffer::with_capacity(prev_length));
+                                tx.send(flushed, &source_name).await.with_context(|| {
+                                    format!("{source_name} failed to flush its measurements after receiving SourceCmd::Flush")
+                                })?;
+                                log::debug!("{source_name} flushed {prev_length} measurements on demand");
+                            }
+                            // start a new flush period
+                            i = 1;
+                            if !paused {
+                                break 'pause;
+                            }
+                        }
+                        SourceCmd::SetInterval {
+                            poll_interval,
+                            flush_interval,
+                        } => {
+                            // modify the trigger in place, keeping the buffer, and restart the round count (like SetTrigger)
+                            match trigger.set_interval(poll_interval, flush_interval) {
+                                Ok(()) => i = 1,
+                                Err(e) => log::warn!("{source_name} ignored {cmd:?}: {e}"),
+                            }
+                            if !paused {
+                                break 'pause;
+                            }
+                        }
                     }
                     commands
                         .changed()
@@ -453,85 +1826,1004 @@ async fn run_source(
                         .expect("command channel of paused source should remain open");
                     cmd = commands.borrow().clone();
                 }
+                if paused {
+                    // resume on the schedule of the trigger, without polling for the ticks missed during the pause
+                    if let Err(e) = trigger.skip_missed_ticks() {
+                        log::warn!("{source_name} could not skip the ticks missed during its pause: {e}");
+                    }
+                }
             }
         }
     }
+    Ok(None)
+}
+
+/// Runs the task of an element, and sends the element to `handover` if it must be moved to another runtime.
+async fn handover_task<T>(
+    task: impl Future<Output = anyhow::Result<Option<T>>>,
+    handover: oneshot::Sender<T>,
+) -> anyhow::Result<()> {
+    if let Some(element) = task.await? {
+        let _ = handover.send(element);
+    }
     Ok(())
 }
 
+/// The state of a source or output, as requested by the last command that it received.
+///
+/// Note that an element that has stopped on its own (for instance because of an error)
+/// keeps the last requested state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ElementState {
+    Running,
+    Paused,
+    Stopped,
+    /// The output has been detached from the pipeline because it was too slow, see [`SlowOutputPolicy::Detach`].
+    Detached,
+}
+
 #[derive(Debug)]
 pub enum TransformCmd {
     Enable,
     Disable,
 }
 
-async fn run_transforms(
-    mut transforms: Vec<ConfiguredTransform>,
-    mut rx: mpsc::Receiver<MeasurementBuffer>,
-    tx: broadcast::Sender<OutputMsg>,
-    active_flags: Arc<AtomicU64>,
-) -> anyhow::Result<()> {
-    loop {
-        if let Some(mut measurements) = rx.recv().await {
-            // Update the list of active transforms (the PipelineController can update the flags).
-            let current_flags = active_flags.load(Ordering::Relaxed);
-
-            // Run the enabled transforms. If one of them fails, the ability to continue running depends on the error type.
-            for (i, t) in &mut transforms.iter_mut().enumerate() {
-                let t_flag = 1 << i;
-                if current_flags & t_flag != 0 {
-                    match t.transform.apply(&mut measurements) {
-                        Ok(()) => (),
-                        Err(TransformError::UnexpectedInput(e)) => {
-                            log::error!("Transform function {} received unexpected measurements: {e:#}", t.name);
-                        }
-                        Err(TransformError::Fatal(e)) => {
-                            log::error!(
-                                "Fatal error in transform {} (this breaks the transform task!): {e:?}",
-                                t.name
-                            );
-                            return Err(e.context(format!("fatal error in transform {}", t.name)));
-                        }
-                    }
-                }
-            }
+/// Designates a transform of the pipeline, see [`ControlHandle::replace_transform`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransformSelector {
+    /// The position of the transform, in the order in which the transforms are applied, route after route.
+    ///
+    /// The transforms that add the constant attributes of the pipeline are counted: there is one at the
+    /// front of every route, if some constant attributes have been set.
+    Index(usize),
+    /// The name of the transform, as generated by the pipeline builder.
+    Name(String),
+}
 
-            // Send the results to the outputs.
-            tx.send(OutputMsg::WriteMeasurements(measurements))
-                .context("could not send the measurements from transforms to the outputs")?;
-        } else {
-            log::debug!("The channel connected to the transform step has been closed, the transforms will stop.");
-            break;
-        }
+impl From<usize> for TransformSelector {
+    fn from(index: usize) -> Self {
+        TransformSelector::Index(index)
     }
-    Ok(())
 }
 
-/// A command for an output.
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub enum OutputCmd {
-    Run,
-    Pause,
-    Stop,
+impl From<&str> for TransformSelector {
+    fn from(name: &str) -> Self {
+        TransformSelector::Name(name.to_owned())
+    }
 }
 
-#[derive(Debug, Clone)]
-pub enum OutputMsg {
-    WriteMeasurements(MeasurementBuffer),
-    RegisterMetrics {
-        metrics: Vec<Metric>,
-        source_name: String,
-        reply_to: tokio::sync::mpsc::Sender<Vec<RawMetricId>>,
-    },
+impl From<String> for TransformSelector {
+    fn from(name: String) -> Self {
+        TransformSelector::Name(name)
+    }
 }
 
-async fn run_output_from_broadcast(
-    output_name: String,
-    mut output: Box<dyn Output>,
-    mut rx: broadcast::Receiver<OutputMsg>,
-    mut commands: watch::Receiver<OutputCmd>,
-    mut ctx: OutputContext,
-) -> anyhow::Result<()> {
+/// The receiving half of a channel of measurement buffers.
+///
+/// The channel that connects the sources to the processing stage (the transforms and the outputs) is shared,
+/// so that the stage can be restarted without disconnecting the sources (see [`RunningPipeline::restart_processing`]).
+/// The first task of the stage locks it for as long as it runs.
+enum BufferReceiver {
+    Owned(mpsc::Receiver<MeasurementBuffer>),
+    Shared(tokio::sync::OwnedMutexGuard<mpsc::Receiver<MeasurementBuffer>>),
+}
+
+impl From<mpsc::Receiver<MeasurementBuffer>> for BufferReceiver {
+    fn from(value: mpsc::Receiver<MeasurementBuffer>) -> Self {
+        BufferReceiver::Owned(value)
+    }
+}
+
+impl Deref for BufferReceiver {
+    type Target = mpsc::Receiver<MeasurementBuffer>;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            BufferReceiver::Owned(rx) => rx,
+            BufferReceiver::Shared(rx) => rx,
+        }
+    }
+}
+
+impl DerefMut for BufferReceiver {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        match self {
+            BufferReceiver::Owned(rx) => rx,
+            BufferReceiver::Shared(rx) => rx,
+        }
+    }
+}
+
+/// The sending half of a broadcast queue to the outputs, used by the transforms.
+#[derive(Clone)]
+struct OutputQueue {
+    tx: broadcast::Sender<OutputMsg>,
+    /// The capacity of the queue, if one of its outputs has the policy [`SlowOutputPolicy::Block`]:
+    /// the messages are only sent when the queue has some free space, so that no message is lost.
+    /// The outputs notify the transforms each time they take a message, see [`OutputReceiver`].
+    blocking: Option<(usize, Arc<Notify>)>,
+    /// `true` if the last measurements have been discarded because no output was listening.
+    discarding: bool,
+}
+
+impl From<broadcast::Sender<OutputMsg>> for OutputQueue {
+    fn from(tx: broadcast::Sender<OutputMsg>) -> Self {
+        OutputQueue {
+            tx,
+            blocking: None,
+            discarding: false,
+        }
+    }
+}
+
+impl OutputQueue {
+    async fn send(&self, msg: OutputMsg) -> Result<usize, broadcast::error::SendError<OutputMsg>> {
+        if let Some((capacity, space)) = &self.blocking {
+            // The broadcast queue has no backpressure: wait for the slowest output to receive the oldest message.
+            loop {
+                let notified = space.notified();
+                tokio::pin!(notified);
+                // Register the waiter before checking the length, so that no notification is missed.
+                notified.as_mut().enable();
+                if self.tx.len() < *capacity {
+                    break;
+                }
+                notified.await;
+            }
+        }
+        self.tx.send(msg)
+    }
+
+    /// Sends the measurements to the outputs.
+    ///
+    /// If no output is listening, because they have all been stopped (or have failed, which they report themselves),
+    /// the measurements are discarded. This is not an error: the sources keep running, and the measurements
+    /// are sent again as soon as an output is added.
+    async fn send_measurements(&mut self, measurements: MeasurementBuffer) {
+        match self.send(OutputMsg::WriteMeasurements(measurements)).await {
+            Ok(_) if self.discarding => {
+                log::info!("An output is listening again, the measurements are not discarded anymore.");
+                self.discarding = false;
+            }
+            Ok(_) => (),
+            Err(_) if !self.discarding => {
+                log::warn!("All the outputs have stopped, the measurements are discarded until an output is added.");
+                self.discarding = true;
+            }
+            Err(_) => (),
+        }
+    }
+}
+
+/// The receiving half of a broadcast queue to the outputs, used by an output.
+struct OutputReceiver {
+    rx: broadcast::Receiver<OutputMsg>,
+    /// Notified each time the output takes a message, if the queue blocks the transforms when it is full
+    /// (see [`OutputQueue::blocking`]).
+    space: Option<Arc<Notify>>,
+}
+
+impl From<broadcast::Receiver<OutputMsg>> for OutputReceiver {
+    fn from(rx: broadcast::Receiver<OutputMsg>) -> Self {
+        OutputReceiver { rx, space: None }
+    }
+}
+
+impl OutputReceiver {
+    async fn recv(&mut self) -> Result<OutputMsg, broadcast::error::RecvError> {
+        let res = self.rx.recv().await;
+        if let Some(space) = &self.space {
+            space.notify_waiters();
+        }
+        res
+    }
+
+    /// Receives the next message while the output does not write the measurements (because it is paused
+    /// or unhealthy), so that the output does not hold back a blocking queue, or waits forever if the queue
+    /// does not block.
+    ///
+    /// Otherwise, the transforms would wait for the output, and the pipeline could not even shut down:
+    /// the outputs are stopped after the transforms.
+    async fn recv_skipped(&mut self) -> Result<OutputMsg, broadcast::error::RecvError> {
+        if self.space.is_none() {
+            return std::future::pending().await;
+        }
+        self.recv().await
+    }
+
+    /// Receives the next message if the output writes the measurements,
+    /// otherwise see [`recv_skipped`](Self::recv_skipped).
+    async fn recv_or_skip(&mut self, writing: bool) -> Result<OutputMsg, broadcast::error::RecvError> {
+        if writing {
+            self.recv().await
+        } else {
+            self.recv_skipped().await
+        }
+    }
+
+    /// Returns `true` if the output has received all the messages of the queue.
+    fn is_empty(&self) -> bool {
+        self.rx.is_empty()
+    }
+}
+
+impl Drop for OutputReceiver {
+    fn drop(&mut self) {
+        if let Some(space) = &self.space {
+            // Release the messages that the output has not received before waking up the transforms:
+            // the new receiver only gets the messages that will be sent from now on.
+            self.rx = self.rx.resubscribe();
+            space.notify_waiters();
+        }
+    }
+}
+
+/// What the processing stage (the transforms and the outputs) needs to be started, or restarted.
+struct ProcessingConfig {
+    /// The channel that receives the measurements of the sources.
+    input: Arc<tokio::sync::Mutex<mpsc::Receiver<MeasurementBuffer>>>,
+    /// The main broadcast queue to the outputs, which also carries the late registrations of metrics.
+    to_outputs: broadcast::Sender<OutputMsg>,
+    source_channel_capacity: usize,
+    output_channel_capacity: usize,
+    /// The metrics registered before the start of the pipeline.
+    metrics: MetricRegistry,
+    instrumentation: bool,
+    input_counters: Option<Arc<InputCounters>>,
+    /// The transforms that add the constant attributes of the pipeline, at the front of every route.
+    constant_attributes: Vec<ConstantAttributesTransform>,
+    /// The limit of the buffers that are sent to the outputs, enforced by the transform tasks.
+    buffer_size_limit: Option<BufferSizeLimit>,
+    /// Number of messages that the tasks of the processing stage are currently handling, see [`InFlightGuard`].
+    in_flight: Arc<InFlight>,
+    /// Where the outputs publish the errors that they recover from.
+    last_errors: Arc<LastErrors>,
+}
+
+/// Allows to control the elements of the processing stage.
+struct ProcessingControllers {
+    outputs_by_plugin: HashMap<String, Vec<OutputController>>,
+    output_counters_by_plugin: OutputCountersByPlugin,
+    active_transforms: Arc<AtomicU64>,
+    transforms_mask_by_plugin: HashMap<String, u64>,
+    transform_names: Vec<String>,
+    transform_replacements: Arc<TransformReplacements>,
+    outputs_ready: OutputsReady,
+    queues: ProcessingQueues,
+    handover: ProcessingHandover,
+}
+
+/// Allows to move the tasks of the processing stage to other runtimes, see [`RunningPipeline::rebuild_runtime`].
+///
+/// The tasks exit when their token is cancelled, and hand their elements over to the controller,
+/// which starts new tasks with them.
+struct ProcessingHandover {
+    /// The routes, in the order of their transforms.
+    routes: Vec<String>,
+    /// Asks the transform tasks to exit, after the buffer that they are processing.
+    transforms_token: CancellationToken,
+    /// Receives the transforms of each transform task, with the name of the task.
+    transforms: Vec<(String, oneshot::Receiver<Vec<ConfiguredTransform>>)>,
+    /// Asks the output tasks to exit, once they have written the messages of their queue.
+    /// Each output is received by the `handover` of its [`OutputController`].
+    outputs_token: CancellationToken,
+}
+
+/// An output whose task has exited in order to be moved to another runtime.
+struct MovedOutput {
+    output: builder::ConfiguredOutput,
+    /// The context of the output, which contains the metrics that have been registered late.
+    ctx: OutputContext,
+}
+
+/// An output to start in [`spawn_processing`].
+enum ProcessingOutput {
+    /// An output that has just been built.
+    New(builder::ConfiguredOutput),
+    /// An output that has been moved from another runtime, which keeps its last command and its counters.
+    Moved {
+        moved: MovedOutput,
+        command: OutputCmd,
+        counters: Arc<OutputCounters>,
+    },
+}
+
+impl ProcessingOutput {
+    fn output(&self) -> &builder::ConfiguredOutput {
+        match self {
+            ProcessingOutput::New(out) => out,
+            ProcessingOutput::Moved { moved, .. } => &moved.output,
+        }
+    }
+}
+
+/// The queues between the tasks of the processing stage, which are empty when the stage is idle.
+///
+/// Only the queues of the routes are stored here: the queue of the sources and the main queue of the outputs
+/// are in the [`PipelineModifierState`].
+#[derive(Default)]
+struct ProcessingQueues {
+    /// The channels from the fan-out task to each route, with the name of the route.
+    /// They are weak, so that the routes stop with the fan-out task.
+    routes: Vec<(String, mpsc::WeakSender<MeasurementBuffer>)>,
+    /// The broadcast queues of the routes, with the name of the route.
+    outputs: Vec<(String, broadcast::Sender<OutputMsg>)>,
+}
+
+/// The number of messages that the tasks of the processing stage are currently handling, see [`InFlightGuard`].
+#[derive(Default)]
+struct InFlight {
+    count: AtomicUsize,
+    /// Notified each time the count drops to zero, which is when the processing stage may have become idle.
+    done: Notify,
+}
+
+/// Counts a message as being handled by a task of the processing stage, until the guard is dropped.
+///
+/// The counter is incremented right after the message has been received, and decremented once the task
+/// has sent its results to the next queue. Hence, a message is always either in a queue or counted,
+/// except between its reception and the creation of the guard.
+struct InFlightGuard<'a>(&'a InFlight);
+
+impl<'a> InFlightGuard<'a> {
+    fn new(in_flight: &'a InFlight) -> Self {
+        in_flight.count.fetch_add(1, Ordering::SeqCst);
+        Self(in_flight)
+    }
+}
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        if self.0.count.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.done.notify_waiters();
+        }
+    }
+}
+
+/// Tells when the tasks of the outputs have started.
+///
+/// The outputs subscribe to their broadcast queue before their tasks are spawned, but the tasks may not run
+/// before the sources when the runtime is under load. The sources wait for this signal before their first poll,
+/// so that the start of the pipeline does not depend on the scheduling of the tasks.
+#[derive(Clone)]
+struct OutputsReady {
+    /// Number of output tasks that have started.
+    started: watch::Receiver<usize>,
+    n_outputs: usize,
+}
+
+impl OutputsReady {
+    async fn wait(mut self) {
+        // Each output task drops its sender once it has started. If the senders are all gone, either the outputs
+        // have started, or some of them have been dropped before running: in both cases, don't wait anymore.
+        let n_outputs = self.n_outputs;
+        let _ = self.started.wait_for(|n| *n >= n_outputs).await;
+    }
+}
+
+/// Spawns the tasks of the outputs and transforms, starting at the end of the pipeline.
+///
+/// `input` is the locked receiver of `config.input`.
+fn spawn_processing(
+    transforms: Vec<ConfiguredTransform>,
+    outputs: Vec<ProcessingOutput>,
+    input: BufferReceiver,
+    config: &ProcessingConfig,
+    join_sets: &mut ElementJoinSets,
+    rt: &tokio::runtime::Handle,
+) -> ProcessingControllers {
+
+    // Group the elements by route. Every route has at least one output (checked by the builder).
+    // With one route, the transforms send their results to the outputs through `to_outputs`.
+    // With multiple routes, each route has its own transform task and broadcast queue.
+    let mut routes: Vec<String> = Vec::new();
+    for out in outputs.iter().map(ProcessingOutput::output) {
+        if !routes.contains(&out.route) {
+            routes.push(out.route.clone());
+        }
+    }
+    let route_queues: HashMap<String, broadcast::Sender<OutputMsg>> = if routes.len() > 1 {
+        routes
+            .iter()
+            .map(|r| (r.clone(), broadcast::Sender::new(config.output_channel_capacity)))
+            .collect()
+    } else {
+        HashMap::new()
+    };
+    // The constant attributes are added before the other transforms of each route.
+    let constants = routes.iter().flat_map(|route| {
+        config.constant_attributes.iter().map(move |t| ConfiguredTransform {
+            transform: Box::new(t.clone()),
+            name: format!("alumet/constant_attributes ({route})"),
+            plugin_name: String::from("alumet"),
+            route: route.clone(),
+            error_policy: TransformErrorPolicy::SkipAndContinue,
+        })
+    });
+    let mut transforms: Vec<ConfiguredTransform> = constants.chain(transforms).collect();
+    // Keep the transforms of each route together, in the order of the routes.
+    // The sort is stable: the order of the transforms of each route is preserved.
+    transforms.sort_by_key(|t| routes.iter().position(|r| r == &t.route));
+    let transform_names: Vec<String> = transforms.iter().map(|t| t.name.clone()).collect();
+    let transform_replacements = Arc::new(TransformReplacements::default());
+
+    // If there is no transform and only one output, the pipeline can be reduced:
+    // the output receives the measurements directly from the sources, without
+    // going through the transform task and the broadcast channel (which clones every buffer).
+    // The limit of the buffer size is enforced by the transform task, which must run in that case.
+    let reduced = transforms.is_empty() && outputs.len() == 1 && config.buffer_size_limit.is_none();
+    let (mut direct_rx, transforms_rx) = if reduced {
+        log::debug!("No transform and only one output: the pipeline is reduced.");
+        (Some(input), None)
+    } else {
+        (None, Some(input))
+    };
+
+    // The transforms of a route wait for its outputs if one of them must not lose any message.
+    // The outputs of these routes notify the transforms when they take a message from the queue.
+    let mut queue_space: HashMap<String, Arc<Notify>> = HashMap::new();
+    for out in outputs.iter().map(ProcessingOutput::output) {
+        if out.slow_policy == SlowOutputPolicy::Block {
+            queue_space.entry(out.route.clone()).or_default();
+        }
+    }
+    let output_queue = |tx: &broadcast::Sender<OutputMsg>, route: &str| OutputQueue {
+        tx: tx.clone(),
+        blocking: queue_space
+            .get(route)
+            .map(|space| (config.output_channel_capacity, space.clone())),
+        discarding: false,
+    };
+
+    // 1. Outputs
+    let mut outputs_by_plugin: HashMap<_, Vec<_>> = HashMap::new();
+    let mut output_counters_by_plugin: HashMap<_, Vec<_>> = HashMap::new();
+    let (started_tx, started_rx) = watch::channel(0);
+    let outputs_ready = OutputsReady {
+        started: started_rx,
+        n_outputs: outputs.len(),
+    };
+    let outputs_token = CancellationToken::new();
+    for out in outputs {
+        // A moved output has already registered the metrics, and keeps its context and its counters.
+        let register_at_start = matches!(out, ProcessingOutput::New(_));
+        let (out, ctx, command, counters) = match out {
+            ProcessingOutput::New(out) => {
+                let ctx = OutputContext {
+                    // Each output task owns its OutputContext, which contains a copy of the MetricRegistry.
+                    // This allows fast, uncontended access to the registry, and avoids a global state (no Arc<Mutex<...>>).
+                    // The cost is a duplication of the registry (increased memory use) in the case where multiple outputs exist.
+                    metrics: config.metrics.clone(),
+                };
+                // Count the messages lost by the output and its failed writes (and its successful writes, if instrumented).
+                let counters = Arc::new(OutputCounters {
+                    written_buffers: config.instrumentation.then(|| AtomicU64::new(0)),
+                    latency: config.input_counters.as_ref().map(|c| c.latency.clone()),
+                    in_flight: config.in_flight.clone(),
+                    errors: config.last_errors.clone(),
+                    ..Default::default()
+                });
+                (out, ctx, OutputCmd::Run, counters)
+            }
+            ProcessingOutput::Moved {
+                moved,
+                command,
+                counters,
+            } => (moved.output, moved.ctx, command, counters),
+        };
+        let msg_rx = OutputReceiver {
+            rx: match route_queues.get(&out.route) {
+                Some(queue) => queue.subscribe(),
+                None => config.to_outputs.subscribe(),
+            },
+            space: queue_space.get(&out.route).cloned(),
+        };
+        let (command_tx, command_rx) = watch::channel(OutputCmd::Run);
+        if command != OutputCmd::Run {
+            // The output only applies the commands that it has not seen yet.
+            command_tx.send_replace(command);
+        }
+        output_counters_by_plugin
+            .entry(out.plugin_name.clone())
+            .or_default()
+            .push((out.name.clone(), counters.clone()));
+
+        // Spawn the task in the JoinSet.
+        // In a reduced pipeline, the output is where the measurements enter the pipeline.
+        let name = out.name.clone();
+        let plugin = out.plugin_name.clone();
+        let direct = direct_rx.take();
+        let output_input_counters = direct.as_ref().and(config.input_counters.clone());
+        let task = run_output_from_broadcast(
+            out,
+            msg_rx,
+            direct,
+            command_rx,
+            ctx,
+            counters.clone(),
+            output_input_counters,
+            outputs_token.clone(),
+            register_at_start,
+        );
+        let started_tx = started_tx.clone();
+        let (handover_tx, handover_rx) = oneshot::channel();
+        let task = async move {
+            started_tx.send_modify(|n| *n += 1);
+            drop(started_tx);
+            handover_task(task, handover_tx).await
+        };
+        let abort = join_sets.output_set.spawn_on(name.clone(), plugin.clone(), task, rt);
+
+        // Store command_tx so that we can accept commands later (commands can target the outputs of a specific plugin).
+        outputs_by_plugin.entry(plugin).or_default().push(OutputController {
+            name,
+            command: command_tx,
+            abort,
+            counters,
+            handover: handover_rx,
+        });
+    }
+
+    // 2. Transforms (all the transforms of a route are in the same task because they are applied one after another)
+    let active_transforms = Arc::new(AtomicU64::new(u64::MAX)); // all active by default
+    let mut transforms_mask_by_plugin: HashMap<_, u64> = HashMap::new();
+    for (i, t) in transforms.iter().enumerate() {
+        let mask: u64 = 1 << i;
+        transforms_mask_by_plugin
+            .entry(t.plugin_name.clone())
+            .or_default()
+            .bitor_assign(mask);
+    }
+    let mut queues = ProcessingQueues::default();
+    let mut handover = ProcessingHandover {
+        routes: routes.clone(),
+        transforms_token: CancellationToken::new(),
+        transforms: Vec::new(),
+        outputs_token,
+    };
+    match transforms_rx {
+        Some(input) if route_queues.is_empty() => {
+            let transforms_task = run_transforms(
+                transforms,
+                input,
+                output_queue(&config.to_outputs, &routes[0]), // the only route
+                active_transforms.clone(),
+                0,
+                config.input_counters.clone(),
+                config.buffer_size_limit,
+                config.in_flight.clone(),
+                transform_replacements.clone(),
+                handover.transforms_token.clone(),
+            );
+            let name = String::from("transforms");
+            let (handover_tx, handover_rx) = oneshot::channel();
+            let task = handover_task(transforms_task, handover_tx);
+            join_sets.transform_set.spawn_on(name.clone(), String::new(), task, rt);
+            handover.transforms.push((name, handover_rx));
+        }
+        Some(input) => {
+            // One transform task per route, fed by a task that copies the measurements to each route.
+            let mut route_inputs = Vec::with_capacity(routes.len());
+            let mut remaining = transforms.into_iter().peekable();
+            let mut flag_offset = 0;
+            for route in &routes {
+                let mut route_transforms = Vec::new();
+                while let Some(t) = remaining.next_if(|t| &t.route == route) {
+                    route_transforms.push(t);
+                }
+                let n_transforms = route_transforms.len();
+                let (route_tx, route_rx) = mpsc::channel::<MeasurementBuffer>(config.source_channel_capacity);
+                let transforms_task = run_transforms(
+                    route_transforms,
+                    route_rx,
+                    output_queue(&route_queues[route], route),
+                    active_transforms.clone(),
+                    flag_offset,
+                    None,
+                    config.buffer_size_limit,
+                    config.in_flight.clone(),
+                    transform_replacements.clone(),
+                    handover.transforms_token.clone(),
+                );
+                let name = format!("transforms ({route})");
+                let (handover_tx, handover_rx) = oneshot::channel();
+                let task = handover_task(transforms_task, handover_tx);
+                join_sets.transform_set.spawn_on(name.clone(), String::new(), task, rt);
+                handover.transforms.push((name, handover_rx));
+                queues.routes.push((route.clone(), route_tx.downgrade()));
+                route_inputs.push(route_tx);
+                flag_offset += n_transforms;
+            }
+            let fan_out_task = fan_out_to_routes(
+                input,
+                route_inputs,
+                config.input_counters.clone(),
+                config.in_flight.clone(),
+            );
+            join_sets.transform_set.spawn_on(String::from("routes"), String::new(), fan_out_task, rt);
+
+            // The late registrations of metrics are sent to `to_outputs`, forward them to every route.
+            let registrations = config.to_outputs.subscribe();
+            queues.outputs = routes.iter().map(|r| (r.clone(), route_queues[r].clone())).collect();
+            let forward_task = forward_registrations(registrations, route_queues.into_values().collect());
+            join_sets.transform_set.spawn_on(String::from("registrations"), String::new(), forward_task, rt);
+        }
+        None => (),
+    }
+
+    ProcessingControllers {
+        outputs_by_plugin,
+        output_counters_by_plugin,
+        active_transforms,
+        transforms_mask_by_plugin,
+        transform_names,
+        transform_replacements,
+        outputs_ready,
+        queues,
+        handover,
+    }
+}
+
+/// Applies the transforms to the buffers received from `rx`, and sends the results to `tx`.
+///
+/// Returns the transforms if the task must be moved to another runtime, which happens when `relocate` is cancelled.
+#[allow(clippy::too_many_arguments)]
+async fn run_transforms(
+    mut transforms: Vec<ConfiguredTransform>,
+    rx: impl Into<BufferReceiver>,
+    tx: impl Into<OutputQueue>,
+    active_flags: Arc<AtomicU64>,
+    flag_offset: usize,
+    input_counters: Option<Arc<InputCounters>>,
+    size_limit: Option<BufferSizeLimit>,
+    in_flight: Arc<InFlight>,
+    replacements: Arc<TransformReplacements>,
+    relocate: CancellationToken,
+) -> anyhow::Result<Option<Vec<ConfiguredTransform>>> {
+    let mut rx = rx.into();
+    let mut tx: OutputQueue = tx.into();
+    loop {
+        let received = tokio::select! {
+            biased;
+            _ = relocate.cancelled() => {
+                // The pending replacements are applied now: the new tasks do not receive them.
+                replacements.take_into(&mut transforms, flag_offset);
+                return Ok(Some(transforms));
+            }
+            received = rx.recv() => received,
+        };
+        if let Some(mut measurements) = received {
+            let _in_flight = InFlightGuard::new(&in_flight);
+            // Only between two buffers, so that a buffer is never processed by a mix of old and new transforms.
+            replacements.take_into(&mut transforms, flag_offset);
+            if let Some(counters) = &input_counters {
+                counters.count(&measurements);
+            }
+
+            // The buffers that have already been processed (e.g. by another Alumet instance) bypass the transforms.
+            if measurements.is_processed() {
+                log::trace!("The measurements have already been processed, the transforms are skipped.");
+            } else if !apply_transforms(&mut transforms, &active_flags, flag_offset, &mut measurements).await? {
+                continue;
+            }
+
+            // Send the results to the outputs, in multiple buffers if the buffer is too large.
+            match size_limit {
+                Some(limit) if measurements.len() > limit.max_points => {
+                    for chunk in limit.apply(measurements) {
+                        tx.send_measurements(chunk).await;
+                    }
+                }
+                _ => tx.send_measurements(measurements).await,
+            }
+        } else {
+            log::debug!("The channel connected to the transform step has been closed, the transforms will stop.");
+            break;
+        }
+    }
+    Ok(None)
+}
+
+/// The transforms that have been given to [`ControlHandle::replace_transform`],
+/// and that the transform tasks have not taken yet.
+#[derive(Default)]
+struct TransformReplacements {
+    /// The new transforms, with the index of the transform that they replace (the index of its flag).
+    pending: Mutex<Vec<(usize, Box<dyn Transform>)>>,
+    /// Whether `pending` is not empty, to avoid taking the lock for every buffer.
+    has_pending: AtomicBool,
+}
+
+impl TransformReplacements {
+    fn push(&self, index: usize, transform: Box<dyn Transform>) {
+        let mut pending = self.pending.lock().unwrap();
+        // A transform that has not been taken yet is itself replaced.
+        pending.retain(|(i, _)| *i != index);
+        pending.push((index, transform));
+        self.has_pending.store(true, Ordering::Release);
+    }
+
+    /// Replaces the transforms of a task, whose first transform has the index `offset`.
+    fn take_into(&self, transforms: &mut [ConfiguredTransform], offset: usize) {
+        if !self.has_pending.load(Ordering::Acquire) {
+            return;
+        }
+        let mut pending = self.pending.lock().unwrap();
+        let range = offset..offset + transforms.len();
+        let (taken, kept): (Vec<_>, Vec<_>) = std::mem::take(&mut *pending)
+            .into_iter()
+            .partition(|(i, _)| range.contains(i));
+        *pending = kept;
+        self.has_pending.store(!pending.is_empty(), Ordering::Release);
+        drop(pending);
+        for (i, new) in taken {
+            let t = &mut transforms[i - offset];
+            // The old transform is dropped here, by the task that used it.
+            t.transform = new;
+            log::info!("Transform {} has been replaced.", t.name);
+        }
+    }
+}
+
+/// Applies the enabled transforms to the measurements, in order.
+///
+/// Returns `false` if a transform has discarded the measurements.
+async fn apply_transforms(
+    transforms: &mut Vec<ConfiguredTransform>,
+    active_flags: &AtomicU64,
+    flag_offset: usize,
+    measurements: &mut MeasurementBuffer,
+) -> anyhow::Result<bool> {
+    // Update the list of active transforms (the PipelineController can update the flags).
+    // The flag of the i-th transform of this task is the bit `flag_offset + i`.
+    let current_flags = active_flags.load(Ordering::Relaxed);
+    let is_enabled = |i: usize| current_flags & (1 << (flag_offset + i)) != 0;
+
+    // Run the enabled transforms, in order.
+    // Consecutive independent transforms are run in parallel, the other ones are run sequentially.
+    let mut i = 0;
+    while i < transforms.len() {
+        if !is_enabled(i) {
+            i += 1;
+            continue;
+        }
+        // Find the end of the group of independent transforms (the disabled transforms do not break the group).
+        let mut end = i + 1;
+        let mut n_enabled = 1;
+        if transforms[i].transform.parallelizable() {
+            while end < transforms.len() && (!is_enabled(end) || transforms[end].transform.parallelizable()) {
+                if is_enabled(end) {
+                    n_enabled += 1;
+                }
+                end += 1;
+            }
+        }
+        if n_enabled > 1 {
+            apply_transforms_in_parallel(transforms, i..end, is_enabled, measurements).await?;
+            i = end;
+        } else {
+            let t = &mut transforms[i];
+            let res = t.transform.apply(measurements);
+            check_transform_result(t, res)?;
+            if measurements.is_empty() {
+                // The transform has discarded the buffer: skip the next transforms.
+                log::trace!("Transform {} has discarded the measurements.", t.name);
+                return Ok(false);
+            }
+            i += 1;
+        }
+    }
+    Ok(true)
+}
+
+/// Sends each buffer received from the sources to the transform task of every route.
+async fn fan_out_to_routes(
+    rx: impl Into<BufferReceiver>,
+    routes: Vec<mpsc::Sender<MeasurementBuffer>>,
+    input_counters: Option<Arc<InputCounters>>,
+    in_flight: Arc<InFlight>,
+) -> anyhow::Result<()> {
+    let mut rx = rx.into();
+    while let Some(measurements) = rx.recv().await {
+        let _in_flight = InFlightGuard::new(&in_flight);
+        if let Some(counters) = &input_counters {
+            counters.count(&measurements);
+        }
+        // Copy the buffer for every route but the last one, which gets the original.
+        if let Some((last, others)) = routes.split_last() {
+            for route in others {
+                route
+                    .send(measurements.clone())
+                    .await
+                    .context("could not send the measurements to a route")?;
+            }
+            last.send(measurements)
+                .await
+                .context("could not send the measurements to a route")?;
+        }
+    }
+    log::debug!("The channel connected to the routes has been closed, the routes will stop.");
+    Ok(())
+}
+
+/// Forwards the late registrations of metrics to the outputs of every route.
+async fn forward_registrations(
+    mut rx: broadcast::Receiver<OutputMsg>,
+    routes: Vec<broadcast::Sender<OutputMsg>>,
+) -> anyhow::Result<()> {
+    loop {
+        match rx.recv().await {
+            Ok(msg) => {
+                for route in &routes {
+                    // The outputs of the route may have stopped, ignore the error.
+                    let _ = route.send(msg.clone());
+                }
+            }
+            Err(broadcast::error::RecvError::Lagged(n)) => {
+                log::warn!("{n} registrations of metrics have been lost before being forwarded to the routes.");
+            }
+            Err(broadcast::error::RecvError::Closed) => break,
+        }
+    }
+    Ok(())
+}
+
+/// Checks the result of a transform. If it failed, the ability to continue running depends on the error type.
+fn check_transform_result(t: &ConfiguredTransform, res: Result<(), TransformError>) -> anyhow::Result<()> {
+    let name = &t.name;
+    let plugin = &t.plugin_name;
+    match res {
+        Ok(()) => Ok(()),
+        Err(TransformError::UnexpectedInput(e)) => {
+            log::error!("Transform function {name} (plugin '{plugin}') received unexpected measurements: {e:#}");
+            Ok(())
+        }
+        Err(TransformError::Fatal(e)) => match t.error_policy {
+            TransformErrorPolicy::Abort => {
+                log::error!("Fatal error in transform {name} (plugin '{plugin}', this breaks the transform task!): {e:?}");
+                Err(e.context(format!("fatal error in transform {name} of plugin '{plugin}'")))
+            }
+            TransformErrorPolicy::SkipAndContinue => {
+                log::error!("Error in transform {name} (plugin '{plugin}', the next transforms will run anyway): {e:?}");
+                Ok(())
+            }
+        },
+    }
+}
+
+/// Applies the independent transforms `transforms[group]` in parallel, each on its own copy of the measurements,
+/// then appends the points that they have added to `measurements`, in the order of the transforms.
+///
+/// See [`Transform::parallelizable`](super::Transform::parallelizable).
+async fn apply_transforms_in_parallel(
+    transforms: &mut Vec<ConfiguredTransform>,
+    group: std::ops::Range<usize>,
+    is_enabled: impl Fn(usize) -> bool,
+    measurements: &mut MeasurementBuffer,
+) -> anyhow::Result<()> {
+    enum Slot {
+        Disabled(ConfiguredTransform),
+        Running(
+            String,
+            JoinHandle<(ConfiguredTransform, MeasurementBuffer, Result<(), TransformError>)>,
+        ),
+    }
+
+    // Move the transforms of the group to blocking tasks, they are put back in the list afterwards.
+    let rest = transforms.split_off(group.end);
+    let slots: Vec<Slot> = transforms
+        .split_off(group.start)
+        .into_iter()
+        .enumerate()
+        .map(|(j, mut t)| {
+            if is_enabled(group.start + j) {
+                let mut copy = measurements.clone();
+                let name = format!("{} (plugin '{}')", t.name, t.plugin_name);
+                let handle = tokio::task::spawn_blocking(move || {
+                    let res = t.transform.apply(&mut copy);
+                    (t, copy, res)
+                });
+                Slot::Running(name, handle)
+            } else {
+                Slot::Disabled(t)
+            }
+        })
+        .collect();
+
+    // Wait for the transforms and merge their results.
+    let initial_len = measurements.len();
+    for slot in slots {
+        let t = match slot {
+            Slot::Disabled(t) => t,
+            Slot::Running(name, handle) => {
+                let (t, copy, res) = handle
+                    .await
+                    .with_context(|| format!("parallel transform {name} panicked"))?;
+                check_transform_result(&t, res)?;
+                if copy.len() < initial_len {
+                    log::error!(
+                        "Transform {name} is declared as independent but it removed some measurements, its result is ignored."
+                    );
+                } else {
+                    for point in copy.iter().skip(initial_len) {
+                        measurements.push(point.clone());
+                    }
+                }
+                t
+            }
+        };
+        transforms.push(t);
+    }
+    transforms.extend(rest);
+    Ok(())
+}
+
+/// What happens when an output is too slow to write the measurements that it receives.
+///
+/// The outputs receive their messages from a bounded queue, shared by the outputs of the same route.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SlowOutputPolicy {
+    /// The oldest messages are dropped: the output continues with the most recent ones.
+    ///
+    /// The lost messages are counted in [`ControlHandle::output_lag`].
+    #[default]
+    DropOldest,
+    /// The transforms wait for the output to catch up, which slows the whole route down.
+    ///
+    /// No message is lost, but the other outputs of the route wait too, and so do the sources
+    /// once the channel that connects them to the transforms is full (see [`SourceOverflowPolicy`]).
+    ///
+    /// The outputs that do not write, because they are paused or unhealthy, do not make the route wait:
+    /// they skip the messages that they receive in the meantime, which are counted in [`ControlHandle::output_lag`].
+    Block,
+    /// The oldest messages are dropped, and the output is detached from the pipeline after it has lagged
+    /// behind `max_lags` times: its task stops, and its state becomes [`ElementState::Detached`].
+    Detach { max_lags: u32 },
+}
+
+/// A command for an output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutputCmd {
+    Run,
+    Pause,
+    Stop,
+}
+
+#[derive(Debug, Clone)]
+pub enum OutputMsg {
+    WriteMeasurements(MeasurementBuffer),
+    RegisterMetrics {
+        metrics: Vec<Metric>,
+        source_name: String,
+        reply_to: tokio::sync::mpsc::Sender<Vec<RawMetricId>>,
+    },
+}
+
+/// Runs an output.
+///
+/// The output receives its messages from the broadcast queue `rx`.
+/// If `direct` is set, the pipeline is reduced: the measurements come from `direct` instead, and `rx`
+/// is only used for the other messages (such as the late registration of metrics).
+///
+/// The number of messages that the output loses because it lags behind, and the number
+/// of buffers that it fails to write, are added to `counters`.
+/// If `input_counters` is set, the buffers received from `direct` are counted in it.
+///
+/// The metrics are registered in the output before it receives any message, unless `register_at_start` is `false`
+/// (the output has been moved from another task). When `relocate` is cancelled, the output writes the messages
+/// that wait in its queue (unless it is paused), then returns itself in order to be moved to another runtime.
+#[allow(clippy::too_many_arguments)]
+async fn run_output_from_broadcast(
+    mut out: builder::ConfiguredOutput,
+    rx: impl Into<OutputReceiver>,
+    mut direct: Option<BufferReceiver>,
+    mut commands: watch::Receiver<OutputCmd>,
+    mut ctx: OutputContext,
+    counters: Arc<OutputCounters>,
+    input_counters: Option<Arc<InputCounters>>,
+    relocate: CancellationToken,
+    register_at_start: bool,
+) -> anyhow::Result<Option<MovedOutput>> {
     // Two possible designs:
     // A) Use one mpsc channel + one shared variable that contains the current command,
     // - when a message is received, check the command and act accordingly
@@ -546,37 +2838,26 @@ async fn run_output_from_broadcast(
     //
     // We have chosen option (B).
 
-    async fn handle_message(
-        received_msg: OutputMsg,
-        output_name: &str,
-        output: &mut dyn Output,
+    /// Writes the measurements once. Returns the buffer along with the result, so that it can be written again.
+    async fn write_measurements(
+        output: &mut OutputKind,
+        measurements: MeasurementBuffer,
         ctx: &mut OutputContext,
-    ) -> anyhow::Result<()> {
-        match received_msg {
-            OutputMsg::WriteMeasurements(measurements) => {
+    ) -> anyhow::Result<(Result<(), WriteError>, MeasurementBuffer)> {
+        match output {
+            OutputKind::Blocking(output) => {
                 // output.write() is blocking, do it in a dedicated thread.
 
                 // Output is not Sync, we could move the value to the future and back (idem for ctx),
                 // but that would likely introduce a needless copy, and would be cumbersome to work with.
                 // Instead, we use the `scoped` module.
-                let res =
-                    scoped::spawn_blocking_with_output(output, ctx, move |out, ctx| out.write(&measurements, ctx))
-                        .await;
+                let res = scoped::spawn_blocking_with_output(output.as_mut(), ctx, move |out, ctx| {
+                    let write_res = out.write(&measurements, ctx);
+                    (write_res, measurements)
+                })
+                .await;
                 match res {
-                    Ok(write_res) => {
-                        match write_res {
-                            Ok(_) => Ok(()),
-                            Err(WriteError::CanRetry(e)) => {
-                                log::error!("Non-fatal error in output {output_name} (in a future version of Alumet, this means that the Output will try to write the same measurements later): {e:#}");
-                                // TODO retry with the same measurements
-                                Ok(())
-                            }
-                            Err(WriteError::Fatal(e)) => {
-                                log::error!("Fatal error in output {output_name} (it will stop running): {e:?}");
-                                Err(e.context(format!("fatal error in output {output_name}")))
-                            }
-                        }
-                    }
+                    Ok(res) => Ok(res),
                     Err(await_err) => {
                         if await_err.is_panic() {
                             Err(anyhow!(
@@ -584,7 +2865,150 @@ async fn run_output_from_broadcast(
                                 await_err
                             ))
                         } else {
-                            todo!("unhandled error");
+                            Err(anyhow!("A blocking writing task has been cancelled: {await_err}"))
+                        }
+                    }
+                }
+            }
+            OutputKind::Async(output) => {
+                // write_async() does not block, await it directly.
+                let write_res = output.write_async(&measurements, ctx).await;
+                Ok((write_res, measurements))
+            }
+        }
+    }
+
+    /// Reconnects the output, see [`Output::reconnect`](super::Output::reconnect).
+    async fn reconnect_output(
+        output: &mut OutputKind,
+        ctx: &mut OutputContext,
+    ) -> anyhow::Result<Result<(), WriteError>> {
+        match output {
+            // reconnect() opens a connection: it is blocking, like write().
+            OutputKind::Blocking(output) => {
+                let res = scoped::spawn_blocking_with_output(output.as_mut(), ctx, |out, _| out.reconnect()).await?;
+                Ok(res)
+            }
+            // Async outputs have no reconnection hook, the measurements are simply written again.
+            OutputKind::Async(_) => Ok(Ok(())),
+        }
+    }
+
+    async fn handle_message(
+        received_msg: OutputMsg,
+        out: &mut builder::ConfiguredOutput,
+        ctx: &mut OutputContext,
+        counters: &OutputCounters,
+    ) -> anyhow::Result<()> {
+        let output_name = &out.name;
+        let plugin = &out.plugin_name;
+        match received_msg {
+            OutputMsg::WriteMeasurements(mut measurements) => {
+                // Only write one of every k buffers, counting the buffers that this output has received.
+                if let Some(k) = out.sampling.filter(|k| *k > 1) {
+                    let received = counters.received_buffers.fetch_add(1, Ordering::Relaxed);
+                    if received % u64::from(k) != 0 {
+                        counters.sampled_out_buffers.fetch_add(1, Ordering::Relaxed);
+                        counters.sampled_out_points.fetch_add(measurements.len() as u64, Ordering::Relaxed);
+                        return Ok(());
+                    }
+                }
+
+                // Each output receives its own copy of the buffer, which we can filter without affecting the others.
+                if let Some(filter) = out.filter.as_deref() {
+                    let filtered: Vec<MeasurementPoint> = measurements.iter().filter(|&p| filter(p)).cloned().collect();
+                    if filtered.is_empty() {
+                        return Ok(());
+                    }
+                    let ingested_at = measurements.ingested_at();
+                    measurements = MeasurementBuffer::from(filtered);
+                    measurements.set_ingested_at(ingested_at);
+                }
+
+                // While the circuit breaker is open, the output is not called. When it is half-open, write once.
+                let breaker_state = counters.breaker.lock().unwrap().state();
+                if breaker_state == CircuitBreakerState::Open {
+                    counters.skipped_writes.fetch_add(1, Ordering::Relaxed);
+                    return Ok(());
+                }
+                let probe = breaker_state == CircuitBreakerState::HalfOpen;
+
+                let mut attempt = 1;
+                // The output is reconnected at most once per attempt.
+                let mut reconnected = false;
+                loop {
+                    let (write_res, buf) = write_measurements(&mut out.output, measurements, ctx)
+                        .await
+                        .with_context(|| format!("output {output_name} of plugin '{plugin}' failed to write"))?;
+                    measurements = buf;
+                    let error = match write_res {
+                        Ok(_) => {
+                            if out.circuit_breaker.is_some() {
+                                let mut breaker = counters.breaker.lock().unwrap();
+                                if breaker.open_until.take().is_some() {
+                                    log::info!("Output {output_name} (plugin '{plugin}') works again, its circuit breaker is closed.");
+                                }
+                                breaker.consecutive_failures = 0;
+                            }
+                            if let Some(written) = &counters.written_buffers {
+                                written.fetch_add(1, Ordering::Relaxed);
+                            }
+                            if let (Some(latency), Some(t)) = (&counters.latency, measurements.ingested_at()) {
+                                latency.record(t.elapsed());
+                            }
+                            return Ok(());
+                        }
+                        Err(WriteError::Disconnected(e)) if !reconnected => {
+                            log::warn!("Output {output_name} (plugin '{plugin}') has been disconnected, reconnecting: {e:#}");
+                            reconnected = true;
+                            let reconnect_res = reconnect_output(&mut out.output, ctx).await.with_context(|| {
+                                format!("output {output_name} of plugin '{plugin}' failed to reconnect")
+                            })?;
+                            match reconnect_res {
+                                // Write the measurements again, without counting a new attempt.
+                                Ok(()) => continue,
+                                Err(WriteError::CanRetry(e) | WriteError::Disconnected(e)) => {
+                                    e.context("the reconnection has failed")
+                                }
+                                Err(WriteError::Fatal(e)) => {
+                                    log::error!("Fatal error while reconnecting output {output_name} (plugin '{plugin}', it will stop running): {e:?}");
+                                    return Err(e.context(format!(
+                                        "fatal error in output {output_name} of plugin '{plugin}'"
+                                    )));
+                                }
+                            }
+                        }
+                        Err(WriteError::CanRetry(e) | WriteError::Disconnected(e)) => e,
+                        Err(WriteError::Fatal(e)) => {
+                            log::error!("Fatal error in output {output_name} (plugin '{plugin}', it will stop running): {e:?}");
+                            return Err(e.context(format!(
+                                "fatal error in output {output_name} of plugin '{plugin}'"
+                            )));
+                        }
+                    };
+                    match &out.retry {
+                        Some(policy) if attempt < policy.max_attempts && !probe => {
+                            let backoff = policy.backoff(attempt);
+                            log::warn!("Non-fatal error in output {output_name} (plugin '{plugin}', attempt {attempt}/{}, retrying in {backoff:?}): {error:#}", policy.max_attempts);
+                            tokio::time::sleep(backoff).await;
+                            attempt += 1;
+                            reconnected = false;
+                        }
+                        _ => {
+                            log::error!("Non-fatal error in output {output_name} (plugin '{plugin}', the measurements are dropped after {attempt} attempt(s)): {error:#}");
+                            counters.failed_writes.fetch_add(1, Ordering::Relaxed);
+                            counters
+                                .errors
+                                .publish(ElementType::Output, plugin, PipelineErrorKind::Recoverable, &error);
+                            if let Some(policy) = &out.circuit_breaker {
+                                let mut breaker = counters.breaker.lock().unwrap();
+                                breaker.consecutive_failures += 1;
+                                if probe || breaker.consecutive_failures >= policy.failure_threshold {
+                                    log::warn!("Output {output_name} (plugin '{plugin}') has failed {} time(s) in a row, its circuit breaker is open for {:?}.", breaker.consecutive_failures, policy.cooldown);
+                                    breaker.open_until = Some(Instant::now() + policy.cooldown);
+                                }
+                            }
+                            return Ok(());
                         }
                     }
                 }
@@ -601,6 +3025,137 @@ async fn run_output_from_broadcast(
         }
     }
 
+    /// Handles a message that the output receives while it does not write, see [`OutputReceiver::recv_skipped`].
+    ///
+    /// The measurements are lost, but the metrics are registered. Returns `false` if the queue has been closed.
+    async fn skip_message(
+        received_msg: Result<OutputMsg, broadcast::error::RecvError>,
+        out: &mut builder::ConfiguredOutput,
+        ctx: &mut OutputContext,
+        counters: &OutputCounters,
+    ) -> anyhow::Result<bool> {
+        match received_msg {
+            Ok(OutputMsg::WriteMeasurements(_)) => {
+                counters.lost_messages.fetch_add(1, Ordering::Relaxed);
+            }
+            Ok(msg) => handle_message(msg, out, ctx, counters).await?,
+            Err(broadcast::error::RecvError::Lagged(n)) => {
+                counters.lost_messages.fetch_add(n, Ordering::Relaxed);
+            }
+            Err(broadcast::error::RecvError::Closed) => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// Calls [`Output::register`](super::Output::register) with the metrics that are known at startup.
+    async fn register_metrics(out: &mut builder::ConfiguredOutput, ctx: &mut OutputContext) -> anyhow::Result<()> {
+        let output_name = &out.name;
+        let plugin = &out.plugin_name;
+        let OutputKind::Blocking(output) = &mut out.output else {
+            return Ok(()); // async outputs have no registration hook
+        };
+        let res = scoped::spawn_blocking_with_output(output.as_mut(), ctx, |out, ctx| out.register(&ctx.metrics))
+            .await
+            .with_context(|| format!("output {output_name} of plugin '{plugin}' failed to register the metrics"))?;
+        match res {
+            Ok(()) => Ok(()),
+            Err(WriteError::CanRetry(e) | WriteError::Disconnected(e)) => {
+                log::error!("Non-fatal error while registering the metrics in output {output_name} (plugin '{plugin}'): {e:#}");
+                Ok(())
+            }
+            Err(WriteError::Fatal(e)) => {
+                log::error!("Fatal error while registering the metrics in output {output_name} (plugin '{plugin}', it will stop running): {e:?}");
+                Err(e.context(format!("fatal error in output {output_name} of plugin '{plugin}'")))
+            }
+        }
+    }
+
+    /// Flushes the output, see [`Output::flush`](super::Output::flush).
+    async fn flush_output(out: &mut builder::ConfiguredOutput, ctx: &mut OutputContext) -> anyhow::Result<()> {
+        let output_name = &out.name;
+        let plugin = &out.plugin_name;
+        let OutputKind::Blocking(output) = &mut out.output else {
+            return Ok(()); // async outputs are not flushed
+        };
+        // flush() writes the buffered measurements: it is blocking, like write().
+        let res = scoped::spawn_blocking_with_output(output.as_mut(), ctx, |out, _| out.flush())
+            .await
+            .with_context(|| format!("output {output_name} of plugin '{plugin}' failed to flush"))?;
+        match res {
+            Ok(()) => Ok(()),
+            Err(WriteError::CanRetry(e) | WriteError::Disconnected(e)) => {
+                log::error!("Non-fatal error while flushing output {output_name} (plugin '{plugin}'): {e:#}");
+                Ok(())
+            }
+            Err(WriteError::Fatal(e)) => {
+                log::error!("Fatal error while flushing output {output_name} (plugin '{plugin}', it will stop running): {e:?}");
+                Err(e.context(format!("fatal error in output {output_name} of plugin '{plugin}'")))
+            }
+        }
+    }
+
+    /// Checks the health of the output, see [`Output::health`](super::Output::health).
+    async fn check_health(out: &mut builder::ConfiguredOutput, ctx: &mut OutputContext) -> anyhow::Result<Health> {
+        let output_name = &out.name;
+        let plugin = &out.plugin_name;
+        let OutputKind::Blocking(output) = &mut out.output else {
+            return Ok(Health::Healthy); // async outputs have no health check
+        };
+        // health() can query the external entity: it is blocking, like write().
+        let health = scoped::spawn_blocking_with_output(output.as_mut(), ctx, |out, _| out.health())
+            .await
+            .with_context(|| format!("output {output_name} of plugin '{plugin}' failed to check its health"))?;
+        Ok(health)
+    }
+
+    /// Waits for the next tick of the timer, or forever if there is no timer.
+    async fn tick(flush_timer: &mut Option<tokio::time::Interval>) {
+        match flush_timer {
+            Some(timer) => {
+                timer.tick().await;
+            }
+            None => std::future::pending().await,
+        }
+    }
+
+    /// Receives the next buffer from the sources, or waits forever if the pipeline is not reduced.
+    async fn recv_direct(direct: &mut Option<BufferReceiver>) -> Option<MeasurementBuffer> {
+        match direct {
+            Some(rx) => rx.recv().await,
+            None => std::future::pending().await,
+        }
+    }
+
+    let mut rx: OutputReceiver = rx.into();
+    // Let the output prepare itself before any data flows.
+    if register_at_start {
+        register_metrics(&mut out, &mut ctx).await?;
+    }
+
+    let output_name = out.name.clone();
+    let mut flush_timer = out.flush_interval.map(|period| {
+        // The first flush happens after one period, not immediately.
+        let mut timer = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+        timer.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        timer
+    });
+    // The first health check happens immediately.
+    let mut health_timer = out.health_check_interval.map(|period| {
+        let mut timer = tokio::time::interval(period);
+        timer.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        timer
+    });
+    // While the output is unhealthy, it does not receive the measurements.
+    let mut healthy = true;
+    // Whether the output has just become healthy again: the messages that it has missed are not a lag.
+    let mut recovered = false;
+
+    // In a reduced pipeline, the broadcast queue can be closed while the output is still receiving measurements.
+    let mut broadcast_open = true;
+    // Number of times that the output has lagged behind, see SlowOutputPolicy::Detach.
+    let mut lags = 0u32;
+    // Whether the output leaves the loop to be moved to another runtime.
+    let mut moving = false;
     loop {
         tokio::select! {
             received_cmd = commands.changed() => {
@@ -609,53 +3164,218 @@ async fn run_output_from_broadcast(
                     Ok(OutputCmd::Run) => (), // continue running
                     Ok(OutputCmd::Pause) => {
                         // wait for the command to change
-                        match commands.wait_for(|cmd| cmd != &OutputCmd::Pause).await {
+                        let new_cmd = loop {
+                            tokio::select! {
+                                new_cmd = commands.wait_for(|cmd| cmd != &OutputCmd::Pause) => {
+                                    break new_cmd.map(|cmd| cmd.clone());
+                                }
+                                received_msg = rx.recv_skipped(), if broadcast_open => {
+                                    broadcast_open = skip_message(received_msg, &mut out, &mut ctx, &counters).await?;
+                                }
+                                _ = relocate.cancelled() => {
+                                    // The messages that wait in the queue are not written.
+                                    moving = true;
+                                    break Ok(OutputCmd::Stop);
+                                }
+                            }
+                        };
+                        if moving {
+                            break;
+                        }
+                        match new_cmd {
                             Ok(new_cmd) => {
                                 log::trace!("{output_name} received {new_cmd:?}");
-                                match *new_cmd {
+                                match new_cmd {
                                     OutputCmd::Run => (), // exit the wait
                                     OutputCmd::Stop => break, // stop the loop,
                                     OutputCmd::Pause => unreachable!(),
                                 }
                             },
-                            Err(_) => todo!("watch channel closed"),
+                            Err(_) => {
+                                log::info!("The command channel of output {output_name} was closed, it will now stop.");
+                                break;
+                            }
                         };
                     },
                     Ok(OutputCmd::Stop) => {
                         log::trace!("{output_name} received OutputCmd::Stop");
                         break // stop the loop
                     },
-                    Err(_) => todo!("watch channel closed")
+                    Err(_) => {
+                        log::info!("The command channel of output {output_name} was closed, it will now stop.");
+                        break
+                    }
                 }
             },
-            received_msg = rx.recv() => {
+            received_msg = rx.recv_or_skip(healthy), if broadcast_open => {
+                if !healthy {
+                    broadcast_open = skip_message(received_msg, &mut out, &mut ctx, &counters).await?;
+                    continue;
+                }
                 match received_msg {
                     Ok(msg) => {
-                        handle_message(msg, &output_name, output.as_mut(), &mut ctx).await?;
+                        recovered = false;
+                        let _in_flight = InFlightGuard::new(&counters.in_flight);
+                        handle_message(msg, &mut out, &mut ctx, &counters).await?;
+                    },
+                    Err(broadcast::error::RecvError::Lagged(n)) if std::mem::take(&mut recovered) => {
+                        log::warn!("Output {output_name} has missed {n} messages while it was unhealthy.");
+                        counters.lost_messages.fetch_add(n, Ordering::Relaxed);
                     },
                     Err(broadcast::error::RecvError::Lagged(n)) => {
                         log::warn!("Output {output_name} is too slow, it lost the oldest {n} messages.");
+                        counters.lost_messages.fetch_add(n, Ordering::Relaxed);
+                        lags += 1;
+                        if matches!(out.slow_policy, SlowOutputPolicy::Detach { max_lags } if lags >= max_lags) {
+                            log::error!("Output {output_name} has lagged behind {lags} times, it is detached from the pipeline.");
+                            counters.detached.store(true, Ordering::Relaxed);
+                            break;
+                        }
                     },
                     Err(broadcast::error::RecvError::Closed) => {
-                        log::warn!("The channel connected to output was closed, it will now stop.");
+                        if direct.is_some() {
+                            broadcast_open = false;
+                        } else {
+                            log::warn!("The channel connected to output {output_name} was closed, it will now stop.");
+                            break;
+                        }
+                    }
+                }
+            }
+            received_buf = recv_direct(&mut direct), if healthy => {
+                match received_buf {
+                    Some(measurements) => {
+                        let _in_flight = InFlightGuard::new(&counters.in_flight);
+                        if let Some(input_counters) = &input_counters {
+                            input_counters.count(&measurements);
+                        }
+                        let msg = OutputMsg::WriteMeasurements(measurements);
+                        handle_message(msg, &mut out, &mut ctx, &counters).await?;
+                    },
+                    None => {
+                        log::debug!("The channel connected to output {output_name} was closed, it will now stop.");
                         break;
                     }
                 }
             }
+            _ = tick(&mut flush_timer), if healthy => {
+                flush_output(&mut out, &mut ctx).await?;
+            }
+            // Once the queue is empty, so that the moved output does not lose its messages
+            // (an unhealthy output does not write them anyway).
+            _ = relocate.cancelled(), if !healthy || !broadcast_open || rx.is_empty() => {
+                moving = true;
+                break;
+            }
+            _ = tick(&mut health_timer) => {
+                let health = check_health(&mut out, &mut ctx).await?;
+                match (&health, healthy) {
+                    (Health::Unhealthy(reason), true) => {
+                        log::warn!("Output {output_name} (plugin '{}') is unhealthy, it is paused until it recovers: {reason}", out.plugin_name);
+                        healthy = false;
+                    }
+                    (Health::Healthy, false) => {
+                        log::info!("Output {output_name} (plugin '{}') is healthy again, it resumes.", out.plugin_name);
+                        healthy = true;
+                        recovered = true;
+                    }
+                    _ => (),
+                }
+                *counters.health.lock().unwrap() = health;
+            }
         }
     }
-    Ok(())
+
+    // The output does not receive anything anymore: don't hold back the queue while it writes its last measurements.
+    drop(rx);
+    if moving {
+        // In a reduced pipeline, the measurements that remain in `direct` are written by the new task.
+        log::debug!("Output {output_name} leaves its task to be moved to another runtime.");
+        return Ok(Some(MovedOutput { output: out, ctx }));
+    }
+    if !healthy {
+        log::warn!("Output {output_name} stops while it is unhealthy, the measurements that it has not written are lost.");
+        return Ok(None);
+    }
+
+    // In a reduced pipeline, there is no transform task to wait for before stopping the output:
+    // write the last measurements that have been sent by the sources.
+    if let Some(rx) = &mut direct {
+        while let Ok(measurements) = rx.try_recv() {
+            if let Some(input_counters) = &input_counters {
+                input_counters.count(&measurements);
+            }
+            let msg = OutputMsg::WriteMeasurements(measurements);
+            handle_message(msg, &mut out, &mut ctx, &counters).await?;
+        }
+    }
+    // Write what the output has buffered before stopping.
+    flush_output(&mut out, &mut ctx).await?;
+    Ok(None)
 }
 
+/// Error that occured in a task of the pipeline.
 #[derive(Debug)]
-pub struct PipelineError {
-    pub error: anyhow::Error,
-    pub element: ElementType,
+pub enum PipelineError {
+    /// An element of the pipeline returned an error, for instance a fatal [`PollError`].
+    Element { element: ElementType, error: anyhow::Error },
+    /// The task of an element panicked or was cancelled.
+    Join { element: ElementType, error: JoinError },
+    /// The task that controls the pipeline panicked or was cancelled.
+    Controller(JoinError),
+    /// The task of an element did not stop before the deadline of the shutdown, and has been aborted.
+    ///
+    /// See [`RunningPipeline::shutdown`].
+    Aborted { element: ElementType, name: String },
+}
+
+/// Error returned by [`RunningPipeline::wait_for_shutdown`] and [`RunningPipeline::shutdown`].
+///
+/// It contains every error that has been encountered while waiting for the tasks of the pipeline,
+/// not just the first one.
+#[derive(Debug)]
+pub struct ShutdownError {
+    pub errors: Vec<PipelineError>,
+}
+
+impl fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PipelineError::Element { element, error } => write!(f, "error in {element:?} task: {error:#}"),
+            PipelineError::Join { element, error } => {
+                if error.is_panic() {
+                    write!(f, "{element:?} task panicked: {error}")
+                } else {
+                    write!(f, "{element:?} task has been cancelled: {error}")
+                }
+            }
+            PipelineError::Controller(error) => write!(f, "the pipeline control task failed: {error}"),
+            PipelineError::Aborted { element, name } => {
+                write!(f, "{element:?} {name} did not stop on time and has been aborted")
+            }
+        }
+    }
+}
+
+impl std::error::Error for PipelineError {}
+
+impl fmt::Display for ShutdownError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let n = self.errors.len();
+        let error_str = if n == 1 { "error" } else { "errors" };
+        write!(f, "{n} {error_str} in the measurement pipeline")?;
+        for err in &self.errors {
+            write!(f, "\n- {err}")?;
+        }
+        Ok(())
+    }
 }
 
+impl std::error::Error for ShutdownError {}
+
 impl From<PollError> for PipelineError {
     fn from(value: PollError) -> Self {
-        Self {
+        Self::Element {
             error: match value {
                 PollError::Fatal(err) => err,
                 PollError::CanRetry(err) => err,
@@ -667,7 +3387,7 @@ impl From<PollError> for PipelineError {
 
 impl From<TransformError> for PipelineError {
     fn from(value: TransformError) -> Self {
-        Self {
+        Self::Element {
             error: match value {
                 TransformError::Fatal(err) => err,
                 TransformError::UnexpectedInput(err) => err,
@@ -679,10 +3399,11 @@ impl From<TransformError> for PipelineError {
 
 impl From<WriteError> for PipelineError {
     fn from(value: WriteError) -> Self {
-        Self {
+        Self::Element {
             error: match value {
                 WriteError::Fatal(err) => err,
                 WriteError::CanRetry(err) => err,
+                WriteError::Disconnected(err) => err,
             },
             element: ElementType::Output,
         }
@@ -700,33 +3421,39 @@ impl From<WriteError> for PipelineError {
 /// of the commands. Thus, `watch::Receiver::changed()` will return an error.
 /// That is why the pipeline control task sends `SourceCmd::Stop` to every source,
 /// and wait for the sources to terminate.
+///
+/// The errors returned by the tasks are collected, and returned at the end of the shutdown.
 async fn pipeline_control_task(
-    mut global_shutdown_recv: UnboundedReceiver<()>,
+    mut global_shutdown_recv: UnboundedReceiver<Option<Duration>>,
     mut message_rx: mpsc::Receiver<ControlMessage>,
     mut state: PipelineControllerState,
-) {
+) -> Vec<PipelineError> {
     // Function for handling errors in tasks.
-    fn handle_task_result(type_str: &str, result: Result<anyhow::Result<()>, JoinError>) {
+    fn handle_task_result(
+        element: ElementType,
+        result: Result<anyhow::Result<()>, JoinError>,
+        errors: &mut Vec<PipelineError>,
+    ) {
         match result {
             Ok(Ok(())) => (), // task completed successfully
-            Ok(Err(err)) => {
+            Ok(Err(error)) => {
                 // task completed with error
-                log::error!("A {type_str} task in the measurement pipeline returned an error: {err:?}");
+                log::error!("A {element:?} task in the measurement pipeline returned an error: {error:?}");
+                errors.push(PipelineError::Element { element, error });
             }
-            Err(err) => {
+            Err(error) => {
                 // task panicked or was cancelled
-                if err.is_panic() {
-                    log::error!("A {type_str} task in the pipeline has panicked! {err:?}");
-                } else if err.is_cancelled() {
-                    log::error!("A {type_str} task in the pipeline has been unexpectedly cancelled. {err:?}");
+                if error.is_panic() {
+                    log::error!("A {element:?} task in the pipeline has panicked! {error:?}");
+                } else if error.is_cancelled() {
+                    log::error!("A {element:?} task in the pipeline has been unexpectedly cancelled. {error:?}");
                 }
+                errors.push(PipelineError::Join { element, error });
             }
         }
     }
 
-    async fn join_next_source(
-        source_set: &mut JoinSet<anyhow::Result<()>>,
-    ) -> Option<Result<anyhow::Result<()>, JoinError>> {
+    async fn join_next_source(source_set: &mut ElementSet) -> Option<Result<anyhow::Result<()>, JoinError>> {
         match timeout(Duration::from_secs(3), source_set.join_next()).await {
             Ok(res) => res,
             Err(_) => {
@@ -736,6 +3463,185 @@ async fn pipeline_control_task(
         }
     }
 
+    /// Waits for all the tasks of `set`, until the `deadline` (if any).
+    ///
+    /// When the deadline expires, the remaining tasks are aborted and reported as errors.
+    async fn join_all(
+        set: &mut ElementSet,
+        element: ElementType,
+        deadline: Option<tokio::time::Instant>,
+        errors: &mut Vec<PipelineError>,
+    ) {
+        loop {
+            let next = match deadline {
+                Some(deadline) => match tokio::time::timeout_at(deadline, set.join_next()).await {
+                    Ok(next) => next,
+                    Err(_) => {
+                        for name in set.abort_all() {
+                            log::error!("{element:?} {name} did not stop before the deadline, it has been aborted.");
+                            errors.push(PipelineError::Aborted { element, name });
+                        }
+                        break;
+                    }
+                },
+                None => set.join_next().await,
+            };
+            match next {
+                Some(task_res) => handle_task_result(element, task_res, errors),
+                None => break,
+            }
+        }
+    }
+
+    /// Stops the transforms and outputs, then starts the new ones, connected to the same sources.
+    ///
+    /// See [`RunningPipeline::restart_processing`].
+    async fn restart_processing(
+        state: &mut PipelineControllerState,
+        transforms: Vec<ConfiguredTransform>,
+        outputs: Vec<builder::ConfiguredOutput>,
+        timeout: Duration,
+        errors: &mut Vec<PipelineError>,
+    ) {
+        log::debug!("Restarting the transforms and outputs...");
+        let modif = &mut state.modifier;
+
+        // The buffer that is being transformed is lost, the next ones wait in the channel of the sources.
+        for name in modif.join_sets.transform_set.abort_all() {
+            log::debug!("Transform task {name} has been aborted for the restart.");
+        }
+        // The outputs finish their current write, but the messages that wait in their queue are lost.
+        for out in state.outputs_by_plugin.values().flatten() {
+            out.command.send_replace(OutputCmd::Stop);
+        }
+        let deadline = tokio::time::Instant::now() + timeout;
+        join_all(&mut modif.join_sets.output_set, ElementType::Output, Some(deadline), errors).await;
+
+        // Wait for the stopped task that received the measurements of the sources to release the channel.
+        let input = BufferReceiver::Shared(modif.processing.input.clone().lock_owned().await);
+        let controllers = spawn_processing(
+            transforms,
+            outputs.into_iter().map(ProcessingOutput::New).collect(),
+            input,
+            &modif.processing,
+            &mut modif.join_sets,
+            &modif.rt_normal,
+        );
+        state.set_processing(controllers);
+        log::debug!("The transforms and outputs have been restarted.");
+    }
+
+    /// Moves the managed sources, the transforms and the outputs to the new runtimes.
+    ///
+    /// See [`RunningPipeline::rebuild_runtime`].
+    async fn rebuild_runtime(
+        state: &mut PipelineControllerState,
+        rt_normal: Option<tokio::runtime::Handle>,
+        rt_priority: Option<tokio::runtime::Handle>,
+        timeout: Duration,
+        errors: &mut Vec<PipelineError>,
+    ) {
+        log::debug!("Moving the elements of the pipeline to the new runtimes...");
+
+        // 1. No measurement enters the pipeline while it moves: the sources flush their buffer, and are parked.
+        let n_sources = park_sources(state, &MessageDestination::All, None).await;
+        // In a reduced pipeline, the buffers that the output has not received yet are received by the new output.
+        let has_transforms = !state.processing_handover.transforms.is_empty();
+        if has_transforms && tokio::time::timeout(timeout, wait_until(state, transforms_idle)).await.is_err() {
+            log::warn!(
+                "The transforms are still busy after {timeout:?}, the buffers that they have not processed may be lost."
+            );
+        }
+
+        // 2. The transforms exit after their current buffer, then the outputs once they have written their queue.
+        let n_constants = state.modifier.processing.constant_attributes.len();
+        let handover = &mut state.processing_handover;
+        handover.transforms_token.cancel();
+        let deadline = tokio::time::Instant::now() + timeout;
+        let mut transforms = Vec::new();
+        for (name, rx) in handover.transforms.drain(..) {
+            match tokio::time::timeout_at(deadline, rx).await {
+                // The transforms of the constant attributes are added again by spawn_processing.
+                Ok(Ok(route_transforms)) => transforms.extend(route_transforms.into_iter().skip(n_constants)),
+                Ok(Err(_)) => {
+                    log::warn!("Transform task {name} has stopped before it could be moved, its transforms are lost.")
+                }
+                Err(_) => {
+                    log::error!("Transform task {name} did not stop within {timeout:?}, its transforms are lost.")
+                }
+            }
+        }
+        // The other tasks of the transforms (the fan-out to the routes, the registrations) have no state.
+        for name in state.modifier.join_sets.transform_set.abort_all() {
+            log::debug!("Transform task {name} has been aborted for the move.");
+        }
+
+        handover.outputs_token.cancel();
+        let deadline = tokio::time::Instant::now() + timeout;
+        let mut outputs = Vec::new();
+        for out in state.outputs_by_plugin.values_mut().flatten() {
+            // The outputs that have stopped are not moved. Those that are still running after the deadline
+            // are aborted by join_all.
+            if let Ok(Ok(moved)) = tokio::time::timeout_at(deadline, &mut out.handover).await {
+                let command = out.command.borrow().clone();
+                let counters = out.counters.clone();
+                outputs.push(ProcessingOutput::Moved {
+                    moved,
+                    command,
+                    counters,
+                });
+            }
+        }
+        join_all(&mut state.modifier.join_sets.output_set, ElementType::Output, Some(deadline), errors).await;
+        // Keep the order of the routes, which is the order of the transforms.
+        let routes = &handover.routes;
+        outputs.sort_by_key(|out| routes.iter().position(|r| r == &out.output().route));
+        transforms.retain(|t| {
+            let has_output = outputs.iter().any(|out| out.output().route == t.route);
+            if !has_output {
+                log::warn!("The outputs of route {} have all stopped, transform {} is not restarted.", t.route, t.name);
+            }
+            has_output
+        });
+
+        // 3. Restart everything on the new runtimes.
+        let modif = &mut state.modifier;
+        if let Some(rt) = rt_normal {
+            modif.rt_normal = rt;
+        }
+        if let Some(rt) = rt_priority {
+            modif.rt_priority = Some(rt);
+        }
+        if outputs.is_empty() {
+            log::warn!("All the outputs have stopped, the transforms are not restarted.");
+        } else {
+            let input = BufferReceiver::Shared(modif.processing.input.clone().lock_owned().await);
+            let controllers = spawn_processing(
+                transforms,
+                outputs,
+                input,
+                &modif.processing,
+                &mut modif.join_sets,
+                &modif.rt_normal,
+            );
+            // The disabled transforms stay disabled. Their flags may move, if some transforms are not restarted.
+            let old_flags = state.active_transforms.load(Ordering::Relaxed);
+            for (i, name) in controllers.transform_names.iter().enumerate() {
+                let old_index = state.transform_names.iter().position(|n| n == name);
+                if old_index.is_some_and(|j| old_flags & (1 << j) == 0) {
+                    controllers.active_transforms.fetch_and(!(1 << i), Ordering::Relaxed);
+                }
+            }
+            state.set_processing(controllers);
+        }
+        respawn_parked_sources(state, &MessageDestination::All);
+        log::debug!("The elements of the pipeline have been moved to the new runtimes ({n_sources} sources).");
+    }
+
+    // Timeout of the shutdown, if any.
+    let mut shutdown_timeout = None;
+    let mut errors = Vec::new();
+
     // Pipeline control loop.
     loop {
         tokio::select! {
@@ -747,80 +3653,107 @@ async fn pipeline_control_task(
                 log::info!("Termination signal received, shutting down...");
                 break;
             },
-            _ = global_shutdown_recv.recv() => {
+            timeout = global_shutdown_recv.recv() => {
                 // Graceful shutdown on shutdown order.
                 log::debug!("Internal shutdown order received, shutting down...");
+                shutdown_timeout = timeout.flatten();
                 break;
             }
             incoming_message = message_rx.recv() => {
-                if let Some(message) = incoming_message {
+                match incoming_message {
+                    Some(ControlMessage::RestartProcessing { transforms, outputs, timeout, reply }) => {
+                        // Handled here, in order to report the errors of the elements that are stopped.
+                        restart_processing(&mut state, transforms, outputs, timeout, &mut errors).await;
+                        let _ = reply.send(());
+                    }
+                    Some(ControlMessage::RebuildRuntime { rt_normal, rt_priority, timeout, reply }) => {
+                        // Idem, for the elements that cannot be moved.
+                        rebuild_runtime(&mut state, rt_normal, rt_priority, timeout, &mut errors).await;
+                        let _ = reply.send(());
+                    }
                     // New message received
-                    handle_control_message(&mut state, message);
-                } else {
+                    Some(message) => handle_control_message(&mut state, message).await,
                     // Channel closed, shut down.
-                    break;
+                    None => break,
                 }
             }
+            // Reclaim the tasks of the sources that have stopped (e.g. because they have been removed).
+            Some(task_res) = state.modifier.join_sets.source_set.join_next(),
+                if !state.modifier.join_sets.source_set.is_empty() => {
+                handle_task_result(ElementType::Source, task_res, &mut errors);
+            }
         }
     }
     // End of the loop = shutdown phase.
     // At this point we no longer accept new messages.
+    message_rx.close();
 
+    let deadline = shutdown_timeout.map(|t| tokio::time::Instant::now() + t);
     let mut join_sets: ElementJoinSets = state.modifier.join_sets;
-    let source_command_senders: Vec<watch::Sender<SourceCmd>> = state
-        .source_command_senders_by_plugin
-        .values()
-        .flatten()
-        .cloned()
-        .collect();
     let output_command_senders: Vec<watch::Sender<OutputCmd>> = state
-        .output_command_senders_by_plugin
+        .outputs_by_plugin
         .values()
         .flatten()
-        .cloned()
+        .map(|out| out.command.clone())
         .collect();
 
     // Stop the sources first, and wait for them to send their last measurements to the transforms.
     log::debug!("Stopping sources...");
-    for source_cs in &source_command_senders {
-        source_cs.send_replace(SourceCmd::Stop);
+    for source in state.sources_by_plugin.values().flatten() {
+        source.command.send_replace(SourceCmd::Stop);
     }
     state.autonomous_shutdown_token.cancel();
-    while let Some(task_res) = join_next_source(&mut join_sets.source_set).await {
-        handle_task_result("source", task_res);
+    match deadline {
+        Some(_) => join_all(&mut join_sets.source_set, ElementType::Source, deadline, &mut errors).await,
+        None => {
+            while let Some(task_res) = join_next_source(&mut join_sets.source_set).await {
+                handle_task_result(ElementType::Source, task_res, &mut errors);
+            }
+        }
     }
 
     // Ensure that all the `channel::Sender` that are connected to the transform task are dropped.
     // Note that autonomous sources have to take care of that themselves (but the automatic drop at the end of the task should be enough).
-    drop(source_command_senders);
     drop(state.modifier.in_tx);
+    // The task that forwards the registrations of metrics to the routes stops when `to_outputs` is closed.
+    drop(state.modifier.processing);
 
     // The transform task will stop because the sending half of the channel is now closed.
     // Stop the transforms, and wait for them to send their last measurements to the outputs.
     log::debug!("Waiting for transforms...");
-    while let Some(task_res) = join_sets.transform_set.join_next().await {
-        handle_task_result("transform", task_res);
-    }
+    join_all(&mut join_sets.transform_set, ElementType::Transform, deadline, &mut errors).await;
 
     // Stop the outputs, and wait for them to write their last measurements.
     log::debug!("Stopping outputs...");
     for output_cs in &output_command_senders {
         output_cs.send_replace(OutputCmd::Stop);
     }
-    while let Some(task_res) = join_sets.output_set.join_next().await {
-        handle_task_result("output", task_res);
-    }
+    join_all(&mut join_sets.output_set, ElementType::Output, deadline, &mut errors).await;
+    errors
+}
+
+/// Sends the number of `sources` to `reply` once they have all exited.
+///
+/// A source has exited when its task has dropped the receiving half of the command channel.
+/// The wait happens in a new task, in order not to block the control loop.
+fn reply_when_exited(sources: Vec<watch::Sender<SourceCmd>>, reply: oneshot::Sender<usize>) {
+    tokio::spawn(async move {
+        for command in &sources {
+            command.closed().await;
+        }
+        let _ = reply.send(sources.len());
+    });
 }
 
 /// Processes a message received by the PipelineController.
 ///
 /// This function uses the `state` to modify the pipeline according to the `message`.
-fn handle_control_message(state: &mut PipelineControllerState, message: ControlMessage) {
+async fn handle_control_message(state: &mut PipelineControllerState, message: ControlMessage) {
     match message {
-        ControlMessage::Shutdown => {
+        ControlMessage::Shutdown(timeout) => {
             state
                 .global_shutdown_send
-                .send(())
+                .send(timeout)
                 .expect("failed to send shutdown message");
         }
         ControlMessage::AddSource {
@@ -834,77 +3767,568 @@ fn handle_control_message(state: &mut PipelineControllerState, message: ControlM
             // prepare the source name, channels, etc.
             let modif = &mut state.modifier;
             let source_name = modif.namegen.deduplicate(format!("{plugin}/{requested_name}"), false);
-            let in_tx = modif.in_tx.clone();
-            let (command_tx, command_rx) = watch::channel(SourceCmd::SetTrigger(Some(trigger)));
+            let in_tx = SourceChannel::new(
+                modif.in_tx.clone(),
+                modif.source_overflow_policy,
+                modif.dropped_source_buffers.clone(),
+                modif.join_sets.source_set.last_errors.clone(),
+            );
+            let runtime = modif.source_runtime(&trigger);
+            let (command_tx, command_rx) = watch::channel(SourceCmd::SetTrigger(Some(trigger.clone())));
+            let poll_now = Arc::new(Notify::new());
+            let (handover_tx, handover_rx) = oneshot::channel();
 
             // save the command sender so that we can control the source task
-            state
-                .source_command_senders_by_plugin
-                .entry(plugin)
-                .or_default()
-                .push(command_tx);
+            state.sources_by_plugin.entry(plugin.clone()).or_default().push(SourceController {
+                name: source_name.clone(),
+                state: ElementState::Running,
+                command: command_tx,
+                poll_now: poll_now.clone(),
+                plugin_name: plugin.clone(),
+                trigger,
+                handover: handover_rx,
+                parked: None,
+            });
 
             // submit the task to the tokio Runtime, unless we are shutting down
-            let task = run_source(source_name, source, in_tx, command_rx);
-            modif.join_sets.source_set.spawn_on(task, &modif.rt_normal);
+            let task = run_source(
+                source_name.clone(),
+                plugin.clone(),
+                source,
+                in_tx,
+                command_rx,
+                poll_now,
+                modif.input_counters.clone(),
+            );
+            modif.join_sets.source_set.spawn_on(source_name, plugin, handover_task(task, handover_tx), &runtime);
+        }
+
+        ControlMessage::ModifySource(ElementCommand {
+            destination,
+            command: SourceCmd::SetPriority(source_type),
+            reply,
+        }) => {
+            // The tasks cannot move by themselves, the sources are restarted by the controller.
+            let n = move_sources(state, &destination, source_type).await;
+            let _ = reply.send(n);
         }
 
         ControlMessage::ModifySource(ElementCommand {
             destination,
-            command: message,
-        }) => match destination {
-            MessageDestination::Plugin(plugin) => {
-                for s in state.source_command_senders_by_plugin.get(&plugin).unwrap() {
-                    s.send(message.clone()).unwrap();
+            command,
+            reply,
+        }) => {
+            let new_state = match command {
+                SourceCmd::Run => Some(ElementState::Running),
+                SourceCmd::Pause => Some(ElementState::Paused),
+                SourceCmd::Stop => Some(ElementState::Stopped),
+                SourceCmd::SetTrigger(_) | SourceCmd::Flush | SourceCmd::SetInterval { .. } => None,
+                SourceCmd::SetPriority(_) => unreachable!("SetPriority is handled by move_sources"),
+            };
+            let modif = &mut state.modifier;
+            let n = for_each_in_destination(&mut state.sources_by_plugin, &destination, |source| {
+                if let SourceCmd::SetTrigger(Some(trigger)) = &command {
+                    source.trigger = trigger.clone();
                 }
-            }
-            MessageDestination::All => {
-                for senders in state.source_command_senders_by_plugin.values() {
-                    for s in senders {
-                        s.send(message.clone()).unwrap();
+                match source.parked.take() {
+                    None => {
+                        // Unlike `send`, `send_replace` does not fail when the receiver has been dropped
+                        // (i.e. the source has stopped).
+                        source.command.send_replace(command.clone());
                     }
+                    // The source has been moved while paused: it is restarted when it runs again.
+                    Some(parked) => match &command {
+                        SourceCmd::Run => respawn_source(modif, source, parked),
+                        SourceCmd::Stop => drop(parked),
+                        _ => source.parked = Some(parked),
+                    },
+                }
+                if let Some(new_state) = new_state {
+                    source.state = new_state;
                 }
+            });
+            let _ = reply.send(n); // the requester may not wait for the reply
+        }
+
+        ControlMessage::PollSourcesNow(ElementCommand { destination, reply, .. }) => {
+            let n = for_each_in_destination(&mut state.sources_by_plugin, &destination, |source| {
+                // If the source is not waiting yet, the permit is stored and the next wait returns immediately.
+                source.poll_now.notify_one();
+            });
+            let _ = reply.send(n);
+        }
+
+        ControlMessage::RemoveSources(ElementCommand { destination, reply, .. }) => {
+            let removed: Vec<SourceController> = match destination {
+                MessageDestination::All => state.sources_by_plugin.drain().flat_map(|(_, v)| v).collect(),
+                MessageDestination::Plugin(plugin) => match state.sources_by_plugin.remove(&plugin) {
+                    Some(sources) => sources,
+                    None => {
+                        log::warn!("Cannot remove the sources: there is no source registered by plugin '{plugin}'.");
+                        Vec::new()
+                    }
+                },
+            };
+            for source in &removed {
+                log::debug!("Removing source {}", source.name);
+                source.command.send_replace(SourceCmd::Stop);
             }
-        },
+            // The shared channel `in_tx` stays open, the other sources are not affected.
+            // Their tasks are reclaimed by the control loop.
+            reply_when_exited(removed.into_iter().map(|source| source.command).collect(), reply);
+        }
 
-        ControlMessage::ModifyOutput(ElementCommand { destination, command }) => match destination {
-            MessageDestination::Plugin(plugin) => {
-                for s in state.output_command_senders_by_plugin.get(&plugin).unwrap() {
-                    s.send(command.clone()).unwrap();
+        ControlMessage::Pause(ElementCommand {
+            destination,
+            command: drain_timeout,
+            reply,
+        }) => {
+            // Pause the sources first, so that no new measurements enter the pipeline.
+            let mut n = for_each_in_destination(&mut state.sources_by_plugin, &destination, |source| {
+                source.command.send_replace(SourceCmd::Pause);
+                source.state = ElementState::Paused;
+            });
+            if let Some(drain_timeout) = drain_timeout {
+                // Let the measurements that the sources have already sent go through the transforms and the outputs,
+                // before pausing the outputs.
+                // This is done in the control loop, so that no other command can be applied in the meantime.
+                if timeout(drain_timeout, wait_until_idle(state)).await.is_err() {
+                    log::warn!(
+                        "The measurements of the sources have not been drained after {drain_timeout:?}, \
+                         the outputs are paused anyway."
+                    );
                 }
             }
-            MessageDestination::All => {
-                for senders in state.output_command_senders_by_plugin.values() {
-                    for s in senders {
-                        s.send(command.clone()).unwrap();
+            n += for_each_in_destination(&mut state.outputs_by_plugin, &destination, |out| {
+                out.command.send_replace(OutputCmd::Pause);
+            });
+            let _ = reply.send(n);
+        }
+
+        ControlMessage::Resume(ElementCommand { destination, reply, .. }) => {
+            // Reverse order: the outputs must be ready to write the new measurements.
+            let mut n = for_each_in_destination(&mut state.outputs_by_plugin, &destination, |out| {
+                out.command.send_replace(OutputCmd::Run);
+            });
+            let modif = &mut state.modifier;
+            n += for_each_in_destination(&mut state.sources_by_plugin, &destination, |source| {
+                match source.parked.take() {
+                    Some(parked) => respawn_source(modif, source, parked),
+                    None => {
+                        source.command.send_replace(SourceCmd::Run);
                     }
                 }
-            }
-        },
+                source.state = ElementState::Running;
+            });
+            let _ = reply.send(n);
+        }
+
+        ControlMessage::StopSources(ElementCommand { destination, reply, .. }) => {
+            let mut stopped = Vec::new();
+            for_each_in_destination(&mut state.sources_by_plugin, &destination, |source| {
+                source.command.send_replace(SourceCmd::Stop);
+                source.state = ElementState::Stopped;
+                stopped.push(source.command.clone());
+            });
+            reply_when_exited(stopped, reply);
+        }
+
+        ControlMessage::ModifyOutput(ElementCommand {
+            destination,
+            command,
+            reply,
+        }) => {
+            let n = for_each_in_destination(&mut state.outputs_by_plugin, &destination, |out| {
+                out.command.send_replace(command.clone());
+            });
+            let _ = reply.send(n);
+        }
+
+        ControlMessage::QuerySourceStates(StateQuery { destination, reply }) => {
+            let mut states = Vec::new();
+            for_each_in_destination(&mut state.sources_by_plugin, &destination, |source| {
+                states.push((source.name.clone(), source.state));
+            });
+            let _ = reply.send(states);
+        }
+
+        ControlMessage::QueryOutputStates(StateQuery { destination, reply }) => {
+            let mut states = Vec::new();
+            for_each_in_destination(&mut state.outputs_by_plugin, &destination, |out| {
+                let output_state = match *out.command.borrow() {
+                    _ if out.counters.detached.load(Ordering::Relaxed) => ElementState::Detached,
+                    OutputCmd::Run => ElementState::Running,
+                    OutputCmd::Pause => ElementState::Paused,
+                    OutputCmd::Stop => ElementState::Stopped,
+                };
+                states.push((out.name.clone(), output_state));
+            });
+            let _ = reply.send(states);
+        }
+        ControlMessage::QueryIdle(reply) => {
+            let _ = reply.send(is_idle(state));
+        }
+        ControlMessage::QueryQueues(reply) => {
+            let _ = reply.send(queue_stats(state));
+        }
+
+        ControlMessage::AbortOutputs(ElementCommand { destination, reply, .. }) => {
+            let n = for_each_in_destination(&mut state.outputs_by_plugin, &destination, |out| {
+                if !out.abort.is_finished() {
+                    log::warn!("Aborting output {}.", out.name);
+                }
+                // The output is reported as stopped from now on.
+                out.command.send_replace(OutputCmd::Stop);
+                out.abort.abort();
+            });
+            let _ = reply.send(n);
+        }
+        ControlMessage::RestartProcessing { .. } => unreachable!("the restart is handled by pipeline_control_task"),
+        ControlMessage::RebuildRuntime { .. } => unreachable!("the rebuild is handled by pipeline_control_task"),
 
-        ControlMessage::ModifyTransform(ElementCommand { destination, command }) => {
+        ControlMessage::ModifyTransform(ElementCommand {
+            destination,
+            command,
+            reply,
+        }) => {
             let mask: u64 = match destination {
-                MessageDestination::All => u64::MAX,
-                MessageDestination::Plugin(plugin) => *state.transforms_mask_by_plugin.get(&plugin).unwrap(),
+                MessageDestination::All => state.transforms_mask_by_plugin.values().fold(0, |acc, m| acc | m),
+                MessageDestination::Plugin(plugin) => match state.transforms_mask_by_plugin.get(&plugin) {
+                    Some(mask) => *mask,
+                    None => {
+                        log::warn!("Cannot apply {command:?}: there is no transform registered by plugin '{plugin}'.");
+                        0
+                    }
+                },
             };
             match command {
                 TransformCmd::Enable => state.active_transforms.fetch_or(mask, Ordering::Relaxed),
                 TransformCmd::Disable => state.active_transforms.fetch_nand(mask, Ordering::Relaxed),
             };
+            let _ = reply.send(mask.count_ones() as usize);
+        }
+
+        ControlMessage::ReplaceTransform {
+            selector,
+            transform,
+            reply,
+        } => {
+            let index = match &selector {
+                TransformSelector::Index(i) if *i < state.transform_names.len() => Ok(*i),
+                TransformSelector::Index(i) => Err(anyhow!(
+                    "there is no transform at index {i}, the pipeline has {} transforms",
+                    state.transform_names.len()
+                )),
+                TransformSelector::Name(name) => state
+                    .transform_names
+                    .iter()
+                    .position(|n| n == name)
+                    .ok_or_else(|| anyhow!("there is no transform named '{name}'")),
+            };
+            let res = index.map(|i| state.transform_replacements.push(i, transform));
+            let _ = reply.send(res);
+        }
+    }
+}
+
+/// Moves the sources to the runtime that matches `source_type`, see [`SourceCmd::SetPriority`].
+///
+/// Returns the number of sources that have been moved.
+async fn move_sources(
+    state: &mut PipelineControllerState,
+    destination: &MessageDestination,
+    source_type: SourceType,
+) -> usize {
+    let n = park_sources(state, destination, Some(source_type)).await;
+    respawn_parked_sources(state, destination);
+    n
+}
+
+/// Asks the tasks of the sources to exit, and keeps the sources in their [`SourceController`], without restarting them.
+///
+/// If `source_type` is set, the sources will run on the runtime that matches it, otherwise on the same kind
+/// of runtime as before. Returns the number of sources that have been parked (including those that already were).
+async fn park_sources(
+    state: &mut PipelineControllerState,
+    destination: &MessageDestination,
+    source_type: Option<SourceType>,
+) -> usize {
+    let targets = |plugin: &String| match destination {
+        MessageDestination::All => true,
+        MessageDestination::Plugin(p) => p == plugin,
+    };
+    // Ask all the tasks to exit first, so that the sources flush their measurements concurrently.
+    for (_, sources) in state.sources_by_plugin.iter().filter(|(plugin, _)| targets(plugin)) {
+        for source in sources.iter().filter(|s| s.parked.is_none() && s.state != ElementState::Stopped) {
+            let source_type = source_type.unwrap_or_else(|| SourceType::of(&source.trigger));
+            source.command.send_replace(SourceCmd::SetPriority(source_type));
+        }
+    }
+    let mut n = 0;
+    for (_, sources) in state.sources_by_plugin.iter_mut().filter(|(plugin, _)| targets(plugin)) {
+        for source in sources.iter_mut().filter(|s| s.state != ElementState::Stopped) {
+            let moved = match source.parked.take() {
+                Some(parked) => parked,
+                None => match (&mut source.handover).await {
+                    Ok(moved) => moved,
+                    Err(_) => {
+                        log::warn!("Source {} has exited before it could be moved to another runtime.", source.name);
+                        // The receiver must not be polled again.
+                        source.handover = oneshot::channel().1;
+                        continue;
+                    }
+                },
+            };
+            if let Some(source_type) = source_type {
+                log::debug!("Moving source {} to the {source_type:?} runtime", source.name);
+                source_type.apply(&mut source.trigger);
+            }
+            source.parked = Some(moved);
+            n += 1;
+        }
+    }
+    n
+}
+
+/// Restarts the sources that have been parked by [`park_sources`], except the paused ones,
+/// which are restarted when they resume.
+fn respawn_parked_sources(state: &mut PipelineControllerState, destination: &MessageDestination) {
+    let modif = &mut state.modifier;
+    for_each_in_destination(&mut state.sources_by_plugin, destination, |source| {
+        if source.state == ElementState::Running {
+            if let Some(parked) = source.parked.take() {
+                respawn_source(modif, source, parked);
+            }
+        }
+    });
+}
+
+/// Starts a new task for a source that has been moved, on the runtime that matches its trigger.
+fn respawn_source(modif: &mut PipelineModifierState, controller: &mut SourceController, source: ManagedSource) {
+    let runtime = modif.source_runtime(&controller.trigger);
+    let (command_tx, command_rx) = watch::channel(SourceCmd::SetTrigger(Some(controller.trigger.clone())));
+    let (handover_tx, handover_rx) = oneshot::channel();
+    controller.command = command_tx;
+    controller.handover = handover_rx;
+    let tx = SourceChannel::new(
+        modif.in_tx.clone(),
+        modif.source_overflow_policy,
+        modif.dropped_source_buffers.clone(),
+        modif.join_sets.source_set.last_errors.clone(),
+    );
+    let task = run_source(
+        controller.name.clone(),
+        controller.plugin_name.clone(),
+        source,
+        tx,
+        command_rx,
+        controller.poll_now.clone(),
+        modif.input_counters.clone(),
+    );
+    let (name, plugin) = (controller.name.clone(), controller.plugin_name.clone());
+    modif.join_sets.source_set.spawn_on(name, plugin, handover_task(task, handover_tx), &runtime);
+}
+
+/// Returns `true` if no message is being handled by the processing stage and its queues are empty.
+fn is_idle(state: &PipelineControllerState) -> bool {
+    let processing = &state.modifier.processing;
+    transforms_idle(state)
+        && processing.to_outputs.is_empty()
+        && state.queues.outputs.iter().all(|(_, q)| q.is_empty())
+}
+
+/// Returns `true` if no message is being handled by the processing stage and the queues of the transforms are empty.
+///
+/// Unlike [`is_idle`], the queues of the outputs may contain messages.
+fn transforms_idle(state: &PipelineControllerState) -> bool {
+    // Check the counter first: a task sends its results to the next queue before releasing its InFlightGuard.
+    let empty = |tx: &mpsc::Sender<MeasurementBuffer>| tx.capacity() == tx.max_capacity();
+    state.modifier.processing.in_flight.count.load(Ordering::SeqCst) == 0
+        && empty(&state.modifier.in_tx)
+        && state.queues.routes.iter().filter_map(|(_, r)| r.upgrade()).all(|tx| empty(&tx))
+}
+
+/// Waits for the processing stage to be idle, see [`is_idle`].
+async fn wait_until_idle(state: &PipelineControllerState) {
+    wait_until(state, is_idle).await
+}
+
+/// Waits until `idle` returns `true`.
+///
+/// The stage is checked again each time its tasks have handled all the messages that they had received.
+async fn wait_until(state: &PipelineControllerState, idle: fn(&PipelineControllerState) -> bool) {
+    let in_flight = &state.modifier.processing.in_flight;
+    loop {
+        let done = in_flight.done.notified();
+        tokio::pin!(done);
+        // Register the waiter before checking the state, so that no notification is missed.
+        done.as_mut().enable();
+        if idle(state) {
+            // A message may have been taken from its queue but not counted yet: let the other tasks run,
+            // and check again.
+            tokio::task::yield_now().await;
+            if idle(state) {
+                return;
+            }
+            continue;
+        }
+        done.await;
+    }
+}
+
+/// Measures the occupancy of the internal queues, from the sources to the outputs.
+fn queue_stats(state: &PipelineControllerState) -> Vec<QueueStats> {
+    let processing = &state.modifier.processing;
+    let mpsc_stats = |name: String, tx: &mpsc::Sender<MeasurementBuffer>| QueueStats {
+        name,
+        len: tx.max_capacity() - tx.capacity(),
+        capacity: tx.max_capacity(),
+    };
+    // tokio rounds the capacity of the broadcast queues up to a power of two
+    let broadcast_stats = |name: String, tx: &broadcast::Sender<OutputMsg>| QueueStats {
+        name,
+        len: tx.len(),
+        capacity: processing.output_channel_capacity.next_power_of_two(),
+    };
+    let mut stats = vec![mpsc_stats(String::from("sources"), &state.modifier.in_tx)];
+    for (route, tx) in &state.queues.routes {
+        if let Some(tx) = tx.upgrade() {
+            stats.push(mpsc_stats(format!("transforms ({route})"), &tx));
+        }
+    }
+    stats.push(broadcast_stats(String::from("outputs"), &processing.to_outputs));
+    for (route, tx) in &state.queues.outputs {
+        stats.push(broadcast_stats(format!("outputs ({route})"), tx));
+    }
+    stats
+}
+
+/// Calls `f` on each element that matches the `destination`.
+///
+/// Returns the number of elements that have been addressed.
+fn for_each_in_destination<E>(
+    elements_by_plugin: &mut HashMap<String, Vec<E>>,
+    destination: &MessageDestination,
+    mut f: impl FnMut(&mut E),
+) -> usize {
+    let mut count = 0;
+    let mut apply = |elements: &mut Vec<E>| {
+        for e in elements {
+            f(e);
+            count += 1;
         }
+    };
+    match destination {
+        MessageDestination::All => elements_by_plugin.values_mut().for_each(&mut apply),
+        MessageDestination::Plugin(plugin) => match elements_by_plugin.get_mut(plugin) {
+            Some(elements) => apply(elements),
+            None => log::warn!("There is no element of that kind registered by plugin '{plugin}', the command has been ignored."),
+        },
     }
+    count
 }
 
 impl RunningPipeline {
+    /// Returns whether the sources that require a "realtime priority" have obtained it, at the start of the pipeline.
+    ///
+    /// See [`PipelineBuilder::require_realtime_priority`](super::builder::PipelineBuilder::require_realtime_priority)
+    /// to fail instead of running at the normal priority.
+    pub fn realtime_priority(&self) -> RealtimePriority {
+        self.realtime_priority
+    }
+
+    /// Returns `true` if the "realtime priority" runtime has been created at the start of the pipeline.
+    ///
+    /// It only exists if some sources require a realtime priority, and if the priority of its threads has been
+    /// increased (otherwise, the sources run on the normal runtime).
+    pub fn has_priority_runtime(&self) -> bool {
+        self.rt_priority.is_some()
+    }
+
+    /// Returns the number of worker threads that currently run with an increased scheduling priority.
+    ///
+    /// This includes the threads of the "realtime priority" runtime created at the start of the pipeline,
+    /// and of the one that is created later if a new source requires it.
+    /// This can be used to check, for instance in CI, that the sources actually obtain the realtime priority.
+    pub fn priority_threads(&self) -> usize {
+        self.priority_threads.load(Ordering::SeqCst)
+    }
+
     /// Blocks the current thread until all tasks in the pipeline finish.
     ///
-    /// If a task returns an error or panicks, `wait_for_all` returns an error without waiting
-    /// for the other tasks.
-    pub fn wait_for_shutdown(mut self) -> anyhow::Result<()> {
+    /// The tasks are awaited in order: sources first, then transforms, then outputs.
+    /// If some tasks return an error or panic, the other tasks are still awaited,
+    /// and all the errors are returned in a [`ShutdownError`].
+    pub fn wait_for_shutdown(mut self) -> Result<(), ShutdownError> {
+        self.join_control_task()
+    }
+
+    /// Blocks the current thread until one of the `signals` is received, then shuts the pipeline down gracefully,
+    /// like [`wait_for_shutdown`](Self::wait_for_shutdown): the sources are stopped first, then the transforms
+    /// process the remaining measurements, and the outputs write them.
+    ///
+    /// This also returns when the pipeline shuts down for another reason, for instance because of
+    /// [`ControlHandle::shutdown`]. Since the pipeline is consumed, the tasks cannot be awaited twice.
+    /// Use [`ShutdownSignal::DEFAULT`] to stop on `SIGINT` and `SIGTERM`, like most daemons.
+    ///
+    /// Note that the pipeline always shuts down on `SIGINT` (Ctrl+C), even if it is not in `signals`.
+    pub fn run_until_signal(mut self, signals: &[ShutdownSignal]) -> Result<(), ShutdownError> {
+        let rt = self.rt_normal.as_ref().unwrap(); // only taken by shutdown()
+        let handle = &self.control_handle;
+        let received = rt.block_on(async {
+            tokio::select! {
+                signal = wait_for_signal(signals) => Some(signal),
+                // The control task closes its channel when the shutdown begins.
+                _ = handle.tx.closed() => None,
+            }
+        });
+        match received {
+            Some(signal) => {
+                log::info!("{signal:?} received, shutting down...");
+                handle.shutdown();
+            }
+            None => log::debug!("The pipeline is shutting down, no longer waiting for a signal."),
+        }
+        self.join_control_task()
+    }
+
+    /// Requests the pipeline to shut down, and blocks the current thread until all tasks in the pipeline
+    /// finish or until the `timeout` expires.
+    ///
+    /// The elements are stopped in order, like in [`wait_for_shutdown`](Self::wait_for_shutdown).
+    /// The tasks that are still running when the timeout expires are aborted, and reported in the
+    /// returned error as [`PipelineError::Aborted`].
+    ///
+    /// A blocking [`Output::write`](super::Output::write) cannot be interrupted: if an output is stuck in it,
+    /// its thread is left behind, and the runtimes are dropped without waiting for it.
+    pub fn shutdown(mut self, timeout: Duration) -> Result<(), ShutdownError> {
+        self.control_handle.send_shutdown(Some(timeout));
+        let res = self.join_control_task();
+
+        // Drop the runtimes without waiting for the blocking threads, which may be stuck.
+        if let Some(rt) = self.rt_blocking.take() {
+            rt.shutdown_background();
+        }
+        if let Some(rt) = self.rt_priority.take() {
+            rt.shutdown_background();
+        }
+        if let Some(rt) = self.rt_normal.take() {
+            rt.shutdown_background();
+        }
+        for rt in self.retired_runtimes.drain(..) {
+            rt.shutdown_background();
+        }
+        res
+    }
+
+    /// Blocks the current thread until the control task finishes, and returns its errors.
+    fn join_control_task(&mut self) -> Result<(), ShutdownError> {
         let handle = self.shutdown_task_handle.take().unwrap(); // cannot be called twice, unwrap should never panic
-        let shutdown_res = self._rt_normal.block_on(async { handle.await });
-        match shutdown_res {
-            Ok(_) => Ok(()),
+        self.to_outputs = None;
+        let rt = self.rt_normal.as_ref().unwrap(); // only taken at the end of shutdown()
+        let shutdown_res = rt.block_on(async { handle.await });
+        let errors = match shutdown_res {
+            Ok(errors) => errors,
             Err(err) => {
                 // task panicked or was cancelled
                 if err.is_panic() {
@@ -912,9 +4336,180 @@ impl RunningPipeline {
                 } else if err.is_cancelled() {
                     log::error!("The shutdown task has been unexpectedly cancelled. {err:#}");
                 }
-                Err(err.into())
+                vec![PipelineError::Controller(err)]
             }
+        };
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ShutdownError { errors })
+        }
+    }
+
+    /// Replaces the transforms and the outputs of the pipeline, without stopping the sources.
+    ///
+    /// The current transforms and outputs are stopped, then the new ones are built, started,
+    /// and connected to the sources, which keep running. The new elements are all enabled and running,
+    /// regardless of the state of the old ones. If the new elements cannot be built, or if their
+    /// configuration is invalid (e.g. a route without output), an error is returned and the old ones keep running.
+    ///
+    /// ## Measurements in flight
+    /// - The buffers that the sources send during the restart wait in the channel that connects them
+    ///   to the transforms, and are processed by the new elements. If the channel is full,
+    ///   the [`SourceOverflowPolicy`] of the pipeline applies.
+    /// - The buffer that is being transformed when the transforms are stopped is lost.
+    /// - The outputs are stopped like at the end of the pipeline: they finish their current write and flush,
+    ///   but the messages that are waiting in their queue are lost. The outputs that are still running
+    ///   after `timeout` are aborted.
+    ///
+    /// The errors of the stopped elements are reported at the shutdown of the pipeline.
+    /// The counters of the new outputs (see [`ControlHandle::output_lag`]) start from zero,
+    /// and the new outputs only know the metrics that have been registered before the start of the pipeline.
+    ///
+    /// ## Exclusive resources
+    /// The new elements are built while the old ones are still running, so that the old ones can keep running
+    /// if a new element cannot be built (the builders can only be called once, the old elements could not be
+    /// built again). Therefore, an output that holds an exclusive resource cannot be restarted: its builder fails,
+    /// because the old output still holds the resource, and this method returns an error. For instance,
+    /// the HTTP server of the Prometheus output cannot bind its port, which is still used by the old one.
+    /// Such an output must use another resource (e.g. another port) after the restart.
+    ///
+    /// Do not use this method in an async context.
+    pub fn restart_processing(
+        &mut self,
+        transforms: Vec<TransformBuilder>,
+        outputs: Vec<OutputBuilder>,
+        timeout: Duration,
+    ) -> anyhow::Result<()> {
+        if outputs.is_empty() {
+            return Err(PipelineBuildError::Invalid(InvalidReason::NoOutput).into());
+        }
+        if let Some(t) = transforms.iter().find(|t| !outputs.iter().any(|o| o.route == t.route)) {
+            return Err(PipelineBuildError::Invalid(InvalidReason::RouteWithoutOutput(t.route.clone())).into());
+        }
+        let rt = self.rt_normal.as_ref().unwrap(); // only taken at the end of shutdown()
+        let to_outputs = self.to_outputs.as_ref().unwrap(); // only taken by join_control_task, which consumes self
+        let pending = PendingPipelineContext::new(to_outputs, rt.handle());
+        let transforms = builder::build_transforms(transforms, &pending);
+        let outputs = builder::build_outputs(outputs, &pending)?;
+
+        let (reply, reply_rx) = oneshot::channel();
+        let msg = ControlMessage::RestartProcessing {
+            transforms,
+            outputs,
+            timeout,
+            reply,
+        };
+        rt.block_on(async {
+            self.control_handle
+                .tx
+                .send(msg)
+                .await
+                .map_err(|_| anyhow!("cannot restart the processing: the pipeline has shut down"))?;
+            reply_rx
+                .await
+                .context("the pipeline has shut down before restarting the processing")
+        })
+    }
+
+    /// Moves the managed sources, the transforms and the outputs to new runtimes, with the given number
+    /// of worker threads, for instance to lower the parallelism of the pipeline when its VM is throttled.
+    ///
+    /// Tokio runtimes cannot be resized. Instead, the elements are stopped gracefully, and restarted in new tasks
+    /// on the new runtimes, with their current state: the paused sources and outputs stay paused, the disabled
+    /// transforms stay disabled, and the counters of the outputs (see [`ControlHandle::output_lag`]) are kept.
+    /// The elements are not built again, hence, unlike with [`restart_processing`](Self::restart_processing),
+    /// the outputs can hold exclusive resources. The runtimes whose number of threads is `None` are kept,
+    /// and the runtime of the blocking sources is never rebuilt.
+    ///
+    /// Returns an error if the pipeline has autonomous sources: they are futures that are consumed by their task,
+    /// they cannot be moved to another runtime. Also returns an error if a new runtime cannot be created,
+    /// in which case the pipeline is not modified.
+    ///
+    /// ## Measurement gap
+    /// The sources do not poll during the rebuild: the measurements that they would have taken meanwhile are missing.
+    /// The gap lasts until the processing stage has written the measurements in flight and the elements have restarted,
+    /// and up to twice `timeout` if some elements are slow.
+    /// - The sources flush their buffer before they move, then the transforms process the buffers that they have
+    ///   received, for at most `timeout`. The buffers that they have not processed by then may be lost.
+    /// - The outputs write the messages of their queue before they move, except the paused outputs, which lose them.
+    ///   The outputs that are still running `timeout` after the transforms have moved are aborted.
+    /// - The outputs that have stopped are not restarted, nor are the transforms of a route whose outputs
+    ///   have all stopped.
+    /// - The triggers of the sources start again: the intervals restart from the end of the rebuild.
+    ///
+    /// The control task of the pipeline, and the tasks that the elements have spawned themselves (for instance
+    /// with the runtime handle of their builder context), keep running on the previous runtimes, which are
+    /// only shut down with the pipeline.
+    ///
+    /// Do not use this method in an async context.
+    pub fn rebuild_runtime(&mut self, params: RuntimeParameters, timeout: Duration) -> anyhow::Result<()> {
+        if self.autonomous_sources > 0 {
+            return Err(anyhow!(
+                "cannot rebuild the runtimes: the {} autonomous source(s) of the pipeline cannot be moved \
+                 to another runtime",
+                self.autonomous_sources
+            ));
+        }
+        // Tokio panics when a runtime has no worker thread.
+        let worker_threads = [
+            ("normal", params.normal_worker_threads),
+            ("realtime priority", params.priority_worker_threads),
+        ];
+        if let Some((runtime, _)) = worker_threads.into_iter().find(|(_, n)| *n == Some(0)) {
+            return Err(PipelineBuildError::Invalid(InvalidReason::ZeroWorkerThreads(runtime)).into());
+        }
+        let prefix = self.thread_name_prefix.as_deref();
+        let new_normal = params
+            .normal_worker_threads
+            .map(|n| builder::new_normal_runtime(Some(n), prefix))
+            .transpose()
+            .context("cannot create the normal runtime")?;
+        let new_priority = match params.priority_worker_threads {
+            Some(n) => match builder::new_priority_runtime(n, self.priority_threads.clone(), prefix)
+                .context("cannot create the \"realtime priority\" runtime")?
+            {
+                Some(rt) => Some(rt),
+                None => {
+                    return Err(anyhow!(
+                        "cannot create the \"realtime priority\" runtime: the scheduling priority of its threads \
+                         cannot be increased (see the previous errors)"
+                    ))
+                }
+            },
+            None => None,
+        };
+        if new_normal.is_none() && new_priority.is_none() {
+            return Ok(());
+        }
+
+        let (reply, reply_rx) = oneshot::channel();
+        let msg = ControlMessage::RebuildRuntime {
+            rt_normal: new_normal.as_ref().map(|rt| rt.handle().clone()),
+            rt_priority: new_priority.as_ref().map(|rt| rt.handle().clone()),
+            timeout,
+            reply,
+        };
+        let rt = self.rt_normal.as_ref().unwrap(); // only taken at the end of shutdown()
+        rt.block_on(async {
+            self.control_handle
+                .tx
+                .send(msg)
+                .await
+                .map_err(|_| anyhow!("cannot rebuild the runtimes: the pipeline has shut down"))?;
+            reply_rx
+                .await
+                .context("the pipeline has shut down before rebuilding the runtimes")
+        })?;
+
+        // The previous runtimes still run the control task, and the tasks spawned by the elements.
+        if let Some(rt) = new_normal {
+            self.retired_runtimes.extend(self.rt_normal.replace(rt));
+        }
+        if let Some(rt) = new_priority {
+            self.retired_runtimes.extend(self.rt_priority.replace(rt));
         }
+        Ok(())
     }
 
     /// Returns a [`ControlHandle`], which allows to change the configuration
@@ -922,6 +4517,13 @@ impl RunningPipeline {
     pub fn control_handle(&mut self) -> ControlHandle {
         self.control_handle.clone()
     }
+
+    /// Returns the registry of the metrics that have been registered before the start of the pipeline.
+    ///
+    /// See [`ControlHandle::metrics`].
+    pub fn metrics(&self) -> &MetricRegistry {
+        self.control_handle.metrics()
+    }
 }
 
 impl Drop for RunningPipeline {
@@ -931,6 +4533,17 @@ impl Drop for RunningPipeline {
 }
 
 impl ControlHandle {
+    /// Returns the registry of the metrics that have been registered before the start of the pipeline,
+    /// for instance to look up the name and unit of a metric.
+    ///
+    /// The registry is immutable, it can be read from any thread without any synchronization.
+    /// Each output has its own copy of the same registry.
+    /// Note that the metrics that are registered late (with a [`LateRegistrationHandle`](super::builder::LateRegistrationHandle))
+    /// are only added to the copies of the outputs, not to this registry.
+    pub fn metrics(&self) -> &MetricRegistry {
+        &self.metrics
+    }
+
     pub fn all(&self) -> ScopedControlHandle {
         ScopedControlHandle {
             handle: self,
@@ -960,101 +4573,750 @@ impl ControlHandle {
 
     /// Requests the pipeline to shut down.
     pub fn shutdown(&self) {
-        match self.tx.try_send(ControlMessage::Shutdown) {
+        self.send_shutdown(None)
+    }
+
+    /// Requests the pipeline to shut down without waiting for its elements: all the tasks are aborted.
+    ///
+    /// Unlike [`shutdown`](Self::shutdown), this works when some elements are stuck, and the measurements
+    /// that have not been written yet are lost. The aborted tasks are reported as [`PipelineError::Aborted`].
+    /// See [`ScopedControlHandle::abort_outputs`] for the limits of an abort.
+    /// [`RunningPipeline::wait_for_shutdown`] drops the runtimes of the pipeline, which waits for the outputs
+    /// that are stuck in a blocking call: use [`RunningPipeline::shutdown`] to leave them behind.
+    pub fn abort_all(&self) {
+        self.send_shutdown(Some(Duration::ZERO))
+    }
+
+    fn send_shutdown(&self, timeout: Option<Duration>) {
+        match self.tx.try_send(ControlMessage::Shutdown(timeout)) {
             Ok(_) => {}
             Err(TrySendError::Closed(_)) => {
                 // This may occur when the pipeline has already shut down. It's okay.
                 log::debug!("ControlHandle::shutdown() has been called but the pipeline is already shutting down.")
             }
-            Err(TrySendError::Full(_)) => {
-                // TODO: handle this case? tx.blocking_send(ControlMessage::Shutdown) could work
-                todo!("buffer is full, cannot send command")
+            Err(TrySendError::Full(msg)) => {
+                // Don't lose the shutdown order: wait for some free space on a new thread, which cannot block
+                // the runtime that runs the control task (this method may be called from an async context).
+                log::warn!("Too many commands are pending, the shutdown order will be sent when the pipeline takes them.");
+                let tx = self.tx.clone();
+                std::thread::spawn(move || {
+                    if tx.blocking_send(msg).is_err() {
+                        log::debug!("The pipeline has shut down before receiving the shutdown order.");
+                    }
+                });
+            }
+        }
+    }
+
+    /// Returns the number of measurement buffers that have been dropped by the sources
+    /// because the channel that connects them to the transforms was full.
+    ///
+    /// See [`SourceOverflowPolicy`].
+    pub fn dropped_source_buffers(&self) -> u64 {
+        self.dropped_source_buffers.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of messages that the outputs of the plugin `plugin_name` have lost
+    /// since the start of the pipeline, because they were too slow.
+    ///
+    /// Returns 0 if the plugin has no output.
+    pub fn output_lag(&self, plugin_name: &str) -> u64 {
+        self.sum_output_counters(plugin_name, |c| &c.lost_messages)
+    }
+
+    /// Returns the number of measurement buffers that the outputs of the plugin `plugin_name`
+    /// have dropped since the start of the pipeline, because they failed to write them
+    /// (after the retries allowed by their [`RetryPolicy`], if any).
+    ///
+    /// Returns 0 if the plugin has no output.
+    pub fn failed_output_writes(&self, plugin_name: &str) -> u64 {
+        self.sum_output_counters(plugin_name, |c| &c.failed_writes)
+    }
+
+    /// Returns the last error of the sources of the plugin `plugin_name`, formatted with its context.
+    ///
+    /// A source that fails stops, hence the error is still available after the end of its task.
+    /// Together with [`source_states`](ScopedControlHandle::source_states), this allows to check
+    /// the health of the sources without waiting for the end of the pipeline.
+    /// Only the errors that stop a task are recorded: the errors that the pipeline recovers from
+    /// (e.g. [`PollError::CanRetry`]) are only logged.
+    ///
+    /// Returns `None` if no source of the plugin has failed.
+    pub fn source_last_error(&self, plugin_name: &str) -> Option<String> {
+        self.last_errors.sources.lock().unwrap().get(plugin_name).cloned()
+    }
+
+    /// Returns the last error of the transform tasks, which are shared by all the plugins.
+    ///
+    /// See [`source_last_error`](Self::source_last_error).
+    pub fn transform_last_error(&self) -> Option<String> {
+        self.last_errors.transforms.lock().unwrap().clone()
+    }
+
+    /// Returns the last error of the outputs of the plugin `plugin_name`.
+    ///
+    /// See [`source_last_error`](Self::source_last_error).
+    pub fn output_last_error(&self, plugin_name: &str) -> Option<String> {
+        self.last_errors.outputs.lock().unwrap().get(plugin_name).cloned()
+    }
+
+    /// Returns a channel that receives the errors of the pipeline, to react to them without parsing the logs
+    /// (for instance, to send alerts).
+    ///
+    /// The errors that stop a task ([`PipelineErrorKind::Fatal`]) are published, as well as the errors that the sources
+    /// and the outputs recover from ([`PipelineErrorKind::Recoverable`]), which are otherwise only logged.
+    /// The pipeline never waits for the consumer: when the channel is full, the new events are dropped.
+    /// Calling this method again replaces the previous channel. Until it is called, the events are not created.
+    pub fn error_receiver(&self) -> mpsc::Receiver<PipelineEvent> {
+        self.last_errors.subscribe()
+    }
+
+    /// Returns statistics about the measurements that have gone through the pipeline since its start.
+    ///
+    /// Returns `None` if the instrumentation has not been enabled
+    /// with [`PipelineBuilder::with_instrumentation`](super::builder::PipelineBuilder::with_instrumentation).
+    pub fn stats(&self) -> Option<PipelineStats> {
+        let input = self.input_counters.as_ref()?;
+        let buffers_written = self
+            .output_counters_by_plugin
+            .lock()
+            .unwrap()
+            .values()
+            .flatten()
+            .filter_map(|(name, c)| {
+                let written = c.written_buffers.as_ref()?;
+                Some((name.clone(), written.load(Ordering::Relaxed)))
+            })
+            .collect();
+        Some(PipelineStats {
+            buffers_in: input.buffers.load(Ordering::Relaxed),
+            points_in: input.points.load(Ordering::Relaxed),
+            buffers_written,
+            poll_overruns: input.poll_overruns.load(Ordering::Relaxed),
+        })
+    }
+
+    /// Returns statistics about the latency between the poll of the measurements and their write by the outputs.
+    ///
+    /// Returns `None` if the instrumentation has not been enabled
+    /// with [`PipelineBuilder::with_instrumentation`](super::builder::PipelineBuilder::with_instrumentation).
+    pub fn latency_stats(&self) -> Option<LatencyStats> {
+        let input = self.input_counters.as_ref()?;
+        Some(input.latency.stats())
+    }
+
+    /// Replaces a transform of the running pipeline by `transform`, for instance to update its configuration.
+    ///
+    /// The transform is designated by its position or its name, see [`TransformSelector`].
+    /// The task of the transform swaps it before processing its next buffer: the buffer that it is currently
+    /// processing, if any, goes through the old transform, and the new transform keeps the enabled state
+    /// of the old one (see [`TransformCmd`]). The old transform is dropped by the task.
+    ///
+    /// Returns an error if no transform matches the selector (e.g. the index is out of range),
+    /// in which case the pipeline is not modified, or if the pipeline has shut down.
+    pub async fn replace_transform(
+        &self,
+        selector: impl Into<TransformSelector>,
+        transform: Box<dyn Transform>,
+    ) -> anyhow::Result<()> {
+        let (reply, reply_rx) = oneshot::channel();
+        let msg = ControlMessage::ReplaceTransform {
+            selector: selector.into(),
+            transform,
+            reply,
+        };
+        self.tx
+            .send(msg)
+            .await
+            .map_err(|_| anyhow!("cannot replace the transform: the pipeline has shut down"))?;
+        reply_rx.await.context("the pipeline has shut down before replacing the transform")?
+    }
+
+    /// Like [`replace_transform`](Self::replace_transform), but blocks the current thread instead of being async.
+    ///
+    /// Do not use it in an async context.
+    pub fn blocking_replace_transform(
+        &self,
+        selector: impl Into<TransformSelector>,
+        transform: Box<dyn Transform>,
+    ) -> anyhow::Result<()> {
+        let (reply, reply_rx) = oneshot::channel();
+        let msg = ControlMessage::ReplaceTransform {
+            selector: selector.into(),
+            transform,
+            reply,
+        };
+        self.tx
+            .blocking_send(msg)
+            .map_err(|_| anyhow!("cannot replace the transform: the pipeline has shut down"))?;
+        reply_rx
+            .blocking_recv()
+            .context("the pipeline has shut down before replacing the transform")?
+    }
+
+    /// Returns the occupancy of the internal queues of the pipeline, with the number of messages that they have lost.
+    ///
+    /// This helps to tune the capacities of the queues, see
+    /// [`PipelineBuilder::source_channel_capacity`](super::builder::PipelineBuilder::source_channel_capacity) and
+    /// [`PipelineBuilder::output_channel_capacity`](super::builder::PipelineBuilder::output_channel_capacity):
+    /// a queue that is often full indicates that the next stage is too slow.
+    /// The occupancy of the queues is a snapshot, which may already be outdated when this function returns.
+    pub async fn channel_stats(&self) -> anyhow::Result<ChannelStats> {
+        let (reply, reply_rx) = oneshot::channel();
+        self.tx
+            .send(ControlMessage::QueryQueues(reply))
+            .await
+            .map_err(|_| anyhow!("cannot query the pipeline: it has shut down"))?;
+        let queues = reply_rx.await.context("the pipeline has shut down before answering the query")?;
+        Ok(self.channel_stats_with(queues))
+    }
+
+    /// Like [`channel_stats`](Self::channel_stats), but blocks the current thread instead of being async.
+    ///
+    /// Do not use it in an async context.
+    pub fn blocking_channel_stats(&self) -> anyhow::Result<ChannelStats> {
+        let (reply, reply_rx) = oneshot::channel();
+        self.tx
+            .blocking_send(ControlMessage::QueryQueues(reply))
+            .map_err(|_| anyhow!("cannot query the pipeline: it has shut down"))?;
+        let queues = reply_rx
+            .blocking_recv()
+            .context("the pipeline has shut down before answering the query")?;
+        Ok(self.channel_stats_with(queues))
+    }
+
+    fn channel_stats_with(&self, queues: Vec<QueueStats>) -> ChannelStats {
+        let lost_output_messages = self
+            .output_counters_by_plugin
+            .lock()
+            .unwrap()
+            .values()
+            .flatten()
+            .map(|(name, c)| (name.clone(), c.lost_messages.load(Ordering::Relaxed)))
+            .collect();
+        ChannelStats {
+            queues,
+            dropped_source_buffers: self.dropped_source_buffers.load(Ordering::Relaxed),
+            lost_output_messages,
+        }
+    }
+
+    /// Waits for the pipeline to be idle: the measurements that the sources have sent have all been
+    /// handled by the transforms and outputs, and no output is writing.
+    ///
+    /// The pipeline keeps running: this is useful to synchronize a test with the outputs, for instance
+    /// after [`poll_sources_now`](ScopedControlHandle::poll_sources_now), instead of sleeping for an arbitrary time.
+    /// The measurements that a source has not flushed yet (see [`SourceCmd`]) are not in the pipeline,
+    /// and a paused output is never idle if some messages wait in its queue.
+    ///
+    /// The pipeline must be idle for two consecutive checks, about a millisecond apart.
+    /// Returns an error if it is still busy after `timeout`, or if it has shut down.
+    pub async fn wait_idle(&self, timeout: Duration) -> anyhow::Result<()> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        let mut idle_checks = 0;
+        loop {
+            let (reply, reply_rx) = oneshot::channel();
+            self.tx
+                .send(ControlMessage::QueryIdle(reply))
+                .await
+                .map_err(|_| anyhow!("cannot check the pipeline: it has shut down"))?;
+            let idle = reply_rx.await.context("the pipeline has shut down before answering the query")?;
+            idle_checks = if idle { idle_checks + 1 } else { 0 };
+            if idle_checks == 2 {
+                return Ok(());
+            }
+            if tokio::time::Instant::now() >= deadline {
+                return Err(anyhow!("the pipeline is still busy after {timeout:?}"));
             }
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+    }
+
+    /// Like [`wait_idle`](Self::wait_idle), but blocks the current thread instead of being async.
+    ///
+    /// Do not use it in an async context.
+    pub fn blocking_wait_idle(&self, timeout: Duration) -> anyhow::Result<()> {
+        let deadline = Instant::now() + timeout;
+        let mut idle_checks = 0;
+        loop {
+            let (reply, reply_rx) = oneshot::channel();
+            self.tx
+                .blocking_send(ControlMessage::QueryIdle(reply))
+                .map_err(|_| anyhow!("cannot check the pipeline: it has shut down"))?;
+            let idle = reply_rx
+                .blocking_recv()
+                .context("the pipeline has shut down before answering the query")?;
+            idle_checks = if idle { idle_checks + 1 } else { 0 };
+            if idle_checks == 2 {
+                return Ok(());
+            }
+            if Instant::now() >= deadline {
+                return Err(anyhow!("the pipeline is still busy after {timeout:?}"));
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    /// Returns the number of measurement buffers that the outputs of the plugin `plugin_name` have dropped
+    /// without trying to write them, because their circuit breaker was open (see [`CircuitBreakerPolicy`]).
+    ///
+    /// Returns 0 if the plugin has no output.
+    pub fn skipped_output_writes(&self, plugin_name: &str) -> u64 {
+        self.sum_output_counters(plugin_name, |c| &c.skipped_writes)
+    }
+
+    /// Returns the number of measurement buffers that the outputs of the plugin `plugin_name` have dropped
+    /// because of their sampling (see [`OutputRegistration::sampling`]).
+    ///
+    /// Returns 0 if the plugin has no output.
+    ///
+    /// [`OutputRegistration::sampling`]: builder::OutputRegistration::sampling
+    pub fn sampled_out_buffers(&self, plugin_name: &str) -> u64 {
+        self.sum_output_counters(plugin_name, |c| &c.sampled_out_buffers)
+    }
+
+    /// Returns the number of measurement points in the buffers counted by [`sampled_out_buffers`](Self::sampled_out_buffers).
+    pub fn sampled_out_points(&self, plugin_name: &str) -> u64 {
+        self.sum_output_counters(plugin_name, |c| &c.sampled_out_points)
+    }
+
+    /// Returns the state of the circuit breaker of each output of the plugin `plugin_name`,
+    /// with the name of the output.
+    ///
+    /// The outputs that have no circuit breaker are always [`Closed`](CircuitBreakerState::Closed).
+    pub fn circuit_breakers(&self, plugin_name: &str) -> Vec<(String, CircuitBreakerState)> {
+        match self.output_counters_by_plugin.lock().unwrap().get(plugin_name) {
+            Some(counters) => counters
+                .iter()
+                .map(|(name, c)| (name.clone(), c.breaker.lock().unwrap().state()))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Returns the result of the last health check of each output of the plugin `plugin_name`,
+    /// with the name of the output.
+    ///
+    /// An unhealthy output is paused until it recovers. The outputs that have no health check are always
+    /// [`Healthy`](Health::Healthy), see
+    /// [`OutputRegistration::health_check`](builder::OutputRegistration::health_check).
+    pub fn output_health(&self, plugin_name: &str) -> Vec<(String, Health)> {
+        match self.output_counters_by_plugin.lock().unwrap().get(plugin_name) {
+            Some(counters) => counters
+                .iter()
+                .map(|(name, c)| (name.clone(), c.health.lock().unwrap().clone()))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    fn sum_output_counters(&self, plugin_name: &str, counter: impl Fn(&OutputCounters) -> &AtomicU64) -> u64 {
+        match self.output_counters_by_plugin.lock().unwrap().get(plugin_name) {
+            Some(counters) => counters.iter().map(|(_, c)| counter(c).load(Ordering::Relaxed)).sum(),
+            None => 0,
         }
     }
 
     /// Adds a new source to the pipeline, without interrupting the elements
     /// (sources, transforms, outputs) that are currently running.
-    pub fn add_source(&self, plugin_name: String, source_name: String, source: Box<dyn Source>, trigger: TriggerSpec) {
+    ///
+    /// The source runs on the runtime that matches its trigger (see [`TriggerSpec`]).
+    /// If the "realtime priority" runtime, or the runtime of the blocking sources, does not exist yet,
+    /// it is created with one worker thread.
+    ///
+    /// Returns an error if the pipeline has been stopped.
+    pub fn add_source(
+        &self,
+        plugin_name: String,
+        source_name: String,
+        source: Box<dyn Source>,
+        trigger: TriggerSpec,
+    ) -> anyhow::Result<()> {
         let msg = ControlMessage::AddSource {
             requested_name: source_name,
             plugin_name,
             source,
             trigger,
         };
-        self.tx.try_send(msg).unwrap()
+        self.tx.try_send(msg).map_err(|e| match e {
+            TrySendError::Closed(_) => anyhow!("the pipeline has been stopped, the source cannot be added"),
+            TrySendError::Full(_) => anyhow!("too many commands are pending, the source cannot be added"),
+        })
     }
 }
 
+/// A [`ControlHandle`] that targets some elements of the pipeline.
+///
+/// The `control_*` methods return the number of elements that have been addressed by the command.
+/// This allows to detect mistakes in the name of the plugin: if no element matches, `Ok(0)` is returned.
 pub struct ScopedControlHandle<'a> {
     handle: &'a ControlHandle,
     destination: MessageDestination,
 }
 impl<'a> ScopedControlHandle<'a> {
-    pub async fn control_sources(self, command: SourceCmd) {
+    pub async fn control_sources(self, command: SourceCmd) -> anyhow::Result<usize> {
         // TODO investigate using try_send instead of send here
-        self.handle
-            .tx
-            .send(ControlMessage::ModifySource(ElementCommand {
-                destination: self.destination.clone(),
-                command,
-            }))
+        let (reply, reply_rx) = oneshot::channel();
+        self.send(ControlMessage::ModifySource(ElementCommand {
+            destination: self.destination.clone(),
+            command,
+            reply,
+        }))
+        .await?;
+        reply_rx.await.context("the pipeline has shut down before applying the command")
+    }
+    pub async fn control_transforms(self, command: TransformCmd) -> anyhow::Result<usize> {
+        let (reply, reply_rx) = oneshot::channel();
+        self.send(ControlMessage::ModifyTransform(ElementCommand {
+            destination: self.destination.clone(),
+            command,
+            reply,
+        }))
+        .await?;
+        reply_rx.await.context("the pipeline has shut down before applying the command")
+    }
+    pub async fn control_outputs(self, command: OutputCmd) -> anyhow::Result<usize> {
+        let (reply, reply_rx) = oneshot::channel();
+        self.send(ControlMessage::ModifyOutput(ElementCommand {
+            destination: self.destination.clone(),
+            command,
+            reply,
+        }))
+        .await?;
+        reply_rx.await.context("the pipeline has shut down before applying the command")
+    }
+
+    /// Polls the sources once, as soon as possible.
+    ///
+    /// This is intended for sources with a [manual trigger](super::trigger::builder::manual).
+    /// If the source is not waiting for its trigger yet, it will be polled immediately the next time it waits.
+    /// Multiple requests that arrive while the source is busy only result in one poll.
+    pub async fn poll_sources_now(self) -> anyhow::Result<usize> {
+        let (reply, reply_rx) = oneshot::channel();
+        self.send(ControlMessage::PollSourcesNow(ElementCommand {
+            destination: self.destination.clone(),
+            command: (),
+            reply,
+        }))
+        .await?;
+        reply_rx.await.context("the pipeline has shut down before applying the command")
+    }
+
+    /// Pauses the sources, then the outputs, in one step.
+    ///
+    /// Pausing the sources and the outputs separately leaves a window where other commands can be applied
+    /// between the two. Here, no other command is applied before both are paused.
+    /// If `drain_timeout` is set, the outputs are paused once the measurements that the sources had already sent
+    /// have been transformed and written, that is once the processing stage is idle (see
+    /// [`ControlHandle::wait_idle`]), or after the timeout expires: the outputs have then written a consistent
+    /// snapshot. With a destination that is not the whole pipeline, the other sources keep running,
+    /// and the processing stage is only idle between their buffers.
+    /// The measurements that reach a paused output wait in its queue, and are written when the output is resumed
+    /// (unless the queue overflows, in which case the oldest ones are lost, or the output has the policy
+    /// [`SlowOutputPolicy::Block`], in which case they are skipped).
+    ///
+    /// Returns the number of paused elements (sources and outputs).
+    pub async fn pause(self, drain_timeout: Option<Duration>) -> anyhow::Result<usize> {
+        let (reply, reply_rx) = oneshot::channel();
+        self.send(ControlMessage::Pause(ElementCommand {
+            destination: self.destination.clone(),
+            command: drain_timeout,
+            reply,
+        }))
+        .await?;
+        reply_rx.await.context("the pipeline has shut down before applying the command")
+    }
+
+    /// Resumes the outputs, then the sources, in one step. This reverts [`pause`](Self::pause).
+    ///
+    /// Returns the number of resumed elements (sources and outputs).
+    pub async fn resume(self) -> anyhow::Result<usize> {
+        let (reply, reply_rx) = oneshot::channel();
+        self.send(ControlMessage::Resume(ElementCommand {
+            destination: self.destination.clone(),
+            command: (),
+            reply,
+        }))
+        .await?;
+        reply_rx.await.context("the pipeline has shut down before applying the command")
+    }
+
+    /// Moves the sources to the runtime that matches `source_type`, for instance to increase their priority.
+    ///
+    /// Returns the number of moved sources once they have been restarted (a paused source is only restarted
+    /// when it resumes). See [`SourceCmd::SetPriority`].
+    pub async fn set_source_priority(self, source_type: SourceType) -> anyhow::Result<usize> {
+        self.control_sources(SourceCmd::SetPriority(source_type)).await
+    }
+
+    /// Stops the sources and waits for them to exit.
+    ///
+    /// Unlike [`control_sources(SourceCmd::Stop)`](Self::control_sources), which returns as soon as
+    /// the command has been sent, this returns the number of stopped sources once they have all exited.
+    /// The transforms and outputs keep running.
+    pub async fn stop_sources(self) -> anyhow::Result<usize> {
+        let (reply, reply_rx) = oneshot::channel();
+        self.send(ControlMessage::StopSources(ElementCommand {
+            destination: self.destination.clone(),
+            command: (),
+            reply,
+        }))
+        .await?;
+        reply_rx
             .await
-            .unwrap();
+            .context("the pipeline has shut down before the sources have stopped")
     }
-    pub async fn control_transforms(self, command: TransformCmd) {
-        self.handle
-            .tx
-            .send(ControlMessage::ModifyTransform(ElementCommand {
-                destination: self.destination.clone(),
-                command,
-            }))
+
+    /// Stops the sources and removes them from the pipeline.
+    ///
+    /// Returns the number of removed sources, once they have all exited.
+    /// Returns an error if there is no source to remove.
+    pub async fn remove_sources(self) -> anyhow::Result<usize> {
+        let (reply, reply_rx) = oneshot::channel();
+        self.send(ControlMessage::RemoveSources(ElementCommand {
+            destination: self.destination.clone(),
+            command: (),
+            reply,
+        }))
+        .await?;
+        let n = reply_rx
             .await
-            .unwrap();
+            .context("the pipeline has shut down before removing the sources")?;
+        check_removed_sources(n, &self.destination)
+    }
+
+    /// Returns the name and state of the sources.
+    ///
+    /// Only the commands [`SourceCmd::Run`], [`SourceCmd::Pause`] and [`SourceCmd::Stop`]
+    /// modify the state, not [`SourceCmd::SetTrigger`].
+    pub async fn source_states(self) -> anyhow::Result<Vec<(String, ElementState)>> {
+        let (reply, reply_rx) = oneshot::channel();
+        self.send(ControlMessage::QuerySourceStates(StateQuery {
+            destination: self.destination.clone(),
+            reply,
+        }))
+        .await?;
+        reply_rx.await.context("the pipeline has shut down before answering the query")
+    }
+
+    /// Returns the name and state of the outputs.
+    pub async fn output_states(self) -> anyhow::Result<Vec<(String, ElementState)>> {
+        let (reply, reply_rx) = oneshot::channel();
+        self.send(ControlMessage::QueryOutputStates(StateQuery {
+            destination: self.destination.clone(),
+            reply,
+        }))
+        .await?;
+        reply_rx.await.context("the pipeline has shut down before answering the query")
+    }
+
+    /// Aborts the tasks of the outputs, without waiting for them to write the measurements of their queue.
+    ///
+    /// This is a last resort, for the outputs that do not respond to [`OutputCmd::Stop`]
+    /// because they are stuck, for instance because of a wedged network connection.
+    /// The measurements that reach an aborted output are lost, and the aborted tasks are reported as errors
+    /// when the pipeline shuts down.
+    ///
+    /// A blocking [`Output::write`](super::Output::write) cannot be interrupted: it runs on its own thread
+    /// and has a mutable access to the output, which prevents the task from being cancelled before it returns.
+    /// Hence, the abort of a blocking output only takes effect after its current call to `write`,
+    /// and one thread of the pipeline waits for this call meanwhile. [`AsyncOutput`](super::AsyncOutput)s are
+    /// aborted at their next `.await` point.
+    ///
+    /// Returns the number of aborted outputs.
+    pub async fn abort_outputs(self) -> anyhow::Result<usize> {
+        let (reply, reply_rx) = oneshot::channel();
+        self.send(ControlMessage::AbortOutputs(ElementCommand {
+            destination: self.destination.clone(),
+            command: (),
+            reply,
+        }))
+        .await?;
+        reply_rx.await.context("the pipeline has shut down before applying the command")
     }
-    pub async fn control_outputs(self, command: OutputCmd) {
+
+    async fn send(&self, message: ControlMessage) -> anyhow::Result<()> {
         self.handle
             .tx
-            .send(ControlMessage::ModifyOutput(ElementCommand {
-                destination: self.destination.clone(),
-                command,
-            }))
+            .send(message)
             .await
-            .unwrap();
+            .map_err(|_| anyhow!("cannot send the command: the pipeline has shut down"))
     }
 }
 
+/// Like [`ScopedControlHandle`], but blocks the current thread instead of being async.
+///
+/// Do not use it in an async context.
 pub struct BlockingScopedControlHandle<'a> {
     handle: &'a ControlHandle,
     destination: MessageDestination,
 }
 impl<'a> BlockingScopedControlHandle<'a> {
-    pub fn control_sources(self, command: SourceCmd) {
-        self.handle
-            .tx
-            .blocking_send(ControlMessage::ModifySource(ElementCommand {
-                destination: self.destination.clone(),
-                command,
-            }))
-            .unwrap();
+    pub fn control_sources(self, command: SourceCmd) -> anyhow::Result<usize> {
+        let (reply, reply_rx) = oneshot::channel();
+        self.send(ControlMessage::ModifySource(ElementCommand {
+            destination: self.destination.clone(),
+            command,
+            reply,
+        }))?;
+        reply_rx
+            .blocking_recv()
+            .context("the pipeline has shut down before applying the command")
     }
-    pub fn control_transforms(self, command: TransformCmd) {
-        self.handle
-            .tx
-            .blocking_send(ControlMessage::ModifyTransform(ElementCommand {
-                destination: self.destination.clone(),
-                command,
-            }))
-            .unwrap();
+    pub fn control_transforms(self, command: TransformCmd) -> anyhow::Result<usize> {
+        let (reply, reply_rx) = oneshot::channel();
+        self.send(ControlMessage::ModifyTransform(ElementCommand {
+            destination: self.destination.clone(),
+            command,
+            reply,
+        }))?;
+        reply_rx
+            .blocking_recv()
+            .context("the pipeline has shut down before applying the command")
+    }
+    pub fn control_outputs(self, command: OutputCmd) -> anyhow::Result<usize> {
+        let (reply, reply_rx) = oneshot::channel();
+        self.send(ControlMessage::ModifyOutput(ElementCommand {
+            destination: self.destination.clone(),
+            command,
+            reply,
+        }))?;
+        reply_rx
+            .blocking_recv()
+            .context("the pipeline has shut down before applying the command")
+    }
+
+    /// Polls the sources once, as soon as possible.
+    ///
+    /// See [`ScopedControlHandle::poll_sources_now`].
+    pub fn poll_sources_now(self) -> anyhow::Result<usize> {
+        let (reply, reply_rx) = oneshot::channel();
+        self.send(ControlMessage::PollSourcesNow(ElementCommand {
+            destination: self.destination.clone(),
+            command: (),
+            reply,
+        }))?;
+        reply_rx
+            .blocking_recv()
+            .context("the pipeline has shut down before applying the command")
+    }
+
+    /// Pauses the sources, then the outputs, in one step.
+    ///
+    /// See [`ScopedControlHandle::pause`].
+    pub fn pause(self, drain_timeout: Option<Duration>) -> anyhow::Result<usize> {
+        let (reply, reply_rx) = oneshot::channel();
+        self.send(ControlMessage::Pause(ElementCommand {
+            destination: self.destination.clone(),
+            command: drain_timeout,
+            reply,
+        }))?;
+        reply_rx
+            .blocking_recv()
+            .context("the pipeline has shut down before applying the command")
+    }
+
+    /// Resumes the outputs, then the sources, in one step.
+    ///
+    /// See [`ScopedControlHandle::resume`].
+    pub fn resume(self) -> anyhow::Result<usize> {
+        let (reply, reply_rx) = oneshot::channel();
+        self.send(ControlMessage::Resume(ElementCommand {
+            destination: self.destination.clone(),
+            command: (),
+            reply,
+        }))?;
+        reply_rx
+            .blocking_recv()
+            .context("the pipeline has shut down before applying the command")
+    }
+
+    /// Moves the sources to the runtime that matches `source_type`.
+    ///
+    /// See [`ScopedControlHandle::set_source_priority`].
+    pub fn set_source_priority(self, source_type: SourceType) -> anyhow::Result<usize> {
+        self.control_sources(SourceCmd::SetPriority(source_type))
+    }
+
+    /// Stops the sources and waits for them to exit.
+    ///
+    /// See [`ScopedControlHandle::stop_sources`].
+    pub fn stop_sources(self) -> anyhow::Result<usize> {
+        let (reply, reply_rx) = oneshot::channel();
+        self.send(ControlMessage::StopSources(ElementCommand {
+            destination: self.destination.clone(),
+            command: (),
+            reply,
+        }))?;
+        reply_rx
+            .blocking_recv()
+            .context("the pipeline has shut down before the sources have stopped")
+    }
+
+    /// Stops the sources and removes them from the pipeline.
+    ///
+    /// See [`ScopedControlHandle::remove_sources`].
+    pub fn remove_sources(self) -> anyhow::Result<usize> {
+        let (reply, reply_rx) = oneshot::channel();
+        self.send(ControlMessage::RemoveSources(ElementCommand {
+            destination: self.destination.clone(),
+            command: (),
+            reply,
+        }))?;
+        let n = reply_rx
+            .blocking_recv()
+            .context("the pipeline has shut down before removing the sources")?;
+        check_removed_sources(n, &self.destination)
+    }
+
+    /// Returns the name and state of the sources.
+    ///
+    /// See [`ScopedControlHandle::source_states`].
+    pub fn source_states(self) -> anyhow::Result<Vec<(String, ElementState)>> {
+        let (reply, reply_rx) = oneshot::channel();
+        self.send(ControlMessage::QuerySourceStates(StateQuery {
+            destination: self.destination.clone(),
+            reply,
+        }))?;
+        reply_rx
+            .blocking_recv()
+            .context("the pipeline has shut down before answering the query")
+    }
+
+    /// Returns the name and state of the outputs.
+    ///
+    /// See [`ScopedControlHandle::output_states`].
+    pub fn output_states(self) -> anyhow::Result<Vec<(String, ElementState)>> {
+        let (reply, reply_rx) = oneshot::channel();
+        self.send(ControlMessage::QueryOutputStates(StateQuery {
+            destination: self.destination.clone(),
+            reply,
+        }))?;
+        reply_rx
+            .blocking_recv()
+            .context("the pipeline has shut down before answering the query")
+    }
+
+    /// Aborts the tasks of the outputs.
+    ///
+    /// See [`ScopedControlHandle::abort_outputs`].
+    pub fn abort_outputs(self) -> anyhow::Result<usize> {
+        let (reply, reply_rx) = oneshot::channel();
+        self.send(ControlMessage::AbortOutputs(ElementCommand {
+            destination: self.destination.clone(),
+            command: (),
+            reply,
+        }))?;
+        reply_rx
+            .blocking_recv()
+            .context("the pipeline has shut down before applying the command")
     }
-    pub fn control_outputs(self, command: OutputCmd) {
+
+    fn send(&self, message: ControlMessage) -> anyhow::Result<()> {
         self.handle
             .tx
-            .blocking_send(ControlMessage::ModifyOutput(ElementCommand {
-                destination: self.destination.clone(),
-                command,
-            }))
-            .unwrap();
+            .blocking_send(message)
+            .map_err(|_| anyhow!("cannot send the command: the pipeline has shut down"))
     }
 }
 
@@ -1071,7 +5333,7 @@ mod tests {
 
     use tokio::{
         runtime::Runtime,
-        sync::{broadcast, mpsc, watch},
+        sync::{broadcast, mpsc, watch, Notify},
     };
 
     use crate::{
@@ -1080,14 +5342,65 @@ mod tests {
             WrappedMeasurementValue,
         },
         metrics::{MetricRegistry, RawMetricId},
-        pipeline::{builder::ConfiguredTransform, trigger::TriggerSpec, OutputContext, Transform},
+        pipeline::{builder::ConfiguredTransform, trigger::TriggerSpec, OutputContext, Transform, TransformError},
         resources::{Resource, ResourceConsumer},
     };
 
     use super::{
-        super::trigger, run_output_from_broadcast, run_source, run_transforms, OutputCmd, OutputMsg, SourceCmd,
+        super::builder::{ConfiguredOutput, OutputFilter, DEFAULT_ROUTE},
+        super::trigger, check_transform_result, run_output_from_broadcast, run_source, run_transforms, LatencyHistogram,
+        OutputCmd, OutputCounters, OutputKind, OutputMsg, OutputsReady, RetryPolicy, SourceChannel, SourceCmd,
+        SourceOverflowPolicy, TransformErrorPolicy,
     };
 
+    #[test]
+    fn outputs_ready() {
+        let rt = new_rt(1);
+        let (started_tx, started_rx) = watch::channel(0);
+        let ready = OutputsReady {
+            started: started_rx,
+            n_outputs: 2,
+        };
+        let waiting = rt.spawn(ready.wait());
+        started_tx.send_modify(|n| *n += 1);
+        sleep(Duration::from_millis(20));
+        assert!(!waiting.is_finished(), "one output has not started yet");
+        started_tx.send_modify(|n| *n += 1);
+        rt.block_on(waiting).unwrap();
+
+        // the sources don't wait forever if the outputs are gone
+        let (started_tx, started_rx) = watch::channel(0);
+        drop(started_tx);
+        let ready = OutputsReady {
+            started: started_rx,
+            n_outputs: 1,
+        };
+        rt.block_on(ready.wait());
+    }
+
+    #[test]
+    fn latency_histogram() {
+        let histogram = LatencyHistogram::default();
+        assert_eq!(histogram.stats().quantile(0.5), None);
+
+        for micros in [0, 3, 3, 100, 5000] {
+            histogram.record(Duration::from_micros(micros));
+        }
+        let stats = histogram.stats();
+        assert_eq!(stats.count, 5);
+        assert_eq!(stats.max, Duration::from_millis(5));
+        assert_eq!(stats.mean, Duration::from_nanos(1_021_200));
+        // 3µs is in [2, 4), 100µs in [64, 128), 5ms in [4096, 8192)
+        assert_eq!(stats.quantile(0.0), Some(Duration::from_micros(1)));
+        assert_eq!(stats.quantile(0.5), Some(Duration::from_micros(4)));
+        assert_eq!(stats.quantile(0.8), Some(Duration::from_micros(128)));
+        assert_eq!(stats.quantile(1.0), Some(Duration::from_micros(8192)));
+
+        // the last bucket has no upper bound
+        histogram.record(Duration::from_secs(3600));
+        assert_eq!(histogram.stats().quantile(1.0), Some(Duration::MAX));
+    }
+
     #[test]
     fn source_triggered_by_time_normal() {
         run_source_trigger_test(false);
@@ -1148,7 +5461,15 @@ mod tests {
         });
 
         // poll the source for some time
-        rt.spawn(run_source(String::from("test_source"), Box::new(source), tx, cmd_rx));
+        rt.spawn(run_source(
+            String::from("test_source"),
+            String::from("test"),
+            Box::new(source),
+            source_channel(tx),
+            cmd_rx,
+            Arc::new(Notify::new()),
+            None,
+        ));
         sleep(2 * period);
 
         // pause source
@@ -1235,6 +5556,8 @@ mod tests {
                 transform: t,
                 name: String::from("test_transform"),
                 plugin_name: String::from(""),
+                route: String::from(DEFAULT_ROUTE),
+                error_policy: TransformErrorPolicy::Abort,
             })
             .collect();
 
@@ -1283,14 +5606,28 @@ mod tests {
         });
 
         // run the transforms
-        rt.spawn(run_transforms(transforms, src_rx, trans_tx, active_flags3));
+        rt.spawn(run_transforms(
+            transforms,
+            src_rx,
+            trans_tx,
+            active_flags3,
+            0,
+            None,
+            None,
+            Default::default(),
+            Default::default(),
+            Default::default(),
+        ));
 
         // poll the source for some time
         rt.spawn(run_source(
             String::from("test_source"),
+            String::from("test"),
             Box::new(source),
-            src_tx,
+            source_channel(src_tx),
             src_cmd_rx,
+            Arc::new(Notify::new()),
+            None,
         ));
         sleep(Duration::from_millis(20));
 
@@ -1307,14 +5644,224 @@ mod tests {
         active_flags.store(1 | 4, Ordering::Relaxed);
         sleep(Duration::from_millis(20));
 
-        // disable all transforms
-        active_flags.store(0, Ordering::Relaxed);
-        check_input_type_for_transform3.store(true, Ordering::Relaxed);
-        sleep(Duration::from_millis(20));
+        // disable all transforms
+        active_flags.store(0, Ordering::Relaxed);
+        check_input_type_for_transform3.store(true, Ordering::Relaxed);
+        sleep(Duration::from_millis(20));
+
+        // enable all transforms
+        active_flags.store(1 | 2 | 4, Ordering::Relaxed);
+        sleep(Duration::from_millis(20));
+    }
+
+    #[test]
+    fn transform_error_context() {
+        struct NoopTransform;
+        impl Transform for NoopTransform {
+            fn apply(&mut self, _measurements: &mut MeasurementBuffer) -> Result<(), TransformError> {
+                Ok(())
+            }
+        }
+        let mut t = ConfiguredTransform {
+            transform: Box::new(NoopTransform),
+            name: String::from("plugin/transform-0"),
+            plugin_name: String::from("plugin"),
+            route: String::from(DEFAULT_ROUTE),
+            error_policy: TransformErrorPolicy::Abort,
+        };
+        assert!(check_transform_result(&t, Ok(())).is_ok());
+        let res = check_transform_result(&t, Err(TransformError::UnexpectedInput(anyhow::anyhow!("bad input"))));
+        assert!(res.is_ok(), "unexpected measurements must not stop the transforms");
+
+        let err = check_transform_result(&t, Err(TransformError::Fatal(anyhow::anyhow!("boom")))).unwrap_err();
+        let msg = format!("{err:#}");
+        assert!(msg.contains("plugin/transform-0") && msg.contains("plugin 'plugin'"), "{msg}");
+
+        // a non-critical transform does not stop the others
+        t.error_policy = TransformErrorPolicy::SkipAndContinue;
+        let res = check_transform_result(&t, Err(TransformError::Fatal(anyhow::anyhow!("boom"))));
+        assert!(res.is_ok());
+    }
+
+    #[test]
+    fn parallel_transforms() {
+        /// Adds one point of metric `metric` for each point that comes from the source (metric 1).
+        struct AppendTransform {
+            metric: usize,
+            parallelizable: bool,
+        }
+        impl Transform for AppendTransform {
+            fn apply(&mut self, measurements: &mut MeasurementBuffer) -> Result<(), crate::pipeline::TransformError> {
+                let new_points: Vec<MeasurementPoint> = measurements
+                    .iter()
+                    .filter(|p| p.metric == RawMetricId(1))
+                    .map(|p| {
+                        let mut new_point = p.clone();
+                        new_point.metric = RawMetricId(self.metric);
+                        new_point
+                    })
+                    .collect();
+                for p in new_points {
+                    measurements.push(p);
+                }
+                Ok(())
+            }
+
+            fn parallelizable(&self) -> bool {
+                self.parallelizable
+            }
+        }
+
+        /// Modifies the existing points, hence depends on the previous transforms.
+        struct DoubleTransform;
+        impl Transform for DoubleTransform {
+            fn apply(&mut self, measurements: &mut MeasurementBuffer) -> Result<(), crate::pipeline::TransformError> {
+                for p in measurements.iter_mut() {
+                    if let WrappedMeasurementValue::U64(n) = p.value {
+                        p.value = WrappedMeasurementValue::U64(2 * n);
+                    }
+                }
+                Ok(())
+            }
+        }
+
+        fn run(parallelizable: bool, active_flags: u64) -> Vec<(usize, u64)> {
+            let rt = new_rt(2);
+            let append = |metric| -> Box<dyn Transform> { Box::new(AppendTransform { metric, parallelizable }) };
+            let transforms: Vec<Box<dyn Transform>> =
+                vec![append(10), append(11), append(12), Box::new(DoubleTransform), append(13), append(14)];
+            let transforms = transforms
+                .into_iter()
+                .map(|t| ConfiguredTransform {
+                    transform: t,
+                    name: String::from("test_transform"),
+                    plugin_name: String::from(""),
+                    route: String::from(DEFAULT_ROUTE),
+                    error_policy: TransformErrorPolicy::Abort,
+                })
+                .collect();
+            let (src_tx, src_rx) = mpsc::channel::<MeasurementBuffer>(64);
+            let (out_tx, mut out_rx) = broadcast::channel::<OutputMsg>(64);
+            let active_flags = Arc::new(AtomicU64::new(active_flags));
+            rt.spawn(run_transforms(
+                transforms,
+                src_rx,
+                out_tx,
+                active_flags,
+                0,
+                None,
+                None,
+                Default::default(),
+                Default::default(),
+                Default::default(),
+            ));
+
+            let points = (1..=3)
+                .map(|n| {
+                    MeasurementPoint::new_untyped(
+                        Timestamp::now(),
+                        RawMetricId(1),
+                        Resource::LocalMachine,
+                        ResourceConsumer::LocalMachine,
+                        WrappedMeasurementValue::U64(n),
+                    )
+                })
+                .collect::<Vec<_>>();
+            rt.block_on(async move {
+                src_tx.send(MeasurementBuffer::from(points)).await.unwrap();
+                match out_rx.recv().await.unwrap() {
+                    OutputMsg::WriteMeasurements(buf) => buf
+                        .iter()
+                        .map(|p| match p.value {
+                            WrappedMeasurementValue::U64(n) => (p.metric.0, n),
+                            _ => panic!("unexpected value type"),
+                        })
+                        .collect(),
+                    _ => panic!("unexpected message"),
+                }
+            })
+        }
+
+        // the result of the parallel application must be identical to the sequential one
+        for active_flags in [u64::MAX, 0b110101, 0b010110, 0] {
+            let sequential = run(false, active_flags);
+            let parallel = run(true, active_flags);
+            assert_eq!(sequential, parallel, "active flags: {active_flags:#b}");
+        }
+        // check the sequential result
+        let expected_metrics = [vec![1; 3], vec![10; 3], vec![11; 3], vec![12; 3], vec![13; 3], vec![14; 3]].concat();
+        let result = run(true, u64::MAX);
+        assert_eq!(result.iter().map(|(m, _)| *m).collect::<Vec<_>>(), expected_metrics);
+        assert_eq!(result[..3], [(1, 2), (1, 4), (1, 6)]);
+        assert_eq!(result[15..], [(14, 2), (14, 4), (14, 6)]);
+    }
+
+    #[test]
+    fn processed_buffer_bypasses_transforms() {
+        struct IncrementTransform;
+        impl Transform for IncrementTransform {
+            fn apply(&mut self, measurements: &mut MeasurementBuffer) -> Result<(), crate::pipeline::TransformError> {
+                for p in measurements.iter_mut() {
+                    if let WrappedMeasurementValue::U64(n) = p.value {
+                        p.value = WrappedMeasurementValue::U64(n + 1);
+                    }
+                }
+                Ok(())
+            }
+        }
+
+        let rt = new_rt(2);
+        let transforms = vec![ConfiguredTransform {
+            transform: Box::new(IncrementTransform),
+            name: String::from("test_transform"),
+            plugin_name: String::from(""),
+            route: String::from(DEFAULT_ROUTE),
+            error_policy: TransformErrorPolicy::Abort,
+        }];
+        let (src_tx, src_rx) = mpsc::channel::<MeasurementBuffer>(64);
+        let (out_tx, mut out_rx) = broadcast::channel::<OutputMsg>(64);
+        let active_flags = Arc::new(AtomicU64::new(u64::MAX));
+        rt.spawn(run_transforms(
+            transforms,
+            src_rx,
+            out_tx,
+            active_flags,
+            0,
+            None,
+            None,
+            Default::default(),
+            Default::default(),
+            Default::default(),
+        ));
 
-        // enable all transforms
-        active_flags.store(1 | 2 | 4, Ordering::Relaxed);
-        sleep(Duration::from_millis(20));
+        let buffer = || {
+            MeasurementBuffer::from(vec![MeasurementPoint::new_untyped(
+                Timestamp::now(),
+                RawMetricId(1),
+                Resource::LocalMachine,
+                ResourceConsumer::LocalMachine,
+                WrappedMeasurementValue::U64(0),
+            )])
+        };
+        let values = rt.block_on(async move {
+            let mut processed = buffer();
+            processed.mark_processed();
+            src_tx.send(processed).await.unwrap();
+            src_tx.send(buffer()).await.unwrap();
+            let mut values = Vec::new();
+            for _ in 0..2 {
+                match out_rx.recv().await.unwrap() {
+                    OutputMsg::WriteMeasurements(buf) => match buf.iter().next().unwrap().value {
+                        WrappedMeasurementValue::U64(n) => values.push(n),
+                        _ => panic!("unexpected value type"),
+                    },
+                    _ => panic!("unexpected message"),
+                }
+            }
+            values
+        });
+        // the processed buffer is forwarded as is, the other one is transformed
+        assert_eq!(values, vec![0, 1]);
     }
 
     #[test]
@@ -1344,14 +5891,37 @@ mod tests {
 
         // start tasks
         rt.spawn(run_output_from_broadcast(
-            String::from("test_output"),
-            output,
+            configured_output("test_output", OutputKind::Blocking(output), None),
             out_rx,
+            None,
             out_cmd_rx,
             out_ctx,
+            Arc::new(OutputCounters::default()),
+            None,
+            Default::default(),
+            true,
+        ));
+        rt.spawn(run_transforms(
+            transforms,
+            trans_rx,
+            trans_tx,
+            active_flags,
+            0,
+            None,
+            None,
+            Default::default(),
+            Default::default(),
+            Default::default(),
+        ));
+        rt.spawn(run_source(
+            String::from("test_source"),
+            String::from("test"),
+            source,
+            source_channel(src_tx),
+            src_cmd_rx,
+            Arc::new(Notify::new()),
+            None,
         ));
-        rt.spawn(run_transforms(transforms, trans_rx, trans_tx, active_flags));
-        rt.spawn(run_source(String::from("test_source"), source, src_tx, src_cmd_rx));
 
         // check the output
         sleep(Duration::from_millis(20));
@@ -1379,6 +5949,664 @@ mod tests {
         assert_eq!(count, output_count.load(Ordering::Relaxed));
     }
 
+    #[test]
+    fn transforms_after_outputs_stop() {
+        let rt = new_rt(2);
+        let (src_tx, trans_rx) = mpsc::channel::<MeasurementBuffer>(64);
+        let (trans_tx, out_rx) = broadcast::channel::<OutputMsg>(64);
+        let active_flags = Arc::new(AtomicU64::new(u64::MAX));
+        let output = Box::new(TestOutput {
+            expected_input_len: 1,
+            output_count: Arc::new(AtomicU32::new(0)),
+        });
+        let (out_cmd_tx, out_cmd_rx) = watch::channel(OutputCmd::Run);
+        let out_ctx = OutputContext {
+            metrics: MetricRegistry::new(),
+        };
+        let output_task = rt.spawn(run_output_from_broadcast(
+            configured_output("test_output", OutputKind::Blocking(output), None),
+            out_rx,
+            None,
+            out_cmd_rx,
+            out_ctx,
+            Arc::new(OutputCounters::default()),
+            None,
+            Default::default(),
+            true,
+        ));
+        let transforms_task =
+            rt.spawn(run_transforms(
+                vec![],
+                trans_rx,
+                trans_tx,
+                active_flags,
+                0,
+                None,
+                None,
+                Default::default(),
+                Default::default(),
+                Default::default(),
+            ));
+
+        // the only output stops, then the transforms keep receiving measurements
+        out_cmd_tx.send(OutputCmd::Stop).unwrap();
+        rt.block_on(async move {
+            output_task.await.unwrap().unwrap();
+            for n in 0..3 {
+                let point = MeasurementPoint::new_untyped(
+                    Timestamp::now(),
+                    RawMetricId(1),
+                    Resource::LocalMachine,
+                    ResourceConsumer::LocalMachine,
+                    WrappedMeasurementValue::U64(n),
+                );
+                src_tx.send(MeasurementBuffer::from(vec![point])).await.unwrap();
+            }
+            drop(src_tx);
+            // the measurements are discarded, this is not an error
+            transforms_task.await.unwrap().unwrap();
+        });
+    }
+
+    #[test]
+    fn output_filter() {
+        let rt = new_rt(2);
+        let point = |metric: usize| {
+            MeasurementPoint::new_untyped(
+                Timestamp::now(),
+                RawMetricId(metric),
+                Resource::LocalMachine,
+                ResourceConsumer::LocalMachine,
+                WrappedMeasurementValue::U64(0),
+            )
+        };
+
+        let (out_tx, out_rx) = broadcast::channel::<OutputMsg>(64);
+        let (_out_cmd_tx, out_cmd_rx) = watch::channel(OutputCmd::Run);
+        let output_count = Arc::new(AtomicU32::new(0));
+        let output = Box::new(TestOutput {
+            expected_input_len: 1,
+            output_count: output_count.clone(),
+        });
+        let out_ctx = OutputContext {
+            metrics: MetricRegistry::new(),
+        };
+        let task = rt.spawn(run_output_from_broadcast(
+            configured_output(
+                "test_output",
+                OutputKind::Blocking(output),
+                Some(Box::new(|p: &MeasurementPoint| p.metric == RawMetricId(1))),
+            ),
+            out_rx,
+            None,
+            out_cmd_rx,
+            out_ctx,
+            Arc::new(OutputCounters::default()),
+            None,
+            Default::default(),
+            true,
+        ));
+
+        // only one point matches the filter
+        let buf = MeasurementBuffer::from(vec![point(1), point(2), point(2)]);
+        out_tx.send(OutputMsg::WriteMeasurements(buf)).unwrap();
+        // no point matches the filter, the output must not be called (TestOutput would panic)
+        let buf = MeasurementBuffer::from(vec![point(2)]);
+        out_tx.send(OutputMsg::WriteMeasurements(buf)).unwrap();
+
+        // closing the channel stops the output after the pending messages
+        drop(out_tx);
+        rt.block_on(task).unwrap().unwrap();
+        assert_eq!(output_count.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn output_lag() {
+        let rt = new_rt(2);
+        let (out_tx, out_rx) = broadcast::channel::<OutputMsg>(4);
+        let (_out_cmd_tx, out_cmd_rx) = watch::channel(OutputCmd::Run);
+        let output_count = Arc::new(AtomicU32::new(0));
+        let output = Box::new(TestOutput {
+            expected_input_len: 1,
+            output_count: output_count.clone(),
+        });
+        let out_ctx = OutputContext {
+            metrics: MetricRegistry::new(),
+        };
+        let counters = Arc::new(OutputCounters::default());
+
+        // fill the queue before starting the output, so that it lags behind
+        let point = MeasurementPoint::new_untyped(
+            Timestamp::now(),
+            RawMetricId(1),
+            Resource::LocalMachine,
+            ResourceConsumer::LocalMachine,
+            WrappedMeasurementValue::U64(0),
+        );
+        for _ in 0..10 {
+            let buf = MeasurementBuffer::from(vec![point.clone()]);
+            out_tx.send(OutputMsg::WriteMeasurements(buf)).unwrap();
+        }
+        drop(out_tx);
+
+        let task = rt.spawn(run_output_from_broadcast(
+            configured_output("test_output", OutputKind::Blocking(output), None),
+            out_rx,
+            None,
+            out_cmd_rx,
+            out_ctx,
+            counters.clone(),
+            None,
+            Default::default(),
+            true,
+        ));
+        rt.block_on(task).unwrap().unwrap();
+        assert_eq!(counters.lost_messages.load(Ordering::Relaxed), 6);
+        assert_eq!(output_count.load(Ordering::Relaxed), 4);
+    }
+
+    #[test]
+    fn set_trigger_restarts_flush_rounds() {
+        let rt = new_rt(2);
+        let manual_trigger = |flush_rounds| trigger::builder::manual().flush_rounds(flush_rounds).build().unwrap();
+        let (src_tx, mut src_rx) = mpsc::channel::<MeasurementBuffer>(64);
+        let (src_cmd_tx, src_cmd_rx) = watch::channel(SourceCmd::SetTrigger(Some(manual_trigger(3))));
+        let poll_now = Arc::new(Notify::new());
+        rt.spawn(run_source(
+            String::from("test_source"),
+            String::from("test"),
+            Box::new(TestSource::new()),
+            source_channel(src_tx),
+            src_cmd_rx,
+            poll_now.clone(),
+            None,
+        ));
+        let poll = |n: usize| {
+            for _ in 0..n {
+                poll_now.notify_one();
+                sleep(Duration::from_millis(10));
+            }
+        };
+
+        // 2 polls out of 3, no flush yet
+        poll(2);
+        assert!(src_rx.try_recv().is_err());
+
+        // change the trigger in the middle of the flush period: the next flush is 4 polls later
+        src_cmd_tx.send(SourceCmd::SetTrigger(Some(manual_trigger(4)))).unwrap();
+        sleep(Duration::from_millis(10));
+        poll(3);
+        assert!(src_rx.try_recv().is_err(), "the flush must occur exactly 4 polls after the new trigger");
+        poll(1);
+        let flushed = src_rx.try_recv().expect("the source should have flushed its measurements");
+        // the measurements polled before the change are kept
+        assert_eq!(flushed.len(), 6);
+
+        // the flush period is regular after that
+        poll(4);
+        assert_eq!(src_rx.try_recv().unwrap().len(), 4);
+        src_cmd_tx.send(SourceCmd::Stop).unwrap();
+    }
+
+    #[test]
+    fn output_command_channel_closed() {
+        let rt = new_rt(2);
+        for initial_cmd in [OutputCmd::Run, OutputCmd::Pause] {
+            let (_out_tx, out_rx) = broadcast::channel::<OutputMsg>(4);
+            let (out_cmd_tx, out_cmd_rx) = watch::channel(OutputCmd::Run);
+            let output = Box::new(TestOutput {
+                expected_input_len: 1,
+                output_count: Arc::new(AtomicU32::new(0)),
+            });
+            let out_ctx = OutputContext {
+                metrics: MetricRegistry::new(),
+            };
+            let task = rt.spawn(run_output_from_broadcast(
+                configured_output("test_output", OutputKind::Blocking(output), None),
+                out_rx,
+                None,
+                out_cmd_rx,
+                out_ctx,
+                Arc::new(OutputCounters::default()),
+                None,
+                Default::default(),
+                true,
+            ));
+            out_cmd_tx.send(initial_cmd).unwrap();
+            sleep(Duration::from_millis(20));
+
+            // dropping the sender stops the output, without any error
+            drop(out_cmd_tx);
+            rt.block_on(task).unwrap().unwrap();
+        }
+    }
+
+    #[test]
+    fn output_retry() {
+        struct FlakyOutput {
+            failures_left: u32,
+            attempts: Arc<AtomicU32>,
+            written: Arc<AtomicU32>,
+        }
+        impl crate::pipeline::Output for FlakyOutput {
+            fn write(
+                &mut self,
+                _measurements: &MeasurementBuffer,
+                _ctx: &OutputContext,
+            ) -> Result<(), crate::pipeline::WriteError> {
+                self.attempts.fetch_add(1, Ordering::Relaxed);
+                if self.failures_left > 0 {
+                    self.failures_left -= 1;
+                    Err(crate::pipeline::WriteError::CanRetry(anyhow::anyhow!("temporary failure")))
+                } else {
+                    self.written.fetch_add(1, Ordering::Relaxed);
+                    Ok(())
+                }
+            }
+        }
+
+        let rt = new_rt(2);
+        let (out_tx, out_rx) = broadcast::channel::<OutputMsg>(64);
+        let (_out_cmd_tx, out_cmd_rx) = watch::channel(OutputCmd::Run);
+        let attempts = Arc::new(AtomicU32::new(0));
+        let written = Arc::new(AtomicU32::new(0));
+        let output = Box::new(FlakyOutput {
+            failures_left: 5,
+            attempts: attempts.clone(),
+            written: written.clone(),
+        });
+        let mut out = configured_output("test_output", OutputKind::Blocking(output), None);
+        out.retry = Some(RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(5),
+        });
+        let out_ctx = OutputContext {
+            metrics: MetricRegistry::new(),
+        };
+        let counters = Arc::new(OutputCounters::default());
+
+        let point = MeasurementPoint::new_untyped(
+            Timestamp::now(),
+            RawMetricId(1),
+            Resource::LocalMachine,
+            ResourceConsumer::LocalMachine,
+            WrappedMeasurementValue::U64(0),
+        );
+        for _ in 0..2 {
+            let buf = MeasurementBuffer::from(vec![point.clone()]);
+            out_tx.send(OutputMsg::WriteMeasurements(buf)).unwrap();
+        }
+        drop(out_tx);
+
+        let task = rt.spawn(run_output_from_broadcast(
+            out,
+            out_rx,
+            None,
+            out_cmd_rx,
+            out_ctx,
+            counters.clone(),
+            None,
+            Default::default(),
+            true,
+        ));
+        rt.block_on(task).unwrap().unwrap();
+        // 1st buffer: 3 failed attempts, dropped
+        // 2nd buffer: 2 failed attempts, then written
+        assert_eq!(attempts.load(Ordering::Relaxed), 6);
+        assert_eq!(written.load(Ordering::Relaxed), 1);
+        assert_eq!(counters.failed_writes.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn output_reconnect() {
+        /// Loses its connection after each write, and fails to reconnect `reconnect_failures` times.
+        struct UnstableOutput {
+            connected: bool,
+            reconnect_failures: u32,
+            reconnections: Arc<AtomicU32>,
+            written: Arc<AtomicU32>,
+        }
+        impl crate::pipeline::Output for UnstableOutput {
+            fn write(
+                &mut self,
+                _measurements: &MeasurementBuffer,
+                _ctx: &OutputContext,
+            ) -> Result<(), crate::pipeline::WriteError> {
+                if !self.connected {
+                    return Err(crate::pipeline::WriteError::Disconnected(anyhow::anyhow!("connection lost")));
+                }
+                self.connected = false;
+                self.written.fetch_add(1, Ordering::Relaxed);
+                Ok(())
+            }
+
+            fn reconnect(&mut self) -> Result<(), crate::pipeline::WriteError> {
+                self.reconnections.fetch_add(1, Ordering::Relaxed);
+                if self.reconnect_failures > 0 {
+                    self.reconnect_failures -= 1;
+                    return Err(crate::pipeline::WriteError::CanRetry(anyhow::anyhow!("no route to host")));
+                }
+                self.connected = true;
+                Ok(())
+            }
+        }
+
+        let rt = new_rt(2);
+        let (out_tx, out_rx) = broadcast::channel::<OutputMsg>(64);
+        let (_out_cmd_tx, out_cmd_rx) = watch::channel(OutputCmd::Run);
+        let reconnections = Arc::new(AtomicU32::new(0));
+        let written = Arc::new(AtomicU32::new(0));
+        let output = Box::new(UnstableOutput {
+            connected: true,
+            reconnect_failures: 1,
+            reconnections: reconnections.clone(),
+            written: written.clone(),
+        });
+        let mut out = configured_output("test_output", OutputKind::Blocking(output), None);
+        out.retry = Some(RetryPolicy {
+            max_attempts: 2,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(5),
+        });
+        let out_ctx = OutputContext {
+            metrics: MetricRegistry::new(),
+        };
+        let counters = Arc::new(OutputCounters::default());
+
+        let point = MeasurementPoint::new_untyped(
+            Timestamp::now(),
+            RawMetricId(1),
+            Resource::LocalMachine,
+            ResourceConsumer::LocalMachine,
+            WrappedMeasurementValue::U64(0),
+        );
+        for _ in 0..3 {
+            let buf = MeasurementBuffer::from(vec![point.clone()]);
+            out_tx.send(OutputMsg::WriteMeasurements(buf)).unwrap();
+        }
+        drop(out_tx);
+
+        let task = rt.spawn(run_output_from_broadcast(
+            out,
+            out_rx,
+            None,
+            out_cmd_rx,
+            out_ctx,
+            counters.clone(),
+            None,
+            Default::default(),
+            true,
+        ));
+        rt.block_on(task).unwrap().unwrap();
+        // 1st buffer: written
+        // 2nd buffer: disconnected, the reconnection fails, then succeeds at the next attempt
+        // 3rd buffer: disconnected, reconnected at once
+        assert_eq!(written.load(Ordering::Relaxed), 3);
+        assert_eq!(reconnections.load(Ordering::Relaxed), 3);
+        assert_eq!(counters.failed_writes.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn retry_backoff() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_millis(25),
+        };
+        assert_eq!(policy.backoff(1), Duration::from_millis(10));
+        assert_eq!(policy.backoff(2), Duration::from_millis(20));
+        assert_eq!(policy.backoff(3), Duration::from_millis(25));
+        assert_eq!(policy.backoff(100), Duration::from_millis(25));
+    }
+
+    #[test]
+    fn async_output() {
+        struct TestAsyncOutput {
+            output_count: Arc<AtomicU32>,
+        }
+        impl crate::pipeline::AsyncOutput for TestAsyncOutput {
+            fn write_async<'a>(
+                &'a mut self,
+                measurements: &'a MeasurementBuffer,
+                _ctx: &'a OutputContext,
+            ) -> trigger::BoxFuture<'a, Result<(), crate::pipeline::WriteError>> {
+                Box::pin(async move {
+                    tokio::task::yield_now().await;
+                    self.output_count.fetch_add(measurements.len() as _, Ordering::Relaxed);
+                    Ok(())
+                })
+            }
+        }
+
+        // the async output is awaited directly, a single thread is enough
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let (out_tx, out_rx) = broadcast::channel::<OutputMsg>(64);
+        let (_out_cmd_tx, out_cmd_rx) = watch::channel(OutputCmd::Run);
+        let output_count = Arc::new(AtomicU32::new(0));
+        let output = Box::new(TestAsyncOutput {
+            output_count: output_count.clone(),
+        });
+        let out_ctx = OutputContext {
+            metrics: MetricRegistry::new(),
+        };
+        let task = rt.spawn(run_output_from_broadcast(
+            configured_output("test_async_output", OutputKind::Async(output), None),
+            out_rx,
+            None,
+            out_cmd_rx,
+            out_ctx,
+            Arc::new(OutputCounters::default()),
+            None,
+            Default::default(),
+            true,
+        ));
+
+        let point = MeasurementPoint::new_untyped(
+            Timestamp::now(),
+            RawMetricId(1),
+            Resource::LocalMachine,
+            ResourceConsumer::LocalMachine,
+            WrappedMeasurementValue::U64(0),
+        );
+        for _ in 0..3 {
+            let buf = MeasurementBuffer::from(vec![point.clone(); 2]);
+            out_tx.send(OutputMsg::WriteMeasurements(buf)).unwrap();
+        }
+        drop(out_tx);
+        rt.block_on(task).unwrap().unwrap();
+        assert_eq!(output_count.load(Ordering::Relaxed), 6);
+    }
+
+    #[test]
+    fn reduced_pipeline() {
+        let rt = new_rt(3);
+        let source = Box::new(TestSource::new());
+        let tp = new_trigger(false, Duration::from_millis(10), 2);
+        let (src_tx, direct_rx) = mpsc::channel::<MeasurementBuffer>(64);
+        let (src_cmd_tx, src_cmd_rx) = watch::channel(SourceCmd::SetTrigger(Some(tp)));
+
+        // no transform task: the output receives the measurements directly from the source
+        let (to_outputs, out_rx) = broadcast::channel::<OutputMsg>(64);
+        drop(to_outputs); // a closed broadcast queue must not stop the output
+        let output_count = Arc::new(AtomicU32::new(0));
+        let output = Box::new(TestOutput {
+            expected_input_len: 2,
+            output_count: output_count.clone(),
+        });
+        let (out_cmd_tx, out_cmd_rx) = watch::channel(OutputCmd::Run);
+        let out_ctx = OutputContext {
+            metrics: MetricRegistry::new(),
+        };
+
+        let output_task = rt.spawn(run_output_from_broadcast(
+            configured_output("test_output", OutputKind::Blocking(output), None),
+            out_rx,
+            Some(direct_rx.into()),
+            out_cmd_rx,
+            out_ctx,
+            Arc::new(OutputCounters::default()),
+            None,
+            Default::default(),
+            true,
+        ));
+        rt.spawn(run_source(
+            String::from("test_source"),
+            String::from("test"),
+            source,
+            source_channel(src_tx),
+            src_cmd_rx,
+            Arc::new(Notify::new()),
+            None,
+        ));
+
+        // check the output
+        sleep(Duration::from_millis(30));
+        assert!(output_count.load(Ordering::Relaxed) > 0);
+
+        // pause and resume
+        out_cmd_tx.send(OutputCmd::Pause).unwrap();
+        sleep(Duration::from_millis(10));
+        let count_at_pause = output_count.load(Ordering::Relaxed);
+        sleep(Duration::from_millis(30));
+        assert_eq!(count_at_pause, output_count.load(Ordering::Relaxed));
+        out_cmd_tx.send(OutputCmd::Run).unwrap();
+        sleep(Duration::from_millis(30));
+        assert!(output_count.load(Ordering::Relaxed) > count_at_pause);
+
+        // stopping the source closes the channel, which stops the output
+        src_cmd_tx.send(SourceCmd::Stop).unwrap();
+        rt.block_on(async { tokio::time::timeout(Duration::from_millis(500), output_task).await })
+            .expect("the output should stop when the sources stop")
+            .unwrap()
+            .unwrap();
+    }
+
+    /// Compares the latency of a reduced pipeline (source -> output) with the latency of
+    /// a full pipeline (source -> transforms -> broadcast -> output).
+    ///
+    /// Run with `cargo test --release -- --ignored --nocapture bench_reduced_pipeline`.
+    #[test]
+    #[ignore = "benchmark"]
+    fn bench_reduced_pipeline() {
+        const N_BUFFERS: u32 = 100_000;
+
+        struct CountingOutput(Arc<AtomicU32>);
+        impl crate::pipeline::Output for CountingOutput {
+            fn write(
+                &mut self,
+                _measurements: &MeasurementBuffer,
+                _ctx: &OutputContext,
+            ) -> Result<(), crate::pipeline::WriteError> {
+                self.0.fetch_add(1, Ordering::Relaxed);
+                Ok(())
+            }
+        }
+
+        fn run(reduced: bool) -> Duration {
+            let rt = new_rt(2);
+            let (src_tx, src_rx) = mpsc::channel::<MeasurementBuffer>(256);
+            let (to_outputs, out_rx) = broadcast::channel::<OutputMsg>(256);
+            let (_out_cmd_tx, out_cmd_rx) = watch::channel(OutputCmd::Run);
+            let count = Arc::new(AtomicU32::new(0));
+            let output = Box::new(CountingOutput(count.clone()));
+            let ctx = OutputContext {
+                metrics: MetricRegistry::new(),
+            };
+
+            let direct = if reduced {
+                drop(to_outputs);
+                Some(src_rx.into())
+            } else {
+                let active_flags = Arc::new(AtomicU64::new(u64::MAX));
+                rt.spawn(run_transforms(
+                    vec![],
+                    src_rx,
+                    to_outputs,
+                    active_flags,
+                    0,
+                    None,
+                    None,
+                    Default::default(),
+                    Default::default(),
+                    Default::default(),
+                ));
+                None
+            };
+            let output_task = rt.spawn(run_output_from_broadcast(
+                configured_output("bench_output", OutputKind::Blocking(output), None),
+                out_rx,
+                direct,
+                out_cmd_rx,
+                ctx,
+                Arc::new(OutputCounters::default()),
+                None,
+                Default::default(),
+                true,
+            ));
+
+            let point = MeasurementPoint::new_untyped(
+                Timestamp::now(),
+                RawMetricId(1),
+                Resource::LocalMachine,
+                ResourceConsumer::LocalMachine,
+                WrappedMeasurementValue::U64(0),
+            );
+            let buf = MeasurementBuffer::from(vec![point; 64]);
+            let start = std::time::Instant::now();
+            rt.block_on(async move {
+                for _ in 0..N_BUFFERS {
+                    src_tx.send(buf.clone()).await.unwrap();
+                }
+                drop(src_tx);
+                output_task.await.unwrap().unwrap();
+            });
+            let elapsed = start.elapsed();
+            assert!(count.load(Ordering::Relaxed) > 0);
+            elapsed
+        }
+
+        let full = run(false);
+        let reduced = run(true);
+        println!(
+            "{N_BUFFERS} buffers: full pipeline {full:?} ({:?}/buffer), reduced pipeline {reduced:?} ({:?}/buffer)",
+            full / N_BUFFERS,
+            reduced / N_BUFFERS
+        );
+    }
+
+    #[test]
+    fn stop_flushes_partial_buffer() {
+        let rt = new_rt(1);
+        let (tx, mut rx) = mpsc::channel::<MeasurementBuffer>(16);
+        let (cmd_tx, cmd_rx) = watch::channel(SourceCmd::SetTrigger(Some(new_trigger(
+            true,
+            Duration::from_millis(5),
+            1000,
+        ))));
+        let task = rt.spawn(run_source(
+            String::from("test_source"),
+            String::from("test"),
+            Box::new(TestSource::new()),
+            source_channel(tx),
+            cmd_rx,
+            Arc::new(Notify::new()),
+            None,
+        ));
+        sleep(Duration::from_millis(30));
+        assert!(rx.try_recv().is_err(), "the source should not have flushed yet");
+
+        // the points collected since the start are sent before the source exits
+        cmd_tx.send(SourceCmd::Stop).unwrap();
+        rt.block_on(task).unwrap().unwrap();
+        let flushed = rx.try_recv().expect("the source should have flushed its measurements");
+        assert!(!flushed.is_empty());
+        assert!(rx.try_recv().is_err());
+    }
+
     fn new_trigger(test_interrupt: bool, period: Duration, flush_rounds: usize) -> TriggerSpec {
         let mut builder = trigger::builder::time_interval(period)
             .flush_rounds(flush_rounds)
@@ -1390,6 +6618,75 @@ mod tests {
         builder.build().unwrap()
     }
 
+    #[test]
+    fn source_overflow_policies() {
+        let rt = new_rt(1);
+        rt.block_on(async {
+            // buffer with n points, to identify it
+            let buf = |n: usize| {
+                let mut buf = MeasurementBuffer::new();
+                for _ in 0..n {
+                    buf.push(MeasurementPoint::new_untyped(
+                        Timestamp::now(),
+                        RawMetricId(1),
+                        Resource::LocalMachine,
+                        ResourceConsumer::LocalMachine,
+                        WrappedMeasurementValue::U64(0),
+                    ));
+                }
+                buf
+            };
+
+            // DropNewest: the buffers that don't fit are dropped
+            let (tx, mut rx) = mpsc::channel::<MeasurementBuffer>(1);
+            let dropped = Arc::new(AtomicU64::new(0));
+            let mut chan =
+                SourceChannel::new(tx, SourceOverflowPolicy::DropNewest, dropped.clone(), Default::default());
+            chan.send(buf(1), "src").await.unwrap();
+            chan.send(buf(2), "src").await.unwrap();
+            assert_eq!(dropped.load(Ordering::Relaxed), 1);
+            assert_eq!(rx.recv().await.unwrap().len(), 1);
+
+            // DropOldest: the newest buffer is kept on the side
+            let (tx, mut rx) = mpsc::channel::<MeasurementBuffer>(1);
+            let dropped = Arc::new(AtomicU64::new(0));
+            let mut chan =
+                SourceChannel::new(tx, SourceOverflowPolicy::DropOldest, dropped.clone(), Default::default());
+            chan.send(buf(1), "src").await.unwrap(); // sent
+            chan.send(buf(2), "src").await.unwrap(); // pending
+            chan.send(buf(3), "src").await.unwrap(); // 2 is dropped, 3 is pending
+            assert_eq!(dropped.load(Ordering::Relaxed), 1);
+            assert_eq!(rx.recv().await.unwrap().len(), 1);
+            chan.flush_pending("src");
+            assert_eq!(rx.recv().await.unwrap().len(), 3);
+            assert_eq!(dropped.load(Ordering::Relaxed), 1);
+
+            // closed channel
+            drop(rx);
+            chan.send(buf(4), "src").await.unwrap_err();
+        });
+    }
+
+    fn source_channel(tx: mpsc::Sender<MeasurementBuffer>) -> SourceChannel {
+        SourceChannel::new(tx, SourceOverflowPolicy::default(), Arc::new(AtomicU64::new(0)), Default::default())
+    }
+
+    fn configured_output(name: &str, output: OutputKind, filter: Option<Box<OutputFilter>>) -> ConfiguredOutput {
+        ConfiguredOutput {
+            output,
+            name: String::from(name),
+            plugin_name: String::from("test"),
+            filter,
+            retry: None,
+            flush_interval: None,
+            slow_policy: super::SlowOutputPolicy::DropOldest,
+            circuit_breaker: None,
+            health_check_interval: None,
+            sampling: None,
+            route: String::from(DEFAULT_ROUTE),
+        }
+    }
+
     fn new_rt(n_threads: usize) -> Runtime {
         tokio::runtime::Builder::new_multi_thread()
             .worker_threads(n_threads)
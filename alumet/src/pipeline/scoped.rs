@@ -14,6 +14,9 @@
 /// }).await
 /// ```
 /// but without requiring the lifetimes of the two arguments to be `'static`.
+///
+/// If the returned future is dropped before the blocking function returns (for instance because
+/// the task has been aborted), the drop blocks the current thread until the function returns.
 pub async fn spawn_blocking_with_output<F, R>(
     output: &mut dyn super::Output,
     ctx: &mut super::OutputContext,
@@ -29,10 +32,23 @@ where
     unsafe impl Send for SendFatPointer {}
     let out_ptr = SendFatPointer(output as *mut _ as _);
     let ctx_ptr = SendThinPointer(ctx as *mut _ as _);
+
+    /// Waits for the blocking function to return, even if the future is dropped.
+    struct WaitOnDrop(std::sync::mpsc::Receiver<()>);
+    impl Drop for WaitOnDrop {
+        fn drop(&mut self) {
+            // Returns when the sender is dropped, i.e. when the blocking function has returned or panicked.
+            let _ = self.0.recv();
+        }
+    }
+    let (done_tx, done_rx) = std::sync::mpsc::channel::<()>();
+    let _wait = WaitOnDrop(done_rx);
+
     let rt = tokio::runtime::Handle::current();
     rt.spawn_blocking(move || {
-        // SAFETY: we wait for the task to finish, and tokio catches the panics,
-        // therefore the pointers remain valid during the entire call to `func`.
+        // SAFETY: we wait for the task to finish (with `.await`, or in `WaitOnDrop::drop` if the future is dropped),
+        // and tokio catches the panics, therefore the pointers remain valid during the entire call to `func`.
+        let _done = done_tx;
         let (out_ptr, ctx_ptr) = (out_ptr, ctx_ptr); // We move the wrappers, not the pointers
         let out = unsafe { &mut *(out_ptr.0 as *mut _) };
         let ctx = unsafe { &mut *(ctx_ptr.0 as *mut _) };
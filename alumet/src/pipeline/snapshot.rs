@@ -0,0 +1,144 @@
+//! An output that keeps the latest measurement of each time series, to read the current values on demand.
+
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+
+use crate::measurement::{MeasurementBuffer, MeasurementPoint};
+
+use super::window::{series_key, SeriesKey};
+use super::{Output, OutputContext, WriteError};
+
+type Snapshot = HashMap<SeriesKey, MeasurementPoint>;
+
+/// An output that maintains a snapshot of the latest measurement point of each time series.
+///
+/// A time series is identified by the metric, the resource, the consumer and the attributes of the points.
+/// A point replaces the one of its series unless it is older, according to their timestamps.
+/// Use [`SnapshotOutput::handle`] to read the snapshot while the pipeline is running, for instance to serve the
+/// current values over HTTP, without waiting for the next poll of the sources.
+///
+/// The snapshot grows with the number of series: the series that disappear (e.g. the processes that have ended)
+/// are kept until [`SnapshotHandle::clear`] is called.
+///
+/// ## Example
+/// ```
+/// use alumet::pipeline::snapshot::SnapshotOutput;
+///
+/// let output = SnapshotOutput::new();
+/// let handle = output.handle();
+/// // add the output to the pipeline, run it, then:
+/// assert!(handle.snapshot().is_empty());
+/// ```
+pub struct SnapshotOutput {
+    latest: Arc<RwLock<Snapshot>>,
+}
+
+/// Gives access to the snapshot maintained by a [`SnapshotOutput`].
+#[derive(Clone)]
+pub struct SnapshotHandle {
+    latest: Arc<RwLock<Snapshot>>,
+}
+
+impl SnapshotOutput {
+    pub fn new() -> Self {
+        Self {
+            latest: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Returns a handle to read the snapshot.
+    pub fn handle(&self) -> SnapshotHandle {
+        SnapshotHandle {
+            latest: self.latest.clone(),
+        }
+    }
+}
+
+impl Default for SnapshotOutput {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Output for SnapshotOutput {
+    fn write(&mut self, measurements: &MeasurementBuffer, _ctx: &OutputContext) -> Result<(), WriteError> {
+        let mut latest = self.latest.write().unwrap();
+        for point in measurements {
+            match latest.entry(series_key(point)) {
+                Entry::Occupied(mut current) => {
+                    if SystemTime::from(current.get().timestamp) <= SystemTime::from(point.timestamp) {
+                        current.insert(point.clone());
+                    }
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(point.clone());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl SnapshotHandle {
+    /// Returns a copy of the latest measurement point of each time series, in no particular order.
+    pub fn snapshot(&self) -> Vec<MeasurementPoint> {
+        self.latest.read().unwrap().values().cloned().collect()
+    }
+
+    /// Returns the number of time series in the snapshot.
+    pub fn len(&self) -> usize {
+        self.latest.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.latest.read().unwrap().is_empty()
+    }
+
+    /// Forgets all the time series.
+    pub fn clear(&self) {
+        self.latest.write().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::measurement::{MeasurementBuffer, MeasurementPoint, WrappedMeasurementValue};
+    use crate::metrics::MetricRegistry;
+    use crate::pipeline::{testing, Output, OutputContext};
+    use crate::resources::Resource;
+
+    use super::SnapshotOutput;
+
+    fn point(secs: u64, pkg: u32, value: u64) -> MeasurementPoint {
+        testing::point().secs(secs).package(pkg).u64(value).build()
+    }
+
+    #[test]
+    fn latest_per_series() {
+        let ctx = OutputContext {
+            metrics: MetricRegistry::new(),
+        };
+        let mut output = SnapshotOutput::new();
+        let handle = output.handle();
+        let buf = MeasurementBuffer::from(vec![point(1, 0, 1), point(1, 1, 10), point(2, 0, 2)]);
+        output.write(&buf, &ctx).unwrap();
+        // a late point does not replace a more recent one
+        output.write(&MeasurementBuffer::from(vec![point(0, 1, 0), point(0, 0, 0)]), &ctx).unwrap();
+
+        let mut values: Vec<(u32, u64)> = handle
+            .snapshot()
+            .iter()
+            .map(|p| match (&p.resource, &p.value) {
+                (Resource::CpuPackage { id }, WrappedMeasurementValue::U64(n)) => (*id, *n),
+                _ => unreachable!(),
+            })
+            .collect();
+        values.sort();
+        assert_eq!(values, vec![(0, 2), (1, 10)]);
+
+        handle.clear();
+        assert!(handle.is_empty());
+    }
+}
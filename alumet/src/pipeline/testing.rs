@@ -0,0 +1,328 @@
+//! Utilities to test the elements of a pipeline in isolation, enabled by the `testing` feature.
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+
+use tokio::sync::{mpsc, watch, Notify};
+
+use crate::measurement::{
+    MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementType, WrappedMeasurementValue,
+};
+use crate::metrics::{Metric, MetricRegistry, RawMetricId};
+use crate::resources::{Resource, ResourceConsumer};
+use crate::units::PrefixedUnit;
+
+use super::runtime::{run_source, SourceChannel, SourceCmd, SourceOverflowPolicy};
+use super::trigger::{TriggerAction, TriggerFutureFn, TriggerSpec};
+use super::Source;
+
+/// Polls `source` exactly `n_polls` times, in the same loop as in a pipeline, and returns the buffers that it flushes.
+///
+/// The mechanism of `trigger` is replaced by a mock one, which fires `n_polls` times in a row, without waiting,
+/// then stops the source like [`TriggerAction::Stop`]: the measurements that remain in the buffer are flushed.
+/// The rest of the configuration of `trigger` applies, for instance its flush rounds, its update rounds and
+/// its poll error policy.
+///
+/// Each command `(n, cmd)` of `commands` is sent with the `n`-th tick of the trigger (counting from 1):
+/// it is applied after the `n`-th poll, or at the next update round if the trigger updates its command less often.
+/// If several commands have the same `n`, only the last one is applied.
+/// The source runs on a single-threaded runtime, created for this call, and its buffers are not transformed.
+///
+/// Returns an error if the source task fails, for instance if `poll` returns a fatal error.
+///
+/// Panics if a command pauses the source, replaces its trigger or moves it to another runtime,
+/// because the mock trigger would not fire anymore.
+///
+/// ## Example
+/// ```no_run
+/// use std::time::Duration;
+/// use alumet::pipeline::runtime::SourceCmd;
+/// use alumet::pipeline::{testing, trigger, Source};
+///
+/// # fn example(source: Box<dyn Source>) -> anyhow::Result<()> {
+/// let trigger = trigger::builder::time_interval(Duration::from_secs(1)).flush_rounds(2).build()?;
+/// // flush after the first poll, in addition to the flush rounds
+/// let buffers = testing::drive_source(source, trigger, vec![(1, SourceCmd::Flush)], 5)?;
+/// assert_eq!(buffers.len(), 3);
+/// # Ok(())
+/// # }
+/// ```
+pub fn drive_source(
+    source: Box<dyn Source>,
+    trigger: TriggerSpec,
+    commands: Vec<(usize, SourceCmd)>,
+    n_polls: usize,
+) -> anyhow::Result<Vec<MeasurementBuffer>> {
+    for (_, cmd) in &commands {
+        assert!(
+            !matches!(cmd, SourceCmd::Pause | SourceCmd::SetTrigger(_) | SourceCmd::SetPriority(_)),
+            "drive_source does not support {cmd:?}"
+        );
+    }
+
+    let (cmd_tx, cmd_rx) = watch::channel(SourceCmd::SetTrigger(None));
+    // The trigger is stored in the channel: it only keeps a weak reference to the sender, to avoid a cycle.
+    let cmd_tx = Arc::new(cmd_tx);
+    let weak_tx = Arc::downgrade(&cmd_tx);
+    let ticks = AtomicUsize::new(0);
+    let mock: TriggerFutureFn = Arc::new(move || {
+        // The future is ready immediately: the trigger is never interrupted, hence each call is one tick.
+        let tick = ticks.fetch_add(1, Ordering::Relaxed) + 1;
+        let action = if tick > n_polls {
+            TriggerAction::Stop
+        } else {
+            if let Some(cmd_tx) = weak_tx.upgrade() {
+                for (_, cmd) in commands.iter().filter(|(n, _)| *n == tick) {
+                    cmd_tx.send_replace(cmd.clone());
+                }
+            }
+            TriggerAction::Continue
+        };
+        Box::pin(std::future::ready(Ok(action)))
+    });
+    cmd_tx.send_replace(SourceCmd::SetTrigger(Some(trigger.with_future_mechanism(mock))));
+
+    let rt = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+    rt.block_on(async move {
+        let (tx, mut rx) = mpsc::channel(16);
+        let dropped_buffers = Arc::new(AtomicU64::new(0));
+        let channel = SourceChannel::new(tx, SourceOverflowPolicy::Block, dropped_buffers, Default::default());
+        let task = tokio::spawn(run_source(
+            String::from("test/source"),
+            String::from("test"),
+            source,
+            channel,
+            cmd_rx,
+            Arc::new(Notify::new()),
+            None,
+        ));
+        let mut buffers = Vec::new();
+        // the channel is closed when the source stops
+        while let Some(buffer) = rx.recv().await {
+            buffers.push(buffer);
+        }
+        task.await??;
+        // the sender must live until the source stops
+        drop(cmd_tx);
+        Ok::<_, anyhow::Error>(buffers)
+    })
+}
+
+/// Builds a measurement point for a test, see [`point`].
+///
+/// Each field has a default value, which can be replaced: the point is taken at the Unix epoch, by the
+/// local machine, with the metric 0 and the value `U64(1)`.
+#[derive(Clone)]
+pub struct PointBuilder {
+    timestamp: Timestamp,
+    metric: RawMetricId,
+    resource: Resource,
+    consumer: ResourceConsumer,
+    value: WrappedMeasurementValue,
+}
+
+/// Returns a [`PointBuilder`] with the default values.
+///
+/// ## Example
+/// ```
+/// use alumet::measurement::WrappedMeasurementValue;
+/// use alumet::pipeline::testing;
+///
+/// // the energy of the second package, 1.5 second after the epoch
+/// let p = testing::point().millis(1500).package(1).f64(12.5).build();
+/// assert!(matches!(p.value, WrappedMeasurementValue::F64(x) if x == 12.5));
+/// ```
+pub fn point() -> PointBuilder {
+    PointBuilder {
+        timestamp: Timestamp::from(UNIX_EPOCH),
+        metric: RawMetricId(0),
+        resource: Resource::LocalMachine,
+        consumer: ResourceConsumer::LocalMachine,
+        value: WrappedMeasurementValue::U64(1),
+    }
+}
+
+impl PointBuilder {
+    /// Takes the point `secs` seconds after the Unix epoch.
+    pub fn secs(self, secs: u64) -> Self {
+        self.at(Duration::from_secs(secs))
+    }
+
+    /// Takes the point `millis` milliseconds after the Unix epoch.
+    pub fn millis(self, millis: u64) -> Self {
+        self.at(Duration::from_millis(millis))
+    }
+
+    /// Takes the point `t` after the Unix epoch.
+    pub fn at(mut self, t: Duration) -> Self {
+        self.timestamp = Timestamp::from(UNIX_EPOCH + t);
+        self
+    }
+
+    pub fn metric(mut self, metric: RawMetricId) -> Self {
+        self.metric = metric;
+        self
+    }
+
+    pub fn resource(mut self, resource: Resource) -> Self {
+        self.resource = resource;
+        self
+    }
+
+    /// Measures the CPU package `id`.
+    pub fn package(self, id: u32) -> Self {
+        self.resource(Resource::CpuPackage { id })
+    }
+
+    pub fn consumer(mut self, consumer: ResourceConsumer) -> Self {
+        self.consumer = consumer;
+        self
+    }
+
+    pub fn value(mut self, value: WrappedMeasurementValue) -> Self {
+        self.value = value;
+        self
+    }
+
+    pub fn u64(self, value: u64) -> Self {
+        self.value(WrappedMeasurementValue::U64(value))
+    }
+
+    pub fn f64(self, value: f64) -> Self {
+        self.value(WrappedMeasurementValue::F64(value))
+    }
+
+    pub fn build(self) -> MeasurementPoint {
+        MeasurementPoint::new_untyped(self.timestamp, self.metric, self.resource, self.consumer, self.value)
+    }
+}
+
+/// Registers a metric with an empty description in `registry`, and returns its id.
+///
+/// Panics if the registry already has a metric with this name.
+pub fn register_metric(
+    registry: &mut MetricRegistry,
+    name: &str,
+    value_type: WrappedMeasurementType,
+    unit: impl Into<PrefixedUnit>,
+) -> RawMetricId {
+    let metric = Metric {
+        name: name.to_owned(),
+        description: String::new(),
+        value_type,
+        unit: unit.into(),
+    };
+    registry.register(metric).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::measurement::{MeasurementAccumulator, MeasurementPoint, Timestamp, WrappedMeasurementValue};
+    use crate::metrics::RawMetricId;
+    use crate::pipeline::runtime::{SourceCmd, MAX_PREALLOCATED_POINTS};
+    use crate::pipeline::{trigger, PollError, Source};
+    use crate::resources::{Resource, ResourceConsumer};
+
+    use super::drive_source;
+
+    /// Pushes the number of the poll.
+    struct PollCounter(u64);
+
+    impl Source for PollCounter {
+        fn poll(&mut self, measurements: &mut MeasurementAccumulator, timestamp: Timestamp) -> Result<(), PollError> {
+            self.0 += 1;
+            measurements.push(MeasurementPoint::new_untyped(
+                timestamp,
+                RawMetricId(0),
+                Resource::LocalMachine,
+                ResourceConsumer::LocalMachine,
+                WrappedMeasurementValue::U64(self.0),
+            ));
+            Ok(())
+        }
+    }
+
+    /// Pushes `n` points at each poll, and tells it in its hint if `hint` is set.
+    struct BatchSource {
+        n: usize,
+        hint: Option<usize>,
+    }
+
+    impl Source for BatchSource {
+        fn poll(&mut self, measurements: &mut MeasurementAccumulator, timestamp: Timestamp) -> Result<(), PollError> {
+            for i in 0..self.n {
+                measurements.push(MeasurementPoint::new_untyped(
+                    timestamp,
+                    RawMetricId(0),
+                    Resource::LocalMachine,
+                    ResourceConsumer::LocalMachine,
+                    WrappedMeasurementValue::U64(i as u64),
+                ));
+            }
+            Ok(())
+        }
+
+        fn points_per_poll_hint(&self) -> Option<usize> {
+            self.hint
+        }
+    }
+
+    /// Returns the capacity of each buffer flushed by a `BatchSource` of 3 points, with 2 polls per flush.
+    fn capacities(hint: Option<usize>) -> Vec<usize> {
+        let trigger = trigger::builder::time_interval(Duration::from_secs(3600))
+            .flush_rounds(2)
+            .build()
+            .unwrap();
+        let buffers = drive_source(Box::new(BatchSource { n: 3, hint }), trigger, Vec::new(), 6).unwrap();
+        assert!(buffers.iter().all(|b| b.len() == 6));
+        buffers.iter().map(|b| b.capacity()).collect()
+    }
+
+    fn polls(commands: Vec<(usize, SourceCmd)>) -> Vec<Vec<u64>> {
+        let trigger = trigger::builder::time_interval(Duration::from_secs(3600))
+            .flush_rounds(2)
+            .build()
+            .unwrap();
+        let buffers = drive_source(Box::new(PollCounter(0)), trigger, commands, 5).unwrap();
+        buffers
+            .iter()
+            .map(|b| {
+                b.iter()
+                    .map(|p| match p.value {
+                        WrappedMeasurementValue::U64(n) => n,
+                        WrappedMeasurementValue::F64(x) => x as u64,
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn flush_rounds() {
+        // the last poll is flushed when the mock trigger stops the source
+        assert_eq!(polls(Vec::new()), vec![vec![1, 2], vec![3, 4], vec![5]]);
+    }
+
+    #[test]
+    fn buffer_capacity() {
+        // the hint sizes the first buffer, then the length of the previous buffer sizes the next ones
+        let hinted = capacities(Some(3));
+        assert_eq!(hinted.len(), 3);
+        assert!(hinted.iter().all(|&c| c >= 6), "{hinted:?}");
+        let unhinted = capacities(None);
+        assert!(unhinted[1..].iter().all(|&c| c >= 6), "{unhinted:?}");
+        // an oversized hint does not overflow, nor exhaust the memory
+        let oversized = capacities(Some(usize::MAX));
+        assert!(oversized.iter().all(|&c| c <= MAX_PREALLOCATED_POINTS), "{oversized:?}");
+    }
+
+    #[test]
+    fn commands() {
+        // the command is applied after the first poll, and restarts the flush rounds
+        assert_eq!(polls(vec![(1, SourceCmd::Flush)]), vec![vec![1], vec![2, 3], vec![4, 5]]);
+        assert_eq!(polls(vec![(3, SourceCmd::Stop)]), vec![vec![1, 2], vec![3]]);
+    }
+}
@@ -1,19 +1,45 @@
 //! Source triggers.
 
+use std::path::PathBuf;
+use std::sync::Arc;
 use std::time::Duration;
 use std::{fmt, time};
 use std::{future::Future, pin::Pin};
 
 use anyhow::Context;
-use tokio::sync::watch;
+use tokio::sync::{watch, Notify};
 
-use super::runtime::SourceCmd;
+use crate::measurement::Timestamp;
+use crate::pipeline::PollError;
+
+use super::cron::CronSchedule;
+#[cfg(target_os = "linux")]
+use super::file_watch::FileWatch;
+use super::runtime::{PollErrorPolicy, RetryPolicy, SourceCmd};
 
 /// A boxed future, from the `futures` crate.
 pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
 
 /// The output of a SourceTrigger.
-pub type SourceTriggerOutput = Result<(), std::io::Error>;
+pub type SourceTriggerOutput = Result<TriggerAction, std::io::Error>;
+
+/// What to do when the future of a trigger completes successfully, see [`builder::future`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriggerAction {
+    /// Polls the source, then waits for the next trigger.
+    Continue,
+    /// Stops the source gracefully, without polling it: the measurements that it has collected since its last flush
+    /// are sent downstream, as with [`SourceCmd::Stop`].
+    ///
+    /// This allows a trigger that follows a finite stream of events to stop the source at the end of the stream,
+    /// while an error stops it as a failure.
+    Stop,
+}
+
+/// A function that returns the future to await before each poll, see [`builder::future`].
+///
+/// Since it is a closure, it can capture some state, like a socket or a channel.
+pub type TriggerFutureFn = Arc<dyn Fn() -> BoxFuture<'static, SourceTriggerOutput> + Send + Sync>;
 
 /// Defines a trigger for measurement sources.
 ///
@@ -23,7 +49,10 @@ pub struct TriggerSpec {
     mechanism: TriggerMechanismSpec,
     interruptible: bool,
     pub(crate) realtime_priority: bool,
+    pub(crate) blocking: bool,
     config: TriggerConfig,
+    /// If set, the initialization of the trigger is retried when it fails with a transient error.
+    pub(crate) init_retry: Option<RetryPolicy>,
 }
 
 /// Controls when the [`Source`](super::Source) is polled for measurements.
@@ -31,6 +60,11 @@ pub(crate) struct Trigger {
     pub config: TriggerConfig,
     mechanism: TriggerMechanism,
     interrupt_signal: Option<watch::Receiver<SourceCmd>>,
+    /// The time at which the mechanism has fired for the last time.
+    fired_at: Timestamp,
+    /// Whether a tick has been coalesced and the source must be polled at the end of the minimum interval,
+    /// see [`next_not_before`](Self::next_not_before).
+    coalesced: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -46,6 +80,17 @@ pub(crate) struct TriggerConfig {
     /// but decreases the time it takes for a [source command](super::runtime::SourceCmd)
     /// to be applied.
     pub update_rounds: usize,
+
+    /// What the source does when its poll fails with a non-fatal error.
+    pub poll_error_policy: PollErrorPolicy,
+
+    /// If set, the source stops by itself after this number of polls.
+    pub max_polls: Option<usize>,
+
+    /// If set, the source is polled at most once per `min_interval`: the ticks that occur sooner after a poll
+    /// are coalesced into a single poll, at the end of the interval.
+    /// See [`builder::FutureTriggerBuilder::min_interval`].
+    pub min_interval: Option<Duration>,
 }
 
 /// Constraints that can be applied to a [`TriggerSpec`] after its construction.
@@ -55,12 +100,17 @@ pub(crate) struct TriggerConstraints {
 
 /// Builder for source triggers.
 ///
-/// See [`builder::time_interval`](self::time_interval).
+/// See [`builder::time_interval`](self::time_interval), [`builder::manual`](self::manual), [`builder::cron`](self::cron)
+/// and [`builder::file_watch`](self::file_watch).
 pub mod builder {
     use core::fmt;
+    use std::path::PathBuf;
     use std::time::{Duration, Instant};
 
-    use super::{TriggerConfig, TriggerMechanismSpec, TriggerSpec};
+    use super::{
+        BoxFuture, CronSchedule, FutureFn, PollErrorPolicy, SourceTriggerOutput, TriggerConfig, TriggerMechanismSpec,
+        TriggerSpec,
+    };
 
     /// Returns a builder for a source trigger that polls the source at regular intervals.
     ///
@@ -87,6 +137,114 @@ pub mod builder {
         TimeTriggerBuilder::new(poll_interval)
     }
 
+    /// Returns a builder for a source trigger that only polls the source on demand.
+    ///
+    /// The source is polled each time [`poll_sources_now`](crate::pipeline::runtime::ScopedControlHandle::poll_sources_now)
+    /// is called for it. This is useful for sources whose measurements are only relevant when an external event occurs.
+    /// The trigger never fires on its own: while no poll is requested, the source only waits for its commands,
+    /// which is cleaner than a time interval that is long enough to never elapse.
+    ///
+    /// ## Flushing
+    /// The measurements are flushed every `flush_rounds` requested polls, that is after each poll by default.
+    /// With more rounds, the measurements stay in the buffer of the source until enough polls have been requested,
+    /// or until the source receives [`SourceCmd::Flush`](crate::pipeline::runtime::SourceCmd::Flush), or stops.
+    ///
+    /// ## Example
+    /// ```
+    /// use alumet::pipeline::trigger;
+    ///
+    /// let trigger_config = trigger::builder::manual()
+    ///     .flush_rounds(2)
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn manual() -> ManualTriggerBuilder {
+        ManualTriggerBuilder::new()
+    }
+
+    /// Returns a builder for a source trigger that polls the source on a schedule, defined by a cron expression.
+    ///
+    /// The expression has five fields: minute, hour, day of month, month and day of week.
+    /// Each field is a comma-separated list of `*`, `n` or `a-b`, optionally followed by a step `/s`.
+    /// The times are evaluated in UTC, and the next polling time is computed from
+    /// [`SystemTime::now()`](std::time::SystemTime::now) after each tick.
+    ///
+    /// ## Example
+    /// ```
+    /// use alumet::pipeline::trigger;
+    ///
+    /// // every day at 00:00
+    /// let trigger_config = trigger::builder::cron("0 0 * * *")
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn cron(expression: &str) -> CronTriggerBuilder {
+        CronTriggerBuilder::new(expression)
+    }
+
+    /// Returns a builder for a source trigger that polls the source each time a file (or directory) changes.
+    ///
+    /// This is useful for the sources whose measurements only change when a file does (e.g. a cgroup config),
+    /// for which polling at regular intervals would be wasteful. The path is watched with inotify,
+    /// therefore this trigger is only available on Linux.
+    ///
+    /// The watch is opened when the source starts: at that moment, the path must exist.
+    /// If the path is replaced later, for instance by renaming a temporary file over it (which is how most
+    /// editors and configuration tools write a file), the source is polled and the new file is watched.
+    /// If the path is deleted, the source stops with an error.
+    ///
+    /// ## Debouncing
+    ///
+    /// The events are coalesced: all the changes that occur before the trigger wakes up result in
+    /// only one poll. In particular, the changes that occur while the source is being polled
+    /// trigger one (and only one) more poll, right after the current one.
+    /// A file that is written in several steps may therefore trigger one or two polls.
+    ///
+    /// ## Example
+    /// ```
+    /// use alumet::pipeline::trigger;
+    ///
+    /// let trigger_config = trigger::builder::file_watch("/sys/fs/cgroup/cgroup.subtree_control")
+    ///     .flush_rounds(1)
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn file_watch(path: impl Into<PathBuf>) -> FileWatchTriggerBuilder {
+        FileWatchTriggerBuilder::new(path.into())
+    }
+
+    /// Returns a builder for a source trigger that polls the source each time a future completes.
+    ///
+    /// Before each poll, the trigger calls `f` and awaits the future that it returns.
+    /// The source stops with an error if the future returns an error.
+    /// Since `f` is a closure, it can capture the state that the future needs, like a socket or a channel:
+    /// this allows to poll the source when an external event occurs.
+    ///
+    /// ## Example
+    /// ```
+    /// use std::sync::Arc;
+    /// use alumet::pipeline::trigger::{self, TriggerAction};
+    /// use tokio::sync::Notify;
+    ///
+    /// let event = Arc::new(Notify::new());
+    /// let trigger_config = trigger::builder::future(move || {
+    ///     let event = event.clone();
+    ///     Box::pin(async move {
+    ///         event.notified().await;
+    ///         Ok(TriggerAction::Continue)
+    ///     })
+    /// })
+    /// .flush_rounds(2)
+    /// .build()
+    /// .unwrap();
+    /// ```
+    pub fn future<F>(f: F) -> FutureTriggerBuilder
+    where
+        F: Fn() -> BoxFuture<'static, SourceTriggerOutput> + Send + Sync + 'static,
+    {
+        FutureTriggerBuilder::new(std::sync::Arc::new(f))
+    }
+
     /// Builder for a source trigger that polls the source at regular intervals.
     pub struct TimeTriggerBuilder {
         start: Instant,
@@ -94,6 +252,10 @@ pub mod builder {
         config: TriggerConfig,
         interruptible: bool,
         realtime_priority: bool,
+        blocking: bool,
+        aligned: bool,
+        max_jitter: Duration,
+        phase_offset: Duration,
     }
 
     #[derive(Debug)]
@@ -121,9 +283,16 @@ pub mod builder {
                 config: TriggerConfig {
                     flush_rounds: 1,
                     update_rounds: 1,
+                    poll_error_policy: PollErrorPolicy::default(),
+                    max_polls: None,
+                    min_interval: None,
                 },
                 interruptible: false,
                 realtime_priority: false,
+                blocking: false,
+                aligned: false,
+                max_jitter: Duration::ZERO,
+                phase_offset: Duration::ZERO,
             }
         }
 
@@ -151,8 +320,7 @@ pub mod builder {
                 return self; // don't modify anything, build() will fail
             }
 
-            // flush_rounds must be non-zero, or the remainder operation will panic (`i % flush_rounds` in the polling loop)
-            self.config.flush_rounds = ((flush_interval.as_nanos() / self.poll_interval.as_nanos()) as usize).max(1);
+            self.config.flush_rounds = super::flush_rounds(self.poll_interval, flush_interval);
             self
         }
 
@@ -176,6 +344,60 @@ pub mod builder {
             self
         }
 
+        /// Stops the source after `max_polls` polls, for a bounded collection (e.g. a benchmark).
+        ///
+        /// After the last poll, the measurements that are still in the buffer are flushed, then the source stops,
+        /// as if it had received [`SourceCmd::Stop`](crate::pipeline::runtime::SourceCmd::Stop).
+        /// The count restarts when the trigger is replaced, for instance when the source moves to another runtime.
+        /// `max_polls` must be non-zero.
+        pub fn max_polls(mut self, max_polls: usize) -> Self {
+            self.config.max_polls = Some(max_polls);
+            self
+        }
+
+        /// Aligns the polling times on the wall clock.
+        ///
+        /// The source is polled when the system time is a multiple of `poll_interval` (since the UNIX epoch).
+        /// For instance, with a `poll_interval` of 1 second, the source is polled at the top of each second.
+        /// This makes it easier to compare the measurements with the data produced by other tools.
+        ///
+        /// The next polling time is computed again from [`SystemTime::now()`](std::time::SystemTime::now)
+        /// after each tick, therefore the drift does not accumulate over time.
+        /// If polling the source takes longer than `poll_interval`, the missed ticks are skipped:
+        /// the source is polled at the next boundary, and there is no "catching up".
+        ///
+        /// When this option is enabled, [`starting_at`](Self::starting_at) is ignored.
+        pub fn align_to_wall_clock(mut self) -> Self {
+            self.aligned = true;
+            self
+        }
+
+        /// Shifts the polling times by `phase_offset`, which must be smaller than `poll_interval`.
+        ///
+        /// The first poll happens `phase_offset` after the start (by default, the creation of the builder, see
+        /// [`starting_at`](Self::starting_at)), and the next ones every `poll_interval` after it.
+        /// With [`align_to_wall_clock`](Self::align_to_wall_clock), the source is polled `phase_offset` after each
+        /// multiple of `poll_interval`. For instance, two sources that poll every 500ms, the second one with an offset
+        /// of 250ms, are polled in turn every 250ms.
+        pub fn phase_offset(mut self, phase_offset: Duration) -> Self {
+            self.phase_offset = phase_offset;
+            self
+        }
+
+        /// Delays the start of the polling by a random duration between zero and `max_jitter`.
+        ///
+        /// When many sources have the same `poll_interval`, this spreads their polls over time,
+        /// instead of polling all of them at the same time.
+        /// The jitter is chosen once, when the trigger is built: the interval between two polls
+        /// is not modified, therefore the polling frequency stays exactly the same.
+        ///
+        /// `max_jitter` must not be greater than `poll_interval`, and the jitter cannot be combined
+        /// with [`align_to_wall_clock`](Self::align_to_wall_clock).
+        pub fn jitter(mut self, max_jitter: Duration) -> Self {
+            self.max_jitter = max_jitter;
+            self
+        }
+
         /// Signals that the pipeline should run the source on a thread with a high scheduling priority.
         ///
         /// The actual implementation of this "high priority" is OS-dependent and comes with no strong guarantee.
@@ -187,24 +409,327 @@ pub mod builder {
             self
         }
 
+        /// Signals that polling the source blocks the thread for a long time (for instance, it reads a slow device file).
+        ///
+        /// The pipeline runs such sources on a dedicated runtime, so that they never prevent the other
+        /// sources, transforms and outputs from running. This setting takes precedence over
+        /// [`realtime_priority`](Self::realtime_priority).
+        pub fn blocking(mut self) -> Self {
+            self.blocking = true;
+            self
+        }
+
         /// Builds the trigger.
         pub fn build(mut self) -> Result<TriggerSpec, Error> {
             if self.poll_interval.is_zero() {
                 return Err(Error::InvalidConfig(String::from("poll_interval must be non-zero")));
             }
+            if self.max_jitter > self.poll_interval {
+                return Err(Error::InvalidConfig(String::from("max_jitter must not be greater than poll_interval")));
+            }
+            if self.aligned && !self.max_jitter.is_zero() {
+                return Err(Error::InvalidConfig(String::from("an aligned trigger cannot have a jitter")));
+            }
+            if self.phase_offset >= self.poll_interval {
+                return Err(Error::InvalidConfig(String::from("phase_offset must be smaller than poll_interval")));
+            }
+            if self.config.max_polls == Some(0) {
+                return Err(Error::InvalidConfig(String::from("max_polls must be non-zero")));
+            }
             // automatically enable `realtime_priority` in some cases
             if self.poll_interval <= Duration::from_millis(3) {
                 self.realtime_priority = true;
             }
+            // apply the jitter once, the poll_interval stays the same
+            if !self.max_jitter.is_zero() {
+                self.start += random_duration(self.max_jitter);
+            }
+            self.start += self.phase_offset;
 
+            let mechanism = if self.aligned {
+                TriggerMechanismSpec::AlignedInterval(self.poll_interval, self.phase_offset)
+            } else {
+                TriggerMechanismSpec::TimeInterval(self.start, self.poll_interval)
+            };
             Ok(TriggerSpec {
-                mechanism: TriggerMechanismSpec::TimeInterval(self.start, self.poll_interval),
+                mechanism,
                 interruptible: self.interruptible,
                 realtime_priority: self.realtime_priority,
+                blocking: self.blocking,
+                config: self.config,
+                init_retry: None,
+            })
+        }
+    }
+
+    /// Builder for a source trigger that only polls the source on demand.
+    pub struct ManualTriggerBuilder {
+        config: TriggerConfig,
+        realtime_priority: bool,
+        blocking: bool,
+    }
+
+    impl ManualTriggerBuilder {
+        pub fn new() -> Self {
+            Self {
+                config: TriggerConfig {
+                    flush_rounds: 1,
+                    update_rounds: 1,
+                    poll_error_policy: PollErrorPolicy::default(),
+                    max_polls: None,
+                    min_interval: None,
+                },
+                realtime_priority: false,
+                blocking: false,
+            }
+        }
+
+        /// Flush the measurements every `flush_rounds` polls.
+        pub fn flush_rounds(mut self, flush_rounds: usize) -> Self {
+            self.config.flush_rounds = flush_rounds;
+            self
+        }
+
+        /// Signals that the pipeline should run the source on a thread with a high scheduling priority.
+        ///
+        /// See [`TimeTriggerBuilder::realtime_priority`].
+        pub fn realtime_priority(mut self) -> Self {
+            self.realtime_priority = true;
+            self
+        }
+
+        /// Signals that polling the source blocks the thread for a long time.
+        ///
+        /// See [`TimeTriggerBuilder::blocking`].
+        pub fn blocking(mut self) -> Self {
+            self.blocking = true;
+            self
+        }
+
+        /// Builds the trigger.
+        pub fn build(self) -> Result<TriggerSpec, Error> {
+            if self.config.flush_rounds == 0 {
+                return Err(Error::InvalidConfig(String::from("flush_rounds must be non-zero")));
+            }
+            Ok(TriggerSpec {
+                mechanism: TriggerMechanismSpec::Manual,
+                // The source can wait for a long time, it must be interrupted by the new commands.
+                interruptible: true,
+                realtime_priority: self.realtime_priority,
+                blocking: self.blocking,
                 config: self.config,
+                init_retry: None,
             })
         }
     }
+
+    /// Builder for a source trigger that polls the source on a cron schedule.
+    pub struct CronTriggerBuilder {
+        expression: String,
+        config: TriggerConfig,
+        blocking: bool,
+    }
+
+    impl CronTriggerBuilder {
+        pub fn new(expression: &str) -> Self {
+            Self {
+                expression: expression.to_owned(),
+                config: TriggerConfig {
+                    flush_rounds: 1,
+                    update_rounds: 1,
+                    poll_error_policy: PollErrorPolicy::default(),
+                    max_polls: None,
+                    min_interval: None,
+                },
+                blocking: false,
+            }
+        }
+
+        /// Flush the measurements every `flush_rounds` polls.
+        pub fn flush_rounds(mut self, flush_rounds: usize) -> Self {
+            self.config.flush_rounds = flush_rounds;
+            self
+        }
+
+        /// Signals that polling the source blocks the thread for a long time.
+        ///
+        /// See [`TimeTriggerBuilder::blocking`].
+        pub fn blocking(mut self) -> Self {
+            self.blocking = true;
+            self
+        }
+
+        /// Builds the trigger.
+        ///
+        /// Fails if the cron expression is malformed, or if it never matches (for instance `0 0 31 2 *`).
+        pub fn build(self) -> Result<TriggerSpec, Error> {
+            if self.config.flush_rounds == 0 {
+                return Err(Error::InvalidConfig(String::from("flush_rounds must be non-zero")));
+            }
+            let expression = &self.expression;
+            let schedule = CronSchedule::parse(expression)
+                .map_err(|e| Error::InvalidConfig(format!("invalid cron expression {expression:?}: {e}")))?;
+            if schedule.next_after(std::time::SystemTime::now()).is_none() {
+                return Err(Error::InvalidConfig(format!("the cron expression {expression:?} never matches")));
+            }
+            Ok(TriggerSpec {
+                mechanism: TriggerMechanismSpec::Cron(schedule),
+                // The source can wait for a long time, it must be interrupted by the new commands.
+                interruptible: true,
+                realtime_priority: false,
+                blocking: self.blocking,
+                config: self.config,
+                init_retry: None,
+            })
+        }
+    }
+
+    /// Builder for a source trigger that polls the source when a path changes.
+    pub struct FileWatchTriggerBuilder {
+        path: PathBuf,
+        config: TriggerConfig,
+        blocking: bool,
+    }
+
+    impl FileWatchTriggerBuilder {
+        pub fn new(path: PathBuf) -> Self {
+            Self {
+                path,
+                config: TriggerConfig {
+                    flush_rounds: 1,
+                    update_rounds: 1,
+                    poll_error_policy: PollErrorPolicy::default(),
+                    max_polls: None,
+                    min_interval: None,
+                },
+                blocking: false,
+            }
+        }
+
+        /// Flush the measurements every `flush_rounds` polls.
+        pub fn flush_rounds(mut self, flush_rounds: usize) -> Self {
+            self.config.flush_rounds = flush_rounds;
+            self
+        }
+
+        /// Signals that polling the source blocks the thread for a long time.
+        ///
+        /// See [`TimeTriggerBuilder::blocking`].
+        pub fn blocking(mut self) -> Self {
+            self.blocking = true;
+            self
+        }
+
+        /// Builds the trigger.
+        pub fn build(self) -> Result<TriggerSpec, Error> {
+            if self.config.flush_rounds == 0 {
+                return Err(Error::InvalidConfig(String::from("flush_rounds must be non-zero")));
+            }
+            Ok(TriggerSpec {
+                mechanism: TriggerMechanismSpec::FileWatch(self.path),
+                // The source can wait for a long time, it must be interrupted by the new commands.
+                interruptible: true,
+                realtime_priority: false,
+                blocking: self.blocking,
+                config: self.config,
+                init_retry: None,
+            })
+        }
+    }
+
+    /// Builder for a source trigger that polls the source each time a future completes.
+    pub struct FutureTriggerBuilder {
+        f: super::TriggerFutureFn,
+        config: TriggerConfig,
+        realtime_priority: bool,
+        blocking: bool,
+    }
+
+    impl FutureTriggerBuilder {
+        pub fn new(f: super::TriggerFutureFn) -> Self {
+            Self {
+                f,
+                config: TriggerConfig {
+                    flush_rounds: 1,
+                    update_rounds: 1,
+                    poll_error_policy: PollErrorPolicy::default(),
+                    max_polls: None,
+                    min_interval: None,
+                },
+                realtime_priority: false,
+                blocking: false,
+            }
+        }
+
+        /// Flush the measurements every `flush_rounds` polls.
+        pub fn flush_rounds(mut self, flush_rounds: usize) -> Self {
+            self.config.flush_rounds = flush_rounds;
+            self
+        }
+
+        /// Coalesces the bursts of events: the source is polled at most once per `min_interval`.
+        ///
+        /// The first event polls the source immediately. The events that occur less than `min_interval` after a poll
+        /// do not poll the source right away: they are coalesced into a single poll, at the end of the interval.
+        /// By default, the source is polled on each event.
+        ///
+        /// The rounds count the polls, not the events: the measurements are flushed every `flush_rounds` polls,
+        /// hence, during a burst, every `flush_rounds * min_interval` at most. Choose a small `flush_rounds`
+        /// to keep the latency of the measurements low.
+        pub fn min_interval(mut self, min_interval: Duration) -> Self {
+            self.config.min_interval = Some(min_interval).filter(|d| !d.is_zero());
+            self
+        }
+
+        /// Signals that the pipeline should run the source on a thread with a high scheduling priority.
+        ///
+        /// See [`TimeTriggerBuilder::realtime_priority`].
+        pub fn realtime_priority(mut self) -> Self {
+            self.realtime_priority = true;
+            self
+        }
+
+        /// Signals that polling the source blocks the thread for a long time.
+        ///
+        /// See [`TimeTriggerBuilder::blocking`].
+        pub fn blocking(mut self) -> Self {
+            self.blocking = true;
+            self
+        }
+
+        /// Builds the trigger.
+        pub fn build(self) -> Result<TriggerSpec, Error> {
+            if self.config.flush_rounds == 0 {
+                return Err(Error::InvalidConfig(String::from("flush_rounds must be non-zero")));
+            }
+            Ok(TriggerSpec {
+                mechanism: TriggerMechanismSpec::Future(FutureFn(self.f)),
+                // The future can take a long time to complete, the source must be interrupted by the new commands.
+                interruptible: true,
+                realtime_priority: self.realtime_priority,
+                blocking: self.blocking,
+                config: self.config,
+                init_retry: None,
+            })
+        }
+    }
+
+    /// Returns a random duration between zero and `max` (inclusive).
+    fn random_duration(max: Duration) -> Duration {
+        use std::collections::hash_map::RandomState;
+        use std::hash::{BuildHasher, Hasher};
+
+        // RandomState is randomly seeded, which is enough to spread the sources (we don't need a good RNG here).
+        let random = RandomState::new().build_hasher().finish();
+        let max_nanos = max.as_nanos().min(u64::MAX as u128) as u64;
+        Duration::from_nanos(random % max_nanos.saturating_add(1))
+    }
+
+    impl Default for ManualTriggerBuilder {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
 }
 
 impl TriggerSpec {
@@ -215,6 +740,13 @@ impl TriggerSpec {
         builder::time_interval(poll_interval).build().unwrap()
     }
 
+    /// Defines a trigger that only polls the source on demand, and flushes its measurements after each poll.
+    ///
+    /// For more options, use [`builder::manual`].
+    pub fn manual() -> TriggerSpec {
+        builder::manual().build().unwrap()
+    }
+
     /// Creates a new builder for a trigger that polls the source at regular intervals.
     /// 
     /// This is equivalent to [`builder::time_interval`].
@@ -222,6 +754,49 @@ impl TriggerSpec {
         builder::time_interval(poll_interval)
     }
 
+    /// Retries the initialization of the trigger, when it fails with a transient error, according to `policy`.
+    ///
+    /// Some mechanisms depend on the environment, which may not be ready when the source starts:
+    /// for instance, the path watched by a [file-watch trigger](builder::file_watch) may not exist yet.
+    /// Without a policy, the source stops on the first failure.
+    ///
+    /// The errors of kind `NotFound`, `ConnectionRefused`, `ConnectionReset`, `ConnectionAborted`,
+    /// `NotConnected`, `AddrNotAvailable`, `TimedOut`, `Interrupted` and `WouldBlock` are considered transient.
+    /// The other errors (e.g. an unsupported mechanism) stop the source immediately.
+    pub fn with_init_retry(mut self, policy: RetryPolicy) -> TriggerSpec {
+        self.init_retry = Some(policy);
+        self
+    }
+
+    /// Chooses what the source does when its poll fails with a non-fatal error ([`PollError::CanRetry`]).
+    ///
+    /// By default, the source is polled again at the next tick, see [`PollErrorPolicy`].
+    pub fn with_poll_error_policy(mut self, policy: PollErrorPolicy) -> TriggerSpec {
+        self.config.poll_error_policy = policy;
+        self
+    }
+
+    /// Replaces the mechanism of the trigger by a future, keeping the rest of its configuration.
+    ///
+    /// This is used by [`testing::drive_source`](super::testing::drive_source) to fire the trigger on demand.
+    #[cfg(any(test, feature = "testing"))]
+    pub(crate) fn with_future_mechanism(mut self, f: TriggerFutureFn) -> TriggerSpec {
+        self.mechanism = TriggerMechanismSpec::Future(FutureFn(f));
+        self.interruptible = true;
+        self
+    }
+
+    /// Checks that the trigger can be used to run a source, without creating its mechanism.
+    pub(crate) fn check(&self) -> Result<(), String> {
+        if self.config.flush_rounds == 0 {
+            return Err(String::from("flush_rounds must be non-zero"));
+        }
+        if self.config.update_rounds == 0 {
+            return Err(String::from("update_rounds must be non-zero"));
+        }
+        Ok(())
+    }
+
     /// Adjusts the trigger specification to respect the given constraints.
     ///
     /// # Constraints
@@ -231,7 +806,8 @@ impl TriggerSpec {
             let max_update_interval = constraints.max_update_interval;
 
             match self.mechanism {
-                TriggerMechanismSpec::TimeInterval(_, poll_interval) => {
+                TriggerMechanismSpec::TimeInterval(_, poll_interval)
+                | TriggerMechanismSpec::AlignedInterval(poll_interval, _) => {
                     let update_interval = match self.config.update_rounds.try_into() {
                         Ok(update_rounds) => poll_interval * update_rounds,
                         Err(_too_big) => time::Duration::MAX,
@@ -267,52 +843,231 @@ impl Default for TriggerConstraints {
 pub(crate) enum TriggerReason {
     Triggered,
     Interrupted,
+    /// The future of the trigger has returned [`TriggerAction::Stop`]: the source must stop.
+    Finished,
+}
+
+/// Returns `true` if the initialization of a trigger has failed because of a transient error, which may not
+/// occur again. See [`TriggerSpec::with_init_retry`].
+pub(crate) fn is_transient_init_error(err: &std::io::Error) -> bool {
+    use std::io::ErrorKind;
+    matches!(
+        err.kind(),
+        ErrorKind::NotFound
+            | ErrorKind::ConnectionRefused
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::NotConnected
+            | ErrorKind::AddrNotAvailable
+            | ErrorKind::TimedOut
+            | ErrorKind::Interrupted
+            | ErrorKind::WouldBlock
+    )
 }
 
 impl Trigger {
-    pub fn new(spec: TriggerSpec, interrupt_signal: watch::Receiver<SourceCmd>) -> Result<Self, std::io::Error> {
+    /// Initializes a new trigger.
+    ///
+    /// `poll_now` is used by manual triggers: the source is polled when it is notified.
+    pub fn new(
+        spec: TriggerSpec,
+        interrupt_signal: watch::Receiver<SourceCmd>,
+        poll_now: Arc<Notify>,
+    ) -> Result<Self, std::io::Error> {
         Ok(Self {
             config: spec.config,
-            mechanism: TriggerMechanism::try_from(spec.mechanism)?,
+            mechanism: TriggerMechanism::new(spec.mechanism, poll_now)?,
             interrupt_signal: Some(interrupt_signal),
+            fired_at: Timestamp::now(),
+            coalesced: false,
         })
     }
 
     #[allow(unused)]
-    pub fn without_signal(spec: TriggerSpec) -> Result<Option<Self>, std::io::Error> {
+    pub fn without_signal(spec: TriggerSpec, poll_now: Arc<Notify>) -> Result<Option<Self>, std::io::Error> {
         if spec.interruptible {
             Ok(None)
         } else {
             Ok(Some(Self {
                 config: spec.config,
-                mechanism: TriggerMechanism::try_from(spec.mechanism)?,
+                mechanism: TriggerMechanism::new(spec.mechanism, poll_now)?,
                 interrupt_signal: None,
+                fired_at: Timestamp::now(),
+                coalesced: false,
             }))
         }
     }
 
+    /// Changes the polling and flushing intervals of a time-based trigger, in place.
+    ///
+    /// The update interval is kept (approximately) the same. Other mechanisms, like manual
+    /// or cron triggers, have no interval: for them, this returns an error of kind
+    /// [`std::io::ErrorKind::Unsupported`] and leaves the trigger unchanged.
+    pub fn set_interval(&mut self, poll_interval: Duration, flush_interval: Duration) -> Result<(), std::io::Error> {
+        if poll_interval.is_zero() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "the poll interval must be non-zero",
+            ));
+        }
+        let previous_interval = match &mut self.mechanism {
+            #[cfg(target_os = "linux")]
+            TriggerMechanism::Timerfd(interval, period, origin) => {
+                // tokio_timerfd::Interval cannot be rearmed, replace it by a new timer.
+                let start = time::Instant::now() + poll_interval;
+                *interval = tokio_timerfd::Interval::new(start, poll_interval)?;
+                *origin = start;
+                std::mem::replace(period, poll_interval)
+            }
+            TriggerMechanism::TokioSleep(next, period) => {
+                *next = tokio::time::Instant::now() + poll_interval;
+                std::mem::replace(period, poll_interval)
+            }
+            TriggerMechanism::AlignedSleep { period, last_boundary, .. } => {
+                // the boundaries of the new period are unrelated to the old ones
+                *last_boundary = None;
+                std::mem::replace(period, poll_interval)
+            }
+            other => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::Unsupported,
+                    format!("{other:?} has no interval"),
+                ));
+            }
+        };
+        let update_interval = previous_interval.as_nanos() * self.config.update_rounds as u128;
+        self.config.update_rounds = ((update_interval / poll_interval.as_nanos()) as usize).max(1);
+        self.config.flush_rounds = flush_rounds(poll_interval, flush_interval);
+        Ok(())
+    }
+
+    /// Returns the interval between two polls, if the trigger is time-based.
+    pub fn poll_interval(&self) -> Option<Duration> {
+        match &self.mechanism {
+            #[cfg(target_os = "linux")]
+            TriggerMechanism::Timerfd(_, period, _) => Some(*period),
+            TriggerMechanism::TokioSleep(_, period) => Some(*period),
+            TriggerMechanism::AlignedSleep { period, .. } => Some(*period),
+            _ => None,
+        }
+    }
+
+    /// Skips the ticks that have been missed while the source was paused, so that the trigger keeps its schedule:
+    /// the next poll occurs at the next tick of the schedule, instead of immediately after the resumption.
+    ///
+    /// The mechanisms that compute their next tick from the current time (aligned and cron triggers)
+    /// already keep their schedule, and the other mechanisms have none: they are not modified.
+    pub fn skip_missed_ticks(&mut self) -> Result<(), std::io::Error> {
+        let now = time::Instant::now();
+        match &mut self.mechanism {
+            #[cfg(target_os = "linux")]
+            TriggerMechanism::Timerfd(interval, period, origin) => {
+                // the timer may have expired during the pause, rearm it on the next tick
+                let next = next_tick_after(*origin, *period, now);
+                if next != *origin {
+                    *interval = tokio_timerfd::Interval::new(next, *period)?;
+                    *origin = next;
+                }
+            }
+            TriggerMechanism::TokioSleep(next, period) => {
+                *next = next_tick_after(next.into_std(), *period, now).into();
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    /// Returns the time at which the trigger has fired for the last time, see [`next`](Self::next).
+    ///
+    /// It is taken as soon as the mechanism fires, before the source is polled.
+    pub fn fired_at(&self) -> Timestamp {
+        self.fired_at
+    }
+
     /// Waits for the next tick of the trigger, or for an interruption.
-    pub async fn next(&mut self) -> anyhow::Result<TriggerReason> {
+    ///
+    /// Returns a [`PollError::CanRetry`] if the mechanism has failed temporarily, for instance if the read
+    /// of a timer has been interrupted while the virtual machine was suspended: `next` can then be called again.
+    pub async fn next(&mut self) -> Result<TriggerReason, PollError> {
         if let Some(signal) = &mut self.interrupt_signal {
             // Use select! to wake up on trigger _or_ signal, the first that occurs
             tokio::select! {
                 biased; // don't choose the branch randomly (for performance)
 
                 res = self.mechanism.next() => {
-                    res?;
-                    Ok(TriggerReason::Triggered)
+                    let action = res.map_err(mechanism_error)?;
+                    Ok(self.fired(action))
                 }
                 res = signal.changed() => {
                     // changed() returns an Error if the watch::Sender has been dropped, which should not happen.
-                    res.context("watch::Sender dropped, which interrupted the Trigger")?;
+                    res.context("watch::Sender dropped, which interrupted the Trigger").map_err(PollError::Fatal)?;
                     Ok(TriggerReason::Interrupted)
                 }
             }
         } else {
             // Simple case: simply wait for the trigger
-            self.mechanism.next().await?;
-            Ok(TriggerReason::Triggered)
+            let action = self.mechanism.next().await.map_err(mechanism_error)?;
+            Ok(self.fired(action))
+        }
+    }
+
+    /// Waits for the next tick of the trigger, like [`next`](Self::next), but coalesces the ticks that occur
+    /// before `not_before` into a single tick, at `not_before`.
+    ///
+    /// If the wait is interrupted, the coalesced tick is kept for the next call.
+    pub async fn next_not_before(&mut self, not_before: Option<time::Instant>) -> Result<TriggerReason, PollError> {
+        let deadline = match not_before {
+            Some(t) if time::Instant::now() < t => t,
+            _ => {
+                if std::mem::take(&mut self.coalesced) {
+                    // the interval has elapsed while the source was interrupted
+                    self.fired_at = Timestamp::now();
+                    return Ok(TriggerReason::Triggered);
+                }
+                return self.next().await;
+            }
+        };
+        if !self.coalesced {
+            match self.next().await? {
+                TriggerReason::Triggered if time::Instant::now() < deadline => self.coalesced = true,
+                reason => return Ok(reason),
+            }
+        }
+        // absorb the ticks until the end of the interval
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep_until(deadline.into()) => {
+                    self.coalesced = false;
+                    self.fired_at = Timestamp::now();
+                    return Ok(TriggerReason::Triggered);
+                }
+                res = self.next() => match res? {
+                    TriggerReason::Triggered => (),
+                    reason => return Ok(reason),
+                }
+            }
+        }
+    }
+
+    fn fired(&mut self, action: TriggerAction) -> TriggerReason {
+        match action {
+            TriggerAction::Continue => {
+                self.fired_at = Timestamp::now();
+                TriggerReason::Triggered
+            }
+            TriggerAction::Stop => TriggerReason::Finished,
+        }
+    }
+}
+
+/// Classifies an error of a trigger mechanism: the errors of kind `Interrupted`, `WouldBlock` and `TimedOut`
+/// are temporary, the other ones are fatal.
+fn mechanism_error(e: std::io::Error) -> PollError {
+    match e.kind() {
+        std::io::ErrorKind::Interrupted | std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut => {
+            PollError::CanRetry(e.into())
         }
+        _ => PollError::Fatal(e.into()),
     }
 }
 
@@ -324,8 +1079,22 @@ impl Trigger {
 #[derive(Debug, Clone)]
 enum TriggerMechanismSpec {
     TimeInterval(time::Instant, time::Duration),
-    #[allow(dead_code)]
-    Future(fn() -> BoxFuture<'static, SourceTriggerOutput>),
+    /// The period and the phase offset.
+    AlignedInterval(time::Duration, time::Duration),
+    Future(FutureFn),
+    Manual,
+    Cron(CronSchedule),
+    FileWatch(PathBuf),
+}
+
+/// A [`TriggerFutureFn`] that implements `Debug`, for [`TriggerMechanismSpec`].
+#[derive(Clone)]
+struct FutureFn(TriggerFutureFn);
+
+impl fmt::Debug for FutureFn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("FutureFn")
+    }
 }
 
 /// The possible trigger mechanisms.
@@ -335,60 +1104,188 @@ enum TriggerMechanism {
     /// but is only available on Linux.
     ///
     /// The source is polled each time `interval.next().await` returns.
+    /// The second field is the period of the interval, the third one is a tick of its schedule.
     #[cfg(target_os = "linux")]
-    Timerfd(tokio_timerfd::Interval),
+    Timerfd(tokio_timerfd::Interval, time::Duration, time::Instant),
 
     /// A trigger based on [`tokio::time::sleep`].
+    ///
+    /// The first field is the time of the next tick, the second one is the period.
     #[allow(dead_code)]
     TokioSleep(tokio::time::Instant, tokio::time::Duration),
 
     /// A trigger based on an arbitrary [`Future`] that is returned on demand
-    /// by a function `f`.
+    /// by a closure `f`.
     ///
     /// The source is polled each time `f().await` returns.
-    Future(fn() -> BoxFuture<'static, SourceTriggerOutput>),
-}
+    Future(TriggerFutureFn),
+
+    /// A trigger that waits for a notification from the pipeline controller.
+    ///
+    /// The source is polled each time `notify.notified().await` returns.
+    Manual(Arc<Notify>),
 
-impl TryFrom<TriggerMechanismSpec> for TriggerMechanism {
-    type Error = std::io::Error;
+    /// A trigger based on [`tokio::time::sleep`], aligned on the wall clock.
+    ///
+    /// The source is polled each time the system time reaches a multiple of `period`.
+    /// `last_boundary` is the index of the last boundary, it prevents the trigger from firing twice for the same boundary.
+    /// Like `last_tick` for the cron triggers, it is only updated once the trigger has fired.
+    AlignedSleep {
+        period: tokio::time::Duration,
+        /// The source is polled `offset` after each boundary.
+        offset: tokio::time::Duration,
+        last_boundary: Option<u128>,
+    },
 
-    fn try_from(value: TriggerMechanismSpec) -> Result<Self, Self::Error> {
+    /// A trigger based on [`tokio::time::sleep`], which follows a cron schedule.
+    ///
+    /// The next polling time is computed from the system time after each tick.
+    /// `last_tick` prevents the trigger from firing twice for the same minute.
+    /// It is only updated once the trigger has fired, an interrupted wait does not skip the tick.
+    Cron {
+        schedule: CronSchedule,
+        last_tick: Option<time::SystemTime>,
+    },
+
+    /// A trigger based on inotify, only available on Linux.
+    ///
+    /// The source is polled each time `watch.changed().await` returns.
+    #[cfg(target_os = "linux")]
+    FileWatch(FileWatch),
+}
+
+impl TriggerMechanism {
+    fn new(value: TriggerMechanismSpec, poll_now: Arc<Notify>) -> Result<Self, std::io::Error> {
         Ok(match value {
             TriggerMechanismSpec::TimeInterval(at, duration) => {
                 // Use timerfd if possible, fallback to `tokio::time::sleep`.
                 #[cfg(target_os = "linux")]
                 {
-                    TriggerMechanism::Timerfd(tokio_timerfd::Interval::new(at, duration)?)
+                    TriggerMechanism::Timerfd(tokio_timerfd::Interval::new(at, duration)?, duration, at)
                 }
 
                 #[cfg(not(target_os = "linux"))]
                 {
-                    TriggerMechanism::TokioSleep(at.into(), duration.into())
+                    let next = next_tick_after(at, duration, time::Instant::now());
+                    TriggerMechanism::TokioSleep(next.into(), duration)
                 }
             }
-            TriggerMechanismSpec::Future(f) => TriggerMechanism::Future(f),
+            TriggerMechanismSpec::Future(FutureFn(f)) => TriggerMechanism::Future(f),
+            TriggerMechanismSpec::Manual => TriggerMechanism::Manual(poll_now),
+            TriggerMechanismSpec::AlignedInterval(period, offset) => TriggerMechanism::AlignedSleep {
+                period,
+                offset,
+                last_boundary: None,
+            },
+            TriggerMechanismSpec::Cron(schedule) => TriggerMechanism::Cron {
+                schedule,
+                last_tick: None,
+            },
+            #[cfg(target_os = "linux")]
+            TriggerMechanismSpec::FileWatch(path) => TriggerMechanism::FileWatch(FileWatch::new(&path)?),
+            #[cfg(not(target_os = "linux"))]
+            TriggerMechanismSpec::FileWatch(_) => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::Unsupported,
+                    "the file-watch trigger is only available on Linux",
+                ));
+            }
         })
     }
 }
 
+/// Computes the number of polls between two flushes. `poll_interval` must be non-zero.
+///
+/// The result is never zero, or the remainder operation would panic (`i % flush_rounds` in the polling loop):
+/// a `flush_interval` smaller than `poll_interval` flushes after each poll.
+/// If `flush_interval` is not a multiple of `poll_interval`, it is rounded down and a warning is emitted.
+fn flush_rounds(poll_interval: Duration, flush_interval: Duration) -> usize {
+    let rounds = ((flush_interval.as_nanos() / poll_interval.as_nanos()) as usize).max(1);
+    if flush_interval.as_nanos() % poll_interval.as_nanos() != 0 {
+        let actual = u32::try_from(rounds).map_or(Duration::MAX, |r| poll_interval.saturating_mul(r));
+        log::warn!("The flush interval ({flush_interval:?}) is not a multiple of the poll interval ({poll_interval:?}), the measurements will be flushed every {actual:?}.");
+    }
+    rounds
+}
+
+/// Returns the first tick of the schedule `tick + k * period` (with `k >= 0`) that is after `now`.
+fn next_tick_after(tick: time::Instant, period: Duration, now: time::Instant) -> time::Instant {
+    if tick > now {
+        return tick;
+    }
+    let missed = (now - tick).as_nanos() / period.as_nanos() + 1;
+    tick + Duration::from_nanos((missed * period.as_nanos()).try_into().unwrap_or(u64::MAX))
+}
+
+/// Computes the time to wait until the next multiple of `period`, given the current time.
+///
+/// The returned boundary is always after `last_boundary`, which is updated.
+fn aligned_delay(now_since_epoch: Duration, period: Duration, last_boundary: &mut Option<u128>) -> Duration {
+    let now = now_since_epoch.as_nanos();
+    let period = period.as_nanos();
+    let mut next = now / period + 1;
+    if let Some(last) = *last_boundary {
+        // The clock may have gone backward, or the timer may have woken up slightly before the boundary.
+        next = next.max(last + 1);
+    }
+    *last_boundary = Some(next);
+    let delay = next * period - now;
+    Duration::from_nanos(delay.try_into().unwrap_or(u64::MAX))
+}
+
 impl TriggerMechanism {
-    pub async fn next(&mut self) -> Result<(), std::io::Error> {
+    pub async fn next(&mut self) -> Result<TriggerAction, std::io::Error> {
         use tokio_stream::StreamExt;
 
         match self {
             #[cfg(target_os = "linux")]
-            TriggerMechanism::Timerfd(interval) => {
-                interval.next().await.unwrap()?;
-                Ok(())
-            }
-            TriggerMechanism::TokioSleep(start, period) => {
-                let start = *start;
-                let now = tokio::time::Instant::now();
-                let deadline = if start > now { start } else { now + *period };
-                tokio::time::sleep_until(deadline).await;
-                Ok(())
+            TriggerMechanism::Timerfd(interval, _, _) => match interval.next().await {
+                Some(res) => {
+                    res?;
+                    Ok(TriggerAction::Continue)
+                }
+                None => Err(std::io::Error::other("the timerfd interval has ended")),
+            },
+            TriggerMechanism::TokioSleep(next, period) => {
+                // If the wait is interrupted, the next call waits for the same tick.
+                tokio::time::sleep_until(*next).await;
+                // a poll that overran the interval skips the missed ticks
+                *next = next_tick_after(next.into_std(), *period, time::Instant::now()).into();
+                Ok(TriggerAction::Continue)
             }
             TriggerMechanism::Future(f) => f().await,
+            TriggerMechanism::Manual(notify) => {
+                notify.notified().await;
+                Ok(TriggerAction::Continue)
+            }
+            TriggerMechanism::AlignedSleep {
+                period,
+                offset,
+                last_boundary,
+            } => {
+                let now = time::SystemTime::now()
+                    .duration_since(time::UNIX_EPOCH)
+                    .unwrap_or(Duration::ZERO);
+                // the grid is shifted by the offset: compute the delay as if the clock was `offset` late
+                let mut boundary = *last_boundary;
+                let delay = aligned_delay(now.saturating_sub(*offset), *period, &mut boundary);
+                tokio::time::sleep(delay).await;
+                *last_boundary = boundary;
+                Ok(TriggerAction::Continue)
+            }
+            TriggerMechanism::Cron { schedule, last_tick } => {
+                let now = time::SystemTime::now();
+                // The timer may have woken up slightly before the tick.
+                let after = last_tick.map_or(now, |last| last.max(now));
+                let next = schedule
+                    .next_after(after)
+                    .ok_or_else(|| std::io::Error::other("the cron schedule has no next time"))?;
+                tokio::time::sleep(next.duration_since(now).unwrap_or(Duration::ZERO)).await;
+                *last_tick = Some(next);
+                Ok(TriggerAction::Continue)
+            }
+            #[cfg(target_os = "linux")]
+            TriggerMechanism::FileWatch(watch) => watch.changed().await.map(|()| TriggerAction::Continue),
         }
     }
 }
@@ -397,18 +1294,30 @@ impl fmt::Debug for TriggerMechanism {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             #[cfg(target_os = "linux")]
-            Self::Timerfd(_) => f.write_str("Timerfd trigger"),
+            Self::Timerfd(_, _, _) => f.write_str("Timerfd trigger"),
             Self::TokioSleep(_, _) => f.write_str("TokioSleep trigger"),
             Self::Future(_) => f.write_str("Future trigger"),
+            Self::Manual(_) => f.write_str("Manual trigger"),
+            Self::AlignedSleep { .. } => f.write_str("AlignedSleep trigger"),
+            Self::Cron { .. } => f.write_str("Cron trigger"),
+            #[cfg(target_os = "linux")]
+            Self::FileWatch(_) => f.write_str("FileWatch trigger"),
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::time::Duration;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
 
-    use super::{builder, TriggerConstraints, TriggerMechanismSpec};
+    use tokio::sync::{watch, Notify};
+
+    use super::{
+        aligned_delay, builder, flush_rounds, next_tick_after, Trigger, TriggerAction, TriggerConstraints,
+        TriggerMechanismSpec, TriggerReason,
+    };
+    use crate::pipeline::runtime::SourceCmd;
 
     #[test]
     fn trigger_auto_config() {
@@ -511,4 +1420,331 @@ mod tests {
         assert_eq!(trigger.config.flush_rounds, 5);
         assert_eq!(trigger.config.update_rounds, 1);
     }
+
+    #[test]
+    fn flush_rounds_rounding() {
+        let ms = Duration::from_millis;
+        assert_eq!(flush_rounds(ms(300), ms(900)), 3);
+        // not a multiple: rounded down (with a warning)
+        assert_eq!(flush_rounds(ms(300), ms(1000)), 3);
+        // smaller than the poll interval: flush after each poll, never zero
+        assert_eq!(flush_rounds(ms(300), ms(100)), 1);
+        assert_eq!(flush_rounds(ms(300), Duration::ZERO), 1);
+    }
+
+    #[test]
+    fn manual_trigger() {
+        assert!(builder::manual().flush_rounds(0).build().is_err());
+
+        let spec = builder::manual().flush_rounds(3).build().unwrap();
+        assert!(matches!(spec.mechanism, TriggerMechanismSpec::Manual));
+        assert!(spec.interruptible);
+        assert_eq!(spec.config.flush_rounds, 3);
+        assert_eq!(spec.config.update_rounds, 1);
+
+        let rt = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        rt.block_on(async {
+            let (_cmd_tx, cmd_rx) = watch::channel(SourceCmd::Run);
+            let poll_now = Arc::new(Notify::new());
+            let mut trigger = Trigger::new(spec, cmd_rx, poll_now.clone()).unwrap();
+
+            // nothing happens until the trigger is notified
+            let res = tokio::time::timeout(Duration::from_millis(50), trigger.next()).await;
+            assert!(res.is_err());
+
+            let notified_at = std::time::SystemTime::now();
+            poll_now.notify_one();
+            let reason = tokio::time::timeout(Duration::from_millis(50), trigger.next())
+                .await
+                .expect("the trigger should fire after a notification")
+                .unwrap();
+            assert_eq!(reason, TriggerReason::Triggered);
+            // the timestamp of the poll is the time at which the trigger has fired
+            assert!(std::time::SystemTime::from(trigger.fired_at()) >= notified_at);
+        });
+    }
+
+    #[test]
+    fn future_trigger() {
+        assert!(builder::future(|| Box::pin(async { Ok(TriggerAction::Continue) })).flush_rounds(0).build().is_err());
+
+        // the closure captures a channel, and the source is polled each time it receives a message
+        let (event_tx, event_rx) = tokio::sync::mpsc::unbounded_channel::<()>();
+        let event_rx = Arc::new(tokio::sync::Mutex::new(event_rx));
+        let spec = builder::future(move || {
+            let event_rx = event_rx.clone();
+            Box::pin(async move {
+                match event_rx.lock().await.recv().await {
+                    Some(()) => Ok(TriggerAction::Continue),
+                    None => Ok(TriggerAction::Stop),
+                }
+            })
+        })
+        .flush_rounds(2)
+        .build()
+        .unwrap();
+        assert!(matches!(spec.mechanism, TriggerMechanismSpec::Future(_)));
+        assert!(spec.interruptible);
+        assert_eq!(spec.config.flush_rounds, 2);
+
+        let rt = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        rt.block_on(async {
+            let (_cmd_tx, cmd_rx) = watch::channel(SourceCmd::Run);
+            let mut trigger = Trigger::new(spec, cmd_rx, Arc::new(Notify::new())).unwrap();
+
+            let res = tokio::time::timeout(Duration::from_millis(50), trigger.next()).await;
+            assert!(res.is_err(), "the trigger should wait for an event");
+
+            event_tx.send(()).unwrap();
+            let reason = tokio::time::timeout(Duration::from_millis(50), trigger.next())
+                .await
+                .expect("the trigger should fire after an event")
+                .unwrap();
+            assert_eq!(reason, TriggerReason::Triggered);
+
+            // the end of the events stops the source
+            drop(event_tx);
+            let reason = tokio::time::timeout(Duration::from_millis(50), trigger.next())
+                .await
+                .expect("the trigger should finish when the channel is closed")
+                .unwrap();
+            assert_eq!(reason, TriggerReason::Finished);
+        });
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn file_watch_trigger() {
+        let dir = std::env::temp_dir().join(format!("alumet-file-watch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("watched");
+        std::fs::write(&path, "0").unwrap();
+
+        let spec = builder::file_watch(&path).build().unwrap();
+        assert!(matches!(spec.mechanism, TriggerMechanismSpec::FileWatch(_)));
+        assert!(spec.interruptible);
+
+        let rt = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        rt.block_on(async {
+            let (_cmd_tx, cmd_rx) = watch::channel(SourceCmd::Run);
+            let mut trigger = Trigger::new(spec, cmd_rx.clone(), Arc::new(Notify::new())).unwrap();
+
+            // nothing happens until the file changes
+            let res = tokio::time::timeout(Duration::from_millis(50), trigger.next()).await;
+            assert!(res.is_err());
+
+            // several writes in a row are coalesced
+            for i in 1..=3 {
+                std::fs::write(&path, i.to_string()).unwrap();
+            }
+            let reason = tokio::time::timeout(Duration::from_millis(100), trigger.next())
+                .await
+                .expect("the trigger should fire after a change")
+                .unwrap();
+            assert_eq!(reason, TriggerReason::Triggered);
+            let res = tokio::time::timeout(Duration::from_millis(50), trigger.next()).await;
+            assert!(res.is_err(), "the pending events should have been consumed");
+
+            // an atomic replacement is a change, and the new file is watched
+            let tmp = dir.join("watched.tmp");
+            std::fs::write(&tmp, "4").unwrap();
+            std::fs::rename(&tmp, &path).unwrap();
+            let reason = tokio::time::timeout(Duration::from_millis(100), trigger.next())
+                .await
+                .expect("the trigger should fire after a replacement")
+                .unwrap();
+            assert_eq!(reason, TriggerReason::Triggered);
+            std::fs::write(&path, "5").unwrap();
+            let reason = tokio::time::timeout(Duration::from_millis(100), trigger.next())
+                .await
+                .expect("the trigger should fire after a change of the new file")
+                .unwrap();
+            assert_eq!(reason, TriggerReason::Triggered);
+
+            // the other files of the directory are ignored
+            std::fs::write(dir.join("other"), "0").unwrap();
+            let res = tokio::time::timeout(Duration::from_millis(50), trigger.next()).await;
+            assert!(res.is_err(), "a change of another file should not fire the trigger");
+
+            // a deleted path cannot be watched anymore
+            std::fs::remove_file(&path).unwrap();
+            let res = tokio::time::timeout(Duration::from_millis(100), trigger.next())
+                .await
+                .expect("the trigger should fail after a deletion");
+            assert!(res.is_err());
+
+            // the path must exist when the trigger starts
+            let spec = builder::file_watch(dir.join("missing")).build().unwrap();
+            let Err(err) = Trigger::new(spec, cmd_rx, Arc::new(Notify::new())) else {
+                panic!("watching a missing path should fail");
+            };
+            assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+        });
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn set_interval() {
+        let rt = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        rt.block_on(async {
+            let (_cmd_tx, cmd_rx) = watch::channel(SourceCmd::Run);
+            let spec = builder::time_interval(Duration::from_millis(100))
+                .flush_interval(Duration::from_millis(200))
+                .update_interval(Duration::from_millis(400))
+                .build()
+                .unwrap();
+            let mut trigger = Trigger::new(spec, cmd_rx.clone(), Arc::new(Notify::new())).unwrap();
+            assert_eq!(trigger.config.flush_rounds, 2);
+            assert_eq!(trigger.config.update_rounds, 4);
+
+            trigger
+                .set_interval(Duration::from_millis(20), Duration::from_millis(100))
+                .unwrap();
+            assert_eq!(trigger.config.flush_rounds, 5);
+            assert_eq!(trigger.config.update_rounds, 20); // the update interval stays the same
+
+            // the new interval is used right away
+            let reason = tokio::time::timeout(Duration::from_millis(80), trigger.next())
+                .await
+                .expect("the trigger should fire with the new interval")
+                .unwrap();
+            assert_eq!(reason, TriggerReason::Triggered);
+
+            assert!(trigger.set_interval(Duration::ZERO, Duration::from_millis(100)).is_err());
+            assert_eq!(trigger.config.flush_rounds, 5);
+
+            // other triggers have no interval
+            let spec = builder::manual().build().unwrap();
+            let mut trigger = Trigger::new(spec, cmd_rx, Arc::new(Notify::new())).unwrap();
+            let err = trigger
+                .set_interval(Duration::from_millis(20), Duration::from_millis(100))
+                .unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::Unsupported);
+            assert_eq!(trigger.config.flush_rounds, 1);
+        });
+    }
+
+    #[test]
+    fn aligned_trigger() {
+        let spec = builder::time_interval(Duration::from_secs(1))
+            .align_to_wall_clock()
+            .flush_interval(Duration::from_secs(5))
+            .build()
+            .unwrap();
+        assert!(matches!(spec.mechanism, TriggerMechanismSpec::AlignedInterval(d, o) if d == Duration::from_secs(1) && o.is_zero()));
+        assert_eq!(spec.config.flush_rounds, 5);
+
+        let period = Duration::from_secs(1);
+        let mut last = None;
+        // first tick: wait until the next second
+        assert_eq!(aligned_delay(Duration::from_millis(1300), period, &mut last), Duration::from_millis(700));
+        assert_eq!(last, Some(2));
+        // the timer woke up a bit early: don't fire twice for the same second
+        assert_eq!(aligned_delay(Duration::from_millis(1999), period, &mut last), Duration::from_millis(1001));
+        assert_eq!(last, Some(3));
+        // the poll overran the interval: skip the missed ticks
+        assert_eq!(aligned_delay(Duration::from_millis(5250), period, &mut last), Duration::from_millis(750));
+        assert_eq!(last, Some(6));
+    }
+
+    #[test]
+    fn cron_trigger() {
+        let spec = builder::cron("0 0 * * *").flush_rounds(2).build().unwrap();
+        assert!(matches!(spec.mechanism, TriggerMechanismSpec::Cron(_)));
+        assert!(spec.interruptible);
+        assert_eq!(spec.config.flush_rounds, 2);
+
+        assert!(builder::cron("0 0 * *").build().is_err());
+        assert!(builder::cron("0 25 * * *").build().is_err());
+        assert!(builder::cron("0 0 31 2 *").build().is_err());
+        assert!(builder::cron("* * * * *").flush_rounds(0).build().is_err());
+    }
+
+    #[test]
+    fn jittered_trigger() {
+        let poll_interval = Duration::from_millis(100);
+        assert!(builder::time_interval(poll_interval)
+            .jitter(Duration::from_millis(101))
+            .build()
+            .is_err());
+        assert!(builder::time_interval(poll_interval)
+            .align_to_wall_clock()
+            .jitter(Duration::from_millis(10))
+            .build()
+            .is_err());
+
+        let start = Instant::now();
+        let max_jitter = Duration::from_millis(50);
+        for _ in 0..10 {
+            let spec = builder::time_interval(poll_interval)
+                .starting_at(start)
+                .jitter(max_jitter)
+                .build()
+                .unwrap();
+            match spec.mechanism {
+                TriggerMechanismSpec::TimeInterval(at, interval) => {
+                    assert_eq!(interval, poll_interval, "the poll interval must not change");
+                    assert!(at >= start && at <= start + max_jitter, "invalid start: {:?}", at - start);
+                }
+                other => panic!("unexpected mechanism {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn phase_offset() {
+        let poll_interval = Duration::from_millis(500);
+        let offset = Duration::from_millis(250);
+        assert!(builder::time_interval(poll_interval).phase_offset(poll_interval).build().is_err());
+
+        let start = Instant::now();
+        let spec = builder::time_interval(poll_interval)
+            .starting_at(start)
+            .phase_offset(offset)
+            .build()
+            .unwrap();
+        assert!(matches!(spec.mechanism, TriggerMechanismSpec::TimeInterval(at, d) if at == start + offset && d == poll_interval));
+
+        let spec = builder::time_interval(poll_interval)
+            .align_to_wall_clock()
+            .phase_offset(offset)
+            .build()
+            .unwrap();
+        assert!(matches!(spec.mechanism, TriggerMechanismSpec::AlignedInterval(d, o) if d == poll_interval && o == offset));
+        // the boundaries are shifted: at 1.1s, the next poll is at 1.25s
+        let mut last = None;
+        let now = Duration::from_millis(1100);
+        assert_eq!(aligned_delay(now - offset, poll_interval, &mut last), Duration::from_millis(150));
+    }
+
+    #[test]
+    fn keep_schedule() {
+        let start = Instant::now();
+        let period = Duration::from_millis(100);
+        assert_eq!(next_tick_after(start + period, period, start), start + period);
+        assert_eq!(next_tick_after(start, period, start), start + period);
+        assert_eq!(next_tick_after(start, period, start + Duration::from_millis(250)), start + 3 * period);
+
+        let spec = builder::time_interval(period).starting_at(start + period).build().unwrap();
+        let rt = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        rt.block_on(async {
+            let (_cmd_tx, cmd_rx) = watch::channel(SourceCmd::Run);
+            let mut trigger = Trigger::new(spec, cmd_rx, Arc::new(Notify::new())).unwrap();
+
+            // an interrupted wait does not delay the tick
+            let res = tokio::time::timeout(Duration::from_millis(50), trigger.next()).await;
+            assert!(res.is_err());
+            assert_eq!(trigger.next().await.unwrap(), TriggerReason::Triggered);
+            let elapsed = start.elapsed();
+            assert!(elapsed >= period && elapsed < Duration::from_millis(140), "fired after {elapsed:?}");
+
+            // after a pause, the missed ticks are skipped: the trigger fires on the next tick of its schedule
+            tokio::time::sleep(Duration::from_millis(250)).await;
+            trigger.skip_missed_ticks().unwrap();
+            assert_eq!(trigger.next().await.unwrap(), TriggerReason::Triggered);
+            let elapsed = start.elapsed();
+            assert!(elapsed >= 4 * period - Duration::from_millis(5), "fired after {elapsed:?}");
+            assert!(elapsed < Duration::from_millis(440), "fired after {elapsed:?}");
+        });
+    }
 }
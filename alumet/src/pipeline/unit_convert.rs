@@ -0,0 +1,140 @@
+//! A transform that converts the values of some metrics to another unit, for instance from microjoules to joules.
+
+use std::collections::HashMap;
+
+use crate::measurement::{MeasurementBuffer, WrappedMeasurementValue};
+use crate::metrics::{MetricRegistry, RawMetricId};
+
+use super::{Transform, TransformError};
+
+/// A transform that multiplies the values of some metrics by a scale factor.
+///
+/// Since the unit is a property of the metric, a converted point is moved to another metric, which has
+/// the target unit: its value is multiplied by the factor and its metric is replaced, while its timestamp,
+/// resource, consumer and attributes are kept. The values of the target metric are always `f64`, whatever
+/// the type of the source metric. The points of the other metrics are not modified.
+///
+/// The factor can be given explicitly, with [`with_factor`](Self::with_factor), or computed from the units
+/// of the metrics, with [`with_conversion`](Self::with_conversion).
+///
+/// ## Example
+/// ```
+/// use alumet::metrics::{MetricId, TypedMetricId};
+/// use alumet::pipeline::unit_convert::UnitConvertTransform;
+///
+/// # fn example(energy_uj: TypedMetricId<u64>, energy_j: TypedMetricId<f64>) {
+/// // the metrics are created by the plugin, for instance with `AlumetStart::create_metric`
+/// let transform = UnitConvertTransform::new().with_factor(energy_uj.untyped_id(), energy_j.untyped_id(), 1e-6);
+/// # }
+/// ```
+#[derive(Default)]
+pub struct UnitConvertTransform {
+    /// The target metric and the factor, by source metric.
+    conversions: HashMap<RawMetricId, (RawMetricId, f64)>,
+}
+
+impl UnitConvertTransform {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Converts the points of the metric `from` into points of the metric `to`, by multiplying their value by `factor`.
+    pub fn with_factor(mut self, from: RawMetricId, to: RawMetricId, factor: f64) -> Self {
+        self.conversions.insert(from, (to, factor));
+        self
+    }
+
+    /// Converts the points of the metric `from` into points of the metric `to`, according to the units of
+    /// these metrics in the `registry` (see [`AlumetStart::metrics`](crate::plugin::AlumetStart::metrics)).
+    ///
+    /// If a metric is not in the registry, or if the units are not compatible (e.g. joules and seconds),
+    /// a warning is logged, once, and the conversion is ignored: the points of `from` are left untouched.
+    /// See [`PrefixedUnit::conversion_factor`](crate::units::PrefixedUnit::conversion_factor).
+    pub fn with_conversion(self, registry: &MetricRegistry, from: RawMetricId, to: RawMetricId) -> Self {
+        let (Some(from_metric), Some(to_metric)) = (registry.with_id(&from), registry.with_id(&to)) else {
+            log::warn!("Cannot convert {from:?} to {to:?}: unknown metric, the conversion is ignored.");
+            return self;
+        };
+        match from_metric.unit.conversion_factor(&to_metric.unit) {
+            Some(factor) => self.with_factor(from, to, factor),
+            None => {
+                log::warn!(
+                    "Cannot convert metric {} to {}: {} and {} are not compatible, the conversion is ignored.",
+                    from_metric.name,
+                    to_metric.name,
+                    from_metric.unit.unique_name(),
+                    to_metric.unit.unique_name()
+                );
+                self
+            }
+        }
+    }
+}
+
+impl Transform for UnitConvertTransform {
+    fn apply(&mut self, measurements: &mut MeasurementBuffer) -> Result<(), TransformError> {
+        for point in measurements.iter_mut() {
+            if let Some((target, factor)) = self.conversions.get(&point.metric) {
+                let value = match point.value {
+                    WrappedMeasurementValue::F64(x) => x,
+                    WrappedMeasurementValue::U64(n) => n as f64,
+                };
+                point.metric = *target;
+                point.value = WrappedMeasurementValue::F64(value * factor);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::measurement::{MeasurementBuffer, WrappedMeasurementType, WrappedMeasurementValue};
+    use crate::metrics::MetricRegistry;
+    use crate::pipeline::testing::{self, register_metric};
+    use crate::pipeline::Transform;
+    use crate::units::{PrefixedUnit, Unit};
+
+    use super::UnitConvertTransform;
+
+    #[test]
+    fn convert_units() {
+        let mut registry = MetricRegistry::new();
+        let f64 = WrappedMeasurementType::F64;
+        let uj = register_metric(&mut registry, "energy_uj", f64.clone(), PrefixedUnit::micro(Unit::Joule));
+        let j = register_metric(&mut registry, "energy_j", f64.clone(), Unit::Joule);
+        let kwh = register_metric(&mut registry, "energy_kwh", f64.clone(), PrefixedUnit::kilo(Unit::WattHour));
+        let time = register_metric(&mut registry, "time", f64.clone(), Unit::Second);
+        let other = register_metric(&mut registry, "other", f64, Unit::Unity);
+
+        let mut transform = UnitConvertTransform::new()
+            .with_conversion(&registry, uj, j)
+            .with_conversion(&registry, kwh, j)
+            // incompatible: ignored
+            .with_conversion(&registry, time, j);
+        let mut buf = MeasurementBuffer::from(vec![
+            testing::point().metric(uj).u64(2_500_000).build(),
+            testing::point().metric(kwh).u64(2).build(),
+            testing::point().metric(time).u64(3).build(),
+            testing::point().metric(other).u64(4).build(),
+        ]);
+        transform.apply(&mut buf).unwrap();
+        // the converted values are f64, the other values keep their type
+        let values: Vec<_> = buf
+            .iter()
+            .map(|p| match p.value {
+                WrappedMeasurementValue::F64(x) => (p.metric, Some(x), None),
+                WrappedMeasurementValue::U64(n) => (p.metric, None, Some(n)),
+            })
+            .collect();
+        assert_eq!(
+            values,
+            vec![
+                (j, Some(2.5), None),
+                (j, Some(7_200_000.0), None),
+                (time, None, Some(3)),
+                (other, None, Some(4)),
+            ]
+        );
+    }
+}
@@ -0,0 +1,324 @@
+//! A transform that aggregates the measurements over time windows, for instance to downsample them.
+
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::measurement::{MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementValue};
+use crate::metrics::RawMetricId;
+use crate::resources::{Resource, ResourceConsumer};
+
+use super::{Transform, TransformError};
+
+/// How the values of a window are aggregated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregation {
+    /// The average of the values. The average of integer values is rounded down.
+    Mean,
+    Min,
+    Max,
+    Sum,
+}
+
+/// A transform that replaces the measurements of each time window by one aggregated point per time series.
+///
+/// A time series is identified by the metric, the resource, the consumer and the attributes of the points.
+/// The windows are aligned on the multiples of the window duration since the Unix epoch, and are based on the
+/// timestamps of the points, not on the time at which they are transformed.
+///
+/// Since the transform receives the measurements buffer by buffer, it keeps the points of the current window
+/// between two calls to [`apply`](Transform::apply), and clears the buffers in the meantime: nothing reaches
+/// the outputs until the window is complete. The window is complete when a point of a later window arrives,
+/// or when a buffer arrives after the end of the window, according to the system clock.
+/// The aggregated points are then sent in place of this buffer, with the timestamp of the start of their window.
+/// The points that arrive after the end of their window, according to the system clock, are added to the next one.
+///
+/// ## Flushing
+/// [`SourceCmd::Flush`](super::runtime::SourceCmd::Flush) forces a source to send its buffer now,
+/// but it does not force this transform to close its window: the flushed points are added to the current window
+/// like the others, and they only reach the outputs, aggregated, at the end of the window.
+///
+/// The transform only runs when it receives a buffer: the last window is sent with the first buffer that arrives
+/// after its end. When the pipeline stops, or when the sources are removed, the points of the current window
+/// are lost.
+///
+/// ## Example
+/// ```
+/// use std::time::Duration;
+/// use alumet::pipeline::window::{Aggregation, WindowTransform};
+///
+/// // from one measurement every 100ms to 1s averages
+/// let transform = WindowTransform::new(Duration::from_secs(1), Aggregation::Mean);
+/// ```
+pub struct WindowTransform {
+    window: Duration,
+    aggregation: Aggregation,
+    /// The start of the current window, `None` until the first point arrives.
+    start: Option<SystemTime>,
+    /// The time series of the current window, in the order of their first point.
+    series: Vec<Series>,
+    index_by_key: HashMap<SeriesKey, usize>,
+    /// The end of the last window that has been closed: the points that arrive later are added to the next window.
+    closed_until: Option<SystemTime>,
+}
+
+/// Identifies a time series: the metric, the resource, the consumer and the attributes of the points.
+pub(super) type SeriesKey = (RawMetricId, Resource, ResourceConsumer, Vec<(String, String)>);
+
+/// Returns the key of the time series that `point` belongs to.
+pub(super) fn series_key(point: &MeasurementPoint) -> SeriesKey {
+    let attributes = point.attributes().map(|(k, v)| (k.to_owned(), v.to_string())).collect();
+    (point.metric, point.resource.clone(), point.consumer.clone(), attributes)
+}
+
+struct Series {
+    /// The first point of the series in the window, which gives its metric, resource, consumer and attributes.
+    first: MeasurementPoint,
+    acc: Accumulator,
+}
+
+enum Accumulator {
+    F64 { sum: f64, min: f64, max: f64, count: u64 },
+    U64 { sum: u64, min: u64, max: u64, count: u64 },
+}
+
+impl WindowTransform {
+    /// Creates a transform that aggregates the measurements over windows of the given duration.
+    ///
+    /// Panics if `window` is zero.
+    pub fn new(window: Duration, aggregation: Aggregation) -> Self {
+        assert!(!window.is_zero(), "the window must not be zero");
+        Self {
+            window,
+            aggregation,
+            start: None,
+            series: Vec::new(),
+            index_by_key: HashMap::new(),
+            closed_until: None,
+        }
+    }
+
+    /// Returns the start of the window that contains `t`.
+    fn window_start(&self, t: SystemTime) -> SystemTime {
+        let since_epoch = t.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+        let window = self.window.as_nanos();
+        let start = since_epoch - since_epoch % window;
+        UNIX_EPOCH + Duration::from_nanos(start as u64)
+    }
+
+    fn add(&mut self, point: &MeasurementPoint) {
+        let key = series_key(point);
+        match self.index_by_key.get(&key) {
+            Some(&i) => self.series[i].acc.add(&point.value),
+            None => {
+                self.index_by_key.insert(key, self.series.len());
+                self.series.push(Series {
+                    first: point.clone(),
+                    acc: Accumulator::new(&point.value),
+                });
+            }
+        }
+    }
+
+    /// Returns the start of the window that a point at `t` opens, which is never before the end of a closed window.
+    fn open_window_start(&self, t: SystemTime) -> SystemTime {
+        let start = self.window_start(t);
+        match self.closed_until {
+            Some(end) if start < end => end,
+            _ => start,
+        }
+    }
+
+    /// Closes the current window and pushes its aggregated points to `out`.
+    fn close(&mut self, out: &mut Vec<MeasurementPoint>) {
+        let start = self.start.take().expect("a window with points should have a start");
+        self.closed_until = Some(start + self.window);
+        let timestamp = Timestamp::from(start);
+        self.index_by_key.clear();
+        for series in self.series.drain(..) {
+            let mut point = series.first;
+            point.timestamp = timestamp;
+            point.value = series.acc.aggregate(self.aggregation);
+            out.push(point);
+        }
+    }
+}
+
+impl Transform for WindowTransform {
+    fn apply(&mut self, measurements: &mut MeasurementBuffer) -> Result<(), TransformError> {
+        self.apply_at(measurements, SystemTime::now());
+        Ok(())
+    }
+}
+
+impl WindowTransform {
+    /// Applies the transform to a buffer that arrives at `now`.
+    fn apply_at(&mut self, measurements: &mut MeasurementBuffer, now: SystemTime) {
+        let mut closed = Vec::new();
+        for point in measurements.iter() {
+            let t = SystemTime::from(point.timestamp);
+            match self.start {
+                None => self.start = Some(self.open_window_start(t)),
+                Some(start) if t >= start + self.window => {
+                    self.close(&mut closed);
+                    self.start = Some(self.window_start(t));
+                }
+                // Points that arrive late are added to the current window.
+                Some(_) => (),
+            }
+            self.add(point);
+        }
+        // Don't wait for a point of a later window, which may come much later, or never (e.g. if the source stops).
+        if self.start.is_some_and(|start| now >= start + self.window) {
+            self.close(&mut closed);
+        }
+        measurements.clear();
+        for point in closed {
+            measurements.push(point);
+        }
+    }
+}
+
+impl Accumulator {
+    fn new(value: &WrappedMeasurementValue) -> Self {
+        match *value {
+            WrappedMeasurementValue::F64(x) => Accumulator::F64 {
+                sum: x,
+                min: x,
+                max: x,
+                count: 1,
+            },
+            WrappedMeasurementValue::U64(x) => Accumulator::U64 {
+                sum: x,
+                min: x,
+                max: x,
+                count: 1,
+            },
+        }
+    }
+
+    fn add(&mut self, value: &WrappedMeasurementValue) {
+        match (self, value) {
+            (Accumulator::F64 { sum, min, max, count }, WrappedMeasurementValue::F64(x)) => {
+                *sum += x;
+                *min = min.min(*x);
+                *max = max.max(*x);
+                *count += 1;
+            }
+            (Accumulator::U64 { sum, min, max, count }, WrappedMeasurementValue::U64(x)) => {
+                *sum = sum.saturating_add(*x);
+                *min = (*min).min(*x);
+                *max = (*max).max(*x);
+                *count += 1;
+            }
+            (_, value) => {
+                let value_type = value.measurement_type();
+                log::warn!("Ignoring a value of type {value_type} in a series of another type");
+            }
+        }
+    }
+
+    fn aggregate(&self, aggregation: Aggregation) -> WrappedMeasurementValue {
+        match (self, aggregation) {
+            (Accumulator::F64 { sum, count, .. }, Aggregation::Mean) => {
+                WrappedMeasurementValue::F64(sum / *count as f64)
+            }
+            (Accumulator::F64 { min, .. }, Aggregation::Min) => WrappedMeasurementValue::F64(*min),
+            (Accumulator::F64 { max, .. }, Aggregation::Max) => WrappedMeasurementValue::F64(*max),
+            (Accumulator::F64 { sum, .. }, Aggregation::Sum) => WrappedMeasurementValue::F64(*sum),
+            (Accumulator::U64 { sum, count, .. }, Aggregation::Mean) => WrappedMeasurementValue::U64(sum / count),
+            (Accumulator::U64 { min, .. }, Aggregation::Min) => WrappedMeasurementValue::U64(*min),
+            (Accumulator::U64 { max, .. }, Aggregation::Max) => WrappedMeasurementValue::U64(*max),
+            (Accumulator::U64 { sum, .. }, Aggregation::Sum) => WrappedMeasurementValue::U64(*sum),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use crate::measurement::{MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementValue};
+    use crate::pipeline::testing;
+
+    use super::{Aggregation, WindowTransform};
+
+    fn point(millis: u64, pkg: u32, value: f64) -> MeasurementPoint {
+        testing::point().millis(millis).package(pkg).f64(value).build()
+    }
+
+    fn values(buf: &MeasurementBuffer) -> Vec<f64> {
+        buf.iter()
+            .map(|p| match p.value {
+                WrappedMeasurementValue::F64(x) => x,
+                _ => unreachable!(),
+            })
+            .collect()
+    }
+
+    // The tests that are not about the system clock apply the transform at the epoch, which closes no window.
+
+    #[test]
+    fn mean_over_windows() {
+        let mut transform = WindowTransform::new(Duration::from_secs(1), Aggregation::Mean);
+
+        // the window is not complete: nothing is forwarded
+        let mut buf = MeasurementBuffer::from(vec![point(1000, 0, 1.0), point(1000, 1, 10.0)]);
+        transform.apply_at(&mut buf, UNIX_EPOCH);
+        assert!(buf.is_empty());
+        let mut buf = MeasurementBuffer::from(vec![point(1500, 0, 3.0)]);
+        transform.apply_at(&mut buf, UNIX_EPOCH);
+        assert!(buf.is_empty());
+
+        // a point of the next window closes the first one
+        let mut buf = MeasurementBuffer::from(vec![point(2100, 0, 5.0)]);
+        transform.apply_at(&mut buf, UNIX_EPOCH);
+        assert_eq!(values(&buf), vec![2.0, 10.0]);
+        assert!(buf
+            .iter()
+            .all(|p| p.timestamp == Timestamp::from(UNIX_EPOCH + Duration::from_secs(1))));
+
+        // the point of the second window is kept for later
+        let mut buf = MeasurementBuffer::from(vec![point(3000, 0, 7.0)]);
+        transform.apply_at(&mut buf, UNIX_EPOCH);
+        assert_eq!(values(&buf), vec![5.0]);
+    }
+
+    #[test]
+    fn other_aggregations() {
+        for (aggregation, expected) in [(Aggregation::Min, 1.0), (Aggregation::Max, 4.0), (Aggregation::Sum, 7.0)] {
+            let mut transform = WindowTransform::new(Duration::from_secs(1), aggregation);
+            let mut buf = MeasurementBuffer::from(vec![point(0, 0, 1.0), point(100, 0, 4.0), point(200, 0, 2.0)]);
+            transform.apply_at(&mut buf, UNIX_EPOCH);
+            assert!(buf.is_empty());
+            let mut buf = MeasurementBuffer::from(vec![point(1000, 0, 0.0)]);
+            transform.apply_at(&mut buf, UNIX_EPOCH);
+            assert_eq!(values(&buf), vec![expected], "wrong result for {aggregation:?}");
+        }
+    }
+    #[test]
+    fn close_on_wall_clock() {
+        let mut transform = WindowTransform::new(Duration::from_secs(1), Aggregation::Sum);
+        let at = |millis| UNIX_EPOCH + Duration::from_millis(millis);
+
+        // the buffer arrives during the window: it is kept
+        let mut buf = MeasurementBuffer::from(vec![point(1000, 0, 1.0)]);
+        transform.apply_at(&mut buf, at(1200));
+        assert!(buf.is_empty());
+
+        // the buffer arrives after the end of the window: the window is closed without a point of the next one
+        let mut buf = MeasurementBuffer::from(vec![point(1900, 0, 2.0)]);
+        transform.apply_at(&mut buf, at(2050));
+        assert_eq!(values(&buf), vec![3.0]);
+
+        // a late point of the closed window is added to the next window
+        let mut buf = MeasurementBuffer::from(vec![point(1950, 0, 4.0)]);
+        transform.apply_at(&mut buf, at(2100));
+        assert!(buf.is_empty());
+        let mut buf = MeasurementBuffer::from(vec![point(2500, 0, 8.0)]);
+        transform.apply_at(&mut buf, at(3000));
+        assert_eq!(values(&buf), vec![12.0]);
+        assert!(buf
+            .iter()
+            .all(|p| p.timestamp == Timestamp::from(UNIX_EPOCH + Duration::from_secs(2))));
+    }
+}
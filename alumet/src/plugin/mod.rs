@@ -88,13 +88,16 @@ use std::marker::PhantomData;
 
 use tokio_util::sync::CancellationToken;
 
-use crate::measurement::{MeasurementBuffer, MeasurementType, WrappedMeasurementType};
-use crate::metrics::{Metric, MetricCreationError, RawMetricId, TypedMetricId};
-use crate::pipeline::builder::{AutonomousSourceBuilder, ManagedSourceBuilder, OutputBuilder, TransformBuilder};
-use crate::pipeline::runtime::{IdlePipeline, RunningPipeline};
+use crate::measurement::{MeasurementBuffer, MeasurementPoint, MeasurementType, WrappedMeasurementType};
+use crate::metrics::{Metric, MetricCreationError, MetricRegistry, RawMetricId, TypedMetricId};
+use crate::pipeline::builder::{
+    AutonomousSourceBuilder, ManagedSourceBuilder, OutputKind, OutputRegistration, SourceKind, TransformBuilder,
+    DEFAULT_ROUTE,
+};
+use crate::pipeline::runtime::{IdlePipeline, RunningPipeline, TransformErrorPolicy};
 use crate::pipeline::trigger::TriggerSpec;
 use crate::pipeline::{builder::PendingPipelineContext, builder::PipelineBuilder};
-use crate::pipeline::{Output, Source, Transform};
+use crate::pipeline::{AsyncOutput, AsyncSource, Output, Source, Transform};
 use crate::units::PrefixedUnit;
 
 use self::rust::AlumetPlugin;
@@ -273,6 +276,13 @@ impl<'a> AlumetStart<'a> {
         self.pipeline_builder.metrics.register(m)
     }
 
+    /// Returns the registry of the metrics that have been created so far, by this plugin and the previous ones.
+    ///
+    /// It can be used, for instance, to configure a transform according to the unit of some metrics.
+    pub fn metrics(&self) -> &MetricRegistry {
+        &self.pipeline_builder.metrics
+    }
+
     /// Adds a measurement source to the Alumet pipeline.
     pub fn add_source(&mut self, source: Box<dyn Source>, trigger: TriggerSpec) {
         let plugin = self.current_plugin_name().to_owned();
@@ -284,7 +294,26 @@ impl<'a> AlumetStart<'a> {
             name,
             plugin,
             trigger,
-            build: Box::new(|_| source),
+            build: Box::new(|_| SourceKind::Sync(source)),
+        })
+    }
+
+    /// Adds an async measurement source to the Alumet pipeline.
+    ///
+    /// Unlike the sources added with [`add_source`](Self::add_source), the pipeline awaits the future
+    /// returned by [`AsyncSource::poll_async`] directly, hence the source can wait for an external entity
+    /// without blocking a thread.
+    pub fn add_async_source(&mut self, source: Box<dyn AsyncSource>, trigger: TriggerSpec) {
+        let plugin = self.current_plugin_name().to_owned();
+        let name = self
+            .pipeline_builder
+            .namegen
+            .deduplicate(format!("{plugin}/source"), true);
+        self.pipeline_builder.sources.push(ManagedSourceBuilder {
+            name,
+            plugin,
+            trigger,
+            build: Box::new(|_| SourceKind::Async(source)),
         })
     }
 
@@ -310,7 +339,7 @@ impl<'a> AlumetStart<'a> {
             name,
             plugin,
             trigger,
-            build: Box::new(source_builder),
+            build: Box::new(move |ctx| SourceKind::Sync(source_builder(ctx))),
         });
     }
 
@@ -376,6 +405,18 @@ impl<'a> AlumetStart<'a> {
 
     /// Adds a transform step to the Alumet pipeline.
     pub fn add_transform(&mut self, transform: Box<dyn Transform>) {
+        self.add_transform_to_route(DEFAULT_ROUTE, transform)
+    }
+
+    /// Adds a transform step to a route of the Alumet pipeline.
+    ///
+    /// Each route has its own chain of transforms, which feeds the outputs of the same route
+    /// (see [`add_output_to_route`](Self::add_output_to_route)). All the routes receive the
+    /// measurements of all the sources. The elements that are added without a route belong
+    /// to the [`DEFAULT_ROUTE`].
+    ///
+    /// A route that contains some transforms must contain at least one output.
+    pub fn add_transform_to_route(&mut self, route: &str, transform: Box<dyn Transform>) {
         let plugin = self.current_plugin_name().to_owned();
         let name = self
             .pipeline_builder
@@ -385,21 +426,115 @@ impl<'a> AlumetStart<'a> {
             name,
             plugin,
             build: Box::new(|_| transform),
+            route: route.to_owned(),
+            error_policy: TransformErrorPolicy::default(),
         });
     }
 
-    /// Adds an output to the Alumet pipeline.
-    pub fn add_output(&mut self, output: Box<dyn Output>) {
+    /// Adds a transform step to the Alumet pipeline, with a specific policy for its fatal errors.
+    ///
+    /// With [`add_transform`](Self::add_transform), a fatal error stops the transform task.
+    /// Use [`TransformErrorPolicy::SkipAndContinue`] for non-critical transforms.
+    pub fn add_transform_with_error_policy(&mut self, transform: Box<dyn Transform>, policy: TransformErrorPolicy) {
         let plugin = self.current_plugin_name().to_owned();
         let name = self
             .pipeline_builder
             .namegen
-            .deduplicate(format!("{plugin}/output"), true);
-        self.pipeline_builder.outputs.push(OutputBuilder {
+            .deduplicate(format!("{plugin}/transform"), true);
+        self.pipeline_builder.transforms.push(TransformBuilder {
             name,
             plugin,
-            build: Box::new(|_| Ok(output)),
-        })
+            build: Box::new(|_| transform),
+            route: String::from(DEFAULT_ROUTE),
+            error_policy: policy,
+        });
+    }
+
+    /// Adds the builder of a transform step to the Alumet pipeline.
+    ///
+    /// Like [`add_source_builder`](Self::add_source_builder), the transform is created during the construction
+    /// of the measurement pipeline, which allows to use some information about the pipeline while creating it,
+    /// for instance to spawn a background task on the runtime of the pipeline.
+    pub fn add_transform_builder<F: FnOnce(&PendingPipelineContext) -> Box<dyn Transform> + 'static>(
+        &mut self,
+        transform_builder: F,
+    ) {
+        let plugin = self.current_plugin_name().to_owned();
+        let name = self
+            .pipeline_builder
+            .namegen
+            .deduplicate(format!("{plugin}/transform"), true);
+        self.pipeline_builder.transforms.push(TransformBuilder {
+            name,
+            plugin,
+            build: Box::new(transform_builder),
+            route: String::from(DEFAULT_ROUTE),
+            error_policy: TransformErrorPolicy::default(),
+        });
+    }
+
+    /// Adds an output to the Alumet pipeline.
+    ///
+    /// By default, the output receives all the measurements of the [`DEFAULT_ROUTE`]. Use the returned
+    /// [`OutputRegistration`] to configure it, for instance to retry its writes and check its health:
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use alumet::pipeline::runtime::{RetryPolicy, SlowOutputPolicy};
+    /// # use alumet::plugin::AlumetStart;
+    /// # use alumet::pipeline::Output;
+    /// # let alumet: &mut AlumetStart = todo!();
+    /// # let output: Box<dyn Output> = todo!();
+    /// # let policy: RetryPolicy = todo!();
+    ///
+    /// alumet
+    ///     .add_output(output)
+    ///     .retry(policy)
+    ///     .health_check(Duration::from_secs(10))
+    ///     .on_slow(SlowOutputPolicy::Block);
+    /// ```
+    pub fn add_output(&mut self, output: Box<dyn Output>) -> OutputRegistration<'_> {
+        self.add_output_kind(Box::new(|_| Ok(OutputKind::Blocking(output))))
+    }
+
+    /// Adds an output to a route of the Alumet pipeline.
+    ///
+    /// The output receives the measurements produced by the transforms of the route.
+    /// This is the same as `add_output(output).route(route)`, see [`OutputRegistration::route`].
+    pub fn add_output_to_route(&mut self, route: &str, output: Box<dyn Output>) -> OutputRegistration<'_> {
+        self.add_output(output).route(route)
+    }
+
+    /// Adds an async output to the Alumet pipeline.
+    ///
+    /// Unlike the outputs added with [`add_output`](Self::add_output), an [`AsyncOutput`]
+    /// is not run on a dedicated thread: the pipeline awaits its future directly.
+    pub fn add_async_output(&mut self, output: Box<dyn AsyncOutput>) -> OutputRegistration<'_> {
+        self.add_output_kind(Box::new(|_| Ok(OutputKind::Async(output))))
+    }
+
+    /// Adds an output to the Alumet pipeline, which only receives the measurement points that match the `filter`.
+    ///
+    /// This is the same as `add_output(output).filter(filter)`, see [`OutputRegistration::filter`].
+    ///
+    /// ## Example
+    /// ```no_run
+    /// use alumet::metrics::MetricId;
+    /// use alumet::units::Unit;
+    /// # use alumet::plugin::AlumetStart;
+    /// # use alumet::pipeline::Output;
+    /// # let alumet: &mut AlumetStart = todo!();
+    /// # let output: Box<dyn Output> = todo!();
+    ///
+    /// let energy_metric = alumet.create_metric::<f64>("rapl_consumed_energy", Unit::Joule, "...").unwrap();
+    /// let energy_id = energy_metric.untyped_id();
+    /// alumet.add_filtered_output(output, move |point| point.metric == energy_id);
+    /// ```
+    pub fn add_filtered_output<F: Fn(&MeasurementPoint) -> bool + Send + Sync + 'static>(
+        &mut self,
+        output: Box<dyn Output>,
+        filter: F,
+    ) -> OutputRegistration<'_> {
+        self.add_output(output).filter(filter)
     }
 
     /// Adds the builder of an output to the Alumet pipeline.
@@ -413,16 +548,15 @@ impl<'a> AlumetStart<'a> {
     pub fn add_output_builder<F: FnOnce(&PendingPipelineContext) -> anyhow::Result<Box<dyn Output>> + 'static>(
         &mut self,
         output_builder: F,
-    ) {
+    ) -> OutputRegistration<'_> {
+        self.add_output_kind(Box::new(|p| output_builder(p).map(OutputKind::Blocking)))
+    }
+
+    fn add_output_kind(
+        &mut self,
+        build: Box<dyn FnOnce(&PendingPipelineContext) -> anyhow::Result<OutputKind>>,
+    ) -> OutputRegistration<'_> {
         let plugin = self.current_plugin_name().to_owned();
-        let name = self
-            .pipeline_builder
-            .namegen
-            .deduplicate(format!("{plugin}/output"), true);
-        self.pipeline_builder.outputs.push(OutputBuilder {
-            name,
-            plugin,
-            build: Box::new(output_builder),
-        })
+        self.pipeline_builder.push_output(&plugin, build)
     }
 }
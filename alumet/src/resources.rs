@@ -41,7 +41,7 @@ pub type StrCow = Cow<'static, str>;
 
 /// Hardware or software entity for which metrics can be gathered.
 #[non_exhaustive]
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 #[repr(C)]
 pub enum Resource {
     /// The whole local machine, for instance the whole physical server.
@@ -60,7 +60,7 @@ pub enum Resource {
 
 /// Consumer of a [`resource`](Resource).
 #[non_exhaustive]
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 #[repr(C)]
 pub enum ResourceConsumer {
     /// The whole local machine.
@@ -218,6 +218,23 @@ impl PrefixedUnit {
     pub fn display_name(&self) -> String {
         format!("{self}")
     }
+
+    /// Returns the factor by which a value in this unit must be multiplied to be expressed in `target`,
+    /// or `None` if the two units measure different quantities.
+    ///
+    /// The units must have the same base unit, except for the energy, which can be converted
+    /// between joules and watt-hours. The custom units are only compatible with themselves.
+    /// The temperatures cannot be converted by a factor, there is no conversion between celsius and fahrenheit.
+    pub fn conversion_factor(&self, target: &PrefixedUnit) -> Option<f64> {
+        let base_factor = match (&self.base_unit, &target.base_unit) {
+            (a, b) if a == b => 1.0,
+            (Unit::WattHour, Unit::Joule) => 3600.0,
+            (Unit::Joule, Unit::WattHour) => 1.0 / 3600.0,
+            _ => return None,
+        };
+        let exponent = self.prefix.exponent() - target.prefix.exponent();
+        Some(base_factor * 10f64.powi(exponent))
+    }
 }
 
 impl From<Unit> for PrefixedUnit {
@@ -248,6 +265,19 @@ impl UnitPrefix {
         }
     }
 
+    /// Returns the power of ten that corresponds to the prefix, for instance `-3` for milli.
+    pub fn exponent(&self) -> i32 {
+        match self {
+            UnitPrefix::Nano => -9,
+            UnitPrefix::Micro => -6,
+            UnitPrefix::Milli => -3,
+            UnitPrefix::Plain => 0,
+            UnitPrefix::Kilo => 3,
+            UnitPrefix::Mega => 6,
+            UnitPrefix::Giga => 9,
+        }
+    }
+
     /// Returns the name to use when displaying (aka printing) the prefix, as specified by the Unified Code for Units of Measure (UCUM).
     ///
     /// See <https://ucum.org/ucum#section-Prefixes>
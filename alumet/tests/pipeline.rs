@@ -0,0 +1,2109 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use alumet::{
+    measurement::{MeasurementAccumulator, MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementValue},
+    metrics::{MetricId, MetricRegistry, TypedMetricId},
+    pipeline::{
+        builder::{
+            ElementType, InvalidReason, OutputBuilder, OutputKind, PipelineBuildError, PipelineBuilder,
+            TransformBuilder, DEFAULT_ROUTE,
+        },
+        config::{Constructors, ElementConfig, PipelineConfig, SourceConfig},
+        memory::MemoryOutput,
+        runtime::{
+            CircuitBreakerPolicy, CircuitBreakerState, ElementState, OutputCmd, OversizedBufferPolicy, PipelineError,
+            PipelineErrorKind, PollErrorPolicy, RealtimePriority, RetryPolicy, RuntimeParameters, ShutdownSignal,
+            SlowOutputPolicy, SourceCmd, SourceType, TransformErrorPolicy,
+        },
+        trigger::{self, TriggerAction},
+        AsyncOutput, AsyncSource, Health, Output, OutputContext, PollError, Source, Transform, TransformError,
+        WriteError,
+    },
+    plugin::AlumetStart,
+    resources::{Resource, ResourceConsumer},
+    units::Unit,
+};
+
+struct CounterSource(TypedMetricId<u64>);
+
+impl Source for CounterSource {
+    fn poll(&mut self, measurements: &mut MeasurementAccumulator, timestamp: Timestamp) -> Result<(), PollError> {
+        measurements.push(MeasurementPoint::new(
+            timestamp,
+            self.0,
+            Resource::LocalMachine,
+            ResourceConsumer::LocalMachine,
+            1,
+        ));
+        Ok(())
+    }
+}
+
+/// An async source that waits for a (simulated) response before pushing a point.
+struct AsyncCounterSource(TypedMetricId<u64>);
+
+impl AsyncSource for AsyncCounterSource {
+    fn poll_async<'a>(
+        &'a mut self,
+        measurements: &'a mut MeasurementAccumulator,
+        timestamp: Timestamp,
+    ) -> trigger::BoxFuture<'a, Result<(), PollError>> {
+        Box::pin(async move {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+            measurements.push(MeasurementPoint::new(
+                timestamp,
+                self.0,
+                Resource::LocalMachine,
+                ResourceConsumer::LocalMachine,
+                1,
+            ));
+            Ok(())
+        })
+    }
+}
+
+/// A source that blocks its thread when polled, and records the name of that thread.
+struct SlowSource {
+    metric: TypedMetricId<u64>,
+    threads: Arc<Mutex<Vec<String>>>,
+}
+
+impl Source for SlowSource {
+    fn poll(&mut self, measurements: &mut MeasurementAccumulator, timestamp: Timestamp) -> Result<(), PollError> {
+        let thread_name = std::thread::current().name().unwrap_or_default().to_owned();
+        self.threads.lock().unwrap().push(thread_name);
+        std::thread::sleep(Duration::from_millis(20));
+        measurements.push(MeasurementPoint::new(
+            timestamp,
+            self.metric,
+            Resource::LocalMachine,
+            ResourceConsumer::LocalMachine,
+            1,
+        ));
+        Ok(())
+    }
+}
+
+/// A source that pushes 10 points at each poll.
+struct BurstSource(TypedMetricId<u64>);
+
+impl Source for BurstSource {
+    fn poll(&mut self, measurements: &mut MeasurementAccumulator, timestamp: Timestamp) -> Result<(), PollError> {
+        for n in 0..10 {
+            measurements.push(MeasurementPoint::new(
+                timestamp,
+                self.0,
+                Resource::LocalMachine,
+                ResourceConsumer::LocalMachine,
+                n,
+            ));
+        }
+        Ok(())
+    }
+}
+
+struct NullOutput;
+
+impl Output for NullOutput {
+    fn write(&mut self, _measurements: &MeasurementBuffer, _ctx: &OutputContext) -> Result<(), WriteError> {
+        Ok(())
+    }
+}
+
+/// An output that records the values that it receives.
+struct RecordingOutput(Arc<Mutex<Vec<u64>>>);
+
+impl Output for RecordingOutput {
+    fn write(&mut self, measurements: &MeasurementBuffer, _ctx: &OutputContext) -> Result<(), WriteError> {
+        let mut values = self.0.lock().unwrap();
+        for p in measurements {
+            if let WrappedMeasurementValue::U64(n) = p.value {
+                values.push(n);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// An output that takes some time to write each buffer.
+struct SlowOutput;
+
+impl Output for SlowOutput {
+    fn write(&mut self, _measurements: &MeasurementBuffer, _ctx: &OutputContext) -> Result<(), WriteError> {
+        std::thread::sleep(Duration::from_millis(5));
+        Ok(())
+    }
+}
+
+/// An output that records the size of the buffers that it receives.
+struct BufferSizeOutput(Arc<Mutex<Vec<usize>>>);
+
+impl Output for BufferSizeOutput {
+    fn write(&mut self, measurements: &MeasurementBuffer, _ctx: &OutputContext) -> Result<(), WriteError> {
+        self.0.lock().unwrap().push(measurements.len());
+        Ok(())
+    }
+}
+
+/// A source that records the calls to its lifecycle methods, and fails to initialize the first time.
+struct LifecycleSource {
+    metric: TypedMetricId<u64>,
+    calls: Arc<Mutex<Vec<&'static str>>>,
+}
+
+impl Source for LifecycleSource {
+    fn init(&mut self) -> Result<(), PollError> {
+        let mut calls = self.calls.lock().unwrap();
+        let first = calls.is_empty();
+        calls.push("init");
+        if first {
+            Err(PollError::CanRetry(anyhow::anyhow!("device not ready")))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn poll(&mut self, measurements: &mut MeasurementAccumulator, timestamp: Timestamp) -> Result<(), PollError> {
+        self.calls.lock().unwrap().push("poll");
+        measurements.push(MeasurementPoint::new(
+            timestamp,
+            self.metric,
+            Resource::LocalMachine,
+            ResourceConsumer::LocalMachine,
+            1,
+        ));
+        Ok(())
+    }
+
+    fn shutdown(&mut self) {
+        self.calls.lock().unwrap().push("shutdown");
+    }
+}
+
+/// A source that always fails.
+struct FailingSource;
+
+impl Source for FailingSource {
+    fn poll(&mut self, _measurements: &mut MeasurementAccumulator, _timestamp: Timestamp) -> Result<(), PollError> {
+        Err(PollError::Fatal(anyhow::anyhow!("sensor unplugged")))
+    }
+}
+
+/// A source that pushes the number of the poll, then fails with a non-fatal error every `fail_every` polls.
+struct UnreliableSource {
+    metric: TypedMetricId<u64>,
+    polls: Arc<AtomicUsize>,
+    fail_every: usize,
+}
+
+impl Source for UnreliableSource {
+    fn poll(&mut self, measurements: &mut MeasurementAccumulator, timestamp: Timestamp) -> Result<(), PollError> {
+        let n = self.polls.fetch_add(1, Ordering::Relaxed) + 1;
+        measurements.push(MeasurementPoint::new(
+            timestamp,
+            self.metric,
+            Resource::LocalMachine,
+            ResourceConsumer::LocalMachine,
+            n as u64,
+        ));
+        if n % self.fail_every == 0 {
+            Err(PollError::CanRetry(anyhow::anyhow!("device busy")))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// An output that fails to start.
+struct FailingOutput;
+
+impl Output for FailingOutput {
+    fn write(&mut self, _measurements: &MeasurementBuffer, _ctx: &OutputContext) -> Result<(), WriteError> {
+        Ok(())
+    }
+
+    fn register(&mut self, _metrics: &MetricRegistry) -> Result<(), WriteError> {
+        Err(WriteError::Fatal(anyhow::anyhow!("database unreachable")))
+    }
+}
+
+/// An output that fails with a non-fatal error until it is healthy, and counts the calls to `write`.
+struct FlakyOutput {
+    healthy: Arc<AtomicBool>,
+    calls: Arc<AtomicUsize>,
+}
+
+impl Output for FlakyOutput {
+    fn write(&mut self, _measurements: &MeasurementBuffer, _ctx: &OutputContext) -> Result<(), WriteError> {
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        if self.healthy.load(Ordering::Relaxed) {
+            Ok(())
+        } else {
+            Err(WriteError::CanRetry(anyhow::anyhow!("service unavailable")))
+        }
+    }
+
+    fn health(&self) -> Health {
+        if self.healthy.load(Ordering::Relaxed) {
+            Health::Healthy
+        } else {
+            Health::Unhealthy(String::from("service unavailable"))
+        }
+    }
+}
+
+/// A transform that multiplies the values by 10.
+struct TenfoldTransform;
+
+impl Transform for TenfoldTransform {
+    fn apply(&mut self, measurements: &mut MeasurementBuffer) -> Result<(), TransformError> {
+        for p in measurements.iter_mut() {
+            if let WrappedMeasurementValue::U64(n) = p.value {
+                p.value = WrappedMeasurementValue::U64(n * 10);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A transform that discards all the measurements.
+struct DiscardTransform;
+
+impl Transform for DiscardTransform {
+    fn apply(&mut self, measurements: &mut MeasurementBuffer) -> Result<(), TransformError> {
+        measurements.clear();
+        Ok(())
+    }
+}
+
+/// A transform that always fails.
+struct FailingTransform;
+
+impl Transform for FailingTransform {
+    fn apply(&mut self, _measurements: &mut MeasurementBuffer) -> Result<(), TransformError> {
+        Err(TransformError::Fatal(anyhow::anyhow!("enrichment failed")))
+    }
+}
+
+/// A transform that records whether it has been applied.
+struct ReachedTransform(Arc<AtomicBool>);
+
+impl Transform for ReachedTransform {
+    fn apply(&mut self, _measurements: &mut MeasurementBuffer) -> Result<(), TransformError> {
+        self.0.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+/// An output that batches the measurements, and only "writes" them on flush.
+struct BatchingOutput {
+    pending: usize,
+    flushed: Arc<Mutex<Vec<usize>>>,
+}
+
+impl Output for BatchingOutput {
+    fn write(&mut self, measurements: &MeasurementBuffer, _ctx: &OutputContext) -> Result<(), WriteError> {
+        self.pending += measurements.len();
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), WriteError> {
+        self.flushed.lock().unwrap().push(std::mem::take(&mut self.pending));
+        Ok(())
+    }
+}
+
+/// An output that resolves the metric names when it's registered, and checks them on each write.
+struct NamedOutput {
+    names: Vec<String>,
+    /// Set to `true` when a measurement is written with a known name.
+    named_write: Arc<AtomicBool>,
+}
+
+impl Output for NamedOutput {
+    fn register(&mut self, metrics: &MetricRegistry) -> Result<(), WriteError> {
+        self.names = metrics.iter().map(|(_, m)| m.name.clone()).collect();
+        Ok(())
+    }
+
+    fn write(&mut self, measurements: &MeasurementBuffer, _ctx: &OutputContext) -> Result<(), WriteError> {
+        if !measurements.is_empty() && self.names.iter().any(|n| n == "counter") {
+            self.named_write.store(true, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+}
+
+/// An output that blocks for a long time.
+struct StuckOutput {
+    entered: Arc<AtomicBool>,
+}
+
+impl Output for StuckOutput {
+    fn write(&mut self, _measurements: &MeasurementBuffer, _ctx: &OutputContext) -> Result<(), WriteError> {
+        self.entered.store(true, Ordering::Relaxed);
+        std::thread::sleep(Duration::from_secs(3));
+        Ok(())
+    }
+}
+
+/// Checks `condition` every millisecond until it holds, returns `false` if it still doesn't after `timeout`.
+fn wait_until(timeout: Duration, mut condition: impl FnMut() -> bool) -> bool {
+    let deadline = Instant::now() + timeout;
+    while !condition() {
+        if Instant::now() > deadline {
+            return false;
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    true
+}
+
+#[test]
+fn shutdown_aborts_stuck_output() {
+    let mut pipeline_builder = PipelineBuilder::new();
+    let entered = Arc::new(AtomicBool::new(false));
+    {
+        let mut alumet = AlumetStart::new(&mut pipeline_builder, String::from("test"));
+        let metric = alumet.create_metric::<u64>("counter", Unit::Unity, "test counter").unwrap();
+        let trigger = trigger::builder::time_interval(Duration::from_millis(10)).build().unwrap();
+        alumet.add_source(Box::new(CounterSource(metric)), trigger);
+        alumet.add_output(Box::new(StuckOutput {
+            entered: entered.clone(),
+        }));
+    }
+    let pipeline = pipeline_builder.build().expect("pipeline should build").start();
+
+    // wait for the output to be stuck
+    let t0 = Instant::now();
+    while !entered.load(Ordering::Relaxed) {
+        assert!(t0.elapsed() < Duration::from_secs(2), "the output should have been called");
+        std::thread::sleep(Duration::from_millis(5));
+    }
+
+    let err = pipeline
+        .shutdown(Duration::from_millis(100))
+        .expect_err("the stuck output should be reported");
+    assert!(
+        err.errors.iter().any(|e| matches!(
+            e,
+            PipelineError::Aborted { element: ElementType::Output, name } if name.starts_with("test/")
+        )),
+        "unexpected errors: {err}"
+    );
+}
+
+/// An async output that never finishes its first write.
+struct HangingAsyncOutput {
+    entered: Arc<AtomicBool>,
+}
+
+impl AsyncOutput for HangingAsyncOutput {
+    fn write_async<'a>(
+        &'a mut self,
+        _measurements: &'a MeasurementBuffer,
+        _ctx: &'a OutputContext,
+    ) -> trigger::BoxFuture<'a, Result<(), WriteError>> {
+        self.entered.store(true, Ordering::Relaxed);
+        Box::pin(std::future::pending())
+    }
+}
+
+#[test]
+fn abort_hanging_output() {
+    let mut pipeline_builder = PipelineBuilder::new();
+    let entered = Arc::new(AtomicBool::new(false));
+    {
+        let mut alumet = AlumetStart::new(&mut pipeline_builder, String::from("test"));
+        let metric = alumet.create_metric::<u64>("counter", Unit::Unity, "test counter").unwrap();
+        let trigger = trigger::builder::time_interval(Duration::from_millis(10)).build().unwrap();
+        alumet.add_source(Box::new(CounterSource(metric)), trigger);
+        alumet.add_async_output(Box::new(HangingAsyncOutput {
+            entered: entered.clone(),
+        }));
+    }
+    let mut pipeline = pipeline_builder.build().expect("pipeline should build").start();
+    let handle = pipeline.control_handle();
+
+    let t0 = Instant::now();
+    while !entered.load(Ordering::Relaxed) {
+        assert!(t0.elapsed() < Duration::from_secs(2), "the output should have been called");
+        std::thread::sleep(Duration::from_millis(5));
+    }
+
+    assert_eq!(handle.blocking_plugin("other").abort_outputs().unwrap(), 0);
+    assert_eq!(handle.blocking_plugin("test").abort_outputs().unwrap(), 1);
+    let states = handle.blocking_all().output_states().unwrap();
+    assert_eq!(states[0].1, ElementState::Stopped);
+
+    // without the abort, the pipeline would wait for the output forever
+    let err = pipeline.wait_for_shutdown().expect_err("the aborted output should be reported");
+    assert!(
+        err.errors.iter().any(|e| matches!(
+            e,
+            PipelineError::Join { element: ElementType::Output, error } if error.is_cancelled()
+        )),
+        "unexpected errors: {err}"
+    );
+}
+
+#[test]
+fn run_until_signal_returns_on_shutdown() {
+    let mut pipeline_builder = PipelineBuilder::new();
+    {
+        let mut alumet = AlumetStart::new(&mut pipeline_builder, String::from("test"));
+        let metric = alumet.create_metric::<u64>("counter", Unit::Unity, "test counter").unwrap();
+        let trigger = trigger::builder::time_interval(Duration::from_millis(10)).build().unwrap();
+        alumet.add_source(Box::new(CounterSource(metric)), trigger);
+        alumet.add_output(Box::new(NullOutput));
+    }
+    let mut pipeline = pipeline_builder.build().expect("pipeline should build").start();
+    let handle = pipeline.control_handle();
+    let shutdown_thread = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(50));
+        handle.shutdown();
+    });
+
+    // no signal is received, but the pipeline stops anyway
+    let t0 = Instant::now();
+    pipeline.run_until_signal(&ShutdownSignal::DEFAULT).unwrap();
+    assert!(t0.elapsed() < Duration::from_secs(2));
+    shutdown_thread.join().unwrap();
+}
+
+#[test]
+fn zero_channel_capacity() {
+    let mut pipeline_builder = PipelineBuilder::new();
+    {
+        let mut alumet = AlumetStart::new(&mut pipeline_builder, String::from("test"));
+        let metric = alumet.create_metric::<u64>("counter", Unit::Unity, "test counter").unwrap();
+        let trigger = trigger::builder::time_interval(Duration::from_millis(10)).build().unwrap();
+        alumet.add_source(Box::new(CounterSource(metric)), trigger);
+        alumet.add_output(Box::new(StuckOutput {
+            entered: Arc::new(AtomicBool::new(false)),
+        }));
+    }
+    pipeline_builder.output_channel_capacity(0);
+    let res = pipeline_builder.build();
+    assert!(matches!(
+        res,
+        Err(PipelineBuildError::Invalid(InvalidReason::ZeroChannelCapacity))
+    ));
+}
+
+#[test]
+fn buffer_size_limit() {
+    for policy in [OversizedBufferPolicy::Split, OversizedBufferPolicy::Truncate] {
+        let mut pipeline_builder = PipelineBuilder::new();
+        let sizes = Arc::new(Mutex::new(Vec::new()));
+        {
+            let mut alumet = AlumetStart::new(&mut pipeline_builder, String::from("test"));
+            let metric = alumet.create_metric::<u64>("counter", Unit::Unity, "test counter").unwrap();
+            let trigger = trigger::builder::time_interval(Duration::from_millis(10)).build().unwrap();
+            alumet.add_source(Box::new(BurstSource(metric)), trigger);
+            alumet.add_output(Box::new(BufferSizeOutput(sizes.clone())));
+        }
+        pipeline_builder.max_buffer_size(4, policy);
+        let pipeline = pipeline_builder.build().expect("pipeline should build").start();
+        std::thread::sleep(Duration::from_millis(100));
+        pipeline.shutdown(Duration::from_secs(1)).unwrap();
+
+        let sizes = sizes.lock().unwrap();
+        match policy {
+            // no point is lost: each buffer of 10 points is split into 4 + 4 + 2
+            OversizedBufferPolicy::Split => {
+                assert!(sizes.windows(3).any(|w| w == [4, 4, 2]), "{sizes:?}");
+                assert!(sizes.iter().all(|n| *n == 4 || *n == 2), "{sizes:?}");
+            }
+            OversizedBufferPolicy::Truncate => {
+                assert!(!sizes.is_empty());
+                assert!(sizes.iter().all(|n| *n == 4), "{sizes:?}");
+            }
+        }
+    }
+
+    // a limit of zero is rejected
+    let mut pipeline_builder = PipelineBuilder::new();
+    {
+        let mut alumet = AlumetStart::new(&mut pipeline_builder, String::from("test"));
+        let metric = alumet.create_metric::<u64>("counter", Unit::Unity, "test counter").unwrap();
+        let trigger = trigger::builder::time_interval(Duration::from_millis(10)).build().unwrap();
+        alumet.add_source(Box::new(CounterSource(metric)), trigger);
+        alumet.add_output(Box::new(NullOutput));
+    }
+    pipeline_builder.max_buffer_size(0, OversizedBufferPolicy::Split);
+    assert!(matches!(
+        pipeline_builder.build(),
+        Err(PipelineBuildError::Invalid(InvalidReason::ZeroBufferSizeLimit))
+    ));
+}
+
+#[test]
+fn query_element_states() {
+    let mut pipeline_builder = PipelineBuilder::new();
+    {
+        let mut alumet = AlumetStart::new(&mut pipeline_builder, String::from("test"));
+        let metric = alumet.create_metric::<u64>("counter", Unit::Unity, "test counter").unwrap();
+        let trigger = trigger::builder::time_interval(Duration::from_millis(10)).build().unwrap();
+        alumet.add_source(Box::new(CounterSource(metric)), trigger);
+        alumet.add_output(Box::new(NullOutput));
+    }
+    let mut pipeline = pipeline_builder.build().expect("pipeline should build").start();
+    let handle = pipeline.control_handle();
+
+    assert!(handle.stats().is_none(), "the instrumentation is disabled by default");
+
+    // the metrics can be read while the pipeline is running
+    let metric = pipeline.metrics().with_name("counter").expect("the metric should be registered");
+    assert_eq!(metric.description, "test counter");
+    assert_eq!(handle.metrics().len(), 1);
+
+    let states = handle.blocking_all().source_states().unwrap();
+    assert_eq!(states.len(), 1);
+    assert_eq!(states[0].1, ElementState::Running);
+
+    // pause the source, then change its trigger: the state must stay "paused"
+    let n = handle.blocking_plugin("test").control_sources(SourceCmd::Pause).unwrap();
+    assert_eq!(n, 1);
+    let trigger = trigger::builder::time_interval(Duration::from_millis(20)).build().unwrap();
+    handle
+        .blocking_plugin("test")
+        .control_sources(SourceCmd::SetTrigger(Some(trigger)))
+        .unwrap();
+    let states = handle.blocking_plugin("test").source_states().unwrap();
+    assert_eq!(states[0].1, ElementState::Paused);
+
+    handle.blocking_all().control_outputs(OutputCmd::Pause).unwrap();
+    let states = handle.blocking_all().output_states().unwrap();
+    assert_eq!(states.len(), 1);
+    assert!(states[0].0.starts_with("test/"));
+    assert_eq!(states[0].1, ElementState::Paused);
+
+    // unknown plugin
+    assert!(handle.blocking_plugin("other").source_states().unwrap().is_empty());
+
+    handle.blocking_all().control_outputs(OutputCmd::Run).unwrap();
+    pipeline.shutdown(Duration::from_secs(1)).unwrap();
+}
+
+#[test]
+fn fluent_builder() {
+    let mut pipeline_builder = PipelineBuilder::new();
+    let metric = AlumetStart::new(&mut pipeline_builder, String::from("test"))
+        .create_metric::<u64>("counter", Unit::Unity, "test counter")
+        .unwrap();
+    let output = MemoryOutput::with_capacity(64);
+    let collected = output.handle();
+    pipeline_builder
+        .add_source("test", Box::new(CounterSource(metric)))
+        .every(Duration::from_millis(10))
+        .flush_every(Duration::from_millis(20));
+    pipeline_builder.add_transform("test", Box::new(TenfoldTransform));
+    pipeline_builder.add_output("test", Box::new(output));
+    let pipeline = pipeline_builder.build().expect("pipeline should build").start();
+    std::thread::sleep(Duration::from_millis(100));
+    pipeline.shutdown(Duration::from_secs(1)).unwrap();
+
+    let buffers = collected.buffers();
+    assert!(!buffers.is_empty());
+    // two polls per flush
+    assert!(buffers.iter().all(|buf| buf.len() <= 2), "{buffers:?}");
+    assert!(collected
+        .measurements()
+        .iter()
+        .all(|p| matches!(p.value, WrappedMeasurementValue::U64(10))));
+}
+
+#[test]
+fn fluent_builder_rejects_invalid_intervals() {
+    let mut pipeline_builder = PipelineBuilder::new();
+    let metric = AlumetStart::new(&mut pipeline_builder, String::from("test"))
+        .create_metric::<u64>("counter", Unit::Unity, "test counter")
+        .unwrap();
+    pipeline_builder
+        .add_source("test", Box::new(CounterSource(metric)))
+        .every(Duration::from_secs(2))
+        .flush_every(Duration::from_secs(1));
+    pipeline_builder.add_output("test", Box::new(NullOutput));
+    let res = pipeline_builder.build();
+    assert!(matches!(
+        res,
+        Err(PipelineBuildError::Invalid(InvalidReason::InvalidSource(name, _))) if name.starts_with("test/source")
+    ));
+}
+
+#[test]
+fn realtime_priority_status() {
+    let new_builder = |priority: bool| {
+        let mut pipeline_builder = PipelineBuilder::new();
+        let metric = AlumetStart::new(&mut pipeline_builder, String::from("test"))
+            .create_metric::<u64>("counter", Unit::Unity, "test counter")
+            .unwrap();
+        let source = pipeline_builder
+            .add_source("test", Box::new(CounterSource(metric)))
+            .every(Duration::from_millis(10));
+        if priority {
+            source.priority();
+        }
+        pipeline_builder.add_output("test", Box::new(NullOutput));
+        pipeline_builder
+    };
+
+    let pipeline = new_builder(false).build().expect("pipeline should build").start();
+    assert_eq!(pipeline.realtime_priority(), RealtimePriority::NotRequired);
+    assert!(!pipeline.has_priority_runtime());
+    assert_eq!(pipeline.priority_threads(), 0);
+    pipeline.shutdown(Duration::from_secs(1)).unwrap();
+
+    // In strict mode, the pipeline either gets the priority, or fails to build (e.g. without CAP_SYS_NICE).
+    let mut pipeline_builder = new_builder(true);
+    pipeline_builder.require_realtime_priority();
+    match pipeline_builder.build() {
+        Ok(pipeline) => {
+            let pipeline = pipeline.start();
+            assert_eq!(pipeline.realtime_priority(), RealtimePriority::Enabled);
+            assert!(pipeline.has_priority_runtime());
+            assert!(pipeline.priority_threads() > 0);
+            pipeline.shutdown(Duration::from_secs(1)).unwrap();
+        }
+        Err(e) => assert!(matches!(e, PipelineBuildError::Io(_)), "unexpected error {e:?}"),
+    }
+}
+
+#[test]
+fn memory_output() {
+    let mut pipeline_builder = PipelineBuilder::new();
+    let output = MemoryOutput::with_capacity(4);
+    let collected = output.handle();
+    {
+        let mut alumet = AlumetStart::new(&mut pipeline_builder, String::from("test"));
+        let metric = alumet.create_metric::<u64>("counter", Unit::Unity, "test counter").unwrap();
+        let trigger = trigger::builder::time_interval(Duration::from_millis(10)).build().unwrap();
+        alumet.add_source(Box::new(CounterSource(metric)), trigger);
+        alumet.add_output(Box::new(output));
+    }
+    let pipeline = pipeline_builder.build().expect("pipeline should build").start();
+    std::thread::sleep(Duration::from_millis(100));
+    pipeline.shutdown(Duration::from_secs(1)).unwrap();
+
+    // only the last buffers are kept
+    let buffers = collected.buffers();
+    assert_eq!(buffers.len(), 4);
+    assert!(buffers.iter().all(|buf| buf.len() == 1));
+    assert_eq!(collected.measurements().len(), 4);
+}
+
+#[test]
+fn flush_source_on_demand() {
+    let mut pipeline_builder = PipelineBuilder::new();
+    let recorded = Arc::new(Mutex::new(Vec::new()));
+    {
+        let mut alumet = AlumetStart::new(&mut pipeline_builder, String::from("test"));
+        let metric = alumet.create_metric::<u64>("counter", Unit::Unity, "test counter").unwrap();
+        let trigger = trigger::builder::time_interval(Duration::from_millis(10))
+            .flush_interval(Duration::from_secs(60))
+            .build()
+            .unwrap();
+        alumet.add_source(Box::new(CounterSource(metric)), trigger);
+        alumet.add_output(Box::new(RecordingOutput(recorded.clone())));
+    }
+    let mut pipeline = pipeline_builder.build().expect("pipeline should build").start();
+    let handle = pipeline.control_handle();
+    std::thread::sleep(Duration::from_millis(100));
+    assert!(recorded.lock().unwrap().is_empty(), "nothing should be flushed before a minute");
+
+    // the flush works on a paused source, which stays paused
+    handle.blocking_plugin("test").control_sources(SourceCmd::Pause).unwrap();
+    std::thread::sleep(Duration::from_millis(30));
+    handle.blocking_plugin("test").control_sources(SourceCmd::Flush).unwrap();
+    std::thread::sleep(Duration::from_millis(50));
+    let n_flushed = recorded.lock().unwrap().len();
+    assert!(n_flushed > 0);
+    let states = handle.blocking_plugin("test").source_states().unwrap();
+    assert_eq!(states[0].1, ElementState::Paused);
+
+    // the buffer is empty now: flushing again does nothing
+    handle.blocking_plugin("test").control_sources(SourceCmd::Flush).unwrap();
+    std::thread::sleep(Duration::from_millis(50));
+    assert_eq!(recorded.lock().unwrap().len(), n_flushed);
+
+    pipeline.shutdown(Duration::from_secs(1)).unwrap();
+}
+
+#[test]
+fn stop_sources_and_wait() {
+    let mut pipeline_builder = PipelineBuilder::new();
+    let polls = Arc::new(Mutex::new(Vec::new()));
+    {
+        let mut alumet = AlumetStart::new(&mut pipeline_builder, String::from("test"));
+        let metric = alumet.create_metric::<u64>("counter", Unit::Unity, "test counter").unwrap();
+        let trigger = trigger::builder::time_interval(Duration::from_millis(10)).build().unwrap();
+        let source = SlowSource {
+            metric,
+            threads: polls.clone(),
+        };
+        alumet.add_source(Box::new(source), trigger);
+        alumet.add_output(Box::new(NullOutput));
+    }
+    let mut pipeline = pipeline_builder.build().expect("pipeline should build").start();
+    let handle = pipeline.control_handle();
+    std::thread::sleep(Duration::from_millis(50));
+
+    // when stop_sources returns, the source has exited: it is not polled anymore
+    let n = handle.blocking_all().stop_sources().unwrap();
+    assert_eq!(n, 1);
+    let n_polls = polls.lock().unwrap().len();
+    std::thread::sleep(Duration::from_millis(50));
+    assert_eq!(polls.lock().unwrap().len(), n_polls);
+    let states = handle.blocking_all().source_states().unwrap();
+    assert_eq!(states[0].1, ElementState::Stopped);
+
+    // the other elements are still running
+    assert_eq!(handle.blocking_all().output_states().unwrap()[0].1, ElementState::Running);
+    pipeline.shutdown(Duration::from_secs(1)).unwrap();
+}
+
+#[test]
+fn pause_and_resume_all() {
+    let mut pipeline_builder = PipelineBuilder::new();
+    let output = MemoryOutput::with_capacity(256);
+    let collected = output.handle();
+    {
+        let mut alumet = AlumetStart::new(&mut pipeline_builder, String::from("test"));
+        let metric = alumet.create_metric::<u64>("counter", Unit::Unity, "test counter").unwrap();
+        let trigger = trigger::builder::time_interval(Duration::from_millis(10)).build().unwrap();
+        alumet.add_source(Box::new(CounterSource(metric)), trigger);
+        alumet.add_output(Box::new(output));
+    }
+    let mut pipeline = pipeline_builder.build().expect("pipeline should build").start();
+    let handle = pipeline.control_handle();
+    std::thread::sleep(Duration::from_millis(50));
+
+    // the source and the output are paused together
+    let n = handle.blocking_all().pause(Some(Duration::from_millis(100))).unwrap();
+    assert_eq!(n, 2);
+    assert_eq!(handle.blocking_all().source_states().unwrap()[0].1, ElementState::Paused);
+    assert_eq!(handle.blocking_all().output_states().unwrap()[0].1, ElementState::Paused);
+    // the measurements of the source have all been written before the output was paused
+    collected.clear();
+    std::thread::sleep(Duration::from_millis(50));
+    assert!(collected.measurements().is_empty());
+
+    // and resumed together
+    let n = handle.blocking_all().resume().unwrap();
+    assert_eq!(n, 2);
+    assert_eq!(handle.blocking_all().source_states().unwrap()[0].1, ElementState::Running);
+    assert_eq!(handle.blocking_all().output_states().unwrap()[0].1, ElementState::Running);
+    std::thread::sleep(Duration::from_millis(100));
+    assert!(!collected.measurements().is_empty());
+
+    pipeline.shutdown(Duration::from_secs(1)).unwrap();
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn retry_trigger_init() {
+    let dir = std::env::temp_dir().join(format!("alumet-init-retry-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("created_later");
+    let _ = std::fs::remove_file(&path);
+
+    let mut pipeline_builder = PipelineBuilder::new();
+    let output = MemoryOutput::with_capacity(256);
+    let collected = output.handle();
+    {
+        let mut alumet = AlumetStart::new(&mut pipeline_builder, String::from("test"));
+        let metric = alumet.create_metric::<u64>("counter", Unit::Unity, "test counter").unwrap();
+        // the watched path does not exist yet: the initialization fails until it is created
+        let trigger = trigger::builder::file_watch(&path)
+            .build()
+            .unwrap()
+            .with_init_retry(RetryPolicy {
+                max_attempts: 50,
+                initial_backoff: Duration::from_millis(10),
+                max_backoff: Duration::from_millis(10),
+            });
+        alumet.add_source(Box::new(CounterSource(metric)), trigger);
+        alumet.add_output(Box::new(output));
+    }
+    let mut pipeline = pipeline_builder.build().expect("pipeline should build").start();
+    let handle = pipeline.control_handle();
+    std::thread::sleep(Duration::from_millis(50));
+    std::fs::write(&path, "0").unwrap();
+    std::thread::sleep(Duration::from_millis(100));
+
+    // the source is running, and polled when the file changes
+    assert_eq!(handle.blocking_all().source_states().unwrap()[0].1, ElementState::Running);
+    std::fs::write(&path, "1").unwrap();
+    std::thread::sleep(Duration::from_millis(100));
+    assert!(!collected.measurements().is_empty());
+
+    pipeline.shutdown(Duration::from_secs(1)).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn remove_sources_while_running() {
+    let mut pipeline_builder = PipelineBuilder::new();
+    let output = MemoryOutput::with_capacity(256);
+    let collected = output.handle();
+    let other_metric = {
+        let mut alumet = AlumetStart::new(&mut pipeline_builder, String::from("test"));
+        let metric = alumet.create_metric::<u64>("counter", Unit::Unity, "test counter").unwrap();
+        for _ in 0..2 {
+            let trigger = trigger::builder::time_interval(Duration::from_millis(10)).build().unwrap();
+            alumet.add_source(Box::new(CounterSource(metric)), trigger);
+        }
+        alumet.add_output(Box::new(output));
+
+        let mut alumet = AlumetStart::new(&mut pipeline_builder, String::from("other"));
+        let metric = alumet.create_metric::<u64>("other_counter", Unit::Unity, "other counter").unwrap();
+        let trigger = trigger::builder::time_interval(Duration::from_millis(10)).build().unwrap();
+        alumet.add_source(Box::new(CounterSource(metric)), trigger);
+        metric
+    };
+    let mut pipeline = pipeline_builder.build().expect("pipeline should build").start();
+    let handle = pipeline.control_handle();
+    std::thread::sleep(Duration::from_millis(50));
+
+    // both sources of "test" are stopped and removed
+    let n = handle.blocking_plugin("test").remove_sources().unwrap();
+    assert_eq!(n, 2);
+    let states = handle.blocking_all().source_states().unwrap();
+    assert_eq!(states.len(), 1);
+    assert!(states[0].0.starts_with("other/"));
+    assert!(handle.blocking_plugin("test").remove_sources().is_err());
+
+    // the remaining source still sends its measurements
+    collected.clear();
+    std::thread::sleep(Duration::from_millis(100));
+    let measurements = collected.measurements();
+    assert!(!measurements.is_empty());
+    assert!(measurements.iter().all(|p| p.metric == other_metric.untyped_id()));
+
+    pipeline.shutdown(Duration::from_secs(1)).unwrap();
+}
+
+#[test]
+fn blocking_source_runs_on_dedicated_runtime() {
+    let mut pipeline_builder = PipelineBuilder::new();
+    let threads = Arc::new(Mutex::new(Vec::new()));
+    {
+        let mut alumet = AlumetStart::new(&mut pipeline_builder, String::from("test"));
+        let metric = alumet.create_metric::<u64>("counter", Unit::Unity, "test counter").unwrap();
+        let trigger = trigger::builder::time_interval(Duration::from_millis(10))
+            .blocking()
+            .build()
+            .unwrap();
+        let source = SlowSource {
+            metric,
+            threads: threads.clone(),
+        };
+        alumet.add_source(Box::new(source), trigger);
+        alumet.add_output(Box::new(NullOutput));
+    }
+    let pipeline = pipeline_builder.build().expect("pipeline should build").start();
+    std::thread::sleep(Duration::from_millis(100));
+    pipeline.shutdown(Duration::from_secs(1)).unwrap();
+
+    let threads = threads.lock().unwrap();
+    assert!(!threads.is_empty(), "the source should have been polled");
+    assert!(
+        threads.iter().all(|name| name.starts_with("blocking-worker-")),
+        "the source should run on the blocking runtime: {threads:?}"
+    );
+}
+
+#[test]
+fn thread_name_prefix() {
+    let mut pipeline_builder = PipelineBuilder::new();
+    let normal_threads = Arc::new(Mutex::new(Vec::new()));
+    let blocking_threads = Arc::new(Mutex::new(Vec::new()));
+    {
+        let mut alumet = AlumetStart::new(&mut pipeline_builder, String::from("test"));
+        let metric = alumet.create_metric::<u64>("counter", Unit::Unity, "test counter").unwrap();
+        let trigger = trigger::builder::time_interval(Duration::from_millis(10)).build().unwrap();
+        let source = SlowSource {
+            metric,
+            threads: normal_threads.clone(),
+        };
+        alumet.add_source(Box::new(source), trigger);
+        let trigger = trigger::builder::time_interval(Duration::from_millis(10))
+            .blocking()
+            .build()
+            .unwrap();
+        let source = SlowSource {
+            metric,
+            threads: blocking_threads.clone(),
+        };
+        alumet.add_source(Box::new(source), trigger);
+        alumet.add_output(Box::new(NullOutput));
+    }
+    pipeline_builder.thread_name_prefix("vm42");
+    let pipeline = pipeline_builder.build().expect("pipeline should build").start();
+    std::thread::sleep(Duration::from_millis(100));
+    pipeline.shutdown(Duration::from_secs(1)).unwrap();
+
+    let normal_threads = normal_threads.lock().unwrap();
+    assert!(!normal_threads.is_empty());
+    assert!(normal_threads.iter().all(|name| name.starts_with("vm42-normal-worker-")), "{normal_threads:?}");
+    let blocking_threads = blocking_threads.lock().unwrap();
+    assert!(!blocking_threads.is_empty());
+    assert!(blocking_threads.iter().all(|name| name.starts_with("vm42-blocking-worker-")), "{blocking_threads:?}");
+}
+
+#[test]
+fn move_source_between_runtimes() {
+    let mut pipeline_builder = PipelineBuilder::new();
+    let threads = Arc::new(Mutex::new(Vec::new()));
+    {
+        let mut alumet = AlumetStart::new(&mut pipeline_builder, String::from("test"));
+        let metric = alumet.create_metric::<u64>("counter", Unit::Unity, "test counter").unwrap();
+        let trigger = trigger::builder::time_interval(Duration::from_millis(30)).build().unwrap();
+        let source = SlowSource {
+            metric,
+            threads: threads.clone(),
+        };
+        alumet.add_source(Box::new(source), trigger);
+        alumet.add_output(Box::new(NullOutput));
+    }
+    let mut pipeline = pipeline_builder.build().expect("pipeline should build").start();
+    let handle = pipeline.control_handle();
+    std::thread::sleep(Duration::from_millis(100));
+    assert!(threads.lock().unwrap().iter().all(|name| name.starts_with("normal-worker-")));
+
+    // the source is restarted on the blocking runtime, which is created on demand
+    let n = handle.blocking_all().set_source_priority(SourceType::Blocking).unwrap();
+    assert_eq!(n, 1);
+    threads.lock().unwrap().clear();
+    std::thread::sleep(Duration::from_millis(100));
+    {
+        let threads = threads.lock().unwrap();
+        assert!(!threads.is_empty(), "the source should have been polled");
+        assert!(threads.iter().all(|name| name.starts_with("blocking-worker-")), "{threads:?}");
+    }
+
+    // a paused source stays paused, and runs on its new runtime when it resumes
+    handle.blocking_all().control_sources(SourceCmd::Pause).unwrap();
+    let n = handle.blocking_all().set_source_priority(SourceType::Normal).unwrap();
+    assert_eq!(n, 1);
+    assert_eq!(handle.blocking_all().source_states().unwrap()[0].1, ElementState::Paused);
+    threads.lock().unwrap().clear();
+    std::thread::sleep(Duration::from_millis(100));
+    assert!(threads.lock().unwrap().is_empty());
+    handle.blocking_all().control_sources(SourceCmd::Run).unwrap();
+    std::thread::sleep(Duration::from_millis(100));
+    {
+        let threads = threads.lock().unwrap();
+        assert!(!threads.is_empty(), "the source should have been resumed");
+        assert!(threads.iter().all(|name| name.starts_with("normal-worker-")), "{threads:?}");
+    }
+
+    pipeline.shutdown(Duration::from_secs(1)).unwrap();
+}
+
+#[test]
+fn add_source_while_running() {
+    let mut pipeline_builder = PipelineBuilder::new();
+    let metric;
+    {
+        let mut alumet = AlumetStart::new(&mut pipeline_builder, String::from("test"));
+        metric = alumet.create_metric::<u64>("counter", Unit::Unity, "test counter").unwrap();
+        let trigger = trigger::builder::time_interval(Duration::from_millis(10)).build().unwrap();
+        alumet.add_source(Box::new(CounterSource(metric)), trigger);
+        alumet.add_output(Box::new(NullOutput));
+    }
+    let mut pipeline = pipeline_builder.build().expect("pipeline should build").start();
+    let handle = pipeline.control_handle();
+
+    // no source is blocking: the runtime of the blocking sources is created on demand
+    let threads = Arc::new(Mutex::new(Vec::new()));
+    let source = SlowSource {
+        metric,
+        threads: threads.clone(),
+    };
+    let trigger = trigger::builder::time_interval(Duration::from_millis(10))
+        .blocking()
+        .build()
+        .unwrap();
+    handle
+        .add_source(String::from("test"), String::from("late"), Box::new(source), trigger)
+        .unwrap();
+    std::thread::sleep(Duration::from_millis(100));
+
+    let states = handle.blocking_plugin("test").source_states().unwrap();
+    assert_eq!(states.len(), 2);
+    {
+        let threads = threads.lock().unwrap();
+        assert!(!threads.is_empty(), "the new source should have been polled");
+        assert!(threads.iter().all(|name| name.starts_with("blocking-worker-")), "{threads:?}");
+    }
+    pipeline.shutdown(Duration::from_secs(1)).unwrap();
+
+    // the pipeline has been stopped
+    let trigger = trigger::builder::time_interval(Duration::from_millis(10)).build().unwrap();
+    let res = handle.add_source(
+        String::from("test"),
+        String::from("too-late"),
+        Box::new(CounterSource(metric)),
+        trigger,
+    );
+    assert!(res.is_err());
+}
+
+#[test]
+fn pipeline_stats() {
+    let mut pipeline_builder = PipelineBuilder::new();
+    {
+        let mut alumet = AlumetStart::new(&mut pipeline_builder, String::from("test"));
+        let metric = alumet.create_metric::<u64>("counter", Unit::Unity, "test counter").unwrap();
+        let trigger = trigger::builder::time_interval(Duration::from_millis(10)).build().unwrap();
+        alumet.add_source(Box::new(CounterSource(metric)), trigger);
+        alumet.add_output(Box::new(NullOutput));
+    }
+    pipeline_builder.with_instrumentation();
+    let mut pipeline = pipeline_builder.build().expect("pipeline should build").start();
+    std::thread::sleep(Duration::from_millis(100));
+
+    let stats = pipeline.control_handle().stats().expect("the instrumentation is enabled");
+    assert!(stats.buffers_in > 0);
+    // one point per buffer (flush_rounds = 1)
+    assert_eq!(stats.points_in, stats.buffers_in);
+    assert_eq!(stats.buffers_written.len(), 1);
+    let (name, written) = stats.buffers_written.iter().next().unwrap();
+    assert!(name.starts_with("test/"));
+    assert!(*written > 0 && *written <= stats.buffers_in);
+    assert_eq!(stats.poll_overruns, 0);
+
+    // the latency is recorded for each write
+    let latency = pipeline.control_handle().latency_stats().expect("the instrumentation is enabled");
+    assert!(latency.count > 0);
+    assert!(latency.mean <= latency.max);
+    assert!(latency.quantile(0.5).unwrap() <= latency.quantile(1.0).unwrap());
+
+    pipeline.shutdown(Duration::from_secs(1)).unwrap();
+}
+
+#[test]
+fn wait_idle() {
+    let mut pipeline_builder = PipelineBuilder::new();
+    {
+        let mut alumet = AlumetStart::new(&mut pipeline_builder, String::from("test"));
+        let metric = alumet.create_metric::<u64>("counter", Unit::Unity, "test counter").unwrap();
+        let trigger = trigger::builder::time_interval(Duration::from_millis(1)).build().unwrap();
+        alumet.add_source(Box::new(CounterSource(metric)), trigger);
+        alumet.add_transform(Box::new(TenfoldTransform));
+        alumet.add_output(Box::new(SlowOutput));
+    }
+    pipeline_builder.with_instrumentation();
+    let mut pipeline = pipeline_builder.build().expect("pipeline should build").start();
+    let handle = pipeline.control_handle();
+    std::thread::sleep(Duration::from_millis(50));
+
+    // the output is slower than the source: once the source has stopped, some buffers are still in the pipeline
+    handle.blocking_all().stop_sources().unwrap();
+    handle.blocking_wait_idle(Duration::from_secs(5)).unwrap();
+    let stats = handle.stats().unwrap();
+    assert!(stats.buffers_in > 0);
+    assert_eq!(stats.buffers_written.values().sum::<u64>(), stats.buffers_in);
+
+    // the pipeline is still running
+    assert_eq!(handle.blocking_all().output_states().unwrap()[0].1, ElementState::Running);
+    pipeline.shutdown(Duration::from_secs(1)).unwrap();
+}
+
+#[test]
+fn slow_output_policies() {
+    let build = |policy| {
+        let mut pipeline_builder = PipelineBuilder::new();
+        {
+            let mut alumet = AlumetStart::new(&mut pipeline_builder, String::from("test"));
+            let metric = alumet.create_metric::<u64>("counter", Unit::Unity, "test counter").unwrap();
+            let trigger = trigger::builder::time_interval(Duration::from_millis(1)).build().unwrap();
+            alumet.add_source(Box::new(CounterSource(metric)), trigger);
+            alumet.add_output(Box::new(SlowOutput)).on_slow(policy);
+            alumet.add_output(Box::new(NullOutput));
+        }
+        pipeline_builder.output_channel_capacity(2);
+        pipeline_builder.with_instrumentation();
+        pipeline_builder.build().expect("pipeline should build").start()
+    };
+
+    // the slow output is detached after lagging behind twice, the other one keeps running
+    let mut pipeline = build(SlowOutputPolicy::Detach { max_lags: 2 });
+    let handle = pipeline.control_handle();
+    let states = || -> Vec<ElementState> {
+        let states = handle.blocking_all().output_states().unwrap();
+        states.into_iter().map(|(_, s)| s).collect()
+    };
+    assert!(
+        wait_until(Duration::from_secs(5), || states().contains(&ElementState::Detached)),
+        "the slow output should be detached"
+    );
+    let mut states = states();
+    states.sort_by_key(|s| *s == ElementState::Running);
+    assert_eq!(states, vec![ElementState::Detached, ElementState::Running]);
+    pipeline.shutdown(Duration::from_secs(1)).unwrap();
+
+    // the slow output makes the whole pipeline wait, and no message is lost
+    let mut pipeline = build(SlowOutputPolicy::Block);
+    let handle = pipeline.control_handle();
+    std::thread::sleep(Duration::from_millis(100));
+    handle.blocking_all().stop_sources().unwrap();
+    handle.blocking_wait_idle(Duration::from_secs(5)).unwrap();
+    assert_eq!(handle.output_lag("test"), 0);
+    let stats = handle.stats().unwrap();
+    assert!(stats.buffers_written.values().all(|n| *n == stats.buffers_in), "{stats:?}");
+    pipeline.shutdown(Duration::from_secs(1)).unwrap();
+}
+
+#[test]
+fn paused_blocking_output() {
+    let mut pipeline_builder = PipelineBuilder::new();
+    let values = Arc::new(Mutex::new(Vec::new()));
+    {
+        let mut alumet = AlumetStart::new(&mut pipeline_builder, String::from("test"));
+        let metric = alumet.create_metric::<u64>("counter", Unit::Unity, "test counter").unwrap();
+        let trigger = trigger::builder::time_interval(Duration::from_millis(1)).build().unwrap();
+        alumet.add_source(Box::new(CounterSource(metric)), trigger);
+        alumet.add_output(Box::new(RecordingOutput(values.clone())));
+    }
+    AlumetStart::new(&mut pipeline_builder, String::from("blocking"))
+        .add_output(Box::new(NullOutput))
+        .on_slow(SlowOutputPolicy::Block);
+    pipeline_builder.output_channel_capacity(2);
+    let mut pipeline = pipeline_builder.build().expect("pipeline should build").start();
+    let handle = pipeline.control_handle();
+    std::thread::sleep(Duration::from_millis(20));
+
+    // the paused output does not hold back the queue: the other output keeps writing
+    handle.blocking_plugin("blocking").control_outputs(OutputCmd::Pause).unwrap();
+    std::thread::sleep(Duration::from_millis(20));
+    let before = values.lock().unwrap().len();
+    std::thread::sleep(Duration::from_millis(100));
+    assert!(values.lock().unwrap().len() > before + 10);
+    assert!(handle.output_lag("blocking") > 0);
+
+    // the pipeline shuts down while the output is still paused
+    let (done_tx, done_rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = done_tx.send(pipeline.wait_for_shutdown());
+    });
+    handle.shutdown();
+    let res = done_rx
+        .recv_timeout(Duration::from_secs(5))
+        .expect("the pipeline should shut down");
+    res.unwrap();
+}
+
+#[test]
+fn poll_error_policies() {
+    let mut pipeline_builder = PipelineBuilder::new();
+    let metric = AlumetStart::new(&mut pipeline_builder, String::from("test"))
+        .create_metric::<u64>("counter", Unit::Unity, "test counter")
+        .unwrap();
+    let skip_polls = Arc::new(AtomicUsize::new(0));
+    let backoff_polls = Arc::new(AtomicUsize::new(0));
+    let values = Arc::new(Mutex::new(Vec::new()));
+    // the backoff is long enough to skip all the polls requested by the test
+    let backoff = PollErrorPolicy::Backoff {
+        initial: Duration::from_secs(3600),
+        max: Duration::from_secs(3600),
+    };
+    let sources = [
+        ("skip", skip_polls.clone(), 2, PollErrorPolicy::SkipRound),
+        ("backoff", backoff_polls.clone(), 1, backoff),
+        ("abort", Arc::new(AtomicUsize::new(0)), 1, PollErrorPolicy::Abort),
+    ];
+    for (plugin, polls, fail_every, policy) in sources {
+        let source = UnreliableSource {
+            metric,
+            polls,
+            fail_every,
+        };
+        let trigger = trigger::TriggerSpec::manual().with_poll_error_policy(policy);
+        AlumetStart::new(&mut pipeline_builder, String::from(plugin)).add_source(Box::new(source), trigger);
+    }
+    pipeline_builder.add_output("test", Box::new(RecordingOutput(values.clone())));
+    let mut pipeline = pipeline_builder.build().expect("pipeline should build").start();
+    let handle = pipeline.control_handle();
+
+    // the polls are requested one by one, each source is polled once per round, except during its backoff
+    handle.blocking_plugin("abort").poll_sources_now().unwrap();
+    for round in 1..=6 {
+        for plugin in ["skip", "backoff"] {
+            handle.blocking_plugin(plugin).poll_sources_now().unwrap();
+        }
+        let polled = wait_until(Duration::from_secs(5), || skip_polls.load(Ordering::Relaxed) == round);
+        assert!(polled, "the source should have been polled {round} times");
+    }
+
+    // the points of the failed polls are discarded
+    assert_eq!(skip_polls.load(Ordering::Relaxed), 6);
+    // the source that always fails waits for its backoff before polling again
+    assert_eq!(backoff_polls.load(Ordering::Relaxed), 1);
+    // the source is stopped on the first failure
+    assert!(wait_until(Duration::from_secs(5), || handle.source_last_error("abort").is_some()));
+    let error = handle.source_last_error("abort").unwrap();
+    assert!(error.contains("device busy"), "{error}");
+    // the error of the aborted source is also returned at shutdown
+    assert!(pipeline.shutdown(Duration::from_secs(1)).is_err());
+
+    // the polls of the source without backoff have odd numbers, and the backoff source always fails
+    let values = values.lock().unwrap();
+    assert!(!values.is_empty());
+    assert!(values.iter().all(|n| n % 2 == 1), "{values:?}");
+}
+
+#[test]
+fn max_polls() {
+    let mut pipeline_builder = PipelineBuilder::new();
+    let polls = Arc::new(AtomicUsize::new(0));
+    let values = Arc::new(Mutex::new(Vec::new()));
+    {
+        let mut alumet = AlumetStart::new(&mut pipeline_builder, String::from("test"));
+        let metric = alumet.create_metric::<u64>("counter", Unit::Unity, "test counter").unwrap();
+        let trigger = trigger::builder::time_interval(Duration::from_millis(5))
+            .flush_rounds(2)
+            .max_polls(5)
+            .build()
+            .unwrap();
+        let source = UnreliableSource {
+            metric,
+            polls: polls.clone(),
+            fail_every: usize::MAX,
+        };
+        alumet.add_source(Box::new(source), trigger);
+        alumet.add_output(Box::new(RecordingOutput(values.clone())));
+    }
+    let mut pipeline = pipeline_builder.build().expect("pipeline should build").start();
+    let handle = pipeline.control_handle();
+    std::thread::sleep(Duration::from_millis(100));
+    assert_eq!(polls.load(Ordering::Relaxed), 5);
+    assert!(handle.source_last_error("test").is_none());
+    pipeline.shutdown(Duration::from_secs(1)).unwrap();
+
+    // the last poll is flushed, although its buffer is not full
+    assert_eq!(*values.lock().unwrap(), vec![1, 2, 3, 4, 5]);
+}
+
+#[test]
+fn future_trigger_stops_source() {
+    let mut pipeline_builder = PipelineBuilder::new();
+    let polls = Arc::new(AtomicUsize::new(0));
+    let values = Arc::new(Mutex::new(Vec::new()));
+    {
+        let mut alumet = AlumetStart::new(&mut pipeline_builder, String::from("test"));
+        let metric = alumet.create_metric::<u64>("counter", Unit::Unity, "test counter").unwrap();
+        // a finite stream of three events
+        let events = Arc::new(AtomicUsize::new(0));
+        let trigger = trigger::builder::future(move || {
+            let events = events.clone();
+            Box::pin(async move {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+                match events.fetch_add(1, Ordering::Relaxed) {
+                    0..=2 => Ok(TriggerAction::Continue),
+                    _ => Ok(TriggerAction::Stop),
+                }
+            })
+        })
+        .flush_rounds(10)
+        .build()
+        .unwrap();
+        let source = UnreliableSource {
+            metric,
+            polls: polls.clone(),
+            fail_every: usize::MAX,
+        };
+        alumet.add_source(Box::new(source), trigger);
+        alumet.add_output(Box::new(RecordingOutput(values.clone())));
+    }
+    let mut pipeline = pipeline_builder.build().expect("pipeline should build").start();
+    let handle = pipeline.control_handle();
+    std::thread::sleep(Duration::from_millis(100));
+    assert_eq!(polls.load(Ordering::Relaxed), 3);
+    // the source has stopped gracefully
+    assert!(handle.source_last_error("test").is_none());
+    pipeline.shutdown(Duration::from_secs(1)).unwrap();
+    assert_eq!(*values.lock().unwrap(), vec![1, 2, 3]);
+}
+
+#[test]
+fn future_trigger_min_interval() {
+    let mut pipeline_builder = PipelineBuilder::new();
+    let polls = Arc::new(AtomicUsize::new(0));
+    {
+        let mut alumet = AlumetStart::new(&mut pipeline_builder, String::from("test"));
+        let metric = alumet.create_metric::<u64>("counter", Unit::Unity, "test counter").unwrap();
+        // a burst of ten events, then nothing
+        let events = Arc::new(AtomicUsize::new(0));
+        let trigger = trigger::builder::future(move || {
+            let events = events.clone();
+            Box::pin(async move {
+                if events.fetch_add(1, Ordering::Relaxed) >= 10 {
+                    std::future::pending::<()>().await;
+                }
+                Ok(TriggerAction::Continue)
+            })
+        })
+        .min_interval(Duration::from_millis(200))
+        .build()
+        .unwrap();
+        let source = UnreliableSource {
+            metric,
+            polls: polls.clone(),
+            fail_every: usize::MAX,
+        };
+        alumet.add_source(Box::new(source), trigger);
+        alumet.add_output(Box::new(NullOutput));
+    }
+    let mut pipeline = pipeline_builder.build().expect("pipeline should build").start();
+    // the first event polls the source, the next ones are coalesced into one poll at the end of the interval
+    let polled = wait_until(Duration::from_secs(5), || polls.load(Ordering::Relaxed) >= 2);
+    assert!(polled, "the source should have been polled twice");
+    // no other poll happens once the burst is over, even after another interval
+    std::thread::sleep(Duration::from_millis(300));
+    let polls = polls.load(Ordering::Relaxed);
+    assert_eq!(polls, 2, "{polls} polls");
+    pipeline.shutdown(Duration::from_secs(1)).unwrap();
+}
+
+#[test]
+fn manual_trigger() {
+    let mut pipeline_builder = PipelineBuilder::new();
+    let polls = Arc::new(AtomicUsize::new(0));
+    let values = Arc::new(Mutex::new(Vec::new()));
+    {
+        let mut alumet = AlumetStart::new(&mut pipeline_builder, String::from("test"));
+        let metric = alumet.create_metric::<u64>("counter", Unit::Unity, "test counter").unwrap();
+        let source = UnreliableSource {
+            metric,
+            polls: polls.clone(),
+            fail_every: usize::MAX,
+        };
+        alumet.add_source(Box::new(source), trigger::TriggerSpec::manual());
+        alumet.add_output(Box::new(RecordingOutput(values.clone())));
+    }
+    let mut pipeline = pipeline_builder.build().expect("pipeline should build").start();
+    let handle = pipeline.control_handle();
+    // the trigger never fires on its own
+    std::thread::sleep(Duration::from_millis(50));
+    assert_eq!(polls.load(Ordering::Relaxed), 0);
+
+    // each requested poll is flushed
+    for _ in 0..2 {
+        handle.blocking_plugin("test").poll_sources_now().unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    assert_eq!(polls.load(Ordering::Relaxed), 2);
+    assert_eq!(*values.lock().unwrap(), vec![1, 2]);
+    pipeline.shutdown(Duration::from_secs(1)).unwrap();
+}
+
+#[test]
+fn output_sampling() {
+    let mut pipeline_builder = PipelineBuilder::new();
+    let all = Arc::new(Mutex::new(Vec::new()));
+    let sampled = Arc::new(Mutex::new(Vec::new()));
+    {
+        let mut alumet = AlumetStart::new(&mut pipeline_builder, String::from("all"));
+        let metric = alumet.create_metric::<u64>("counter", Unit::Unity, "test counter").unwrap();
+        let trigger = trigger::builder::time_interval(Duration::from_millis(5)).build().unwrap();
+        alumet.add_source(Box::new(CounterSource(metric)), trigger);
+        alumet.add_output(Box::new(RecordingOutput(all.clone())));
+    }
+    {
+        let mut alumet = AlumetStart::new(&mut pipeline_builder, String::from("sampled"));
+        alumet.add_output(Box::new(RecordingOutput(sampled.clone()))).sampling(3);
+    }
+    let mut pipeline = pipeline_builder.build().expect("pipeline should build").start();
+    let handle = pipeline.control_handle();
+    std::thread::sleep(Duration::from_millis(100));
+    pipeline.shutdown(Duration::from_secs(1)).unwrap();
+
+    // one point per buffer: the sampled output writes the first buffer, then one of every three buffers
+    let (all, sampled) = (all.lock().unwrap().len(), sampled.lock().unwrap().len());
+    assert!(all > 3);
+    assert_eq!(sampled, (all + 2) / 3);
+    assert_eq!(handle.sampled_out_points("sampled"), (all - sampled) as u64);
+    assert_eq!(handle.sampled_out_buffers("sampled"), (all - sampled) as u64);
+    assert_eq!(handle.sampled_out_buffers("all"), 0);
+}
+
+#[test]
+fn error_events() {
+    let mut pipeline_builder = PipelineBuilder::new();
+    let metric = AlumetStart::new(&mut pipeline_builder, String::from("test"))
+        .create_metric::<u64>("counter", Unit::Unity, "test counter")
+        .unwrap();
+    for (plugin, policy) in [("flaky", PollErrorPolicy::Retry), ("broken", PollErrorPolicy::Abort)] {
+        let source = UnreliableSource {
+            metric,
+            polls: Arc::new(AtomicUsize::new(0)),
+            fail_every: 3,
+        };
+        pipeline_builder
+            .add_source(plugin, Box::new(source))
+            .every(Duration::from_millis(10))
+            .on_poll_error(policy);
+    }
+    pipeline_builder.add_output("test", Box::new(NullOutput));
+    let mut pipeline = pipeline_builder.build().expect("pipeline should build").start();
+    let mut errors = pipeline.control_handle().error_receiver();
+    std::thread::sleep(Duration::from_millis(100));
+    let _ = pipeline.shutdown(Duration::from_secs(1));
+
+    let mut events = Vec::new();
+    while let Ok(event) = errors.try_recv() {
+        events.push(event);
+    }
+    assert!(events.iter().all(|e| e.stage == ElementType::Source && e.error.contains("device busy")));
+    // the flaky source keeps running, the broken source stops on its first error
+    let kinds = |plugin: &str| -> Vec<PipelineErrorKind> {
+        events.iter().filter(|e| e.plugin == plugin).map(|e| e.kind).collect()
+    };
+    assert!(kinds("flaky").len() > 1, "{events:?}");
+    assert!(kinds("flaky").iter().all(|k| *k == PipelineErrorKind::Recoverable));
+    assert_eq!(kinds("broken"), vec![PipelineErrorKind::Fatal]);
+}
+
+#[test]
+fn channel_stats() {
+    let mut pipeline_builder = PipelineBuilder::new();
+    pipeline_builder.source_channel_capacity(8);
+    pipeline_builder.output_channel_capacity(5);
+    {
+        let mut alumet = AlumetStart::new(&mut pipeline_builder, String::from("test"));
+        let metric = alumet.create_metric::<u64>("counter", Unit::Unity, "test counter").unwrap();
+        let trigger = trigger::builder::time_interval(Duration::from_millis(5)).build().unwrap();
+        alumet.add_source(Box::new(CounterSource(metric)), trigger);
+        alumet.add_transform(Box::new(TenfoldTransform));
+        alumet.add_output(Box::new(NullOutput));
+    }
+    let mut pipeline = pipeline_builder.build().expect("pipeline should build").start();
+    let handle = pipeline.control_handle();
+    std::thread::sleep(Duration::from_millis(20));
+    let stats = handle.blocking_channel_stats().unwrap();
+    pipeline.shutdown(Duration::from_secs(1)).unwrap();
+
+    let queues: Vec<(&str, usize)> = stats.queues.iter().map(|q| (q.name.as_str(), q.capacity)).collect();
+    // the capacity of the broadcast queue is rounded up to a power of two
+    assert_eq!(queues, vec![("sources", 8), ("outputs", 8)]);
+    assert!(stats.queues.iter().all(|q| q.len <= q.capacity));
+    assert_eq!(stats.dropped_source_buffers, 0);
+    assert_eq!(stats.lost_output_messages.values().collect::<Vec<_>>(), vec![&0]);
+    assert!(handle.blocking_channel_stats().is_err(), "the pipeline has shut down");
+}
+
+#[test]
+fn last_errors() {
+    let mut pipeline_builder = PipelineBuilder::new();
+    {
+        let mut alumet = AlumetStart::new(&mut pipeline_builder, String::from("ok"));
+        let metric = alumet.create_metric::<u64>("counter", Unit::Unity, "test counter").unwrap();
+        let trigger = trigger::builder::time_interval(Duration::from_millis(5)).build().unwrap();
+        alumet.add_source(Box::new(CounterSource(metric)), trigger);
+        alumet.add_output(Box::new(NullOutput));
+    }
+    {
+        let mut alumet = AlumetStart::new(&mut pipeline_builder, String::from("bad"));
+        let trigger = trigger::builder::time_interval(Duration::from_millis(5)).build().unwrap();
+        alumet.add_source(Box::new(FailingSource), trigger);
+        alumet.add_transform(Box::new(FailingTransform));
+        alumet.add_output(Box::new(FailingOutput));
+    }
+    let mut pipeline = pipeline_builder.build().expect("pipeline should build").start();
+    let handle = pipeline.control_handle();
+    std::thread::sleep(Duration::from_millis(100));
+
+    // the errors are available while the pipeline is running, although the failed tasks have exited
+    let source_error = handle.source_last_error("bad").expect("the source should have failed");
+    assert!(source_error.contains("sensor unplugged"), "{source_error}");
+    let transform_error = handle.transform_last_error().expect("the transforms should have failed");
+    assert!(transform_error.contains("enrichment failed"), "{transform_error}");
+    let output_error = handle.output_last_error("bad").expect("the output should have failed");
+    assert!(output_error.contains("database unreachable"), "{output_error}");
+    assert!(handle.source_last_error("ok").is_none());
+    assert!(handle.output_last_error("ok").is_none());
+    let _ = pipeline.shutdown(Duration::from_secs(1));
+}
+
+#[test]
+fn output_circuit_breaker() {
+    let mut pipeline_builder = PipelineBuilder::new();
+    let healthy = Arc::new(AtomicBool::new(false));
+    let calls = Arc::new(AtomicUsize::new(0));
+    {
+        let mut alumet = AlumetStart::new(&mut pipeline_builder, String::from("test"));
+        let metric = alumet.create_metric::<u64>("counter", Unit::Unity, "test counter").unwrap();
+        let trigger = trigger::builder::time_interval(Duration::from_millis(5)).build().unwrap();
+        alumet.add_source(Box::new(CounterSource(metric)), trigger);
+        let output = FlakyOutput {
+            healthy: healthy.clone(),
+            calls: calls.clone(),
+        };
+        let policy = CircuitBreakerPolicy {
+            failure_threshold: 3,
+            cooldown: Duration::from_millis(150),
+        };
+        alumet.add_output(Box::new(output)).circuit_breaker(policy);
+    }
+    let mut pipeline = pipeline_builder.build().expect("pipeline should build").start();
+    let handle = pipeline.control_handle();
+    std::thread::sleep(Duration::from_millis(100));
+
+    // after 3 failures, the output is not called anymore
+    assert_eq!(calls.load(Ordering::Relaxed), 3);
+    assert!(handle.skipped_output_writes("test") > 0);
+    let breakers = handle.circuit_breakers("test");
+    assert_eq!(breakers.len(), 1);
+    assert_eq!(breakers[0].1, CircuitBreakerState::Open);
+
+    // after the cooldown, a successful write closes the breaker
+    healthy.store(true, Ordering::Relaxed);
+    std::thread::sleep(Duration::from_millis(150));
+    assert_eq!(handle.circuit_breakers("test")[0].1, CircuitBreakerState::Closed);
+    assert!(calls.load(Ordering::Relaxed) > 4);
+    assert_eq!(handle.failed_output_writes("test"), 3);
+    pipeline.shutdown(Duration::from_secs(1)).unwrap();
+}
+
+#[test]
+fn output_health_check() {
+    let mut pipeline_builder = PipelineBuilder::new();
+    let healthy = Arc::new(AtomicBool::new(false));
+    let calls = Arc::new(AtomicUsize::new(0));
+    {
+        let mut alumet = AlumetStart::new(&mut pipeline_builder, String::from("test"));
+        let metric = alumet.create_metric::<u64>("counter", Unit::Unity, "test counter").unwrap();
+        let trigger = trigger::builder::time_interval(Duration::from_millis(5)).build().unwrap();
+        alumet.add_source(Box::new(CounterSource(metric)), trigger);
+        let output = FlakyOutput {
+            healthy: healthy.clone(),
+            calls: calls.clone(),
+        };
+        alumet.add_output(Box::new(output)).health_check(Duration::from_millis(20));
+    }
+    let mut pipeline = pipeline_builder.build().expect("pipeline should build").start();
+    let handle = pipeline.control_handle();
+    std::thread::sleep(Duration::from_millis(100));
+
+    // the output is unhealthy: it is paused instead of failing
+    assert_eq!(calls.load(Ordering::Relaxed), 0);
+    let health = handle.output_health("test");
+    assert_eq!(health.len(), 1);
+    assert_eq!(health[0].1, Health::Unhealthy(String::from("service unavailable")));
+
+    // it resumes when it recovers
+    healthy.store(true, Ordering::Relaxed);
+    std::thread::sleep(Duration::from_millis(100));
+    assert_eq!(handle.output_health("test")[0].1, Health::Healthy);
+    assert!(calls.load(Ordering::Relaxed) > 0);
+    assert_eq!(handle.failed_output_writes("test"), 0);
+    pipeline.shutdown(Duration::from_secs(1)).unwrap();
+}
+
+#[test]
+fn source_lifecycle() {
+    let mut pipeline_builder = PipelineBuilder::new();
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    {
+        let mut alumet = AlumetStart::new(&mut pipeline_builder, String::from("test"));
+        let metric = alumet.create_metric::<u64>("counter", Unit::Unity, "test counter").unwrap();
+        let trigger = trigger::builder::time_interval(Duration::from_millis(5)).build().unwrap();
+        let source = LifecycleSource {
+            metric,
+            calls: calls.clone(),
+        };
+        alumet.add_source(Box::new(source), trigger);
+        alumet.add_output(Box::new(NullOutput));
+    }
+    let mut pipeline = pipeline_builder.build().expect("pipeline should build").start();
+    let handle = pipeline.control_handle();
+    std::thread::sleep(Duration::from_millis(50));
+    handle.blocking_all().stop_sources().unwrap();
+
+    // the failed init is retried before the first poll, and the source is shut down once when it stops
+    let calls = calls.lock().unwrap().clone();
+    assert_eq!(calls[..3], ["init", "init", "poll"], "{calls:?}");
+    assert_eq!(calls.last(), Some(&"shutdown"), "{calls:?}");
+    assert_eq!(calls.iter().filter(|c| **c != "poll").count(), 3, "{calls:?}");
+    pipeline.shutdown(Duration::from_secs(1)).unwrap();
+}
+
+#[test]
+fn async_source() {
+    let mut pipeline_builder = PipelineBuilder::new();
+    let values = Arc::new(Mutex::new(Vec::new()));
+    {
+        let mut alumet = AlumetStart::new(&mut pipeline_builder, String::from("test"));
+        let metric = alumet.create_metric::<u64>("counter", Unit::Unity, "test counter").unwrap();
+        let trigger = trigger::builder::time_interval(Duration::from_millis(10)).build().unwrap();
+        alumet.add_async_source(Box::new(AsyncCounterSource(metric)), trigger);
+        alumet.add_output(Box::new(RecordingOutput(values.clone())));
+    }
+    let pipeline = pipeline_builder.build().expect("pipeline should build").start();
+    std::thread::sleep(Duration::from_millis(60));
+    pipeline.shutdown(Duration::from_secs(1)).unwrap();
+
+    let values = values.lock().unwrap();
+    assert!(values.len() > 2, "{values:?}");
+}
+
+#[test]
+fn poll_overruns() {
+    let mut pipeline_builder = PipelineBuilder::new();
+    {
+        let mut alumet = AlumetStart::new(&mut pipeline_builder, String::from("test"));
+        let metric = alumet.create_metric::<u64>("counter", Unit::Unity, "test counter").unwrap();
+        // the source takes 20ms to poll, more than its interval
+        let trigger = trigger::builder::time_interval(Duration::from_millis(10))
+            .blocking()
+            .build()
+            .unwrap();
+        let source = SlowSource {
+            metric,
+            threads: Arc::new(Mutex::new(Vec::new())),
+        };
+        alumet.add_source(Box::new(source), trigger);
+        alumet.add_output(Box::new(NullOutput));
+    }
+    pipeline_builder.with_instrumentation();
+    let mut pipeline = pipeline_builder.build().expect("pipeline should build").start();
+    std::thread::sleep(Duration::from_millis(150));
+
+    let stats = pipeline.control_handle().stats().expect("the instrumentation is enabled");
+    assert!(stats.poll_overruns >= 1, "the overrun should be detected: {stats:?}");
+    pipeline.shutdown(Duration::from_secs(1)).unwrap();
+}
+
+#[test]
+fn transform_discards_buffer() {
+    let mut pipeline_builder = PipelineBuilder::new();
+    let output = MemoryOutput::with_capacity(16);
+    let collected = output.handle();
+    let reached = Arc::new(AtomicBool::new(false));
+    {
+        let mut alumet = AlumetStart::new(&mut pipeline_builder, String::from("test"));
+        let metric = alumet.create_metric::<u64>("counter", Unit::Unity, "test counter").unwrap();
+        let trigger = trigger::builder::time_interval(Duration::from_millis(10)).build().unwrap();
+        alumet.add_source(Box::new(CounterSource(metric)), trigger);
+        alumet.add_transform(Box::new(DiscardTransform));
+        alumet.add_transform(Box::new(ReachedTransform(reached.clone())));
+        alumet.add_output(Box::new(output));
+    }
+    let pipeline = pipeline_builder.build().expect("pipeline should build").start();
+    std::thread::sleep(Duration::from_millis(100));
+    pipeline.shutdown(Duration::from_secs(1)).unwrap();
+
+    assert!(collected.buffers().is_empty(), "the discarded buffers should not reach the output");
+    assert!(!reached.load(Ordering::Relaxed), "the next transforms should be skipped");
+}
+
+#[test]
+fn replace_transform() {
+    let mut pipeline_builder = PipelineBuilder::new();
+    let values = Arc::new(Mutex::new(Vec::new()));
+    {
+        let mut alumet = AlumetStart::new(&mut pipeline_builder, String::from("test"));
+        let metric = alumet.create_metric::<u64>("counter", Unit::Unity, "test counter").unwrap();
+        let trigger = trigger::builder::time_interval(Duration::from_millis(5)).build().unwrap();
+        alumet.add_source(Box::new(CounterSource(metric)), trigger);
+        alumet.add_transform(Box::new(ReachedTransform(Arc::new(AtomicBool::new(false)))));
+        alumet.add_output(Box::new(RecordingOutput(values.clone())));
+    }
+    let mut pipeline = pipeline_builder.build().expect("pipeline should build").start();
+    let handle = pipeline.control_handle();
+    std::thread::sleep(Duration::from_millis(50));
+    handle.blocking_replace_transform(0, Box::new(TenfoldTransform)).unwrap();
+    // nothing is replaced if the selector does not match any transform
+    assert!(handle.blocking_replace_transform(1, Box::new(DiscardTransform)).is_err());
+    assert!(handle.blocking_replace_transform("unknown", Box::new(DiscardTransform)).is_err());
+    std::thread::sleep(Duration::from_millis(50));
+    pipeline.shutdown(Duration::from_secs(1)).unwrap();
+
+    // every buffer goes through the old transform or the new one, which applies to all the next buffers
+    let values = values.lock().unwrap();
+    let n_old = values.iter().take_while(|v| **v == 1).count();
+    assert!(n_old > 0 && n_old < values.len(), "{values:?}");
+    assert!(values[n_old..].iter().all(|v| *v == 10), "{values:?}");
+}
+
+#[test]
+fn transform_skip_and_continue() {
+    let mut pipeline_builder = PipelineBuilder::new();
+    let output = MemoryOutput::with_capacity(16);
+    let collected = output.handle();
+    {
+        let mut alumet = AlumetStart::new(&mut pipeline_builder, String::from("test"));
+        let metric = alumet.create_metric::<u64>("counter", Unit::Unity, "test counter").unwrap();
+        let trigger = trigger::builder::time_interval(Duration::from_millis(10)).build().unwrap();
+        alumet.add_source(Box::new(CounterSource(metric)), trigger);
+        alumet.add_transform_with_error_policy(Box::new(FailingTransform), TransformErrorPolicy::SkipAndContinue);
+        alumet.add_transform(Box::new(TenfoldTransform));
+        alumet.add_output(Box::new(output));
+    }
+    let pipeline = pipeline_builder.build().expect("pipeline should build").start();
+    std::thread::sleep(Duration::from_millis(100));
+    // the failures of the first transform do not stop the pipeline
+    pipeline.shutdown(Duration::from_secs(1)).unwrap();
+
+    let measurements = collected.measurements();
+    assert!(!measurements.is_empty());
+    assert!(measurements
+        .iter()
+        .all(|p| matches!(p.value, WrappedMeasurementValue::U64(10))));
+}
+
+#[test]
+fn output_flush_interval() {
+    let mut pipeline_builder = PipelineBuilder::new();
+    let flushed = Arc::new(Mutex::new(Vec::new()));
+    {
+        let mut alumet = AlumetStart::new(&mut pipeline_builder, String::from("test"));
+        let metric = alumet.create_metric::<u64>("counter", Unit::Unity, "test counter").unwrap();
+        let trigger = trigger::builder::time_interval(Duration::from_millis(10)).build().unwrap();
+        alumet.add_source(Box::new(CounterSource(metric)), trigger);
+        let output = BatchingOutput {
+            pending: 0,
+            flushed: flushed.clone(),
+        };
+        alumet.add_output(Box::new(output)).flush_every(Duration::from_millis(50));
+    }
+    let pipeline = pipeline_builder.build().expect("pipeline should build").start();
+    std::thread::sleep(Duration::from_millis(180));
+    let n_flushes = flushed.lock().unwrap().len();
+    assert!(n_flushes >= 2, "the output should have been flushed periodically, got {n_flushes} flushes");
+
+    // the output is flushed one last time when it stops
+    pipeline.shutdown(Duration::from_secs(1)).unwrap();
+    let flushed = flushed.lock().unwrap();
+    assert!(flushed.len() > n_flushes);
+    assert!(flushed.iter().sum::<usize>() > 0);
+}
+
+#[test]
+fn output_registers_metrics_at_startup() {
+    let mut pipeline_builder = PipelineBuilder::new();
+    let named_write = Arc::new(AtomicBool::new(false));
+    {
+        let mut alumet = AlumetStart::new(&mut pipeline_builder, String::from("test"));
+        let metric = alumet.create_metric::<u64>("counter", Unit::Unity, "test counter").unwrap();
+        let trigger = trigger::builder::time_interval(Duration::from_millis(10)).build().unwrap();
+        alumet.add_source(Box::new(CounterSource(metric)), trigger);
+        let output = NamedOutput {
+            names: Vec::new(),
+            named_write: named_write.clone(),
+        };
+        alumet.add_output(Box::new(output));
+    }
+    let pipeline = pipeline_builder.build().expect("pipeline should build").start();
+    std::thread::sleep(Duration::from_millis(50));
+    pipeline.shutdown(Duration::from_secs(1)).unwrap();
+    assert!(named_write.load(Ordering::Relaxed), "the metrics should be registered before the first write");
+}
+
+#[test]
+fn routes() {
+    let mut pipeline_builder = PipelineBuilder::new();
+    let processed = Arc::new(Mutex::new(Vec::new()));
+    let raw = Arc::new(Mutex::new(Vec::new()));
+    {
+        let mut alumet = AlumetStart::new(&mut pipeline_builder, String::from("test"));
+        let metric = alumet.create_metric::<u64>("counter", Unit::Unity, "test counter").unwrap();
+        let trigger = trigger::builder::time_interval(Duration::from_millis(10)).build().unwrap();
+        alumet.add_source(Box::new(CounterSource(metric)), trigger);
+        // the transform only applies to the outputs of the same route
+        alumet.add_transform_to_route("processed", Box::new(TenfoldTransform));
+        alumet.add_output(Box::new(RecordingOutput(processed.clone()))).route("processed");
+        alumet.add_output_to_route("raw", Box::new(RecordingOutput(raw.clone())));
+    }
+    let pipeline = pipeline_builder.build().expect("pipeline should build").start();
+    std::thread::sleep(Duration::from_millis(100));
+    pipeline.shutdown(Duration::from_secs(1)).unwrap();
+
+    let processed = processed.lock().unwrap();
+    let raw = raw.lock().unwrap();
+    assert!(!processed.is_empty() && !raw.is_empty());
+    assert!(processed.iter().all(|n| *n == 10), "{processed:?}");
+    assert!(raw.iter().all(|n| *n == 1), "{raw:?}");
+}
+
+#[test]
+fn combined_output_options() {
+    let mut pipeline_builder = PipelineBuilder::new();
+    let processed = Arc::new(Mutex::new(Vec::new()));
+    let sampled = Arc::new(Mutex::new(Vec::new()));
+    {
+        let mut alumet = AlumetStart::new(&mut pipeline_builder, String::from("test"));
+        let metric = alumet.create_metric::<u64>("counter", Unit::Unity, "test counter").unwrap();
+        let trigger = trigger::builder::time_interval(Duration::from_millis(5)).build().unwrap();
+        alumet.add_source(Box::new(CounterSource(metric)), trigger);
+        alumet.add_transform_to_route("processed", Box::new(TenfoldTransform));
+        alumet.add_output(Box::new(RecordingOutput(processed.clone()))).route("processed");
+    }
+    {
+        // the options of the registration apply together
+        let mut alumet = AlumetStart::new(&mut pipeline_builder, String::from("sampled"));
+        alumet
+            .add_output(Box::new(RecordingOutput(sampled.clone())))
+            .route("processed")
+            .sampling(2)
+            .on_slow(SlowOutputPolicy::Block)
+            .flush_every(Duration::from_millis(20));
+    }
+    let mut pipeline = pipeline_builder.build().expect("pipeline should build").start();
+    let handle = pipeline.control_handle();
+    std::thread::sleep(Duration::from_millis(100));
+    pipeline.shutdown(Duration::from_secs(1)).unwrap();
+
+    let (processed, sampled) = (processed.lock().unwrap(), sampled.lock().unwrap());
+    assert!(processed.len() > 2);
+    assert!(sampled.iter().all(|n| *n == 10), "{sampled:?}");
+    assert_eq!(sampled.len(), (processed.len() + 1) / 2);
+    assert_eq!(handle.sampled_out_buffers("sampled"), (processed.len() - sampled.len()) as u64);
+}
+
+#[test]
+fn restart_processing_while_running() {
+    let mut pipeline_builder = PipelineBuilder::new();
+    let before = Arc::new(Mutex::new(Vec::new()));
+    {
+        let mut alumet = AlumetStart::new(&mut pipeline_builder, String::from("test"));
+        let metric = alumet.create_metric::<u64>("counter", Unit::Unity, "test counter").unwrap();
+        let trigger = trigger::builder::time_interval(Duration::from_millis(10)).build().unwrap();
+        alumet.add_source(Box::new(CounterSource(metric)), trigger);
+        alumet.add_output(Box::new(RecordingOutput(before.clone())));
+    }
+    let mut pipeline = pipeline_builder.build().expect("pipeline should build").start();
+    std::thread::sleep(Duration::from_millis(50));
+
+    // replace the output, and multiply the values by 10
+    let after = Arc::new(Mutex::new(Vec::new()));
+    let transform: Box<dyn Transform> = Box::new(TenfoldTransform);
+    let output: Box<dyn Output> = Box::new(RecordingOutput(after.clone()));
+    let transforms = vec![TransformBuilder {
+        name: String::from("test/tenfold"),
+        plugin: String::from("test"),
+        build: Box::new(|_| transform),
+        route: String::from(DEFAULT_ROUTE),
+        error_policy: TransformErrorPolicy::Abort,
+    }];
+    let outputs = vec![OutputBuilder::new(
+        String::from("test/after"),
+        String::from("test"),
+        Box::new(|_| Ok(OutputKind::Blocking(output))),
+    )];
+    pipeline
+        .restart_processing(transforms, outputs, Duration::from_secs(1))
+        .expect("restart should succeed");
+    let n_before = before.lock().unwrap().len();
+    assert!(n_before > 0);
+    std::thread::sleep(Duration::from_millis(100));
+
+    // an invalid configuration is rejected, and the current elements keep running
+    let err = pipeline
+        .restart_processing(vec![], vec![], Duration::from_secs(1))
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<PipelineBuildError>(),
+        Some(PipelineBuildError::Invalid(InvalidReason::NoOutput))
+    ));
+    pipeline.shutdown(Duration::from_secs(1)).unwrap();
+
+    // the sources have kept running, and only the new output has received their measurements
+    assert_eq!(before.lock().unwrap().len(), n_before);
+    let after = after.lock().unwrap();
+    assert!(!after.is_empty());
+    assert!(after.iter().all(|n| *n == 10), "{after:?}");
+}
+
+#[test]
+fn rebuild_runtime_while_running() {
+    let mut pipeline_builder = PipelineBuilder::new();
+    let threads = Arc::new(Mutex::new(Vec::new()));
+    let values = Arc::new(Mutex::new(Vec::new()));
+    {
+        let mut alumet = AlumetStart::new(&mut pipeline_builder, String::from("test"));
+        let metric = alumet.create_metric::<u64>("counter", Unit::Unity, "test counter").unwrap();
+        let trigger = trigger::builder::time_interval(Duration::from_millis(10)).build().unwrap();
+        let source = SlowSource {
+            metric,
+            threads: threads.clone(),
+        };
+        alumet.add_source(Box::new(source), trigger);
+        alumet.add_output(Box::new(RecordingOutput(values.clone())));
+    }
+    {
+        let mut alumet = AlumetStart::new(&mut pipeline_builder, String::from("other"));
+        let metric = alumet.create_metric::<u64>("other_counter", Unit::Unity, "test counter").unwrap();
+        let trigger = trigger::builder::time_interval(Duration::from_millis(10)).build().unwrap();
+        alumet.add_source(Box::new(CounterSource(metric)), trigger);
+        alumet.add_output(Box::new(NullOutput));
+    }
+    let mut pipeline = pipeline_builder.build().expect("pipeline should build").start();
+    let handle = pipeline.control_handle();
+    std::thread::sleep(Duration::from_millis(50));
+    handle.blocking_plugin("other").control_sources(SourceCmd::Pause).unwrap();
+    handle.blocking_plugin("other").control_outputs(OutputCmd::Pause).unwrap();
+
+    // zero threads is rejected, and the pipeline is not modified
+    let params = RuntimeParameters {
+        normal_worker_threads: Some(0),
+        ..Default::default()
+    };
+    let err = pipeline.rebuild_runtime(params, Duration::from_secs(1)).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<PipelineBuildError>(),
+        Some(PipelineBuildError::Invalid(InvalidReason::ZeroWorkerThreads(_)))
+    ));
+
+    // the elements are moved to a runtime with a single thread
+    let params = RuntimeParameters {
+        normal_worker_threads: Some(1),
+        ..Default::default()
+    };
+    pipeline
+        .rebuild_runtime(params, Duration::from_secs(1))
+        .expect("rebuild should succeed");
+    let n_before = values.lock().unwrap().len();
+    assert!(n_before > 0);
+    threads.lock().unwrap().clear();
+    std::thread::sleep(Duration::from_millis(100));
+    {
+        let threads = threads.lock().unwrap();
+        assert!(!threads.is_empty(), "the source should have been restarted");
+        assert!(threads.iter().all(|name| name.starts_with("normal-worker-")), "{threads:?}");
+        assert!(threads.iter().all(|name| *name == threads[0]), "{threads:?}");
+    }
+
+    // the paused elements stay paused
+    let states = handle.blocking_plugin("other").source_states().unwrap();
+    assert_eq!(states[0].1, ElementState::Paused);
+    let states = handle.blocking_plugin("other").output_states().unwrap();
+    assert_eq!(states[0].1, ElementState::Paused);
+    let states = handle.blocking_plugin("test").output_states().unwrap();
+    assert_eq!(states[0].1, ElementState::Running);
+    pipeline.shutdown(Duration::from_secs(1)).unwrap();
+    assert!(values.lock().unwrap().len() > n_before, "the output should have been restarted");
+}
+
+#[test]
+fn rebuild_runtime_with_autonomous_source() {
+    let mut pipeline_builder = PipelineBuilder::new();
+    {
+        let mut alumet = AlumetStart::new(&mut pipeline_builder, String::from("test"));
+        alumet.add_autonomous_source(|_ctx, cancel, _tx| async move {
+            cancel.cancelled().await;
+            Ok(())
+        });
+        alumet.add_output(Box::new(NullOutput));
+    }
+    let mut pipeline = pipeline_builder.build().expect("pipeline should build").start();
+    let params = RuntimeParameters {
+        normal_worker_threads: Some(1),
+        ..Default::default()
+    };
+    let err = pipeline.rebuild_runtime(params, Duration::from_secs(1)).unwrap_err();
+    assert!(format!("{err}").contains("autonomous"), "{err}");
+    pipeline.shutdown(Duration::from_secs(1)).unwrap();
+}
+
+#[test]
+fn pipeline_from_config() {
+    let values = Arc::new(Mutex::new(Vec::new()));
+    let recorded = values.clone();
+    let constructors = Constructors::new()
+        .source("counter", |alumet, _params| {
+            let metric = alumet.create_metric::<u64>("counter", Unit::Unity, "test counter")?;
+            Ok(Box::new(CounterSource(metric)))
+        })
+        .transform("scale", |_alumet, params| match params.get("factor").and_then(|v| v.as_integer()) {
+            Some(10) => Ok(Box::new(TenfoldTransform)),
+            other => Err(anyhow::anyhow!("unsupported factor {other:?}")),
+        })
+        .output("memory", move |_alumet, _params| Ok(Box::new(RecordingOutput(recorded.clone()))));
+
+    let mut config = PipelineConfig::default();
+    config.sources.push(SourceConfig::new("counter", Duration::from_millis(5)));
+    config.transforms.push(ElementConfig::new("scale"));
+    config.outputs.push(ElementConfig::new("memory"));
+    config.threads.normal_worker_threads = Some(2);
+
+    // the constructor rejects the missing parameter
+    let err = config.instantiate(&constructors).err().expect("the transform should fail");
+    assert!(format!("{err:#}").contains("unsupported factor None"), "{err:#}");
+    config.transforms[0].params.insert(String::from("factor"), toml::Value::Integer(10));
+
+    // every element must have a constructor
+    let mut unknown = config.clone();
+    unknown.outputs.push(ElementConfig::new("csv"));
+    assert!(unknown.instantiate(&constructors).is_err());
+
+    let pipeline_builder = config.instantiate(&constructors).unwrap();
+    assert_eq!(pipeline_builder.source_count(), 1);
+    assert_eq!(pipeline_builder.metric_count(), 1);
+    let pipeline = pipeline_builder.build().expect("pipeline should build").start();
+    std::thread::sleep(Duration::from_millis(50));
+    pipeline.shutdown(Duration::from_secs(1)).unwrap();
+
+    let values = values.lock().unwrap();
+    assert!(!values.is_empty());
+    assert!(values.iter().all(|v| *v == 10));
+}
+
+#[test]
+fn validate_reports_all_errors() {
+    let mut pipeline_builder = PipelineBuilder::new();
+    let metric = AlumetStart::new(&mut pipeline_builder, String::from("test"))
+        .create_metric::<u64>("counter", Unit::Unity, "test counter")
+        .unwrap();
+    pipeline_builder
+        .add_source("test", Box::new(CounterSource(metric)))
+        .every(Duration::from_secs(2))
+        .flush_every(Duration::from_secs(1));
+    pipeline_builder.source_channel_capacity(0);
+    pipeline_builder.normal_worker_threads(0);
+
+    let errors = pipeline_builder.validate().unwrap_err();
+    assert!(matches!(
+        errors.as_slice(),
+        [
+            InvalidReason::NoOutput,
+            InvalidReason::ZeroChannelCapacity,
+            InvalidReason::ZeroWorkerThreads("normal"),
+            InvalidReason::InvalidSource(_, _),
+        ]
+    ));
+
+    // the errors that have been fixed are not reported anymore
+    pipeline_builder.add_output("test", Box::new(NullOutput));
+    pipeline_builder.source_channel_capacity(16);
+    pipeline_builder.normal_worker_threads(1);
+    let errors = pipeline_builder.validate().unwrap_err();
+    assert!(matches!(errors.as_slice(), [InvalidReason::InvalidSource(_, _)]));
+}
+
+#[test]
+fn route_without_output() {
+    let mut pipeline_builder = PipelineBuilder::new();
+    {
+        let mut alumet = AlumetStart::new(&mut pipeline_builder, String::from("test"));
+        let metric = alumet.create_metric::<u64>("counter", Unit::Unity, "test counter").unwrap();
+        let trigger = trigger::builder::time_interval(Duration::from_millis(10)).build().unwrap();
+        alumet.add_source(Box::new(CounterSource(metric)), trigger);
+        alumet.add_transform_to_route("lost", Box::new(TenfoldTransform));
+        alumet.add_output(Box::new(NullOutput));
+    }
+    let res = pipeline_builder.build();
+    assert!(matches!(
+        res,
+        Err(PipelineBuildError::Invalid(InvalidReason::RouteWithoutOutput(route))) if route == "lost"
+    ));
+}
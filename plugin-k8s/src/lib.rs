@@ -210,7 +210,7 @@ impl AlumetPlugin for K8sPlugin {
                                     pod_uid.to_string(),
                                     Box::new(probe),
                                     TriggerSpec::at_interval(pod_detect.poll_interval),
-                                );
+                                )?;
                             }
                         }
                         Ok(())
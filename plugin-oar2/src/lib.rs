@@ -196,7 +196,7 @@ impl AlumetPlugin for Oar2Plugin {
                                     source_name,
                                     new_source,
                                     TriggerSpec::at_interval(job_detect.poll_interval),
-                                );
+                                )?;
                             }
                         }
                     }
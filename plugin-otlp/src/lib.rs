@@ -0,0 +1,60 @@
+use std::collections::HashMap;
+
+use alumet::plugin::rust::{deserialize_config, serialize_config, AlumetPlugin};
+use serde::{Deserialize, Serialize};
+
+pub use output::OtlpOutput;
+
+mod output;
+
+pub struct OtlpPlugin {
+    config: Option<Config>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Config {
+    /// The URI of the OpenTelemetry collector (gRPC endpoint), for instance `http://localhost:4317`.
+    endpoint: String,
+
+    /// Attributes of the OpenTelemetry resource, attached to all the exported metrics.
+    resource_attributes: HashMap<String, String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            endpoint: String::from("http://localhost:4317"),
+            resource_attributes: HashMap::from([(String::from("service.name"), String::from("alumet"))]),
+        }
+    }
+}
+
+impl AlumetPlugin for OtlpPlugin {
+    fn name() -> &'static str {
+        "otlp"
+    }
+
+    fn version() -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+
+    fn default_config() -> anyhow::Result<Option<alumet::plugin::ConfigTable>> {
+        Ok(Some(serialize_config(Config::default())?))
+    }
+
+    fn init(config: alumet::plugin::ConfigTable) -> anyhow::Result<Box<Self>> {
+        let config = deserialize_config(config)?;
+        Ok(Box::new(OtlpPlugin { config: Some(config) }))
+    }
+
+    fn start(&mut self, alumet: &mut alumet::plugin::AlumetStart) -> anyhow::Result<()> {
+        let config = self.config.take().unwrap();
+        let output = OtlpOutput::new(config.endpoint, config.resource_attributes)?;
+        alumet.add_output(Box::new(output));
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
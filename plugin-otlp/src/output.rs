@@ -0,0 +1,291 @@
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use alumet::measurement::{
+    AttributeValue, MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementType, WrappedMeasurementValue,
+};
+use alumet::metrics::{Metric, RawMetricId};
+use alumet::pipeline::{Output, OutputContext, WriteError};
+use alumet::units::Unit;
+use anyhow::{anyhow, Context};
+use opentelemetry_proto::tonic::collector::metrics::v1::metrics_service_client::MetricsServiceClient;
+use opentelemetry_proto::tonic::collector::metrics::v1::ExportMetricsServiceRequest;
+use opentelemetry_proto::tonic::common::v1::{any_value, AnyValue, InstrumentationScope, KeyValue};
+use opentelemetry_proto::tonic::metrics::v1::{
+    metric, number_data_point, AggregationTemporality, Gauge, NumberDataPoint, ResourceMetrics, ScopeMetrics, Sum,
+};
+use opentelemetry_proto::tonic::resource::v1::Resource;
+use tonic::transport::{Channel, Endpoint};
+
+/// An output that exports the measurements to an OpenTelemetry collector, with OTLP over gRPC.
+///
+/// Each call to [`Output::write`] sends the whole buffer in one export request.
+/// The energy metrics, and the integer metrics without unit, are exported as delta sums (counters).
+/// The other metrics are exported as gauges.
+pub struct OtlpOutput {
+    client: MetricsServiceClient<Channel>,
+    resource: Resource,
+    /// The runtime of the gRPC client. A Tonic client can only be used from the runtime that it has been created with.
+    ///
+    /// It is only taken by `drop`.
+    rt: Option<tokio::runtime::Runtime>,
+}
+
+impl OtlpOutput {
+    /// Creates an output that exports the measurements to `endpoint`, for instance `http://localhost:4317`.
+    ///
+    /// The `resource_attributes` are attached to the OpenTelemetry resource of every export.
+    /// The connection is established on the first write, and re-established if it is lost.
+    pub fn new(endpoint: String, resource_attributes: HashMap<String, String>) -> anyhow::Result<Self> {
+        let endpoint = Endpoint::from_shared(endpoint.clone())
+            .with_context(|| format!("invalid endpoint {endpoint}"))?;
+        let rt = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+        let channel = {
+            // The channel spawns its background task on the current runtime.
+            let _guard = rt.enter();
+            endpoint.connect_lazy()
+        };
+        let attributes = resource_attributes
+            .into_iter()
+            .map(|(key, value)| key_value(key, any_value::Value::StringValue(value)))
+            .collect();
+        Ok(Self {
+            client: MetricsServiceClient::new(channel),
+            resource: Resource {
+                attributes,
+                ..Default::default()
+            },
+            rt: Some(rt),
+        })
+    }
+}
+
+impl Output for OtlpOutput {
+    fn write(&mut self, measurements: &MeasurementBuffer, ctx: &OutputContext) -> Result<(), WriteError> {
+        let metrics = convert_measurements(measurements, |id| ctx.metrics.with_id(id));
+        let request = ExportMetricsServiceRequest {
+            resource_metrics: vec![ResourceMetrics {
+                resource: Some(self.resource.clone()),
+                scope_metrics: vec![ScopeMetrics {
+                    scope: Some(InstrumentationScope {
+                        name: String::from("alumet"),
+                        version: String::from(env!("CARGO_PKG_VERSION")),
+                        ..Default::default()
+                    }),
+                    metrics,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+        };
+
+        log::debug!("Sending OTLP export request with {} measurement points", measurements.len());
+        // `write` is called on a dedicated thread, outside of any async context: we can block on our runtime.
+        let rt = self.rt.as_ref().unwrap();
+        let response = rt.block_on(self.client.export(request)).map_err(|status| {
+            let error = anyhow!("OTLP export failed: {status}");
+            match status.code() {
+                tonic::Code::Unavailable | tonic::Code::DeadlineExceeded | tonic::Code::ResourceExhausted => {
+                    WriteError::CanRetry(error)
+                }
+                _ => WriteError::Fatal(error),
+            }
+        })?;
+        if let Some(partial) = response.into_inner().partial_success {
+            if partial.rejected_data_points > 0 {
+                log::warn!(
+                    "The OpenTelemetry collector has rejected {} data points: {}",
+                    partial.rejected_data_points,
+                    partial.error_message
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for OtlpOutput {
+    fn drop(&mut self) {
+        // The output may be dropped in an async context, where a runtime cannot be dropped normally.
+        if let Some(rt) = self.rt.take() {
+            rt.shutdown_background();
+        }
+    }
+}
+
+/// Converts the measurements to OTLP metrics, with one OTLP metric per Alumet metric.
+///
+/// `metric_def` returns the definition of a metric. The measurements of unknown metrics are ignored.
+fn convert_measurements<'a>(
+    measurements: &MeasurementBuffer,
+    metric_def: impl Fn(&RawMetricId) -> Option<&'a Metric>,
+) -> Vec<opentelemetry_proto::tonic::metrics::v1::Metric> {
+    // Group the points by metric, in the order of their first appearance.
+    let mut points_by_metric: Vec<(RawMetricId, Vec<NumberDataPoint>)> = Vec::new();
+    let mut index_by_metric: HashMap<RawMetricId, usize> = HashMap::new();
+    for m in measurements {
+        let index = *index_by_metric.entry(m.metric).or_insert_with(|| {
+            points_by_metric.push((m.metric, Vec::new()));
+            points_by_metric.len() - 1
+        });
+        points_by_metric[index].1.push(data_point(m));
+    }
+
+    points_by_metric
+        .into_iter()
+        .filter_map(|(id, data_points)| {
+            let Some(def) = metric_def(&id) else {
+                log::warn!("Unknown metric {id:?}, its {} measurements will not be exported", data_points.len());
+                return None;
+            };
+            let data = if is_counter(def) {
+                metric::Data::Sum(Sum {
+                    data_points,
+                    aggregation_temporality: AggregationTemporality::Delta as i32,
+                    is_monotonic: true,
+                })
+            } else {
+                metric::Data::Gauge(Gauge { data_points })
+            };
+            Some(opentelemetry_proto::tonic::metrics::v1::Metric {
+                name: def.name.clone(),
+                description: def.description.clone(),
+                unit: def.unit.unique_name(),
+                data: Some(data),
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+/// Returns `true` if the measurements of the metric are increments, which should be added together.
+///
+/// The sources measure the energy consumed since their previous poll, and the integer metrics without unit
+/// count the events that occurred since their previous poll.
+fn is_counter(metric: &Metric) -> bool {
+    match metric.unit.base_unit {
+        Unit::Joule | Unit::WattHour => true,
+        Unit::Unity => matches!(metric.value_type, WrappedMeasurementType::U64),
+        _ => false,
+    }
+}
+
+fn data_point(m: &MeasurementPoint) -> NumberDataPoint {
+    let value = match m.value {
+        WrappedMeasurementValue::F64(x) => number_data_point::Value::AsDouble(x),
+        WrappedMeasurementValue::U64(x) => number_data_point::Value::AsInt(i64::try_from(x).unwrap_or(i64::MAX)),
+    };
+
+    // Resources and consumers are translated to attributes.
+    let mut attributes = vec![
+        key_value("resource_kind", any_value::Value::StringValue(m.resource.kind().to_owned())),
+        key_value(
+            "resource_id",
+            any_value::Value::StringValue(m.resource.id_string().unwrap_or_default()),
+        ),
+        key_value(
+            "resource_consumer_kind",
+            any_value::Value::StringValue(m.consumer.kind().to_owned()),
+        ),
+        key_value(
+            "resource_consumer_id",
+            any_value::Value::StringValue(m.consumer.id_string().unwrap_or_default()),
+        ),
+    ];
+    attributes.extend(m.attributes().map(|(key, value)| {
+        let value = match value {
+            AttributeValue::F64(v) => any_value::Value::DoubleValue(*v),
+            AttributeValue::U64(v) => any_value::Value::IntValue(i64::try_from(*v).unwrap_or(i64::MAX)),
+            AttributeValue::Bool(v) => any_value::Value::BoolValue(*v),
+            AttributeValue::String(v) => any_value::Value::StringValue(v.to_owned()),
+            AttributeValue::Str(v) => any_value::Value::StringValue(v.to_string()),
+        };
+        key_value(key, value)
+    }));
+
+    NumberDataPoint {
+        attributes,
+        time_unix_nano: unix_nanos(m.timestamp),
+        value: Some(value),
+        ..Default::default()
+    }
+}
+
+fn key_value(key: impl Into<String>, value: any_value::Value) -> KeyValue {
+    KeyValue {
+        key: key.into(),
+        value: Some(AnyValue { value: Some(value) }),
+    }
+}
+
+fn unix_nanos(timestamp: Timestamp) -> u64 {
+    let since_epoch = SystemTime::from(timestamp)
+        .duration_since(UNIX_EPOCH)
+        .expect("Every timestamp should be obtained from system_time_now()");
+    since_epoch.as_nanos() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use alumet::measurement::{
+        MeasurementBuffer, MeasurementPoint, Timestamp, WrappedMeasurementType, WrappedMeasurementValue,
+    };
+    use alumet::metrics::{Metric, RawMetricId};
+    use alumet::resources::{Resource, ResourceConsumer};
+    use alumet::units::{PrefixedUnit, Unit};
+    use opentelemetry_proto::tonic::metrics::v1::metric::Data;
+
+    use super::convert_measurements;
+
+    fn metric(name: &str, value_type: WrappedMeasurementType, unit: Unit) -> Metric {
+        Metric {
+            name: name.to_owned(),
+            description: String::new(),
+            value_type,
+            unit: PrefixedUnit::from(unit),
+        }
+    }
+
+    fn point(id: u64, value: WrappedMeasurementValue) -> MeasurementPoint {
+        MeasurementPoint::new_untyped(
+            Timestamp::now(),
+            RawMetricId::from_u64(id),
+            Resource::LocalMachine,
+            ResourceConsumer::LocalMachine,
+            value,
+        )
+    }
+
+    #[test]
+    fn gauges_and_counters() {
+        let defs = HashMap::from([
+            (RawMetricId::from_u64(0), metric("energy", WrappedMeasurementType::F64, Unit::Joule)),
+            (RawMetricId::from_u64(1), metric("temperature", WrappedMeasurementType::F64, Unit::DegreeCelsius)),
+        ]);
+        let mut buf = MeasurementBuffer::new();
+        buf.push(point(0, WrappedMeasurementValue::F64(1.5)));
+        buf.push(point(1, WrappedMeasurementValue::F64(40.0)));
+        buf.push(point(0, WrappedMeasurementValue::F64(2.5)));
+        // unknown metric: ignored
+        buf.push(point(2, WrappedMeasurementValue::U64(1)));
+
+        let metrics = convert_measurements(&buf, |id| defs.get(id));
+        assert_eq!(metrics.len(), 2);
+        assert_eq!(metrics[0].name, "energy");
+        assert_eq!(metrics[0].unit, "J");
+        match &metrics[0].data {
+            Some(Data::Sum(sum)) => {
+                assert!(sum.is_monotonic);
+                assert_eq!(sum.data_points.len(), 2);
+            }
+            other => panic!("energy should be a sum, got {other:?}"),
+        }
+        assert_eq!(metrics[1].name, "temperature");
+        match &metrics[1].data {
+            Some(Data::Gauge(gauge)) => assert_eq!(gauge.data_points.len(), 1),
+            other => panic!("temperature should be a gauge, got {other:?}"),
+        }
+    }
+}
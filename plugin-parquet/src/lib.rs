@@ -0,0 +1,68 @@
+mod output;
+
+use std::{path::PathBuf, time::Duration};
+
+use alumet::plugin::{
+    rust::{deserialize_config, serialize_config, AlumetPlugin},
+    ConfigTable,
+};
+use serde::{Deserialize, Serialize};
+
+pub use output::ArrowOutput;
+
+pub struct ParquetPlugin {
+    config: Config,
+}
+
+impl AlumetPlugin for ParquetPlugin {
+    fn name() -> &'static str {
+        "parquet"
+    }
+
+    fn version() -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+
+    fn default_config() -> anyhow::Result<Option<ConfigTable>> {
+        Ok(Some(serialize_config(Config::default())?))
+    }
+
+    fn init(config: ConfigTable) -> anyhow::Result<Box<Self>> {
+        let config: Config = deserialize_config(config)?;
+        if config.max_rows_per_batch == 0 {
+            anyhow::bail!("max_rows_per_batch must be non-zero");
+        }
+        Ok(Box::new(ParquetPlugin { config }))
+    }
+
+    fn start(&mut self, alumet: &mut alumet::plugin::AlumetStart) -> anyhow::Result<()> {
+        let output = Box::new(ArrowOutput::new(&self.config.output_path, self.config.max_rows_per_batch)?);
+        // Write the pending rows periodically, so that a small number of measurements is not delayed forever.
+        alumet.add_output(output).flush_every(self.config.flush_interval);
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+struct Config {
+    output_path: PathBuf,
+    /// Number of measurement points per record batch.
+    max_rows_per_batch: usize,
+    /// Maximum time between two writes of the pending points.
+    #[serde(with = "humantime_serde")]
+    flush_interval: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            output_path: PathBuf::from("alumet-output.parquet"),
+            max_rows_per_batch: 10_000,
+            flush_interval: Duration::from_secs(60),
+        }
+    }
+}
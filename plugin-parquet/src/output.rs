@@ -0,0 +1,260 @@
+use std::{
+    fs::File,
+    path::Path,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use alumet::measurement::{MeasurementBuffer, MeasurementPoint, WrappedMeasurementValue};
+use alumet::metrics::Metric;
+use alumet::pipeline::{Output, OutputContext, WriteError};
+use anyhow::Context;
+use arrow::array::{ArrayRef, Float64Builder, StringBuilder, TimestampNanosecondBuilder, UInt64Builder};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+
+/// Writes the measurements to a Parquet file, in batches of Apache Arrow records.
+///
+/// The points are accumulated in Arrow arrays, one per column, which are written to the file as a
+/// [`RecordBatch`] when they contain `max_rows_per_batch` points, and when the output is flushed.
+/// The columns are: the timestamp, the id, name and unit of the metric (the name and unit come from the
+/// [`MetricRegistry`](alumet::metrics::MetricRegistry)), the resource, the consumer and the value.
+/// The values are converted to `f64`, hence the integers larger than 2^53 lose some precision.
+/// The attributes of the points are not exported.
+///
+/// The file is only readable once it has been finalized, which happens when the output is dropped.
+pub struct ArrowOutput {
+    schema: SchemaRef,
+    /// The Parquet writer, `None` once the file has been finalized.
+    writer: Option<ArrowWriter<File>>,
+    max_rows_per_batch: usize,
+    columns: Columns,
+}
+
+/// The columns of the next record batch.
+struct Columns {
+    timestamp: TimestampNanosecondBuilder,
+    metric_id: UInt64Builder,
+    metric_name: StringBuilder,
+    unit: StringBuilder,
+    resource_kind: StringBuilder,
+    resource_id: StringBuilder,
+    consumer_kind: StringBuilder,
+    consumer_id: StringBuilder,
+    value: Float64Builder,
+    /// Number of rows in the columns.
+    len: usize,
+}
+
+fn schema() -> Schema {
+    let text = |name| Field::new(name, DataType::Utf8, false);
+    Schema::new(vec![
+        Field::new("timestamp", DataType::Timestamp(TimeUnit::Nanosecond, Some("UTC".into())), false),
+        Field::new("metric_id", DataType::UInt64, false),
+        text("metric_name"),
+        text("unit"),
+        text("resource_kind"),
+        text("resource_id"),
+        text("consumer_kind"),
+        text("consumer_id"),
+        Field::new("value", DataType::Float64, false),
+    ])
+}
+
+impl ArrowOutput {
+    /// Creates (or truncates) the Parquet file `output_file`.
+    ///
+    /// Panics if `max_rows_per_batch` is zero.
+    pub fn new(output_file: impl AsRef<Path>, max_rows_per_batch: usize) -> anyhow::Result<Self> {
+        assert!(max_rows_per_batch > 0, "the batches must not be empty");
+        let path = output_file.as_ref();
+        let file = File::create(path).with_context(|| format!("could not create {path:?}"))?;
+        let schema = Arc::new(schema());
+        let props = WriterProperties::builder().set_compression(Compression::SNAPPY).build();
+        let writer = ArrowWriter::try_new(file, schema.clone(), Some(props))?;
+        Ok(Self {
+            schema,
+            writer: Some(writer),
+            max_rows_per_batch,
+            columns: Columns::with_capacity(max_rows_per_batch),
+        })
+    }
+
+    /// Adds a point to the next record batch, and writes the batch if it is full.
+    fn append(&mut self, point: &MeasurementPoint, metric: &Metric) -> anyhow::Result<()> {
+        self.columns.push(point, metric)?;
+        if self.columns.len >= self.max_rows_per_batch {
+            self.write_batch()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the pending rows, if any, as a record batch.
+    fn write_batch(&mut self) -> anyhow::Result<()> {
+        if self.columns.len == 0 {
+            return Ok(());
+        }
+        let writer = self.writer.as_mut().context("the Parquet file has already been finalized")?;
+        let batch = RecordBatch::try_new(self.schema.clone(), self.columns.finish())?;
+        writer.write(&batch)?;
+        log::trace!("{} rows written to the Parquet file", batch.num_rows());
+        Ok(())
+    }
+}
+
+impl Columns {
+    fn with_capacity(capacity: usize) -> Self {
+        let text = || StringBuilder::with_capacity(capacity, capacity * 8);
+        Self {
+            timestamp: TimestampNanosecondBuilder::with_capacity(capacity).with_timezone("UTC"),
+            metric_id: UInt64Builder::with_capacity(capacity),
+            metric_name: text(),
+            unit: text(),
+            resource_kind: text(),
+            resource_id: text(),
+            consumer_kind: text(),
+            consumer_id: text(),
+            value: Float64Builder::with_capacity(capacity),
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, point: &MeasurementPoint, metric: &Metric) -> anyhow::Result<()> {
+        let since_epoch = SystemTime::from(point.timestamp)
+            .duration_since(UNIX_EPOCH)
+            .context("the timestamp is before the UNIX epoch")?;
+        let nanos = i64::try_from(since_epoch.as_nanos()).context("the timestamp is too far in the future")?;
+        let value = match point.value {
+            WrappedMeasurementValue::F64(x) => x,
+            WrappedMeasurementValue::U64(x) => x as f64,
+        };
+        self.timestamp.append_value(nanos);
+        self.metric_id.append_value(point.metric.as_u64());
+        self.metric_name.append_value(&metric.name);
+        self.unit.append_value(metric.unit.unique_name());
+        self.resource_kind.append_value(point.resource.kind());
+        self.resource_id.append_value(point.resource.id_display().to_string());
+        self.consumer_kind.append_value(point.consumer.kind());
+        self.consumer_id.append_value(point.consumer.id_display().to_string());
+        self.value.append_value(value);
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Returns the columns as Arrow arrays, in the order of the schema, and clears the builders.
+    fn finish(&mut self) -> Vec<ArrayRef> {
+        self.len = 0;
+        vec![
+            Arc::new(self.timestamp.finish()),
+            Arc::new(self.metric_id.finish()),
+            Arc::new(self.metric_name.finish()),
+            Arc::new(self.unit.finish()),
+            Arc::new(self.resource_kind.finish()),
+            Arc::new(self.resource_id.finish()),
+            Arc::new(self.consumer_kind.finish()),
+            Arc::new(self.consumer_id.finish()),
+            Arc::new(self.value.finish()),
+        ]
+    }
+}
+
+impl Output for ArrowOutput {
+    fn write(&mut self, measurements: &MeasurementBuffer, ctx: &OutputContext) -> Result<(), WriteError> {
+        for m in measurements.iter() {
+            let metric = ctx
+                .metrics
+                .with_id(&m.metric)
+                .with_context(|| format!("Unknown metric {:?}", m.metric))?;
+            self.append(m, metric)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), WriteError> {
+        self.write_batch()?;
+        if let Some(writer) = &mut self.writer {
+            // close the current row group, so that the data reaches the file
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for ArrowOutput {
+    fn drop(&mut self) {
+        if let Err(e) = self.write_batch() {
+            log::error!("Could not write the last rows to the Parquet file: {e:#}");
+        }
+        if let Some(writer) = self.writer.take() {
+            if let Err(e) = writer.close() {
+                log::error!("Could not finalize the Parquet file: {e}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use alumet::measurement::{MeasurementPoint, Timestamp, WrappedMeasurementType, WrappedMeasurementValue};
+    use alumet::metrics::{Metric, RawMetricId};
+    use alumet::resources::{Resource, ResourceConsumer};
+    use alumet::units::{PrefixedUnit, Unit};
+    use arrow::array::{Array, Float64Array, StringArray};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    use super::ArrowOutput;
+
+    #[test]
+    fn batches() {
+        let path = std::env::temp_dir().join("alumet-test-parquet-batches.parquet");
+        let metric = Metric {
+            name: String::from("energy"),
+            description: String::new(),
+            value_type: WrappedMeasurementType::F64,
+            unit: PrefixedUnit::from(Unit::Joule),
+        };
+        let point = |value| {
+            MeasurementPoint::new_untyped(
+                Timestamp::now(),
+                RawMetricId::from_u64(0),
+                Resource::CpuPackage { id: 1 },
+                ResourceConsumer::LocalMachine,
+                WrappedMeasurementValue::F64(value),
+            )
+        };
+
+        let mut output = ArrowOutput::new(&path, 2).unwrap();
+        for value in [1.0, 2.0, 3.0] {
+            output.append(&point(value), &metric).unwrap();
+        }
+        // the first batch is full, the last row is pending until the file is finalized
+        assert_eq!(output.columns.len, 1);
+        drop(output);
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let batches: Vec<_> = reader.collect::<Result<_, _>>().unwrap();
+        let values: Vec<f64> = batches
+            .iter()
+            .flat_map(|b| {
+                let column = b.column_by_name("value").unwrap();
+                let column = column.as_any().downcast_ref::<Float64Array>().unwrap();
+                column.values().to_vec()
+            })
+            .collect();
+        assert_eq!(values, vec![1.0, 2.0, 3.0]);
+
+        let names = batches[0].column_by_name("metric_name").unwrap();
+        let names = names.as_any().downcast_ref::<StringArray>().unwrap();
+        assert_eq!(names.value(0), "energy");
+        assert_eq!(names.len(), batches[0].num_rows());
+        std::fs::remove_file(path).unwrap();
+    }
+}
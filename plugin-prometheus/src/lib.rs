@@ -0,0 +1,74 @@
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::Duration;
+
+use alumet::plugin::rust::{deserialize_config, serialize_config, AlumetPlugin};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+pub use output::PrometheusOutput;
+
+mod output;
+mod server;
+
+pub struct PrometheusPlugin {
+    config: Option<Config>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Config {
+    /// The port of the HTTP server. The metrics are served on `/metrics`.
+    port: u16,
+
+    /// How long a value is exposed after its last update.
+    /// Past this delay, the measured metric is considered to have disappeared.
+    #[serde(with = "humantime_serde")]
+    stale_ttl: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            port: 9091,
+            stale_ttl: Duration::from_secs(60),
+        }
+    }
+}
+
+impl AlumetPlugin for PrometheusPlugin {
+    fn name() -> &'static str {
+        "prometheus"
+    }
+
+    fn version() -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+
+    fn default_config() -> anyhow::Result<Option<alumet::plugin::ConfigTable>> {
+        Ok(Some(serialize_config(Config::default())?))
+    }
+
+    fn init(config: alumet::plugin::ConfigTable) -> anyhow::Result<Box<Self>> {
+        let config = deserialize_config(config)?;
+        Ok(Box::new(PrometheusPlugin { config: Some(config) }))
+    }
+
+    fn start(&mut self, alumet: &mut alumet::plugin::AlumetStart) -> anyhow::Result<()> {
+        let config = self.config.take().unwrap();
+
+        // The HTTP server runs on the tokio runtime of the pipeline, which is only available in the output builder.
+        alumet.add_output_builder(move |pipeline| {
+            let addr = SocketAddr::from((Ipv4Addr::UNSPECIFIED, config.port));
+            let mut output = PrometheusOutput::new(config.stale_ttl);
+            output
+                .serve(addr, pipeline.async_runtime_handle())
+                .with_context(|| format!("failed to start the HTTP server on {addr}"))?;
+            log::info!("Prometheus metrics are available on http://{addr}/metrics");
+            Ok(Box::new(output))
+        });
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,300 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use alumet::measurement::{MeasurementBuffer, WrappedMeasurementValue};
+use alumet::metrics::{MetricRegistry, RawMetricId};
+use alumet::pipeline::{Output, OutputContext, WriteError};
+use tokio::task::AbortHandle;
+
+use crate::server;
+
+/// An output that exposes the latest measurements in the Prometheus text format, over HTTP.
+///
+/// [`Output::write`] only updates the latest value of each time series (one per metric, resource,
+/// consumer and attributes). The values are served by a small HTTP server, see [`PrometheusOutput::serve`].
+/// The values that have not been updated for a while (the `stale_ttl`) are no longer exposed.
+pub struct PrometheusOutput {
+    state: Arc<Mutex<State>>,
+    names: FamilyNames,
+    /// The task of the HTTP server, aborted when the output is dropped.
+    server: Option<AbortHandle>,
+}
+
+/// The latest values, by metric name.
+pub(crate) struct State {
+    stale_ttl: Duration,
+    families: BTreeMap<String, Family>,
+}
+
+/// The time series of a metric.
+struct Family {
+    help: String,
+    /// The latest sample, by set of labels.
+    series: BTreeMap<Vec<(String, String)>, Sample>,
+}
+
+struct Sample {
+    value: f64,
+    updated: Instant,
+}
+
+/// The Prometheus name of each metric.
+///
+/// Different Alumet names can give the same Prometheus name (e.g. `a.b` and `a_b`):
+/// the metrics that come after the first one get a numeric suffix, to keep one family per metric.
+#[derive(Default)]
+struct FamilyNames {
+    by_metric: HashMap<RawMetricId, String>,
+    taken: HashSet<String>,
+}
+
+/// The labels that are derived from the resource and consumer of the measurements.
+const RESOURCE_LABELS: [&str; 4] = [
+    "resource_kind",
+    "resource_id",
+    "resource_consumer_kind",
+    "resource_consumer_id",
+];
+
+impl PrometheusOutput {
+    /// Creates an output that exposes each value for `stale_ttl` after its last update.
+    pub fn new(stale_ttl: Duration) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State {
+                stale_ttl,
+                families: BTreeMap::new(),
+            })),
+            names: FamilyNames::default(),
+            server: None,
+        }
+    }
+
+    /// Starts the HTTP server on `addr`, in the given runtime. The metrics are served on `/metrics`.
+    pub fn serve(&mut self, addr: SocketAddr, rt: &tokio::runtime::Handle) -> std::io::Result<()> {
+        // Bind now, in order to report the errors (e.g. port already in use) immediately.
+        let listener = std::net::TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        let listener = {
+            let _guard = rt.enter();
+            tokio::net::TcpListener::from_std(listener)?
+        };
+        let task = rt.spawn(server::run(listener, self.state.clone()));
+        self.server = Some(task.abort_handle());
+        Ok(())
+    }
+}
+
+impl Drop for PrometheusOutput {
+    fn drop(&mut self) {
+        if let Some(server) = self.server.take() {
+            server.abort();
+        }
+    }
+}
+
+impl Output for PrometheusOutput {
+    fn register(&mut self, metrics: &MetricRegistry) -> Result<(), WriteError> {
+        // Resolve the names in the order of creation of the metrics, so that the suffixes don't change between runs.
+        let mut metrics: Vec<_> = metrics.iter().collect();
+        metrics.sort_by_key(|(id, _)| id.as_u64());
+        for (id, metric) in metrics {
+            self.names.resolve(*id, &metric.name);
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, measurements: &MeasurementBuffer, ctx: &OutputContext) -> Result<(), WriteError> {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        for m in measurements {
+            let Some(metric) = ctx.metrics.with_id(&m.metric) else {
+                log::warn!("Unknown metric {:?}, its measurements will not be exposed", m.metric);
+                continue;
+            };
+            // The metrics created while the pipeline is running are resolved here.
+            let name = self.names.resolve(m.metric, &metric.name).to_owned();
+            let family = state.families.entry(name).or_insert_with(|| Family {
+                help: format!("{} (unit: {})", metric.description, metric.unit.unique_name()),
+                series: BTreeMap::new(),
+            });
+
+            // Resources and consumers are translated to labels, like the attributes.
+            let values = [
+                m.resource.kind().to_owned(),
+                m.resource.id_string().unwrap_or_default(),
+                m.consumer.kind().to_owned(),
+                m.consumer.id_string().unwrap_or_default(),
+            ];
+            let mut labels: Vec<_> = RESOURCE_LABELS.iter().map(|l| l.to_string()).zip(values).collect();
+            labels.extend(m.attributes().map(|(key, value)| (label_name(key), value.to_string())));
+            labels.sort();
+
+            let value = match m.value {
+                WrappedMeasurementValue::F64(x) => x,
+                WrappedMeasurementValue::U64(x) => x as f64,
+            };
+            family.series.insert(labels, Sample { value, updated: now });
+        }
+        state.remove_stale(now);
+        Ok(())
+    }
+}
+
+impl FamilyNames {
+    /// Returns the Prometheus name of the metric `id`, called `name` in Alumet.
+    fn resolve(&mut self, id: RawMetricId, name: &str) -> &str {
+        let taken = &mut self.taken;
+        self.by_metric.entry(id).or_insert_with(|| {
+            let base = metric_name(name);
+            let mut res = base.clone();
+            let mut n = 2;
+            while !taken.insert(res.clone()) {
+                res = format!("{base}_{n}");
+                n += 1;
+            }
+            if res != base {
+                log::warn!("The metric {name} has the same Prometheus name as another metric, it is exposed as {res}");
+            }
+            res
+        })
+    }
+}
+
+impl State {
+    /// Removes the values that have not been updated since `stale_ttl`.
+    fn remove_stale(&mut self, now: Instant) {
+        let ttl = self.stale_ttl;
+        self.families.retain(|_, family| {
+            family
+                .series
+                .retain(|_, sample| now.saturating_duration_since(sample.updated) <= ttl);
+            !family.series.is_empty()
+        });
+    }
+
+    /// Formats the values that are not stale in the Prometheus text exposition format.
+    ///
+    /// See <https://prometheus.io/docs/instrumenting/exposition_formats/#text-based-format>.
+    pub(crate) fn render(&mut self, now: Instant) -> String {
+        self.remove_stale(now);
+        let mut res = String::new();
+        for (name, family) in &self.families {
+            let help = family.help.replace('\\', "\\\\").replace('\n', "\\n");
+            writeln!(res, "# HELP {name} {help}").unwrap();
+            writeln!(res, "# TYPE {name} gauge").unwrap();
+            for (labels, sample) in &family.series {
+                let labels: Vec<String> = labels
+                    .iter()
+                    .map(|(key, value)| format!("{key}=\"{}\"", escape_label_value(value)))
+                    .collect();
+                writeln!(res, "{name}{{{}}} {}", labels.join(","), sample.value).unwrap();
+            }
+        }
+        res
+    }
+}
+
+/// Turns an Alumet metric name into a valid Prometheus metric name.
+fn metric_name(name: &str) -> String {
+    sanitize(name, |c| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+}
+
+/// Turns an Alumet attribute key into a valid Prometheus label name.
+///
+/// The attributes that would clash with the labels of the resource and consumer are prefixed by `attr_`.
+fn label_name(key: &str) -> String {
+    let res = sanitize(key, |c| c.is_ascii_alphanumeric() || c == '_');
+    if RESOURCE_LABELS.contains(&res.as_str()) {
+        format!("attr_{res}")
+    } else {
+        res
+    }
+}
+
+fn sanitize(name: &str, is_valid: impl Fn(char) -> bool) -> String {
+    let mut res: String = name.chars().map(|c| if is_valid(c) { c } else { '_' }).collect();
+    if !res.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        res.insert(0, '_');
+    }
+    res
+}
+
+fn escape_label_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::time::{Duration, Instant};
+
+    use alumet::metrics::RawMetricId;
+
+    use super::{label_name, metric_name, Family, FamilyNames, Sample, State};
+
+    #[test]
+    fn names() {
+        assert_eq!(metric_name("rapl_consumed_energy"), "rapl_consumed_energy");
+        assert_eq!(metric_name("cpu.usage-percent"), "cpu_usage_percent");
+        assert_eq!(metric_name("1st"), "_1st");
+    }
+
+    #[test]
+    fn name_collisions() {
+        // the metrics that have the same sanitized name get a suffix, and keep it
+        let mut names = FamilyNames::default();
+        let id = RawMetricId::from_u64;
+        assert_eq!(names.resolve(id(0), "a.b"), "a_b");
+        assert_eq!(names.resolve(id(1), "a_b"), "a_b_2");
+        assert_eq!(names.resolve(id(2), "a-b"), "a_b_3");
+        assert_eq!(names.resolve(id(3), "a_b_2"), "a_b_2_2");
+        assert_eq!(names.resolve(id(1), "a_b"), "a_b_2");
+        assert_eq!(names.resolve(id(0), "a.b"), "a_b");
+
+        // the attributes don't override the labels of the resource and consumer
+        assert_eq!(label_name("domain"), "domain");
+        assert_eq!(label_name("resource_kind"), "attr_resource_kind");
+        assert_eq!(label_name("resource.id"), "attr_resource_id");
+        assert_eq!(label_name("resource_consumer_kind"), "attr_resource_consumer_kind");
+        assert_eq!(label_name("attr_resource_kind"), "attr_resource_kind");
+    }
+
+    #[test]
+    fn render_and_expire() {
+        let now = Instant::now();
+        let sample = |value, updated| Sample { value, updated };
+        let labels = |id: &str| vec![(String::from("domain"), id.to_owned())];
+        let mut state = State {
+            stale_ttl: Duration::from_secs(10),
+            families: BTreeMap::from([(
+                String::from("energy"),
+                Family {
+                    help: String::from("energy consumed (unit: J)"),
+                    series: BTreeMap::from([
+                        (labels("package"), sample(1.5, now)),
+                        (labels("dram\"0\""), sample(2.0, now - Duration::from_secs(5))),
+                    ]),
+                },
+            )]),
+        };
+        assert_eq!(
+            state.render(now),
+            "# HELP energy energy consumed (unit: J)\n\
+             # TYPE energy gauge\n\
+             energy{domain=\"dram\\\"0\\\"\"} 2\n\
+             energy{domain=\"package\"} 1.5\n"
+        );
+
+        // the dram value is stale
+        let text = state.render(now + Duration::from_secs(8));
+        assert!(text.contains("package"));
+        assert!(!text.contains("dram"));
+
+        // the metric has disappeared
+        assert_eq!(state.render(now + Duration::from_secs(20)), "");
+        assert!(state.families.is_empty());
+    }
+}
@@ -13,6 +13,7 @@ use std::time::Duration;
 /// ```sh
 /// rapl:sources pause
 /// rapl:sources run
+/// rapl:sources flush
 /// rapl:sources trigger every 5s
 /// outputs pause
 /// ```
@@ -28,6 +29,7 @@ pub async fn parse_and_run(command: String, handle: &ControlHandle) -> anyhow::R
             ["pause"] => Ok(SourceCmd::Pause),
             ["run"] => Ok(SourceCmd::Run),
             ["stop"] => Ok(SourceCmd::Stop),
+            ["flush"] => Ok(SourceCmd::Flush),
             ["trigger", "every", interval_str] => {
                 let poll_interval = parse_duration(interval_str)?;
                 let flush_interval = poll_interval;
@@ -61,15 +63,18 @@ pub async fn parse_and_run(command: String, handle: &ControlHandle) -> anyhow::R
     }
 
     async fn run_command(handle: ScopedControlHandle<'_>, element: &str, args: &[&str]) -> anyhow::Result<()> {
-        match element {
-            "source" | "sources" => handle.control_sources(parse_source_command(args)?).await,
-            "transform" | "transforms" => handle.control_transforms(parse_transform_command(args)?).await,
-            "output" | "outputs" => handle.control_outputs(parse_output_command(args)?).await,
+        let n_addressed = match element {
+            "source" | "sources" => handle.control_sources(parse_source_command(args)?).await?,
+            "transform" | "transforms" => handle.control_transforms(parse_transform_command(args)?).await?,
+            "output" | "outputs" => handle.control_outputs(parse_output_command(args)?).await?,
             _ => {
                 return Err(anyhow!(
                     "invalid element \"{element}\", it should be source, transform or output"
                 ))
             }
+        };
+        if n_addressed == 0 {
+            log::warn!("The command did not match any {element}, is the plugin name correct?");
         }
         Ok(())
     }